@@ -0,0 +1,361 @@
+use crate::core::SendTraced;
+use crate::errors::DnessError;
+use aws_credential_types::provider::ProvideCredentials;
+use aws_credential_types::Credentials;
+use aws_sigv4::http_request::{sign, SignableBody, SignableRequest, SigningSettings};
+use aws_sigv4::sign::v4;
+use handlebars::{
+    Context, Handlebars, Helper, HelperDef, HelperResult, Output, RenderContext, RenderErrorReason,
+};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+#[derive(Deserialize, PartialEq, Clone, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct GetSecretValueResponse {
+    secret_string: Option<String>,
+}
+
+/// The body of a rejected request, like `{"__type":"ResourceNotFoundException","Message":"..."}`
+#[derive(Deserialize, PartialEq, Clone, Debug)]
+struct ErrorResponse {
+    #[serde(rename = "__type")]
+    kind: String,
+
+    #[serde(default, alias = "Message")]
+    message: Option<String>,
+}
+
+/// The region of a secret's arn, like "us-east-1" in
+/// "arn:aws:secretsmanager:us-east-1:123456789012:secret:dness-AbCdEf"
+fn arn_region(arn: &str) -> Option<&str> {
+    match arn.split(':').collect::<Vec<_>>().as_slice() {
+        ["arn", _, "secretsmanager", region, ..] if !region.is_empty() => Some(region),
+        _ => None,
+    }
+}
+
+/// Resolves credentials with the standard AWS SDK credential chain: environment variables,
+/// config files, and then instance metadata
+async fn load_credentials(region: &str) -> Result<Credentials, DnessError> {
+    let sdk_config = aws_config::defaults(aws_config::BehaviorVersion::latest())
+        .region(aws_config::Region::new(String::from(region)))
+        .load()
+        .await;
+    let provider = sdk_config.credentials_provider().ok_or_else(|| {
+        DnessError::message(String::from("no aws credentials provider is available"))
+    })?;
+
+    provider
+        .provide_credentials()
+        .await
+        .map_err(|e| DnessError::message(format!("unable to load aws credentials: {}", e)))
+}
+
+/// https://docs.aws.amazon.com/secretsmanager/latest/apireference/API_GetSecretValue.html
+async fn get_secret_value(
+    client: &reqwest::Client,
+    endpoint: &str,
+    region: &str,
+    credentials: Credentials,
+    arn: &str,
+) -> Result<String, DnessError> {
+    let context = "secretsmanager get secret value";
+    let body = serde_json::to_vec(&serde_json::json!({ "SecretId": arn }))
+        .map_err(|e| DnessError::message(format!("unable to encode {}: {}", context, e)))?;
+    let headers = [
+        ("content-type", "application/x-amz-json-1.1"),
+        ("x-amz-target", "secretsmanager.GetSecretValue"),
+    ];
+
+    let identity = credentials.into();
+    let params = v4::SigningParams::builder()
+        .identity(&identity)
+        .region(region)
+        .name("secretsmanager")
+        .time(SystemTime::now())
+        .settings(SigningSettings::default())
+        .build()
+        .map_err(|e| DnessError::message(format!("unable to sign {}: {}", context, e)))?
+        .into();
+
+    let signable = SignableRequest::new(
+        "POST",
+        endpoint,
+        headers.iter().copied(),
+        SignableBody::Bytes(&body),
+    )
+    .and_then(|request| sign(request, &params))
+    .map_err(|e| DnessError::message(format!("unable to sign {}: {}", context, e)))?;
+
+    let (instructions, _signature) = signable.into_parts();
+    let mut request = client.post(endpoint).body(body);
+    for (name, value) in headers.iter().copied().chain(instructions.headers()) {
+        request = request.header(name, value);
+    }
+
+    let response = request
+        .send_traced()
+        .await
+        .map_err(|e| DnessError::send_http(endpoint, context, e))?;
+
+    // Rejected requests describe the error in the body, which is more telling than the status
+    if response.status() == reqwest::StatusCode::BAD_REQUEST {
+        let body = response
+            .text()
+            .await
+            .map_err(|e| DnessError::deserialize(endpoint, context, e))?;
+        return Err(match serde_json::from_str::<ErrorResponse>(&body) {
+            Ok(error) if error.kind.ends_with("ResourceNotFoundException") => {
+                DnessError::message(format!("secret {} was not found", arn))
+            }
+            Ok(error) => DnessError::message(format!(
+                "{} failed ({}): {}",
+                context,
+                error.kind,
+                error.message.as_deref().unwrap_or("no message given")
+            )),
+            Err(_) => DnessError::message(format!("{} failed: {}", context, body)),
+        });
+    }
+
+    let response: GetSecretValueResponse = response
+        .error_for_status()
+        .map_err(|e| DnessError::bad_response(endpoint, context, e))?
+        .json()
+        .await
+        .map_err(|e| DnessError::deserialize(endpoint, context, e))?;
+
+    response
+        .secret_string
+        .ok_or_else(|| DnessError::message(format!("secret {} does not have a string value", arn)))
+}
+
+/// Config rendering is synchronous, so the secret is fetched from its own thread and runtime,
+/// which works whether or not the caller is already within a runtime
+fn block_on<F>(future: F) -> Result<String, DnessError>
+where
+    F: Future<Output = Result<String, DnessError>> + Send,
+{
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .map_err(|e| DnessError::message(format!("unable to create a runtime: {}", e)))?
+                .block_on(future)
+        })
+        .join()
+        .unwrap_or_else(|_| Err(DnessError::message(String::from("secret fetch panicked"))))
+    })
+}
+
+/// The `{{secret "arn:aws:secretsmanager:..."}}` config helper, which substitutes the string
+/// value of the secret. Each secret is only fetched once while the config is parsed.
+#[derive(Debug, Default)]
+pub struct SecretHelper {
+    /// Overrides the regional endpoint, like the AWS_ENDPOINT_URL_SECRETS_MANAGER environment
+    /// variable does for the AWS SDK
+    endpoint: Option<String>,
+    cache: Mutex<HashMap<String, String>>,
+}
+
+impl SecretHelper {
+    pub fn new() -> Self {
+        SecretHelper {
+            endpoint: std::env::var("AWS_ENDPOINT_URL_SECRETS_MANAGER").ok(),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn fetch(&self, arn: &str) -> Result<String, DnessError> {
+        let region = arn_region(arn)
+            .ok_or_else(|| DnessError::message(format!("{} is not a secrets manager arn", arn)))?;
+
+        let endpoint = self
+            .endpoint
+            .clone()
+            .unwrap_or_else(|| format!("https://secretsmanager.{}.amazonaws.com/", region));
+
+        block_on(async {
+            let credentials = load_credentials(region).await?;
+            let client = reqwest::Client::new();
+            get_secret_value(&client, &endpoint, region, credentials, arn).await
+        })
+    }
+
+    fn secret(&self, arn: &str) -> Result<String, DnessError> {
+        if let Some(value) = self.cache.lock().ok().and_then(|x| x.get(arn).cloned()) {
+            return Ok(value);
+        }
+
+        let value = self.fetch(arn)?;
+        if let Ok(mut cache) = self.cache.lock() {
+            cache.insert(String::from(arn), value.clone());
+        }
+        Ok(value)
+    }
+}
+
+impl HelperDef for SecretHelper {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        let arn = h
+            .param(0)
+            .and_then(|x| x.value().as_str())
+            .ok_or(RenderErrorReason::ParamNotFoundForIndex("secret", 0))?;
+
+        let value = self.secret(arn).map_err(|e| {
+            let msg = format!("could not fetch secret {}: {}", arn, e);
+            RenderErrorReason::NestedError(msg.into())
+        })?;
+
+        out.write(&value)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    const ARN: &str = "arn:aws:secretsmanager:us-east-1:123456789012:secret:dness-AbCdEf";
+    const MISSING_ARN: &str = "arn:aws:secretsmanager:us-east-1:123456789012:secret:missing";
+
+    macro_rules! secrets_server {
+        () => {{
+            use rouille::Response;
+            use rouille::Server;
+
+            let requests = Arc::new(AtomicUsize::new(0));
+            let count = requests.clone();
+            let server = Server::new("localhost:0", move |request| {
+                // Every request must be signed
+                if request.header("Authorization").is_none() {
+                    return Response::text("unsigned").with_status_code(403);
+                }
+
+                if request.header("X-Amz-Target") != Some("secretsmanager.GetSecretValue") {
+                    return Response::empty_404();
+                }
+
+                count.fetch_add(1, Ordering::SeqCst);
+                let mut body = String::new();
+                let mut data = request.data().unwrap();
+                std::io::Read::read_to_string(&mut data, &mut body).unwrap();
+                if body.contains(ARN) {
+                    Response::from_data("application/json", r#"{"SecretString":"dec0de"}"#)
+                } else {
+                    Response::from_data(
+                        "application/json",
+                        r#"{"__type":"ResourceNotFoundException","Message":"Secrets Manager can't find the specified secret."}"#,
+                    )
+                    .with_status_code(400)
+                }
+            })
+            .unwrap();
+
+            let (tx, rx) = std::sync::mpsc::sync_channel(1);
+            let addr = server.server_addr().clone();
+            std::thread::spawn(move || {
+                while let Err(_) = rx.try_recv() {
+                    server.poll();
+                    std::thread::sleep(std::time::Duration::from_millis(50))
+                }
+            });
+            (tx, addr, requests)
+        }};
+    }
+
+    /// Renders the template with a new helper, as each parse of the config does
+    fn render(endpoint: &str, template: &str) -> Result<String, handlebars::RenderError> {
+        let helper = SecretHelper {
+            endpoint: Some(String::from(endpoint)),
+            ..SecretHelper::default()
+        };
+
+        let mut handlebars = Handlebars::new();
+        handlebars.register_helper("secret", Box::new(helper));
+        handlebars.render_template(template, &())
+    }
+
+    #[test]
+    fn test_arn_region() {
+        assert_eq!(arn_region(ARN), Some("us-east-1"));
+        assert_eq!(arn_region("arn:aws:s3:::bucket"), None);
+        assert_eq!(arn_region("dness"), None);
+    }
+
+    #[tokio::test]
+    async fn test_get_secret_value() {
+        let (tx, addr, _) = secrets_server!();
+        let http_client = reqwest::Client::new();
+        let endpoint = format!("http://{}/", addr);
+        let credentials = || Credentials::new("AKID", "secret", None, None, "test");
+
+        let value = get_secret_value(&http_client, &endpoint, "us-east-1", credentials(), ARN)
+            .await
+            .unwrap();
+        let missing = get_secret_value(
+            &http_client,
+            &endpoint,
+            "us-east-1",
+            credentials(),
+            MISSING_ARN,
+        )
+        .await;
+        tx.send(()).unwrap();
+
+        assert_eq!(value, "dec0de");
+        assert_eq!(
+            missing.unwrap_err().to_string(),
+            format!("secret {} was not found", MISSING_ARN)
+        );
+    }
+
+    #[test]
+    fn test_secret_helper() {
+        std::env::set_var("AWS_ACCESS_KEY_ID", "AKID");
+        std::env::set_var("AWS_SECRET_ACCESS_KEY", "secret");
+        let (tx, addr, requests) = secrets_server!();
+        let endpoint = format!("http://{}/", addr);
+
+        // The secret is only fetched once however often it is referenced in a parse
+        let template = format!(
+            r#"token = "{{{{secret "{0}"}}}}"
+key = "{{{{secret "{0}"}}}}""#,
+            ARN
+        );
+        let rendered = render(&endpoint, &template).unwrap();
+        assert_eq!(rendered, "token = \"dec0de\"\nkey = \"dec0de\"");
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        // But the next parse fetches it again, so that a rotated secret is picked up
+        render(&endpoint, &template).unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+        let template = format!(r#"token = "{{{{secret "{}"}}}}""#, MISSING_ARN);
+        let err = render(&endpoint, &template).unwrap_err();
+        tx.send(()).unwrap();
+
+        assert!(err
+            .to_string()
+            .contains(&format!("secret {} was not found", MISSING_ARN)));
+
+        let err = render(&endpoint, r#"token = "{{secret "dness"}}""#).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("dness is not a secrets manager arn"));
+    }
+}
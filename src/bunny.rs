@@ -0,0 +1,485 @@
+use crate::config::{BunnyConfig, IpType};
+use crate::core::{dry_run_prefix, DnsProvider, SendTraced, UpdateContext, Updates};
+use crate::errors::DnessError;
+use crate::retry::retry;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::net::IpAddr;
+use tracing::{debug, info};
+
+/// The ttl of records that are created
+const DEFAULT_TTL: u32 = 300;
+
+/// Bunny identifies the type of a record with a number instead of its name
+fn record_type(ip_type: IpType) -> u8 {
+    match ip_type {
+        IpType::V4 => 0,
+        IpType::V6 => 1,
+    }
+}
+
+#[derive(Deserialize, PartialEq, Clone, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct BunnyZoneList {
+    items: Vec<BunnyZone>,
+    current_page: i32,
+    has_more_items: bool,
+}
+
+#[derive(Deserialize, PartialEq, Clone, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct BunnyZone {
+    id: i64,
+    domain: String,
+
+    #[serde(default)]
+    records: Vec<BunnyRecord>,
+}
+
+#[derive(Deserialize, PartialEq, Clone, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct BunnyRecord {
+    id: i64,
+    r#type: u8,
+
+    #[serde(default)]
+    name: String,
+    value: String,
+}
+
+#[derive(Serialize, PartialEq, Clone, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct BunnyUpdateRecord {
+    id: i64,
+    value: String,
+}
+
+#[derive(Serialize, PartialEq, Clone, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct BunnyCreateRecord<'a> {
+    r#type: u8,
+    name: &'a str,
+    value: String,
+    ttl: u32,
+}
+
+// Bunny denotes the root of the zone with an empty name
+fn record_name(name: &str) -> &str {
+    if name == "@" {
+        ""
+    } else {
+        name
+    }
+}
+
+#[derive(Debug)]
+struct BunnyClient<'a> {
+    base_url: &'a str,
+    config: &'a BunnyConfig,
+    client: &'a reqwest::Client,
+    dry_run: bool,
+}
+
+impl<'a> BunnyClient<'a> {
+    /// Pages through the account's zones to find the id of the configured zone.
+    /// https://docs.bunny.net/reference/dnszonepublic_index
+    async fn zone_id(&self) -> Result<i64, DnessError> {
+        let url = format!("{}/dnszone", self.base_url);
+        let mut page = 1;
+        loop {
+            let response: BunnyZoneList = self
+                .client
+                .get(&url)
+                .header("AccessKey", &self.config.api_key)
+                .query(&[("page", page)])
+                .query(&[("search", &self.config.zone)])
+                .send_traced()
+                .await
+                .map_err(|e| DnessError::send_http(&url, "bunny list zones", e))?
+                .error_for_status()
+                .map_err(|e| DnessError::bad_response(&url, "bunny list zones", e))?
+                .json()
+                .await
+                .map_err(|e| DnessError::deserialize(&url, "bunny list zones", e))?;
+
+            let zone = response
+                .items
+                .iter()
+                .find(|x| x.domain.eq_ignore_ascii_case(&self.config.zone));
+            if let Some(zone) = zone {
+                return Ok(zone.id);
+            }
+
+            if !response.has_more_items {
+                return Err(DnessError::message(format!(
+                    "unable to find bunny zone {}",
+                    self.config.zone
+                )));
+            }
+            page = response.current_page + 1;
+        }
+    }
+
+    /// https://docs.bunny.net/reference/dnszonepublic_index2
+    async fn records(&self, zone_id: i64, ip_type: IpType) -> Result<Vec<BunnyRecord>, DnessError> {
+        let url = format!("{}/dnszone/{}", self.base_url, zone_id);
+        let zone: BunnyZone = self
+            .client
+            .get(&url)
+            .header("AccessKey", &self.config.api_key)
+            .send_traced()
+            .await
+            .map_err(|e| DnessError::send_http(&url, "bunny get zone", e))?
+            .error_for_status()
+            .map_err(|e| DnessError::bad_response(&url, "bunny get zone", e))?
+            .json()
+            .await
+            .map_err(|e| DnessError::deserialize(&url, "bunny get zone", e))?;
+
+        Ok(zone
+            .records
+            .into_iter()
+            .filter(|x| x.r#type == record_type(ip_type))
+            .collect())
+    }
+
+    /// https://docs.bunny.net/reference/dnszonepublic_updaterecord
+    async fn update_record(
+        &self,
+        zone_id: i64,
+        record: &BunnyRecord,
+        addr: IpAddr,
+    ) -> Result<(), DnessError> {
+        if self.dry_run {
+            return Ok(());
+        }
+
+        let url = format!(
+            "{}/dnszone/{}/records/{}",
+            self.base_url, zone_id, record.id
+        );
+        self.client
+            .post(&url)
+            .header("AccessKey", &self.config.api_key)
+            .json(&BunnyUpdateRecord {
+                id: record.id,
+                value: addr.to_string(),
+            })
+            .send_traced()
+            .await
+            .map_err(|e| DnessError::send_http(&url, "bunny update record", e))?
+            .error_for_status()
+            .map_err(|e| DnessError::bad_response(&url, "bunny update record", e))?;
+
+        Ok(())
+    }
+
+    /// https://docs.bunny.net/reference/dnszonepublic_addrecord
+    async fn create_record(
+        &self,
+        zone_id: i64,
+        name: &str,
+        addr: IpAddr,
+    ) -> Result<(), DnessError> {
+        if self.dry_run {
+            return Ok(());
+        }
+
+        let url = format!("{}/dnszone/{}/records", self.base_url, zone_id);
+        self.client
+            .put(&url)
+            .header("AccessKey", &self.config.api_key)
+            .json(&BunnyCreateRecord {
+                r#type: record_type(IpType::from(addr)),
+                name,
+                value: addr.to_string(),
+                ttl: DEFAULT_TTL,
+            })
+            .send_traced()
+            .await
+            .map_err(|e| DnessError::send_http(&url, "bunny create record", e))?
+            .error_for_status()
+            .map_err(|e| DnessError::bad_response(&url, "bunny create record", e))?;
+
+        Ok(())
+    }
+}
+
+/// Bunny dns is updated as follows:
+///
+/// 1. Page through the account's zones to find the id of the configured zone
+/// 2. Get the zone, which includes all of its records, and keep those of the address type
+/// 3. Find all the expected records (and log those that are missing) and check their value
+/// 4. Create the missing records when `create_missing` is set
+/// 5. Update the value of the records that are out of date
+#[tracing::instrument(skip(client, config), fields(domain = %config.zone))]
+pub async fn update_domains(
+    client: &reqwest::Client,
+    config: &BunnyConfig,
+    addr: IpAddr,
+    dry_run: bool,
+) -> Result<Updates, DnessError> {
+    let bunny = BunnyClient {
+        base_url: config.base_url.trim_end_matches('/'),
+        config,
+        client,
+        dry_run,
+    };
+
+    let zone_id = bunny.zone_id().await?;
+    let records = bunny.records(zone_id, IpType::from(addr)).await?;
+
+    let expected = config
+        .records
+        .iter()
+        .map(|x| String::from(record_name(x)))
+        .collect::<HashSet<_>>();
+    let actual = records
+        .iter()
+        .map(|x| x.name.clone())
+        .collect::<HashSet<_>>();
+    let missing = crate::core::log_missing_domains(&expected, &actual, "Bunny", &config.zone);
+
+    let mut summary = Updates {
+        missing: missing as i32,
+        ..Updates::default()
+    };
+
+    if config.create_missing {
+        let mut missing = expected.difference(&actual).collect::<Vec<_>>();
+        missing.sort();
+        for name in missing {
+            bunny.create_record(zone_id, name, addr).await?;
+            summary.missing -= 1;
+            summary.updated += 1;
+            info!(
+                "{}{} from zone {} created with {}",
+                dry_run_prefix(dry_run),
+                name,
+                config.zone,
+                addr
+            );
+        }
+    }
+
+    for record in records.iter().filter(|x| expected.contains(&x.name)) {
+        if record.value.parse::<IpAddr>().ok() == Some(addr) {
+            summary.current += 1;
+            debug!(
+                "{} from zone {} is already current",
+                record.name, config.zone
+            );
+        } else {
+            bunny.update_record(zone_id, record, addr).await?;
+            summary.updated += 1;
+            info!(
+                "{}{} from zone {} updated from {} to {}",
+                dry_run_prefix(dry_run),
+                record.name,
+                config.zone,
+                record.value,
+                addr
+            );
+        }
+    }
+
+    Ok(summary)
+}
+
+#[async_trait]
+impl DnsProvider for BunnyConfig {
+    async fn update(
+        &self,
+        ctx: &UpdateContext<'_>,
+        addr: IpAddr,
+    ) -> Result<Updates, Box<dyn std::error::Error>> {
+        retry(ctx.policy, ctx.name, || {
+            update_domains(ctx.client, self, addr, ctx.dry_run)
+        })
+        .await
+        .map_err(|e| e.into())
+    }
+
+    fn name(&self) -> &str {
+        "bunny"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    macro_rules! bunny_server {
+        () => {{
+            use rouille::Response;
+            use rouille::Server;
+
+            let server = Server::new("localhost:0", |request| {
+                if request.header("AccessKey") != Some("deadbeef") {
+                    return Response::text("unauthorized").with_status_code(401);
+                }
+
+                let page = request.get_param("page");
+                match (request.method(), request.url().as_str(), page.as_deref()) {
+                    ("GET", "/dnszone", Some("1")) => Response::from_data(
+                        "application/json",
+                        include_bytes!("../assets/bunny-zones-page-1.json").to_vec(),
+                    ),
+                    ("GET", "/dnszone", Some("2")) => Response::from_data(
+                        "application/json",
+                        r#"{"Items": [{"Id": 1002, "Domain": "example.com"}], "CurrentPage": 2, "TotalItems": 2, "HasMoreItems": false}"#,
+                    ),
+                    ("GET", "/dnszone/1002", _) => Response::from_data(
+                        "application/json",
+                        include_bytes!("../assets/bunny-zone.json").to_vec(),
+                    ),
+                    ("POST", "/dnszone/1002/records/1", _) => {
+                        let body: serde_json::Value = rouille::input::json_input(request).unwrap();
+                        if body["Id"] == 1 && body["Value"] == "2.2.2.2" {
+                            Response::empty_204()
+                        } else {
+                            Response::text("bad update").with_status_code(400)
+                        }
+                    }
+                    ("PUT", "/dnszone/1002/records", _) => {
+                        let body: serde_json::Value = rouille::input::json_input(request).unwrap();
+                        if body["Name"] == "www" && body["Type"] == 0 && body["Value"] == "2.2.2.2"
+                        {
+                            Response::from_data("application/json", "{}")
+                        } else {
+                            Response::text("bad create").with_status_code(400)
+                        }
+                    }
+                    _ => Response::empty_404(),
+                }
+            })
+            .unwrap();
+
+            let (tx, rx) = std::sync::mpsc::sync_channel(1);
+            let addr = server.server_addr().clone();
+            std::thread::spawn(move || {
+                while let Err(_) = rx.try_recv() {
+                    server.poll();
+                    std::thread::sleep(std::time::Duration::from_millis(50))
+                }
+            });
+            (tx, addr)
+        }};
+    }
+
+    fn config(addr: std::net::SocketAddr, zone: &str, create_missing: bool) -> BunnyConfig {
+        BunnyConfig {
+            base_url: format!("http://{}", addr),
+            api_key: String::from("deadbeef"),
+            zone: String::from(zone),
+            records: vec![String::from("@"), String::from("sub"), String::from("www")],
+            ip_types: vec![IpType::V4, IpType::V6],
+            request_timeout_secs: None,
+            create_missing,
+            log_level: None,
+            name: None,
+        }
+    }
+
+    #[test]
+    fn deserialize_bunny_zone() {
+        let json_str = &include_str!("../assets/bunny-zone.json");
+        let zone: BunnyZone = serde_json::from_str(json_str).unwrap();
+        assert_eq!(zone.id, 1002);
+        assert_eq!(
+            zone.records[0],
+            BunnyRecord {
+                id: 1,
+                r#type: 0,
+                name: String::from(""),
+                value: String::from("1.1.1.1"),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bunny_update() {
+        let (tx, addr) = bunny_server!();
+        let http_client = reqwest::Client::new();
+        let new_ip = IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2));
+        let config = config(addr, "example.com", false);
+
+        let summary = update_domains(&http_client, &config, new_ip, false)
+            .await
+            .unwrap();
+        tx.send(()).unwrap();
+
+        assert_eq!(
+            summary,
+            Updates {
+                current: 1,
+                updated: 1,
+                missing: 1,
+                ..Updates::default()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bunny_create_missing() {
+        let (tx, addr) = bunny_server!();
+        let http_client = reqwest::Client::new();
+        let new_ip = IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2));
+        let config = config(addr, "example.com", true);
+
+        let summary = update_domains(&http_client, &config, new_ip, false)
+            .await
+            .unwrap();
+        tx.send(()).unwrap();
+
+        assert_eq!(
+            summary,
+            Updates {
+                current: 1,
+                updated: 2,
+                ..Updates::default()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bunny_ipv6() {
+        let (tx, addr) = bunny_server!();
+        let http_client = reqwest::Client::new();
+        let new_ip = IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1));
+        let config = BunnyConfig {
+            records: vec![String::from("@")],
+            ..config(addr, "example.com", false)
+        };
+
+        let summary = update_domains(&http_client, &config, new_ip, false)
+            .await
+            .unwrap();
+        tx.send(()).unwrap();
+
+        assert_eq!(
+            summary,
+            Updates {
+                current: 1,
+                ..Updates::default()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bunny_unknown_zone() {
+        let (tx, addr) = bunny_server!();
+        let http_client = reqwest::Client::new();
+        let new_ip = IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2));
+        let config = config(addr, "example.net", false);
+
+        let err = update_domains(&http_client, &config, new_ip, false)
+            .await
+            .unwrap_err();
+        tx.send(()).unwrap();
+
+        assert_eq!(err.to_string(), "unable to find bunny zone example.net");
+    }
+}
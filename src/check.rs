@@ -0,0 +1,158 @@
+use crate::config::OutputFormat;
+use crate::core::Updates;
+use serde::Serialize;
+use std::error;
+use std::fmt::Write;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+/// The state of a domain's records without any changes applied
+#[derive(Serialize, Clone, PartialEq, Eq, Debug)]
+pub struct DomainStatus {
+    pub name: String,
+    pub current: i32,
+    pub stale: i32,
+    pub missing: i32,
+    pub error: Option<String>,
+}
+
+impl DomainStatus {
+    /// Interprets the result of a dry run, where the records that would have been updated are
+    /// the ones that are stale
+    pub fn new(name: String, result: &Result<Updates, Box<dyn error::Error>>) -> DomainStatus {
+        match result {
+            Ok(updates) => DomainStatus {
+                name,
+                current: updates.current,
+                stale: updates.updated,
+                missing: updates.missing,
+                error: None,
+            },
+            Err(e) => DomainStatus {
+                name,
+                current: 0,
+                stale: 0,
+                missing: 0,
+                error: Some(e.to_string()),
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Clone, PartialEq, Eq, Debug)]
+pub struct CheckReport {
+    pub addr: Ipv4Addr,
+    pub addr6: Option<Ipv6Addr>,
+    pub domains: Vec<DomainStatus>,
+}
+
+impl CheckReport {
+    /// 0 when all records are current, 1 when any are stale or missing, and 2 when a domain
+    /// could not be checked
+    pub fn exit_code(&self) -> i32 {
+        if self.domains.iter().any(|d| d.error.is_some()) {
+            2
+        } else if self.domains.iter().any(|d| d.stale > 0 || d.missing > 0) {
+            1
+        } else {
+            0
+        }
+    }
+
+    pub fn render(&self, format: OutputFormat) -> String {
+        match format {
+            OutputFormat::Text => self.render_text(),
+            OutputFormat::Json => serde_json::to_string(self).unwrap_or_default(),
+        }
+    }
+
+    fn render_text(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "WAN IP: {}", self.addr);
+        if let Some(addr6) = self.addr6 {
+            let _ = writeln!(out, "WAN IPv6: {}", addr6);
+        }
+
+        for domain in &self.domains {
+            let _ = match &domain.error {
+                Some(e) => writeln!(out, "{}: error: {}", domain.name, e),
+                None => writeln!(
+                    out,
+                    "{}: current: {}, stale: {}, missing: {}",
+                    domain.name, domain.current, domain.stale, domain.missing
+                ),
+            };
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::DnessError;
+
+    fn report() -> CheckReport {
+        let ok = Ok(Updates {
+            current: 2,
+            ..Updates::default()
+        });
+        CheckReport {
+            addr: Ipv4Addr::new(2, 2, 2, 2),
+            addr6: None,
+            domains: vec![DomainStatus::new(
+                String::from("example.com (cloudflare)"),
+                &ok,
+            )],
+        }
+    }
+
+    #[test]
+    fn test_check_exit_code() {
+        let mut report = report();
+        assert_eq!(report.exit_code(), 0);
+
+        let stale = Ok(Updates {
+            updated: 1,
+            ..Updates::default()
+        });
+        report.domains.push(DomainStatus::new(
+            String::from("a.example.com (he)"),
+            &stale,
+        ));
+        assert_eq!(report.exit_code(), 1);
+
+        let err: Result<Updates, Box<dyn error::Error>> =
+            Err(Box::new(DnessError::message(String::from("boom"))));
+        report
+            .domains
+            .push(DomainStatus::new(String::from("b.example.com (he)"), &err));
+        assert_eq!(report.exit_code(), 2);
+    }
+
+    #[test]
+    fn test_check_render() {
+        let report = report();
+        assert_eq!(
+            report.render(OutputFormat::Text),
+            "WAN IP: 2.2.2.2\nexample.com (cloudflare): current: 2, stale: 0, missing: 0\n"
+        );
+
+        let json: serde_json::Value =
+            serde_json::from_str(&report.render(OutputFormat::Json)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "addr": "2.2.2.2",
+                "addr6": null,
+                "domains": [{
+                    "name": "example.com (cloudflare)",
+                    "current": 2,
+                    "stale": 0,
+                    "missing": 0,
+                    "error": null
+                }]
+            })
+        );
+    }
+}
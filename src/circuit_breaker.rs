@@ -0,0 +1,118 @@
+use crate::config::CircuitBreakerConfig;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// The consecutive failures of a domain's updates across runs
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct Circuit {
+    pub failures: u32,
+    pub last_failure: DateTime<Utc>,
+}
+
+/// Circuits keyed by the name of the domain's update
+pub type Circuits = BTreeMap<String, Circuit>;
+
+/// Suspends the updates of a domain that keeps failing (eg: the provider's api is down) so that
+/// the api isn't sent requests on every run while it recovers
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    circuits: Circuits,
+    max_failures: u32,
+    cooldown: Duration,
+}
+
+impl CircuitBreaker {
+    pub fn new(circuits: Circuits, config: &CircuitBreakerConfig) -> Self {
+        CircuitBreaker {
+            circuits,
+            max_failures: config.max_failures,
+            cooldown: Duration::seconds(config.cooldown_secs as i64),
+        }
+    }
+
+    /// How much longer the updates of the domain are suspended, if they are. Once the cooldown
+    /// elapses, an update is let through and another failure suspends the domain again.
+    pub fn suspended_for(&self, name: &str, now: DateTime<Utc>) -> Option<Duration> {
+        if self.max_failures == 0 {
+            return None;
+        }
+
+        let circuit = self.circuits.get(name)?;
+        let remaining = circuit.last_failure + self.cooldown - now;
+        if circuit.failures >= self.max_failures && remaining > Duration::zero() {
+            Some(remaining)
+        } else {
+            None
+        }
+    }
+
+    pub fn record_success(&mut self, name: &str) {
+        self.circuits.remove(name);
+    }
+
+    /// Counts a failure, returning whether it suspends the domain
+    pub fn record_failure(&mut self, name: &str, now: DateTime<Utc>) -> bool {
+        let circuit = self
+            .circuits
+            .entry(String::from(name))
+            .or_insert_with(|| Circuit {
+                failures: 0,
+                last_failure: now,
+            });
+        circuit.failures += 1;
+        circuit.last_failure = now;
+        self.max_failures != 0 && circuit.failures >= self.max_failures
+    }
+
+    pub fn circuits(&self) -> Circuits {
+        self.circuits.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_circuit_breaker() {
+        let config = CircuitBreakerConfig {
+            max_failures: 2,
+            cooldown_secs: 300,
+        };
+        let now = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let mut breaker = CircuitBreaker::new(Circuits::new(), &config);
+        let name = "example.com (cloudflare)";
+
+        assert!(!breaker.record_failure(name, now));
+        assert_eq!(breaker.suspended_for(name, now), None);
+        assert!(breaker.record_failure(name, now));
+        assert_eq!(
+            breaker.suspended_for(name, now + Duration::seconds(60)),
+            Some(Duration::seconds(240))
+        );
+
+        // Once the cooldown elapses, an update is tried again and a failure re-opens the circuit
+        let later = now + Duration::seconds(300);
+        assert_eq!(breaker.suspended_for(name, later), None);
+        assert!(breaker.record_failure(name, later));
+        assert!(breaker.suspended_for(name, later).is_some());
+
+        breaker.record_success(name);
+        assert_eq!(breaker.suspended_for(name, later), None);
+        assert!(breaker.circuits().is_empty());
+    }
+
+    #[test]
+    fn test_circuit_breaker_disabled() {
+        let config = CircuitBreakerConfig {
+            max_failures: 0,
+            cooldown_secs: 300,
+        };
+        let now = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let mut breaker = CircuitBreaker::new(Circuits::new(), &config);
+        assert!(!breaker.record_failure("example.com (cloudflare)", now));
+        assert_eq!(breaker.suspended_for("example.com (cloudflare)", now), None);
+    }
+}
@@ -1,13 +1,25 @@
-use crate::config::CloudflareConfig;
-use crate::core::Updates;
-use log::{debug, info, warn};
+use crate::config::{CloudflareConfig, CloudflareMultiConfig, IpType};
+use crate::core::{
+    dry_run_prefix, log_unmatched_patterns, split_record_patterns, DnsProvider, SendTraced,
+    UpdateContext, Updates,
+};
+use crate::dns::DnsResolver;
+use crate::retry::{retry, Retryable};
+use crate::state::ZoneCache;
+use async_trait::async_trait;
+use futures::future::join_all;
+use glob::Pattern;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 use std::error;
 use std::fmt;
-use std::net::Ipv4Addr;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, warn};
 
-trait CloudflareAuthorizer: fmt::Debug {
+trait CloudflareAuthorizer: fmt::Debug + Send + Sync {
     fn with_auth(&self, request_builder: reqwest::RequestBuilder) -> reqwest::RequestBuilder;
 }
 
@@ -37,21 +49,75 @@ impl CloudflareAuthorizer for EmailKeyAuthorizer {
 }
 
 #[derive(Deserialize, PartialEq, Clone, Debug)]
-struct CloudflareZone {
-    id: String,
-    name: String,
+pub struct CloudflareZone {
+    pub id: String,
+    pub name: String,
 }
 
 #[derive(Deserialize, PartialEq, Clone, Debug)]
 struct CloudflareDnsRecord {
     id: String,
     name: String,
+
+    #[serde(rename = "type")]
+    record_type: String,
     content: String,
+
+    #[serde(default)]
+    ttl: Option<u32>,
+
+    #[serde(default)]
+    proxied: bool,
 }
 
 #[derive(Serialize, PartialEq, Clone, Debug)]
 struct CloudflareDnsRecordUpdate {
-    content: String,
+    /// The type of the existing record, which cloudflare requires for some updates
+    #[serde(rename = "type")]
+    record_type: String,
+
+    /// Serialized as the textual form of the address for both A and AAAA records
+    content: IpAddr,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ttl: Option<u32>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    proxied: Option<bool>,
+}
+
+/// A record update within a batch, which is an update that names the record it applies to
+#[derive(Serialize, PartialEq, Clone, Debug)]
+struct BatchUpdateItem {
+    id: String,
+
+    #[serde(flatten)]
+    update: CloudflareDnsRecordUpdate,
+}
+
+#[derive(Serialize, PartialEq, Clone, Debug)]
+struct CloudflareBatchRequest {
+    patches: Vec<BatchUpdateItem>,
+}
+
+#[derive(Deserialize, PartialEq, Clone, Debug)]
+struct CloudflareBatchResult {
+    #[serde(default)]
+    patches: Vec<CloudflareDnsRecord>,
+}
+
+#[derive(Serialize, PartialEq, Clone, Debug)]
+struct CloudflareDnsRecordCreate {
+    #[serde(rename = "type")]
+    record_type: &'static str,
+    name: String,
+    content: IpAddr,
+
+    /// A ttl of 1 is cloudflare's "automatic" ttl
+    ttl: u32,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    proxied: Option<bool>,
 }
 
 #[derive(Deserialize, PartialEq, Clone, Debug)]
@@ -81,9 +147,21 @@ struct CloudflareResultInfo {
 struct CloudflareClient<'a> {
     zone_name: String,
     zone_id: String,
+
+    /// Whether the zone id came from the cache rather than the zones api
+    zone_cached: bool,
     records: HashSet<String>,
+    patterns: Vec<Pattern>,
     authorizer: Box<dyn CloudflareAuthorizer>,
     client: &'a reqwest::Client,
+    dry_run: bool,
+    create_missing: bool,
+    ttl: Option<u32>,
+    proxied: Option<bool>,
+    use_batch_updates: bool,
+    use_per_record_queries: bool,
+    verify_after_update: bool,
+    verify_timeout_secs: u64,
 }
 
 #[derive(Debug)]
@@ -96,8 +174,10 @@ pub enum ClErrorKind {
     SendHttp(&'static str, reqwest::Error),
     DecodeHttp(&'static str, reqwest::Error),
     ErrorResponse(&'static str, Vec<CloudflareError>),
+    CreateRecord(String, Vec<CloudflareError>),
     MissingResult(&'static str),
     UnexpectedNumberOfZones(usize),
+    RateLimited(u64),
 }
 
 impl error::Error for ClError {
@@ -125,16 +205,62 @@ impl fmt::Display for ClError {
                 }
                 Ok(())
             }
+            ClErrorKind::CreateRecord(ref name, ref errors) => {
+                write!(
+                    f,
+                    "cloudflare returned an error response creating {}: ",
+                    name
+                )?;
+                for error in errors {
+                    write!(f, "{}: {}. ", error.code, error.message)?;
+                }
+                Ok(())
+            }
             ClErrorKind::MissingResult(action) => {
                 write!(f, "no cloudflare result found for {}", action)
             }
+            ClErrorKind::UnexpectedNumberOfZones(0) => write!(
+                f,
+                "expected 1 zone to be returned, not 0 (the zones of the account can be listed \
+                 with --cloudflare-list-zones)"
+            ),
             ClErrorKind::UnexpectedNumberOfZones(zones) => {
                 write!(f, "expected 1 zone to be returned, not {}", zones)
             }
+            ClErrorKind::RateLimited(retry_after) => {
+                write!(f, "rate limited, retry after {}s", retry_after)
+            }
         }
     }
 }
 
+impl Retryable for ClError {
+    fn is_retryable(&self) -> bool {
+        matches!(self.kind, ClErrorKind::SendHttp(..))
+    }
+}
+
+/// How long to wait when cloudflare rate limits a request without saying how long to wait
+const DEFAULT_RETRY_AFTER_SECS: u64 = 5;
+
+/// Zones with more records than this are queried by record name when few records are configured
+const PER_RECORD_QUERY_THRESHOLD: i32 = 10;
+
+/// The most configured records that are queried by name instead of paging through the zone
+const PER_RECORD_QUERY_MAX_RECORDS: usize = 3;
+
+/// How long to wait between lookups when verifying an update
+const VERIFY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The number of seconds cloudflare asks that we wait before sending another request
+fn retry_after(headers: &HeaderMap) -> u64 {
+    headers
+        .get(RETRY_AFTER)
+        .and_then(|x| x.to_str().ok())
+        .and_then(|x| x.trim().parse::<u64>().ok())
+        .unwrap_or(DEFAULT_RETRY_AFTER_SECS)
+}
+
 fn empty_to_none<P: AsRef<str>>(s: P) -> Option<P> {
     if s.as_ref().is_empty() {
         None
@@ -152,7 +278,7 @@ fn create_authorizer(config: &CloudflareConfig) -> Box<dyn CloudflareAuthorizer>
     // as that is considered more secure
     if let Some(token) = token {
         if email.is_some() || key.is_some() {
-            log::warn!(
+            warn!(
                 "ignoring email and key fields as token is already given for zone: {}",
                 &config.zone
             );
@@ -168,7 +294,7 @@ fn create_authorizer(config: &CloudflareConfig) -> Box<dyn CloudflareAuthorizer>
         })
     } else {
         // If neither are provided, log an error and create a dummy authorizer
-        log::error!(
+        error!(
             "missing either token or email + key in cloudflare config for zone: {}",
             &config.zone
         );
@@ -179,116 +305,291 @@ fn create_authorizer(config: &CloudflareConfig) -> Box<dyn CloudflareAuthorizer>
     }
 }
 
-impl<'a> CloudflareClient<'a> {
-    async fn create<'b>(
-        client: &'b reqwest::Client,
-        config: &CloudflareConfig,
-    ) -> Result<CloudflareClient<'b>, ClError> {
-        let authorizer = create_authorizer(config);
+async fn fetch_zone_id(
+    client: &reqwest::Client,
+    config: &CloudflareConfig,
+    authorizer: &dyn CloudflareAuthorizer,
+) -> Result<String, ClError> {
+    let mut request_builder: reqwest::RequestBuilder = client
+        .get("https://api.cloudflare.com/client/v4/zones")
+        .query(&[("name", &config.zone)]);
+
+    request_builder = authorizer.with_auth(request_builder);
+
+    let response: CloudflareResponse<Vec<CloudflareZone>> = request_builder
+        .send_traced()
+        .await
+        .map_err(|e| ClError {
+            kind: ClErrorKind::SendHttp("get zones", e),
+        })?
+        .json()
+        .await
+        .map_err(|e| ClError {
+            kind: ClErrorKind::DecodeHttp("get zones", e),
+        })?;
+
+    if !response.success {
+        Err(ClError {
+            kind: ClErrorKind::ErrorResponse("zones", response.errors.clone()),
+        })
+    } else if let Some(zone) = response.result {
+        if zone.len() != 1 {
+            return Err(ClError {
+                kind: ClErrorKind::UnexpectedNumberOfZones(zone.len()),
+            });
+        }
 
-        // Need to translate our zone name into an id
-        let mut request_builder: reqwest::RequestBuilder = client
-            .get("https://api.cloudflare.com/client/v4/zones")
-            .query(&[("name", &config.zone)]);
+        Ok(zone[0].id.clone())
+    } else {
+        Err(ClError {
+            kind: ClErrorKind::MissingResult("zones"),
+        })
+    }
+}
 
-        request_builder = authorizer.with_auth(request_builder);
+/// Lists every zone the token has access to, following the pages of the zones api
+async fn list_zones(
+    client: &reqwest::Client,
+    zones_url: &str,
+    authorizer: &dyn CloudflareAuthorizer,
+) -> Result<Vec<CloudflareZone>, ClError> {
+    let mut zones = Vec::new();
+    let mut page = 0;
 
-        let response: CloudflareResponse<Vec<CloudflareZone>> = request_builder
-            .send()
+    loop {
+        page += 1;
+        debug!("grabbing page {} from {}", page, zones_url);
+        let request_builder = client.get(zones_url).query(&[("page", page)]);
+        let response: CloudflareResponse<Vec<CloudflareZone>> = authorizer
+            .with_auth(request_builder)
+            .send_traced()
             .await
             .map_err(|e| ClError {
-                kind: ClErrorKind::SendHttp("get zones", e),
+                kind: ClErrorKind::SendHttp("list zones", e),
             })?
             .json()
             .await
             .map_err(|e| ClError {
-                kind: ClErrorKind::DecodeHttp("get zones", e),
+                kind: ClErrorKind::DecodeHttp("list zones", e),
             })?;
 
         if !response.success {
+            return Err(ClError {
+                kind: ClErrorKind::ErrorResponse("list zones", response.errors.clone()),
+            });
+        }
+
+        let result = response.result.ok_or(ClError {
+            kind: ClErrorKind::MissingResult("list zones"),
+        })?;
+        zones.extend(result);
+
+        match response.result_info {
+            Some(info) if info.total_pages > page => continue,
+            _ => return Ok(zones),
+        }
+    }
+}
+
+/// Lists the zones of the account, so that the zone name for the config can be found
+pub async fn cloudflare_list_zones(
+    client: &reqwest::Client,
+    token: &str,
+) -> Result<Vec<CloudflareZone>, ClError> {
+    let authorizer = BearerAuthorizer {
+        token: String::from(token),
+    };
+    list_zones(
+        client,
+        "https://api.cloudflare.com/client/v4/zones",
+        &authorizer,
+    )
+    .await
+}
+
+impl<'a> CloudflareClient<'a> {
+    /// Translates the zone name into cloudflare's id, preferring the id cached by a previous run
+    async fn create<'b>(
+        client: &'b reqwest::Client,
+        config: &CloudflareConfig,
+        zones: &ZoneCache,
+        dry_run: bool,
+    ) -> Result<CloudflareClient<'b>, ClError> {
+        let authorizer = create_authorizer(config);
+        let now = chrono::Utc::now();
+        let ttl = chrono::Duration::seconds(config.zone_cache_ttl_secs as i64);
+        let (zone_id, zone_cached) = match zones.get(&config.zone, ttl, now) {
+            Some(zone_id) => {
+                debug!("using cached id {} for zone {}", zone_id, config.zone);
+                (zone_id, true)
+            }
+            None => {
+                let zone_id = fetch_zone_id(client, config, authorizer.as_ref()).await?;
+                if let Some(previous) = zones.insert(&config.zone, zone_id.clone(), now) {
+                    info!(
+                        "id of zone {} changed from {} to {}",
+                        config.zone, previous, zone_id
+                    );
+                }
+                (zone_id, false)
+            }
+        };
+
+        let (records, patterns) = split_record_patterns(&config.records);
+        Ok(CloudflareClient {
+            zone_name: config.zone.clone(),
+            zone_id,
+            zone_cached,
+            records,
+            patterns,
+            client,
+            authorizer,
+            dry_run,
+            create_missing: config.create_missing,
+            ttl: config.ttl,
+            proxied: config.proxied,
+            use_batch_updates: config.use_batch_updates,
+            use_per_record_queries: config.use_per_record_queries,
+            verify_after_update: config.verify_after_update,
+            verify_timeout_secs: config.verify_timeout_secs,
+        })
+    }
+
+    /// Sends the request built by the given function. When cloudflare responds that we are being
+    /// rate limited, the request is sent once more after waiting as long as cloudflare asks.
+    async fn send_rate_limited<F>(
+        &self,
+        action: &'static str,
+        build: F,
+    ) -> Result<reqwest::Response, ClError>
+    where
+        F: Fn() -> reqwest::RequestBuilder,
+    {
+        let send = || async {
+            build().send_traced().await.map_err(|e| ClError {
+                kind: ClErrorKind::SendHttp(action, e),
+            })
+        };
+
+        let response = send().await?;
+        if response.status() != StatusCode::TOO_MANY_REQUESTS {
+            return Ok(response);
+        }
+
+        let wait = retry_after(response.headers());
+        warn!(
+            "cloudflare rate limited {} for zone {}, retrying in {}s",
+            action, self.zone_name, wait
+        );
+        tokio::time::sleep(Duration::from_secs(wait)).await;
+
+        let response = send().await?;
+        if response.status() == StatusCode::TOO_MANY_REQUESTS {
             Err(ClError {
-                kind: ClErrorKind::ErrorResponse("zones", response.errors.clone()),
+                kind: ClErrorKind::RateLimited(retry_after(response.headers())),
             })
-        } else if let Some(zone) = response.result {
-            if zone.len() != 1 {
-                return Err(ClError {
-                    kind: ClErrorKind::UnexpectedNumberOfZones(zone.len()),
-                });
-            }
+        } else {
+            Ok(response)
+        }
+    }
 
-            let zone_id = zone[0].id.clone();
+    /// Sends a GET for a page of the zone's records of the address type, optionally only those
+    /// with the given name
+    async fn get_records(
+        &self,
+        ip_type: IpType,
+        page: i32,
+        name: Option<&str>,
+    ) -> Result<(Vec<CloudflareDnsRecord>, Option<CloudflareResultInfo>), ClError> {
+        let record_url = format!(
+            "https://api.cloudflare.com/client/v4/zones/{}/dns_records",
+            self.zone_id
+        );
+
+        debug!("grabbing page {} from {}", page, record_url);
+        let response: CloudflareResponse<Vec<CloudflareDnsRecord>> = self
+            .send_rate_limited("get records", || {
+                let mut request_builder = self
+                    .client
+                    .get(&record_url)
+                    .query(&[("page", page)])
+                    .query(&[("type", ip_type.record_type())]);
+                if let Some(name) = name {
+                    request_builder = request_builder.query(&[("name", name)]);
+                }
+                self.authorizer.with_auth(request_builder)
+            })
+            .await?
+            .json()
+            .await
+            .map_err(|e| ClError {
+                kind: ClErrorKind::DecodeHttp("get records", e),
+            })?;
 
-            Ok(CloudflareClient {
-                zone_name: config.zone.clone(),
-                zone_id,
-                records: config.records.iter().cloned().collect(),
-                client,
-                authorizer,
+        if !response.success {
+            Err(ClError {
+                kind: ClErrorKind::ErrorResponse("get records", response.errors.clone()),
             })
+        } else if let Some(records) = response.result {
+            Ok((records, response.result_info))
         } else {
             Err(ClError {
-                kind: ClErrorKind::MissingResult("zones"),
+                kind: ClErrorKind::MissingResult("get records"),
             })
         }
     }
 
+    /// Whether querying each of the few configured records by name takes fewer requests than
+    /// paging through the rest of a large zone. Patterns need every record in the zone.
+    fn prefers_per_record_queries(&self, info: &CloudflareResultInfo) -> bool {
+        self.use_per_record_queries
+            && self.patterns.is_empty()
+            && self.records.len() <= PER_RECORD_QUERY_MAX_RECORDS
+            && info.total_count > PER_RECORD_QUERY_THRESHOLD
+            && info.total_pages > info.page
+    }
+
     // Grab all the sub domains in the zone, but since there can be many of them, cloudflare
     // paginates the results.
-    async fn paginate_domains(&self) -> Result<Vec<CloudflareDnsRecord>, ClError> {
+    async fn paginate_domains(&self, ip_type: IpType) -> Result<Vec<CloudflareDnsRecord>, ClError> {
         let mut done = false;
         let mut page = 0;
         let mut dns_records: Vec<CloudflareDnsRecord> = Vec::new();
 
-        let record_url = format!(
-            "https://api.cloudflare.com/client/v4/zones/{}/dns_records",
-            self.zone_id
-        );
-
         while !done {
             page += 1;
 
-            debug!("grabbing page {} from {}", page, record_url);
-            let mut request_builder: reqwest::RequestBuilder = self
-                .client
-                .get(&record_url)
-                .query(&[("page", page)])
-                .query(&[("type", "A")]);
-
-            request_builder = self.authorizer.with_auth(request_builder);
-
-            let response: CloudflareResponse<Vec<CloudflareDnsRecord>> = request_builder
-                .send()
-                .await
-                .map_err(|e| ClError {
-                    kind: ClErrorKind::SendHttp("get records", e),
-                })?
-                .json()
-                .await
-                .map_err(|e| ClError {
-                    kind: ClErrorKind::DecodeHttp("get records", e),
-                })?;
-
-            if !response.success {
-                return Err(ClError {
-                    kind: ClErrorKind::ErrorResponse("get records", response.errors.clone()),
-                });
-            } else if let Some(records) = response.result {
-                dns_records.extend(records);
-
-                if let Some(info) = response.result_info {
-                    done = info.total_pages <= page;
-                } else {
+            let (records, result_info) = self.get_records(ip_type, page, None).await?;
+            match result_info {
+                Some(info) if page == 1 && self.prefers_per_record_queries(&info) => {
+                    debug!(
+                        "zone {} has {} records, so querying configured records by name",
+                        self.zone_name, info.total_count
+                    );
+                    return self.query_domains(ip_type).await;
+                }
+                Some(info) => done = info.total_pages <= page,
+                None => {
                     done = true;
                     warn!(
                         "did not receive a result info page for {}, assuming no more results",
                         self.zone_name
                     );
                 }
-            } else {
-                return Err(ClError {
-                    kind: ClErrorKind::MissingResult("get records"),
-                });
             }
+
+            dns_records.extend(records);
+        }
+
+        Ok(dns_records)
+    }
+
+    /// Grab the configured domains one at a time with the name filter
+    async fn query_domains(&self, ip_type: IpType) -> Result<Vec<CloudflareDnsRecord>, ClError> {
+        let mut dns_records: Vec<CloudflareDnsRecord> = Vec::new();
+        for name in &self.records {
+            let (records, _) = self.get_records(ip_type, 1, Some(name)).await?;
+            dns_records.extend(records);
         }
 
         Ok(dns_records)
@@ -301,62 +602,288 @@ impl<'a> CloudflareClient<'a> {
             .map(|x| &x.name)
             .cloned()
             .collect::<HashSet<String>>();
+        log_unmatched_patterns(&self.patterns, &actual, "cloudflare", &self.zone_name);
         crate::core::log_missing_domains(&self.records, &actual, "cloudflare", &self.zone_name)
     }
 
-    async fn update(&self, addr: Ipv4Addr) -> Result<Updates, ClError> {
-        let mut dns_records = self.paginate_domains().await?;
-        let missing = self.log_missing_domains(&dns_records) as i32;
+    /// Whether the record is one of the configured records or matches one of the patterns
+    fn is_configured(&self, name: &str) -> bool {
+        self.records.contains(name) || self.patterns.iter().any(|x| x.matches(name))
+    }
+
+    // The domains found in the config but not in cloudflare, in a stable order
+    fn missing_domains(&self, remote_domains: &[CloudflareDnsRecord]) -> Vec<String> {
+        let actual = remote_domains
+            .iter()
+            .map(|x| &x.name)
+            .collect::<HashSet<&String>>();
+        let mut missing = self
+            .records
+            .iter()
+            .filter(|x| !actual.contains(x))
+            .cloned()
+            .collect::<Vec<_>>();
+        missing.sort();
+        missing
+    }
+
+    async fn update(&self, addr: IpAddr) -> Result<Updates, ClError> {
+        let ip_type = IpType::from(addr);
+        let mut dns_records = self.paginate_domains(ip_type).await?;
         let mut current = 0;
         let mut updated = 0;
 
+        let missing = if self.create_missing {
+            for name in self.missing_domains(&dns_records) {
+                self.create_record(&name, addr).await?;
+                updated += 1;
+                info!(
+                    "{}{} from zone {} created with {}",
+                    dry_run_prefix(self.dry_run),
+                    name,
+                    self.zone_name,
+                    addr
+                );
+            }
+            0
+        } else {
+            self.log_missing_domains(&dns_records) as i32
+        };
+
         let recs = dns_records
             .iter_mut()
-            .filter(|x| self.records.contains(&x.name));
+            .filter(|x| self.is_configured(&x.name));
+
+        // Out of date records are collected so that they can be sent in a single batch, and are
+        // only logged as updated once the update succeeds
+        let mut pending = Vec::new();
 
+        // The records are only those of the address type, as the query filters on the type, so the
+        // type echoed back in the update always matches the family of the address
         for record in recs {
-            match record.content.parse::<Ipv4Addr>() {
+            match record.content.parse::<IpAddr>() {
+                Ok(ip) if ip == addr && !self.proxied_changed(record) => {
+                    current += 1;
+                    debug!(
+                        "{} from zone {} is already current",
+                        record.name, self.zone_name
+                    )
+                }
                 Ok(ip) => {
-                    if ip != addr {
-                        updated += 1;
-                        self.update_record(record, addr).await?;
-
-                        info!(
-                            "{} from zone {} updated from {} to {}",
-                            record.name, self.zone_name, record.content, addr
+                    let msg = if ip != addr {
+                        format!(
+                            "{}{} from zone {} updated from {} to {}",
+                            dry_run_prefix(self.dry_run),
+                            record.name,
+                            self.zone_name,
+                            record.content,
+                            addr
                         )
                     } else {
-                        current += 1;
-                        debug!(
-                            "{} from zone {} is already current",
-                            record.name, self.zone_name
+                        format!(
+                            "{}{} from zone {} updated proxied from {} to {}",
+                            dry_run_prefix(self.dry_run),
+                            record.name,
+                            self.zone_name,
+                            record.proxied,
+                            !record.proxied
                         )
-                    }
+                    };
+                    pending.push((&*record, msg));
                 }
                 Err(ref e) => {
-                    updated += 1;
-                    warn!("could not parse domain {} address {} as ipv4 -- will replace it. Original error: {}", record.name, record.content, e);
-                    self.update_record(record, addr).await?;
+                    warn!("could not parse domain {} address {} as {} -- will replace it. Original error: {}", record.name, record.content, ip_type, e);
+                    let msg = format!(
+                        "{}{} from zone {} updated from {} to {}",
+                        dry_run_prefix(self.dry_run),
+                        record.name,
+                        self.zone_name,
+                        record.content,
+                        addr
+                    );
+                    pending.push((&*record, msg));
+                }
+            }
+        }
 
-                    info!(
-                        "{} from zone {} update from {} to {}",
-                        record.name, self.zone_name, record.content, addr
-                    )
+        let records = pending
+            .iter()
+            .map(|(record, _)| *record)
+            .collect::<Vec<_>>();
+        self.update_records(&records, addr).await?;
+        for (_, msg) in &pending {
+            updated += 1;
+            info!("{}", msg);
+        }
+
+        // An unverified update is counted as failed, but isn't an error as cloudflare accepted it.
+        // The records are verified at the same time, so that they share the verify timeout.
+        let mut failed = 0;
+        if self.verify_after_update && !self.dry_run {
+            let deadline = Instant::now() + Duration::from_secs(self.verify_timeout_secs);
+            let mut checks = Vec::new();
+            for record in records {
+                if self.proxied.unwrap_or(record.proxied) {
+                    debug!(
+                        "{} from zone {} is proxied, so its address can't be verified",
+                        record.name, self.zone_name
+                    );
+                } else {
+                    checks.push(self.verify(&record.name, addr, deadline));
                 }
             }
+
+            failed = join_all(checks).await.into_iter().filter(|x| !x).count() as i32;
         }
 
         Ok(Updates {
             updated,
             current,
             missing,
+            failed,
+            ..Updates::default()
         })
     }
 
+    /// Looks up the record with cloudflare's resolver until it resolves to the address, returning
+    /// whether it did so before the deadline
+    async fn verify(&self, name: &str, addr: IpAddr, deadline: Instant) -> bool {
+        let query = format!("{}.", name);
+        loop {
+            // A new resolver is created for each lookup so that a cached answer isn't returned
+            let resolved = match DnsResolver::create_cloudflare().await {
+                Ok(resolver) => resolver.ip_lookup(&query, IpType::from(addr)).await.ok(),
+                Err(_) => None,
+            };
+
+            if resolved == Some(addr) {
+                debug!("{} from zone {} verified as {}", name, self.zone_name, addr);
+                return true;
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                let resolved = resolved.map_or_else(|| String::from("nothing"), |x| x.to_string());
+                warn!(
+                    "{} from zone {} resolved to {} instead of {} after {}s",
+                    name, self.zone_name, resolved, addr, self.verify_timeout_secs
+                );
+                return false;
+            }
+
+            tokio::time::sleep(VERIFY_INTERVAL.min(deadline - now)).await;
+        }
+    }
+
+    /// Whether the record's proxy state differs from the configured one
+    fn proxied_changed(&self, record: &CloudflareDnsRecord) -> bool {
+        self.proxied
+            .is_some_and(|proxied| proxied != record.proxied)
+    }
+
+    /// Updates the records in a single batch request when there is more than one, falling back to
+    /// updating them one at a time if the batch fails
+    async fn update_records(
+        &self,
+        records: &[&CloudflareDnsRecord],
+        addr: IpAddr,
+    ) -> Result<(), ClError> {
+        if self.use_batch_updates && records.len() > 1 {
+            match self.batch_update(records, addr).await {
+                Ok(()) => return Ok(()),
+                Err(e) => warn!(
+                    "batch update for zone {} failed, updating records individually: {}",
+                    self.zone_name, e
+                ),
+            }
+        }
+
+        for record in records {
+            self.update_record(record, addr).await?;
+        }
+
+        Ok(())
+    }
+
+    fn record_update(
+        &self,
+        record: &CloudflareDnsRecord,
+        addr: IpAddr,
+    ) -> CloudflareDnsRecordUpdate {
+        CloudflareDnsRecordUpdate {
+            record_type: record.record_type.clone(),
+            content: addr,
+            ttl: self.ttl,
+            proxied: self.proxied,
+        }
+    }
+
+    /// Cloudflare applies all the updates of a batch or none of them:
+    /// https://developers.cloudflare.com/dns/manage-dns-records/how-to/batch-record-changes/
+    async fn batch_update(
+        &self,
+        records: &[&CloudflareDnsRecord],
+        addr: IpAddr,
+    ) -> Result<(), ClError> {
+        let url = format!(
+            "https://api.cloudflare.com/client/v4/zones/{}/dns_records/batch",
+            self.zone_id
+        );
+
+        debug!(
+            "zone {} updating {} records to {} in a batch: {}",
+            self.zone_name,
+            records.len(),
+            addr,
+            &url
+        );
+
+        if self.dry_run {
+            return Ok(());
+        }
+
+        let batch = CloudflareBatchRequest {
+            patches: records
+                .iter()
+                .map(|record| BatchUpdateItem {
+                    id: record.id.clone(),
+                    update: self.record_update(record, addr),
+                })
+                .collect(),
+        };
+
+        let response: CloudflareResponse<CloudflareBatchResult> = self
+            .send_rate_limited("batch update dns", || {
+                let request_builder = self.client.post(&url).json(&batch);
+                self.authorizer.with_auth(request_builder)
+            })
+            .await?
+            .json()
+            .await
+            .map_err(|e| ClError {
+                kind: ClErrorKind::DecodeHttp("batch update dns", e),
+            })?;
+
+        if !response.success {
+            return Err(ClError {
+                kind: ClErrorKind::ErrorResponse("batch update dns", response.errors),
+            });
+        }
+
+        let updated = response.result.map(|x| x.patches.len()).unwrap_or(0);
+        if updated != records.len() {
+            return Err(ClError {
+                kind: ClErrorKind::MissingResult("batch update dns"),
+            });
+        }
+
+        Ok(())
+    }
+
     async fn update_record(
         &self,
         record: &CloudflareDnsRecord,
-        addr: Ipv4Addr,
+        addr: IpAddr,
     ) -> Result<(), ClError> {
         let url = format!(
             "https://api.cloudflare.com/client/v4/zones/{}/dns_records/{}",
@@ -368,20 +895,18 @@ impl<'a> CloudflareClient<'a> {
             record.name, self.zone_name, record.content, addr, &url
         );
 
-        let update = CloudflareDnsRecordUpdate {
-            content: addr.to_string(),
-        };
+        if self.dry_run {
+            return Ok(());
+        }
 
-        let mut request_builder: reqwest::RequestBuilder = self.client.patch(&url);
-        request_builder = self.authorizer.with_auth(request_builder);
+        let update = self.record_update(record, addr);
 
-        let response: CloudflareResponse<CloudflareDnsRecord> = request_builder
-            .json(&update)
-            .send()
-            .await
-            .map_err(|e| ClError {
-                kind: ClErrorKind::SendHttp("update dns", e),
-            })?
+        let response: CloudflareResponse<CloudflareDnsRecord> = self
+            .send_rate_limited("update dns", || {
+                let request_builder = self.client.patch(&url).json(&update);
+                self.authorizer.with_auth(request_builder)
+            })
+            .await?
             .json()
             .await
             .map_err(|e| ClError {
@@ -396,30 +921,139 @@ impl<'a> CloudflareClient<'a> {
             Ok(())
         }
     }
+
+    async fn create_record(&self, name: &str, addr: IpAddr) -> Result<(), ClError> {
+        let url = format!(
+            "https://api.cloudflare.com/client/v4/zones/{}/dns_records",
+            self.zone_id
+        );
+
+        debug!(
+            "{} from zone {} creating with {}: {}",
+            name, self.zone_name, addr, &url
+        );
+
+        if self.dry_run {
+            return Ok(());
+        }
+
+        let create = CloudflareDnsRecordCreate {
+            record_type: IpType::from(addr).record_type(),
+            name: String::from(name),
+            content: addr,
+            ttl: self.ttl.unwrap_or(1),
+            proxied: self.proxied,
+        };
+
+        let response: CloudflareResponse<CloudflareDnsRecord> = self
+            .send_rate_limited("create dns", || {
+                let request_builder = self.client.post(&url).json(&create);
+                self.authorizer.with_auth(request_builder)
+            })
+            .await?
+            .json()
+            .await
+            .map_err(|e| ClError {
+                kind: ClErrorKind::DecodeHttp("create dns", e),
+            })?;
+
+        if !response.success {
+            Err(ClError {
+                kind: ClErrorKind::CreateRecord(String::from(name), response.errors),
+            })
+        } else {
+            Ok(())
+        }
+    }
 }
 
 /// Updating cloudflare domain works as follows:
-///  1. Send GET to translate the zone (example.com) to cloudflare's id
+///  1. Send GET to translate the zone (example.com) to cloudflare's id, unless the id is cached
 ///  2. Send GET to find all the domains under the zone and their ids
 ///    - Cloudflare paginates the response to handle many subdomains
 ///    - It is possible to query for individual domains but as long as more
 ///      than one desired domain in each page -- this methods cuts down requests
 ///  3. Each desired domain in the config is checked to ensure that it is set to our address. In
 ///     this way cloudflare is our cache (to guard against nefarious users updating out of band)
+///  4. All the out of date records are updated with a single batch request
+#[tracing::instrument(skip(client, config), fields(domain = %config.zone))]
 pub async fn update_domains(
     client: &reqwest::Client,
     config: &CloudflareConfig,
-    addr: Ipv4Addr,
+    addr: IpAddr,
+    zones: &ZoneCache,
+    dry_run: bool,
 ) -> Result<Updates, ClError> {
-    CloudflareClient::create(client, config)
-        .await?
-        .update(addr)
+    let cloudflare = CloudflareClient::create(client, config, zones, dry_run).await?;
+    let result = cloudflare.update(addr).await;
+
+    // A zone that was recreated has a new id, so a cached id is looked up again after a failure
+    if result.is_err() && cloudflare.zone_cached {
+        zones.remove(&config.zone);
+    }
+
+    result
+}
+
+#[async_trait]
+impl DnsProvider for CloudflareConfig {
+    async fn update(
+        &self,
+        ctx: &UpdateContext<'_>,
+        addr: IpAddr,
+    ) -> Result<Updates, Box<dyn std::error::Error>> {
+        retry(ctx.policy, ctx.name, || {
+            update_domains(ctx.client, self, addr, ctx.zones, ctx.dry_run)
+        })
         .await
+        .map_err(|e| e.into())
+    }
+
+    fn name(&self) -> &str {
+        "cloudflare"
+    }
+}
+
+/// Each zone with records of the address type is updated at the same time, as if it was a
+/// cloudflare domain of its own. The first error is returned once every zone has finished.
+#[async_trait]
+impl DnsProvider for CloudflareMultiConfig {
+    async fn update(
+        &self,
+        ctx: &UpdateContext<'_>,
+        addr: IpAddr,
+    ) -> Result<Updates, Box<dyn std::error::Error>> {
+        let ip_type = IpType::from(addr);
+        let configs = self
+            .zones
+            .iter()
+            .filter(|x| x.ip_types.contains(&ip_type))
+            .map(|x| self.zone_config(x))
+            .collect::<Vec<_>>();
+
+        let results = futures::future::join_all(configs.iter().map(|config| {
+            retry(ctx.policy, ctx.name, move || {
+                update_domains(ctx.client, config, addr, ctx.zones, ctx.dry_run)
+            })
+        }))
+        .await;
+
+        let mut updates = Updates::default();
+        for result in results {
+            updates += result?;
+        }
+        Ok(updates)
+    }
+
+    fn name(&self) -> &str {
+        "cloudflaremulti"
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr};
 
     #[test]
     fn deserialize_cloudflare_error() {
@@ -439,6 +1073,119 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_list_zones_pages() {
+        use rouille::{Response, Server};
+
+        let server = Server::new("localhost:0", |request| {
+            if request.header("Authorization") != Some("Bearer my-token") {
+                return Response::from_data(
+                    "application/json",
+                    r#"{"result":null,"success":false,"errors":[{"code":9109,"message":"Invalid access token"}]}"#,
+                );
+            }
+
+            let body = match request.get_param("page").as_deref() {
+                Some("1") => {
+                    r#"{"result":[{"id":"1","name":"example.com"}],"result_info":{"page":1,"per_page":1,"total_pages":2,"count":1,"total_count":2},"success":true,"errors":[]}"#
+                }
+                _ => {
+                    r#"{"result":[{"id":"2","name":"example.org"}],"result_info":{"page":2,"per_page":1,"total_pages":2,"count":1,"total_count":2},"success":true,"errors":[]}"#
+                }
+            };
+            Response::from_data("application/json", body)
+        })
+        .unwrap();
+
+        let (tx, rx) = std::sync::mpsc::sync_channel::<()>(1);
+        let url = format!("http://{}/client/v4/zones", server.server_addr());
+        std::thread::spawn(move || {
+            while rx.try_recv().is_err() {
+                server.poll();
+                std::thread::sleep(std::time::Duration::from_millis(50))
+            }
+        });
+
+        let client = reqwest::Client::new();
+        let authorizer = BearerAuthorizer {
+            token: String::from("my-token"),
+        };
+        let zones = list_zones(&client, &url, &authorizer).await.unwrap();
+
+        let bad_authorizer = BearerAuthorizer {
+            token: String::from("wrong"),
+        };
+        let err = list_zones(&client, &url, &bad_authorizer)
+            .await
+            .unwrap_err();
+        tx.send(()).unwrap();
+
+        let names = zones.iter().map(|x| x.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["example.com", "example.org"]);
+        assert!(err.to_string().contains("Invalid access token"));
+    }
+
+    #[test]
+    fn test_prefers_per_record_queries() {
+        let http_client = reqwest::Client::new();
+        let client = |records: &[&str], use_per_record_queries: bool| CloudflareClient {
+            zone_name: String::from("example.com"),
+            zone_id: String::from("abc"),
+            zone_cached: false,
+            records: records.iter().map(|x| String::from(*x)).collect(),
+            patterns: Vec::new(),
+            authorizer: Box::new(BearerAuthorizer {
+                token: String::from("dec0de"),
+            }),
+            client: &http_client,
+            dry_run: false,
+            create_missing: false,
+            ttl: None,
+            proxied: None,
+            use_batch_updates: true,
+            use_per_record_queries,
+            verify_after_update: false,
+            verify_timeout_secs: 10,
+        };
+        let info = |total_count, total_pages| CloudflareResultInfo {
+            page: 1,
+            per_page: 100,
+            total_pages,
+            count: 100,
+            total_count,
+        };
+
+        let few = client(&["a.example.com", "b.example.com"], true);
+        assert!(few.prefers_per_record_queries(&info(250, 3)));
+
+        // Every record is already in the first page
+        assert!(!few.prefers_per_record_queries(&info(50, 1)));
+
+        let many = client(
+            &[
+                "a.example.com",
+                "b.example.com",
+                "c.example.com",
+                "d.example.com",
+            ],
+            true,
+        );
+        assert!(!many.prefers_per_record_queries(&info(250, 3)));
+
+        let opted_out = client(&["a.example.com"], false);
+        assert!(!opted_out.prefers_per_record_queries(&info(250, 3)));
+
+        // A pattern could match any record in the zone
+        let patterned = CloudflareClient {
+            patterns: vec![Pattern::new("*.example.com").unwrap()],
+            ..client(&["a.example.com"], true)
+        };
+        assert!(!patterned.prefers_per_record_queries(&info(250, 3)));
+        assert!(patterned.is_configured("a.example.com"));
+        assert!(patterned.is_configured("b.example.com"));
+        assert!(!patterned.is_configured("example.com"));
+    }
+
     #[test]
     fn deserialize_cloudflare_zone() {
         let json_str = &include_str!("../assets/cloudflare-zone-response.json");
@@ -477,7 +1224,10 @@ mod tests {
                 result: Some(CloudflareDnsRecord {
                     id: String::from("372e67954025e0ba6aaa6d586b9e0b59"),
                     name: String::from("example.com"),
+                    record_type: String::from("A"),
                     content: String::from("198.51.100.4"),
+                    ttl: Some(3600),
+                    proxied: false,
                 }),
                 result_info: None,
                 success: true,
@@ -485,4 +1235,151 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn serialize_cloudflare_batch() {
+        let batch = CloudflareBatchRequest {
+            patches: vec![BatchUpdateItem {
+                id: String::from("372e67954025e0ba6aaa6d586b9e0b59"),
+                update: CloudflareDnsRecordUpdate {
+                    record_type: String::from("A"),
+                    content: IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2)),
+                    ttl: None,
+                    proxied: Some(false),
+                },
+            }],
+        };
+
+        assert_eq!(
+            serde_json::to_value(&batch).unwrap(),
+            serde_json::json!({
+                "patches": [{
+                    "id": "372e67954025e0ba6aaa6d586b9e0b59",
+                    "type": "A",
+                    "content": "2.2.2.2",
+                    "proxied": false
+                }]
+            })
+        );
+    }
+
+    #[test]
+    fn deserialize_cloudflare_batch_response() {
+        let json_str = &include_str!("../assets/cloudflare-batch-response.json");
+        let response: CloudflareResponse<CloudflareBatchResult> =
+            serde_json::from_str(json_str).unwrap();
+
+        assert!(response.success);
+        assert_eq!(
+            response.result.unwrap().patches,
+            vec![
+                CloudflareDnsRecord {
+                    id: String::from("372e67954025e0ba6aaa6d586b9e0b59"),
+                    name: String::from("example.com"),
+                    record_type: String::from("A"),
+                    content: String::from("2.2.2.2"),
+                    ttl: Some(1),
+                    proxied: false,
+                },
+                CloudflareDnsRecord {
+                    id: String::from("023e105f4ecef8ad9ca31a8372d0c353"),
+                    name: String::from("n.example.com"),
+                    record_type: String::from("A"),
+                    content: String::from("2.2.2.2"),
+                    ttl: Some(1),
+                    proxied: true,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn serialize_cloudflare_create() {
+        let addr = IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2));
+        let create = CloudflareDnsRecordCreate {
+            record_type: IpType::from(addr).record_type(),
+            name: String::from("n.example.com"),
+            content: addr,
+            ttl: 1,
+            proxied: None,
+        };
+
+        assert_eq!(
+            serde_json::to_value(&create).unwrap(),
+            serde_json::json!({
+                "type": "A",
+                "name": "n.example.com",
+                "content": "2.2.2.2",
+                "ttl": 1
+            })
+        );
+
+        let addr = IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1));
+        let create = CloudflareDnsRecordCreate {
+            record_type: IpType::from(addr).record_type(),
+            content: addr,
+            ..create
+        };
+
+        assert_eq!(
+            serde_json::to_value(&create).unwrap(),
+            serde_json::json!({
+                "type": "AAAA",
+                "name": "n.example.com",
+                "content": "2001:db8::1",
+                "ttl": 1
+            })
+        );
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers), DEFAULT_RETRY_AFTER_SECS);
+
+        headers.insert(RETRY_AFTER, "30".parse().unwrap());
+        assert_eq!(retry_after(&headers), 30);
+
+        headers.insert(
+            RETRY_AFTER,
+            "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap(),
+        );
+        assert_eq!(retry_after(&headers), DEFAULT_RETRY_AFTER_SECS);
+    }
+
+    #[test]
+    fn serialize_cloudflare_update() {
+        let update = CloudflareDnsRecordUpdate {
+            record_type: String::from("A"),
+            content: IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2)),
+            ttl: None,
+            proxied: None,
+        };
+        assert_eq!(
+            serde_json::to_value(&update).unwrap(),
+            serde_json::json!({ "type": "A", "content": "2.2.2.2" })
+        );
+
+        let update = CloudflareDnsRecordUpdate {
+            record_type: String::from("A"),
+            content: IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2)),
+            ttl: Some(120),
+            proxied: Some(true),
+        };
+        assert_eq!(
+            serde_json::to_value(&update).unwrap(),
+            serde_json::json!({ "type": "A", "content": "2.2.2.2", "ttl": 120, "proxied": true })
+        );
+
+        let update = CloudflareDnsRecordUpdate {
+            record_type: String::from("AAAA"),
+            content: IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)),
+            ttl: None,
+            proxied: None,
+        };
+        assert_eq!(
+            serde_json::to_value(&update).unwrap(),
+            serde_json::json!({ "type": "AAAA", "content": "2001:db8::1" })
+        );
+    }
 }
@@ -1,12 +1,19 @@
-use handlebars::{Handlebars, RenderError, TemplateError};
+use crate::core::DnsProvider;
+use handlebars::{
+    Context, Handlebars, Helper, HelperResult, Output, RenderContext, RenderError,
+    RenderErrorReason, TemplateError,
+};
+use ipnetwork::IpNetwork;
 use log::LevelFilter;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::fs::File;
 use std::io::Error as IoError;
 use std::io::Read;
-use std::path::Path;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
 use std::{collections::HashMap, error};
+use tracing::warn;
 
 #[derive(Debug)]
 pub struct ConfigError {
@@ -20,6 +27,7 @@ pub enum ConfigErrorKind {
     Parse(toml::de::Error),
     Template(TemplateError),
     Render(RenderError),
+    Invalid(String),
 }
 
 impl error::Error for ConfigError {
@@ -30,6 +38,7 @@ impl error::Error for ConfigError {
             ConfigErrorKind::Parse(ref e) => Some(e),
             ConfigErrorKind::Template(ref e) => Some(e),
             ConfigErrorKind::Render(ref e) => Some(e),
+            ConfigErrorKind::Invalid(_) => None,
         }
     }
 }
@@ -43,6 +52,7 @@ impl fmt::Display for ConfigError {
             ConfigErrorKind::Parse(ref _e) => write!(f, "a parsing error"),
             ConfigErrorKind::Template(ref _e) => write!(f, "config template error"),
             ConfigErrorKind::Render(ref _e) => write!(f, "config template rendering error"),
+            ConfigErrorKind::Invalid(ref msg) => write!(f, "{}", msg),
         }
     }
 }
@@ -51,7 +61,97 @@ impl fmt::Display for ConfigError {
 #[serde(deny_unknown_fields)]
 pub struct DnsConfig {
     #[serde(default = "default_resolver")]
-    pub ip_resolver: String,
+    pub ip_resolver: IpResolverConfig,
+
+    #[serde(default)]
+    pub ip_resolver_v4: Option<IpResolverConfig>,
+
+    #[serde(default)]
+    pub ip_resolver_v6: Option<IpResolverConfig>,
+
+    #[serde(default)]
+    pub ip_resolver_url: Option<String>,
+
+    #[serde(default)]
+    pub ip_resolver_interface: Option<String>,
+
+    #[serde(default)]
+    pub ip_allowlist: Vec<IpNetwork>,
+
+    #[serde(default)]
+    pub ip_blocklist: Vec<IpNetwork>,
+
+    #[serde(default)]
+    pub reject_private_ips: bool,
+
+    #[serde(default)]
+    pub reject_private_ips_exit_code: bool,
+
+    #[serde(default)]
+    pub allow_private_ips: bool,
+
+    #[serde(default)]
+    pub proxy_url: Option<String>,
+
+    #[serde(default)]
+    pub user_agent: Option<String>,
+
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+
+    #[serde(default = "default_retry_base_delay_ms")]
+    pub retry_base_delay_ms: u64,
+
+    #[serde(default)]
+    pub request_timeout_secs: Option<u64>,
+
+    #[serde(default)]
+    pub connect_timeout_secs: Option<u64>,
+
+    #[serde(default)]
+    pub read_timeout_secs: Option<u64>,
+
+    #[serde(
+        default = "default_max_concurrent_updates",
+        alias = "max_concurrent_providers"
+    )]
+    pub max_concurrent_updates: usize,
+
+    #[serde(default)]
+    pub interval_secs: Option<u64>,
+
+    #[serde(default)]
+    pub systemd_notify: bool,
+
+    #[serde(default)]
+    pub fail_on_missing: bool,
+
+    #[serde(default)]
+    pub state_file: Option<PathBuf>,
+
+    #[serde(default = "default_state_ttl_secs")]
+    pub state_ttl_secs: u64,
+
+    #[serde(default)]
+    pub min_update_interval_secs: u64,
+
+    #[serde(default)]
+    pub history_file: Option<PathBuf>,
+
+    #[serde(default = "default_history_max_entries")]
+    pub history_max_entries: usize,
+
+    #[serde(default)]
+    pub notifications: NotificationsConfig,
+
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+
+    #[serde(default)]
+    pub circuit_breaker: CircuitBreakerConfig,
+
+    #[serde(default)]
+    pub output_format: OutputFormat,
 
     #[serde(default)]
     pub log: LogConfig,
@@ -60,165 +160,2030 @@ pub struct DnsConfig {
     pub domains: Vec<DomainConfig>,
 }
 
-fn default_resolver() -> String {
-    String::from("opendns")
+/// The networks of addresses that can't be reached from the internet: private (RFC 1918 and
+/// unique local), shared (CGNAT), and loopback
+const PRIVATE_NETWORKS: [&str; 7] = [
+    "10.0.0.0/8",
+    "172.16.0.0/12",
+    "192.168.0.0/16",
+    "100.64.0.0/10",
+    "127.0.0.0/8",
+    "fc00::/7",
+    "::1/128",
+];
+
+/// Whether the address is loopback, link-local, or private, which would make every domain
+/// unreachable if it was pushed to public dns
+fn is_private_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_loopback() || ip.is_link_local() || ip.is_private(),
+        IpAddr::V6(ip) => ip.is_loopback() || ip.is_unicast_link_local() || ip.is_unique_local(),
+    }
+}
+
+/// Why the resolved address can't be trusted as the WAN IP
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IpRejection {
+    /// A private address, which always fails the run as pushing one to public dns would make
+    /// every domain unreachable
+    Private,
+
+    /// An address the config doesn't trust, which only fails the run when asked to
+    Untrusted(String),
+}
+
+impl IpRejection {
+    /// Whether the run fails for the rejected address
+    pub fn fails_run(&self, config: &DnsConfig) -> bool {
+        match self {
+            IpRejection::Private => true,
+            IpRejection::Untrusted(_) => config.reject_private_ips_exit_code,
+        }
+    }
+}
+
+impl fmt::Display for IpRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IpRejection::Private => write!(f, "it looks like a private address"),
+            IpRejection::Untrusted(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl DnsConfig {
+    /// The reason the resolved address can't be trusted as the WAN IP, if any. Private addresses
+    /// are rejected unless `allow_private_ips` is set, but the networks of `reject_private_ips`
+    /// are rejected even then. The allowlist only applies to addresses of the same type as one of
+    /// its networks.
+    pub fn rejected_ip_reason(&self, ip: IpAddr) -> Option<IpRejection> {
+        if let Some(network) = self.ip_blocklist.iter().find(|x| x.contains(ip)) {
+            let reason = format!("it is within the blocklisted {}", network);
+            return Some(IpRejection::Untrusted(reason));
+        }
+
+        if self.reject_private_ips {
+            let private = PRIVATE_NETWORKS
+                .iter()
+                .filter_map(|x| x.parse::<IpNetwork>().ok())
+                .find(|x| x.contains(ip));
+            if let Some(network) = private {
+                let reason = format!("it is within the private {}", network);
+                return Some(IpRejection::Untrusted(reason));
+            }
+        }
+
+        if !self.allow_private_ips && is_private_ip(ip) {
+            return Some(IpRejection::Private);
+        }
+
+        let mut allowlist = self
+            .ip_allowlist
+            .iter()
+            .filter(|x| x.is_ipv4() == ip.is_ipv4())
+            .peekable();
+        if allowlist.peek().is_some() && !allowlist.any(|x| x.contains(ip)) {
+            let reason = String::from("it is outside the allowlist");
+            return Some(IpRejection::Untrusted(reason));
+        }
+
+        None
+    }
+}
+
+fn default_resolver() -> IpResolverConfig {
+    IpResolverConfig::Single(String::from("opendns"))
+}
+
+fn default_max_retries() -> u32 {
+    3
+}
+
+fn default_retry_base_delay_ms() -> u64 {
+    500
+}
+
+fn default_max_concurrent_updates() -> usize {
+    4
+}
+
+fn default_state_ttl_secs() -> u64 {
+    3600
+}
+
+fn default_history_max_entries() -> usize {
+    1000
 }
 
 impl Default for DnsConfig {
     fn default() -> Self {
         DnsConfig {
             ip_resolver: default_resolver(),
+            ip_resolver_v4: None,
+            ip_resolver_v6: None,
+            ip_resolver_url: None,
+            ip_resolver_interface: None,
+            ip_allowlist: Vec::new(),
+            ip_blocklist: Vec::new(),
+            reject_private_ips: false,
+            reject_private_ips_exit_code: false,
+            allow_private_ips: false,
+            proxy_url: None,
+            user_agent: None,
+            max_retries: default_max_retries(),
+            retry_base_delay_ms: default_retry_base_delay_ms(),
+            request_timeout_secs: None,
+            connect_timeout_secs: None,
+            read_timeout_secs: None,
+            max_concurrent_updates: default_max_concurrent_updates(),
+            interval_secs: None,
+            systemd_notify: false,
+            fail_on_missing: false,
+            state_file: None,
+            state_ttl_secs: default_state_ttl_secs(),
+            min_update_interval_secs: 0,
+            history_file: None,
+            history_max_entries: default_history_max_entries(),
+            notifications: Default::default(),
+            metrics: Default::default(),
+            circuit_breaker: Default::default(),
+            output_format: OutputFormat::default(),
             log: Default::default(),
             domains: Default::default(),
         }
     }
 }
 
-#[derive(Deserialize, Clone, PartialEq, Debug)]
-#[serde(deny_unknown_fields)]
-pub struct LogConfig {
-    #[serde(default = "default_log_level")]
-    pub level: LevelFilter,
+impl DnsConfig {
+    /// The resolvers of the WAN IP for the address type, where `ip_resolver_v4` and
+    /// `ip_resolver_v6` take precedence over `ip_resolver`
+    pub fn ip_resolver_for(&self, ip_type: IpType) -> &IpResolverConfig {
+        let resolver = match ip_type {
+            IpType::V4 => self.ip_resolver_v4.as_ref(),
+            IpType::V6 => self.ip_resolver_v6.as_ref(),
+        };
+        resolver.unwrap_or(&self.ip_resolver)
+    }
+
+    /// Whether the resolver is used for either address type
+    fn uses_resolver(&self, resolver: &str) -> bool {
+        [IpType::V4, IpType::V6]
+            .iter()
+            .any(|x| self.ip_resolver_for(*x).contains(resolver))
+    }
+
+    /// Ensures the resolver url and interface are only given for the resolver that uses them, as
+    /// either without their resolver is most likely a config mistake
+    fn validate_resolver(&self) -> Result<(), ConfigError> {
+        let is_http = self.uses_resolver("http");
+        let is_interface = self.uses_resolver("interface");
+        let msg = match (is_http, &self.ip_resolver_url) {
+            (true, None) => "ip_resolver_url is required when ip_resolver is \"http\"",
+            (false, Some(_)) => "ip_resolver_url is only valid when ip_resolver is \"http\"",
+            _ => match (is_interface, &self.ip_resolver_interface) {
+                (true, None) => {
+                    "ip_resolver_interface is required when ip_resolver is \"interface\""
+                }
+                (false, Some(_)) => {
+                    "ip_resolver_interface is only valid when ip_resolver is \"interface\""
+                }
+                _ => return Ok(()),
+            },
+        };
+
+        Err(ConfigError {
+            kind: ConfigErrorKind::Invalid(String::from(msg)),
+        })
+    }
+
+    fn validate_interval(&self) -> Result<(), ConfigError> {
+        match self.interval_secs {
+            Some(0) => Err(ConfigError {
+                kind: ConfigErrorKind::Invalid(String::from(
+                    "interval_secs must be greater than zero",
+                )),
+            }),
+            _ => Ok(()),
+        }
+    }
 }
 
-fn default_log_level() -> LevelFilter {
-    LevelFilter::Info
+/// The resolvers used to find the WAN IP. When given a list, each resolver is tried in order
+/// until one succeeds.
+#[derive(Deserialize, Serialize, Clone, PartialEq, Eq, Debug)]
+#[serde(untagged)]
+pub enum IpResolverConfig {
+    Single(String),
+    Chain(Vec<String>),
 }
 
-impl Default for LogConfig {
-    fn default() -> LogConfig {
-        LogConfig {
-            level: default_log_level(),
+impl IpResolverConfig {
+    pub fn resolvers(&self) -> &[String] {
+        match self {
+            IpResolverConfig::Single(resolver) => std::slice::from_ref(resolver),
+            IpResolverConfig::Chain(resolvers) => resolvers,
         }
     }
+
+    /// The configured resolvers that are able to resolve the ipv6 WAN IP
+    pub fn ipv6_resolvers(&self) -> Vec<&String> {
+        self.resolvers()
+            .iter()
+            .filter(|x| {
+                ["opendns", "ipify", "interface"]
+                    .iter()
+                    .any(|r| x.eq_ignore_ascii_case(r))
+            })
+            .collect()
+    }
+
+    /// Whether the given resolver is one of the configured resolvers
+    pub fn contains(&self, resolver: &str) -> bool {
+        self.resolvers()
+            .iter()
+            .any(|x| x.eq_ignore_ascii_case(resolver))
+    }
 }
 
-#[derive(Deserialize, Clone, PartialEq, Debug)]
-#[serde(tag = "type")]
+impl fmt::Display for IpResolverConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.resolvers().join(", "))
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
 #[serde(rename_all = "lowercase")]
-pub enum DomainConfig {
-    Cloudflare(CloudflareConfig),
-    GoDaddy(GoDaddyConfig),
-    Namecheap(NamecheapConfig),
-    He(HeConfig),
-    NoIp(NoIpConfig),
-    Dynu(DynuConfig),
-    Porkbun(PorkbunConfig),
+pub enum IpType {
+    V4,
+    V6,
 }
 
-impl DomainConfig {
-    pub fn display_name(&self) -> String {
+impl IpType {
+    /// The dns record type that holds an address of this type
+    pub fn record_type(&self) -> &'static str {
+        match self {
+            IpType::V4 => "A",
+            IpType::V6 => "AAAA",
+        }
+    }
+
+    /// The other address type, as when falling back from one address family to the other
+    pub fn opposite(&self) -> IpType {
         match self {
-            DomainConfig::Cloudflare(c) => format!("{} ({})", c.zone, "cloudflare"),
-            DomainConfig::GoDaddy(c) => format!("{} ({})", c.domain, "godaddy"),
-            DomainConfig::Namecheap(c) => format!("{} ({})", c.domain, "namecheap"),
-            DomainConfig::He(c) => format!("{} ({})", c.hostname, "he"),
-            DomainConfig::NoIp(c) => format!("{} ({})", c.hostname, "noip"),
-            DomainConfig::Dynu(c) => format!("{} ({})", c.hostname, "dynu"),
-            DomainConfig::Porkbun(c) => format!("{} ({})", c.domain, "porkbun"),
+            IpType::V4 => IpType::V6,
+            IpType::V6 => IpType::V4,
         }
     }
 }
 
-#[derive(Deserialize, Clone, PartialEq, Debug)]
-#[serde(deny_unknown_fields)]
-pub struct CloudflareConfig {
-    pub email: Option<String>,
-    pub key: Option<String>,
-    pub token: Option<String>,
-    pub zone: String,
-    pub records: Vec<String>,
+/// The address type of an address
+impl From<IpAddr> for IpType {
+    fn from(addr: IpAddr) -> Self {
+        match addr {
+            IpAddr::V4(_) => IpType::V4,
+            IpAddr::V6(_) => IpType::V6,
+        }
+    }
 }
 
-#[derive(Deserialize, Clone, PartialEq, Debug)]
-#[serde(deny_unknown_fields)]
-pub struct GoDaddyConfig {
-    #[serde(default = "godaddy_base_url")]
-    pub base_url: String,
-    pub key: String,
-    pub secret: String,
-    pub domain: String,
-    pub records: Vec<String>,
+pub fn default_ip_types() -> Vec<IpType> {
+    vec![IpType::V4]
 }
 
-#[derive(Deserialize, Clone, PartialEq, Debug)]
-#[serde(deny_unknown_fields)]
-pub struct NamecheapConfig {
-    #[serde(default = "namecheap_base_url")]
-    pub base_url: String,
-    pub domain: String,
-    pub ddns_password: String,
-    pub records: Vec<String>,
+impl fmt::Display for IpType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IpType::V4 => write!(f, "ipv4"),
+            IpType::V6 => write!(f, "ipv6"),
+        }
+    }
 }
 
 #[derive(Deserialize, Clone, PartialEq, Debug)]
 #[serde(deny_unknown_fields)]
-pub struct HeConfig {
-    #[serde(default = "he_base_url")]
-    pub base_url: String,
-    pub hostname: String,
-    pub password: String,
-    pub records: Vec<String>,
+pub struct LogConfig {
+    #[serde(default = "default_log_level")]
+    pub level: LevelFilter,
+
+    #[serde(default)]
+    pub log_format: LogFormat,
+
+    #[serde(default)]
+    pub target: LogTarget,
+
+    #[serde(default)]
+    pub log_http: bool,
 }
 
-#[derive(Deserialize, Clone, PartialEq, Debug)]
-#[serde(deny_unknown_fields)]
-pub struct NoIpConfig {
-    #[serde(default = "noip_base_url")]
-    pub base_url: String,
-    pub username: String,
-    pub password: String,
-    pub hostname: String,
+/// How the report of a check and the summary of a run are written to stdout
+#[derive(Deserialize, clap::ValueEnum, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+/// How each log line is formatted
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+/// Where log lines are written
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LogTarget {
+    #[default]
+    Stdout,
+    Stderr,
+
+    /// The local syslog daemon, under the daemon facility
+    Syslog,
+}
+
+fn default_log_level() -> LevelFilter {
+    LevelFilter::Info
+}
+
+impl Default for LogConfig {
+    fn default() -> LogConfig {
+        LogConfig {
+            level: default_log_level(),
+            log_format: LogFormat::default(),
+            target: LogTarget::default(),
+            log_http: false,
+        }
+    }
 }
 
 #[derive(Deserialize, Clone, PartialEq, Debug)]
 #[serde(deny_unknown_fields)]
-pub struct DynuConfig {
-    #[serde(default = "dynu_base_url")]
-    pub base_url: String,
-    pub hostname: String,
-    pub username: String,
-    pub password: String,
-    pub records: Vec<String>,
+pub struct NotificationsConfig {
+    #[serde(default)]
+    pub webhook_url: Option<String>,
+
+    #[serde(default = "default_webhook_method")]
+    pub webhook_method: String,
+
+    #[serde(default)]
+    pub slack_webhook_url: Option<String>,
+
+    #[serde(default)]
+    pub discord_webhook_url: Option<String>,
+
+    #[serde(default)]
+    pub ntfy_url: Option<String>,
+
+    #[serde(default)]
+    pub ntfy_token: Option<String>,
+
+    #[serde(default)]
+    pub smtp: Option<SmtpConfig>,
 }
 
 #[derive(Deserialize, Clone, PartialEq, Debug)]
 #[serde(deny_unknown_fields)]
-pub struct PorkbunConfig {
-    #[serde(default = "porkbun_base_url")]
-    pub base_url: String,
-    pub domain: String,
-    pub key: String,
-    pub secret: String,
-    pub records: Vec<String>,
-}
+pub struct SmtpConfig {
+    pub smtp_host: String,
 
-fn godaddy_base_url() -> String {
-    String::from("https://api.godaddy.com")
-}
+    /// Defaults to 587 with TLS and 25 without. TLS is negotiated with STARTTLS, except on port
+    /// 465 where the connection is encrypted from the start.
+    #[serde(default)]
+    pub smtp_port: Option<u16>,
 
-fn namecheap_base_url() -> String {
-    String::from("https://dynamicdns.park-your-domain.com")
-}
+    #[serde(default)]
+    pub smtp_username: Option<String>,
 
-fn he_base_url() -> String {
-    String::from("https://dyn.dns.he.net")
+    #[serde(default)]
+    pub smtp_password: Option<String>,
+
+    #[serde(default = "default_smtp_tls")]
+    pub smtp_tls: bool,
+
+    pub from: String,
+    pub to: String,
 }
 
-fn noip_base_url() -> String {
-    String::from("https://dynupdate.no-ip.com")
+fn default_smtp_tls() -> bool {
+    true
 }
 
-fn dynu_base_url() -> String {
-    String::from("https://api.dynu.com")
+fn default_webhook_method() -> String {
+    String::from("POST")
 }
 
-fn porkbun_base_url() -> String {
-    String::from("https://api.porkbun.com/api/json/v3")
+impl Default for NotificationsConfig {
+    fn default() -> NotificationsConfig {
+        NotificationsConfig {
+            webhook_url: None,
+            webhook_method: default_webhook_method(),
+            slack_webhook_url: None,
+            discord_webhook_url: None,
+            ntfy_url: None,
+            ntfy_token: None,
+            smtp: None,
+        }
+    }
 }
 
-pub fn parse_config<P: AsRef<Path>>(path: P) -> Result<DnsConfig, ConfigError> {
-    let mut f = File::open(path).map_err(|e| ConfigError {
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct MetricsConfig {
+    #[serde(default)]
+    pub enabled: bool,
+
+    #[serde(default = "default_metrics_listen_addr")]
+    pub listen_addr: String,
+}
+
+fn default_metrics_listen_addr() -> String {
+    String::from("0.0.0.0:9090")
+}
+
+impl Default for MetricsConfig {
+    fn default() -> MetricsConfig {
+        MetricsConfig {
+            enabled: false,
+            listen_addr: default_metrics_listen_addr(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct CircuitBreakerConfig {
+    #[serde(default = "default_circuit_max_failures")]
+    pub max_failures: u32,
+
+    #[serde(default = "default_circuit_cooldown_secs")]
+    pub cooldown_secs: u64,
+}
+
+fn default_circuit_max_failures() -> u32 {
+    5
+}
+
+fn default_circuit_cooldown_secs() -> u64 {
+    300
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> CircuitBreakerConfig {
+        CircuitBreakerConfig {
+            max_failures: default_circuit_max_failures(),
+            cooldown_secs: default_circuit_cooldown_secs(),
+        }
+    }
+}
+
+/// The `type` of each provider's domains
+pub const PROVIDER_TYPES: [&str; 24] = [
+    "cloudflare",
+    "godaddy",
+    "namecheap",
+    "namecheapapi",
+    "he",
+    "noip",
+    "dynu",
+    "porkbun",
+    "dnsomatic",
+    "ovh",
+    "route53",
+    "linode",
+    "namecom",
+    "inwx",
+    "infomaniak",
+    "strato",
+    "ddnss",
+    "bunny",
+    "zoneedit",
+    "ipv64",
+    "dnspod",
+    "njalla",
+    "regfish",
+    "cloudflaremulti",
+];
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+#[serde(tag = "type")]
+#[serde(rename_all = "lowercase")]
+pub enum DomainConfig {
+    Cloudflare(CloudflareConfig),
+    GoDaddy(GoDaddyConfig),
+    Namecheap(NamecheapConfig),
+    NamecheapApi(NamecheapApiConfig),
+    He(HeConfig),
+    NoIp(NoIpConfig),
+    Dynu(DynuConfig),
+    Porkbun(PorkbunConfig),
+    DnsOMatic(DnsOMaticConfig),
+    Ovh(OvhConfig),
+    Route53(Route53Config),
+    Linode(LinodeConfig),
+    NameCom(NameComConfig),
+    Inwx(InwxConfig),
+    Infomaniak(InfomaniakConfig),
+    Strato(StratoConfig),
+    Ddnss(DdnssConfig),
+    Bunny(BunnyConfig),
+    ZoneEdit(ZoneEditConfig),
+    Ipv64(Ipv64Config),
+    Dnspod(DnspodConfig),
+    Njalla(NjallaConfig),
+    Regfish(RegfishConfig),
+    CloudflareMulti(CloudflareMultiConfig),
+}
+
+impl DomainConfig {
+    /// The configured name of the domain, else the domain and its provider
+    pub fn display_name(&self) -> String {
+        match self.name() {
+            Some(name) => name.clone(),
+            None => format!("{} ({})", self.domain_name(), self.provider_name()),
+        }
+    }
+
+    pub fn name(&self) -> Option<&String> {
+        match self {
+            DomainConfig::Cloudflare(c) => c.name.as_ref(),
+            DomainConfig::GoDaddy(c) => c.name.as_ref(),
+            DomainConfig::Namecheap(c) => c.name.as_ref(),
+            DomainConfig::NamecheapApi(c) => c.name.as_ref(),
+            DomainConfig::He(c) => c.name.as_ref(),
+            DomainConfig::NoIp(c) => c.name.as_ref(),
+            DomainConfig::Dynu(c) => c.name.as_ref(),
+            DomainConfig::Porkbun(c) => c.name.as_ref(),
+            DomainConfig::DnsOMatic(c) => c.name.as_ref(),
+            DomainConfig::Ovh(c) => c.name.as_ref(),
+            DomainConfig::Route53(c) => c.name.as_ref(),
+            DomainConfig::Linode(c) => c.name.as_ref(),
+            DomainConfig::NameCom(c) => c.name.as_ref(),
+            DomainConfig::Inwx(c) => c.name.as_ref(),
+            DomainConfig::Infomaniak(c) => c.name.as_ref(),
+            DomainConfig::Strato(c) => c.name.as_ref(),
+            DomainConfig::Ddnss(c) => c.name.as_ref(),
+            DomainConfig::Bunny(c) => c.name.as_ref(),
+            DomainConfig::ZoneEdit(c) => c.name.as_ref(),
+            DomainConfig::Ipv64(c) => c.name.as_ref(),
+            DomainConfig::Dnspod(c) => c.name.as_ref(),
+            DomainConfig::Njalla(c) => c.name.as_ref(),
+            DomainConfig::Regfish(c) => c.name.as_ref(),
+            DomainConfig::CloudflareMulti(c) => c.name.as_ref(),
+        }
+    }
+
+    /// The provider that keeps the domain's records current
+    pub fn provider(&self) -> &dyn DnsProvider {
+        match self {
+            DomainConfig::Cloudflare(c) => c,
+            DomainConfig::GoDaddy(c) => c,
+            DomainConfig::Namecheap(c) => c,
+            DomainConfig::NamecheapApi(c) => c,
+            DomainConfig::He(c) => c,
+            DomainConfig::NoIp(c) => c,
+            DomainConfig::Dynu(c) => c,
+            DomainConfig::Porkbun(c) => c,
+            DomainConfig::DnsOMatic(c) => c,
+            DomainConfig::Ovh(c) => c,
+            DomainConfig::Route53(c) => c,
+            DomainConfig::Linode(c) => c,
+            DomainConfig::NameCom(c) => c,
+            DomainConfig::Inwx(c) => c,
+            DomainConfig::Infomaniak(c) => c,
+            DomainConfig::Strato(c) => c,
+            DomainConfig::Ddnss(c) => c,
+            DomainConfig::Bunny(c) => c,
+            DomainConfig::ZoneEdit(c) => c,
+            DomainConfig::Ipv64(c) => c,
+            DomainConfig::Dnspod(c) => c,
+            DomainConfig::Njalla(c) => c,
+            DomainConfig::Regfish(c) => c,
+            DomainConfig::CloudflareMulti(c) => c,
+        }
+    }
+
+    pub fn provider_name(&self) -> &str {
+        self.provider().name()
+    }
+
+    pub fn domain_name(&self) -> String {
+        match self {
+            DomainConfig::Cloudflare(c) => c.zone.clone(),
+            DomainConfig::GoDaddy(c) => c.domain.clone(),
+            DomainConfig::Namecheap(c) => c.domain.clone(),
+            DomainConfig::NamecheapApi(c) => c.domain.clone(),
+            DomainConfig::He(c) => c.hostname.clone(),
+            DomainConfig::NoIp(c) => c.hostname.clone(),
+            DomainConfig::Dynu(c) => c.hostname.clone(),
+            DomainConfig::Porkbun(c) => c.domain.clone(),
+            DomainConfig::DnsOMatic(c) => c.hostnames.join(", "),
+            DomainConfig::Ovh(c) => c.hostname.clone(),
+            DomainConfig::Route53(c) => c.hosted_zone_id.clone(),
+            DomainConfig::Linode(c) => c.domain.clone(),
+            DomainConfig::NameCom(c) => c.domain.clone(),
+            DomainConfig::Inwx(c) => c.hostname.clone(),
+            DomainConfig::Infomaniak(c) => c.hostname.clone(),
+            DomainConfig::Strato(c) => c.domain.clone(),
+            DomainConfig::Ddnss(c) => c.hostname.clone(),
+            DomainConfig::Bunny(c) => c.zone.clone(),
+            DomainConfig::ZoneEdit(c) => c.hostname.clone(),
+            DomainConfig::Ipv64(c) => c.domain.clone(),
+            DomainConfig::Dnspod(c) => c.domain.clone(),
+            DomainConfig::Njalla(c) => c.hostname.clone(),
+            DomainConfig::Regfish(c) => c.domain.clone(),
+            DomainConfig::CloudflareMulti(c) => c.zone_names().join(", "),
+        }
+    }
+
+    /// The types of addresses that should be kept current for this provider
+    pub fn get_ip_types(&self) -> Vec<IpType> {
+        match self {
+            DomainConfig::Cloudflare(c) => c.ip_types.clone(),
+            DomainConfig::GoDaddy(c) => c.ip_types.clone(),
+            DomainConfig::Namecheap(c) => c.ip_types.clone(),
+            DomainConfig::He(c) => c.ip_types.clone(),
+            DomainConfig::NoIp(c) => c.ip_types.clone(),
+            DomainConfig::Route53(c) => c.ip_types.clone(),
+            DomainConfig::Linode(c) => c.ip_types.clone(),
+            DomainConfig::NameCom(c) => c.ip_types.clone(),
+            DomainConfig::Inwx(c) => c.ip_types.clone(),
+            DomainConfig::Infomaniak(c) => c.ip_types.clone(),
+            DomainConfig::Strato(c) => c.ip_types.clone(),
+            DomainConfig::Ddnss(c) => c.ip_types.clone(),
+            DomainConfig::Bunny(c) => c.ip_types.clone(),
+            DomainConfig::ZoneEdit(c) => c.ip_types.clone(),
+            DomainConfig::Ipv64(c) => c.ip_types.clone(),
+            DomainConfig::Dnspod(c) => c.ip_types.clone(),
+            DomainConfig::Njalla(c) => c.ip_types.clone(),
+            DomainConfig::Regfish(c) => c.ip_types.clone(),
+            DomainConfig::Porkbun(c) => c.ip_types.clone(),
+            DomainConfig::Dynu(c) => c.record_ip_types(),
+            DomainConfig::CloudflareMulti(c) => c.zone_ip_types(),
+            _ => default_ip_types(),
+        }
+    }
+
+    /// Keeps only the given address type, returning whether the domain has any left to keep
+    /// current
+    pub fn retain_ip_type(&mut self, ip_type: IpType) -> bool {
+        let ip_types = match self {
+            DomainConfig::Cloudflare(c) => &mut c.ip_types,
+            DomainConfig::GoDaddy(c) => &mut c.ip_types,
+            DomainConfig::Namecheap(c) => &mut c.ip_types,
+            DomainConfig::He(c) => &mut c.ip_types,
+            DomainConfig::NoIp(c) => &mut c.ip_types,
+            DomainConfig::Route53(c) => &mut c.ip_types,
+            DomainConfig::Linode(c) => &mut c.ip_types,
+            DomainConfig::NameCom(c) => &mut c.ip_types,
+            DomainConfig::Inwx(c) => &mut c.ip_types,
+            DomainConfig::Infomaniak(c) => &mut c.ip_types,
+            DomainConfig::Strato(c) => &mut c.ip_types,
+            DomainConfig::Ddnss(c) => &mut c.ip_types,
+            DomainConfig::Bunny(c) => &mut c.ip_types,
+            DomainConfig::ZoneEdit(c) => &mut c.ip_types,
+            DomainConfig::Ipv64(c) => &mut c.ip_types,
+            DomainConfig::Dnspod(c) => &mut c.ip_types,
+            DomainConfig::Njalla(c) => &mut c.ip_types,
+            DomainConfig::Regfish(c) => &mut c.ip_types,
+            DomainConfig::Porkbun(c) => &mut c.ip_types,
+            DomainConfig::Dynu(c) => {
+                for record_types in c.records.iter_mut().filter_map(|x| x.ip_types.as_mut()) {
+                    record_types.retain(|x| *x == ip_type);
+                }
+                &mut c.ip_types
+            }
+            DomainConfig::CloudflareMulti(c) => {
+                for zone in &mut c.zones {
+                    zone.ip_types.retain(|x| *x == ip_type);
+                }
+                c.zones.retain(|x| !x.ip_types.is_empty());
+                return !c.zones.is_empty();
+            }
+            _ => return ip_type == IpType::V4,
+        };
+
+        ip_types.retain(|x| *x == ip_type);
+        !self.get_ip_types().is_empty()
+    }
+
+    /// Keeps only the records with one of the given names, returning whether the domain has any
+    /// left to keep current. Providers with a single hostname keep it when it is named.
+    pub fn retain_records(&mut self, names: &[String]) -> bool {
+        let domain = self.domain_name();
+        let is_named = |record: &str| names.iter().any(|x| is_record_name(x, record, &domain));
+        let records = match self {
+            DomainConfig::Cloudflare(c) => &mut c.records,
+            DomainConfig::GoDaddy(c) => &mut c.records,
+            DomainConfig::Namecheap(c) => &mut c.records,
+            DomainConfig::NamecheapApi(c) => &mut c.records,
+            DomainConfig::He(c) => &mut c.records,
+            DomainConfig::Porkbun(c) => &mut c.records,
+            DomainConfig::DnsOMatic(c) => &mut c.hostnames,
+            DomainConfig::Ovh(c) => &mut c.records,
+            DomainConfig::Route53(c) => &mut c.records,
+            DomainConfig::Linode(c) => &mut c.records,
+            DomainConfig::NameCom(c) => &mut c.records,
+            DomainConfig::Inwx(c) => &mut c.records,
+            DomainConfig::Infomaniak(c) => &mut c.records,
+            DomainConfig::Strato(c) => &mut c.records,
+            DomainConfig::Bunny(c) => &mut c.records,
+            DomainConfig::ZoneEdit(c) => &mut c.records,
+            DomainConfig::Ipv64(c) => &mut c.records,
+            DomainConfig::Dnspod(c) => &mut c.records,
+            DomainConfig::Njalla(c) => &mut c.records,
+            DomainConfig::Regfish(c) => &mut c.records,
+            DomainConfig::Dynu(c) => {
+                c.records.retain(|x| is_named(&x.name));
+                return !c.records.is_empty();
+            }
+            DomainConfig::CloudflareMulti(c) => {
+                for zone in &mut c.zones {
+                    zone.records.retain(|x| is_named(x));
+                }
+                c.zones.retain(|x| !x.records.is_empty());
+                return !c.zones.is_empty();
+            }
+            DomainConfig::NoIp(c) => return is_named(&c.hostname),
+            DomainConfig::Ddnss(c) => return is_named(&c.hostname),
+        };
+
+        records.retain(|x| is_named(x));
+        !records.is_empty()
+    }
+
+    /// The records of the domain, each alongside its fully qualified name. Providers with a single
+    /// hostname have it as their only record.
+    pub fn qualified_records(&self) -> Vec<(&str, String)> {
+        let (records, domain): (Vec<&str>, _) = match self {
+            DomainConfig::Cloudflare(c) => (record_names(&c.records), Some(&c.zone)),
+            DomainConfig::GoDaddy(c) => (record_names(&c.records), Some(&c.domain)),
+            DomainConfig::Namecheap(c) => (record_names(&c.records), Some(&c.domain)),
+            DomainConfig::NamecheapApi(c) => (record_names(&c.records), Some(&c.domain)),
+            DomainConfig::He(c) => (record_names(&c.records), Some(&c.hostname)),
+            DomainConfig::Porkbun(c) => (record_names(&c.records), Some(&c.domain)),
+            DomainConfig::Ovh(c) => (record_names(&c.records), Some(&c.hostname)),
+            DomainConfig::Linode(c) => (record_names(&c.records), Some(&c.domain)),
+            DomainConfig::NameCom(c) => (record_names(&c.records), Some(&c.domain)),
+            DomainConfig::Inwx(c) => (record_names(&c.records), Some(&c.hostname)),
+            DomainConfig::Infomaniak(c) => (record_names(&c.records), Some(&c.hostname)),
+            DomainConfig::Strato(c) => (record_names(&c.records), Some(&c.domain)),
+            DomainConfig::Bunny(c) => (record_names(&c.records), Some(&c.zone)),
+            DomainConfig::ZoneEdit(c) => (record_names(&c.records), Some(&c.hostname)),
+            DomainConfig::Ipv64(c) => (record_names(&c.records), Some(&c.domain)),
+            DomainConfig::Dnspod(c) => (record_names(&c.records), Some(&c.domain)),
+            DomainConfig::Njalla(c) => (record_names(&c.records), Some(&c.hostname)),
+            DomainConfig::Regfish(c) => (record_names(&c.records), Some(&c.domain)),
+            DomainConfig::Dynu(c) => (
+                c.records.iter().map(|x| x.name.as_str()).collect(),
+                Some(&c.hostname),
+            ),
+
+            // The hostnames of dns-o-matic and the records of route53 are already qualified
+            DomainConfig::DnsOMatic(c) => (record_names(&c.hostnames), None),
+            DomainConfig::Route53(c) => (record_names(&c.records), None),
+            DomainConfig::NoIp(c) => (vec![c.hostname.as_str()], None),
+            DomainConfig::Ddnss(c) => (vec![c.hostname.as_str()], None),
+            DomainConfig::CloudflareMulti(c) => {
+                return c
+                    .zones
+                    .iter()
+                    .flat_map(|zone| {
+                        zone.records
+                            .iter()
+                            .map(move |x| (x.as_str(), qualified_name(x, &zone.name)))
+                    })
+                    .collect();
+            }
+        };
+
+        records
+            .into_iter()
+            .map(|x| match domain {
+                Some(domain) => (x, qualified_name(x, domain)),
+                None => (x, String::from(x)),
+            })
+            .collect()
+    }
+
+    /// Whether the provider is able to keep AAAA records current
+    pub fn supports_ipv6(&self) -> bool {
+        matches!(
+            self,
+            DomainConfig::Cloudflare(_)
+                | DomainConfig::GoDaddy(_)
+                | DomainConfig::Namecheap(_)
+                | DomainConfig::He(_)
+                | DomainConfig::NoIp(_)
+                | DomainConfig::Route53(_)
+                | DomainConfig::Linode(_)
+                | DomainConfig::NameCom(_)
+                | DomainConfig::Porkbun(_)
+                | DomainConfig::Dynu(_)
+                | DomainConfig::Inwx(_)
+                | DomainConfig::Infomaniak(_)
+                | DomainConfig::Strato(_)
+                | DomainConfig::Ddnss(_)
+                | DomainConfig::Bunny(_)
+                | DomainConfig::ZoneEdit(_)
+                | DomainConfig::Ipv64(_)
+                | DomainConfig::Dnspod(_)
+                | DomainConfig::Njalla(_)
+                | DomainConfig::Regfish(_)
+                | DomainConfig::CloudflareMulti(_)
+        )
+    }
+
+    /// Whether the provider updates the A and AAAA records in a single request, rather than a
+    /// request for each address type
+    pub fn updates_ip_types_together(&self) -> bool {
+        matches!(self, DomainConfig::Ddnss(_))
+    }
+
+    /// The log level of the provider's module, if it overrides the global level
+    pub fn log_level(&self) -> Option<LevelFilter> {
+        match self {
+            DomainConfig::Cloudflare(c) => c.log_level,
+            DomainConfig::GoDaddy(c) => c.log_level,
+            DomainConfig::Namecheap(c) => c.log_level,
+            DomainConfig::NamecheapApi(c) => c.log_level,
+            DomainConfig::He(c) => c.log_level,
+            DomainConfig::NoIp(c) => c.log_level,
+            DomainConfig::Dynu(c) => c.log_level,
+            DomainConfig::Porkbun(c) => c.log_level,
+            DomainConfig::DnsOMatic(c) => c.log_level,
+            DomainConfig::Ovh(c) => c.log_level,
+            DomainConfig::Route53(c) => c.log_level,
+            DomainConfig::Linode(c) => c.log_level,
+            DomainConfig::NameCom(c) => c.log_level,
+            DomainConfig::Inwx(c) => c.log_level,
+            DomainConfig::Infomaniak(c) => c.log_level,
+            DomainConfig::Strato(c) => c.log_level,
+            DomainConfig::Ddnss(c) => c.log_level,
+            DomainConfig::Bunny(c) => c.log_level,
+            DomainConfig::ZoneEdit(c) => c.log_level,
+            DomainConfig::Ipv64(c) => c.log_level,
+            DomainConfig::Dnspod(c) => c.log_level,
+            DomainConfig::Njalla(c) => c.log_level,
+            DomainConfig::Regfish(c) => c.log_level,
+            DomainConfig::CloudflareMulti(c) => c.log_level,
+        }
+    }
+
+    /// The module that updates the domain, which is the target of the provider's log events
+    pub fn module_path(&self) -> &'static str {
+        match self {
+            DomainConfig::Cloudflare(_) => "dness::cloudflare",
+            DomainConfig::GoDaddy(_) => "dness::godaddy",
+            DomainConfig::Namecheap(_) => "dness::namecheap",
+            DomainConfig::NamecheapApi(_) => "dness::namecheap_api",
+            DomainConfig::He(_) => "dness::he",
+            DomainConfig::NoIp(_) => "dness::noip",
+            DomainConfig::Dynu(_) => "dness::dynu",
+            DomainConfig::Porkbun(_) => "dness::porkbun",
+            DomainConfig::DnsOMatic(_) => "dness::dnsomatic",
+            DomainConfig::Ovh(_) => "dness::ovh",
+            DomainConfig::Route53(_) => "dness::route53",
+            DomainConfig::Linode(_) => "dness::linode",
+            DomainConfig::NameCom(_) => "dness::namecom",
+            DomainConfig::Inwx(_) => "dness::inwx",
+            DomainConfig::Infomaniak(_) => "dness::infomaniak",
+            DomainConfig::Strato(_) => "dness::strato",
+            DomainConfig::Ddnss(_) => "dness::ddnss",
+            DomainConfig::Bunny(_) => "dness::bunny",
+            DomainConfig::ZoneEdit(_) => "dness::zoneedit",
+            DomainConfig::Ipv64(_) => "dness::ipv64",
+            DomainConfig::Dnspod(_) => "dness::dnspod",
+            DomainConfig::Njalla(_) => "dness::njalla",
+            DomainConfig::Regfish(_) => "dness::regfish",
+            DomainConfig::CloudflareMulti(_) => "dness::cloudflare",
+        }
+    }
+
+    /// The request timeout configured for this provider, if any, which takes precedence over the
+    /// global timeout
+    pub fn request_timeout_secs(&self) -> Option<u64> {
+        match self {
+            DomainConfig::Cloudflare(c) => c.request_timeout_secs,
+            DomainConfig::GoDaddy(c) => c.request_timeout_secs,
+            DomainConfig::Namecheap(c) => c.request_timeout_secs,
+            DomainConfig::NamecheapApi(c) => c.request_timeout_secs,
+            DomainConfig::He(c) => c.request_timeout_secs,
+            DomainConfig::NoIp(c) => c.request_timeout_secs,
+            DomainConfig::Dynu(c) => c.request_timeout_secs,
+            DomainConfig::Porkbun(c) => c.request_timeout_secs,
+            DomainConfig::DnsOMatic(c) => c.request_timeout_secs,
+            DomainConfig::Ovh(c) => c.request_timeout_secs,
+            DomainConfig::Route53(c) => c.request_timeout_secs,
+            DomainConfig::Linode(c) => c.request_timeout_secs,
+            DomainConfig::NameCom(c) => c.request_timeout_secs,
+            DomainConfig::Inwx(c) => c.request_timeout_secs,
+            DomainConfig::Infomaniak(c) => c.request_timeout_secs,
+            DomainConfig::Strato(c) => c.request_timeout_secs,
+            DomainConfig::Ddnss(c) => c.request_timeout_secs,
+            DomainConfig::Bunny(c) => c.request_timeout_secs,
+            DomainConfig::ZoneEdit(c) => c.request_timeout_secs,
+            DomainConfig::Ipv64(c) => c.request_timeout_secs,
+            DomainConfig::Dnspod(c) => c.request_timeout_secs,
+            DomainConfig::Njalla(c) => c.request_timeout_secs,
+            DomainConfig::Regfish(c) => c.request_timeout_secs,
+            DomainConfig::CloudflareMulti(c) => c.request_timeout_secs,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct CloudflareConfig {
+    pub email: Option<String>,
+    pub key: Option<String>,
+    pub token: Option<String>,
+    pub zone: String,
+    pub records: Vec<String>,
+
+    #[serde(default)]
+    pub request_timeout_secs: Option<u64>,
+
+    #[serde(default)]
+    pub create_missing: bool,
+
+    #[serde(default)]
+    pub ttl: Option<u32>,
+
+    #[serde(default)]
+    pub proxied: Option<bool>,
+
+    #[serde(default = "default_use_batch_updates")]
+    pub use_batch_updates: bool,
+
+    #[serde(default = "default_use_per_record_queries")]
+    pub use_per_record_queries: bool,
+
+    #[serde(default = "default_zone_cache_ttl_secs")]
+    pub zone_cache_ttl_secs: u64,
+
+    #[serde(default)]
+    pub verify_after_update: bool,
+
+    #[serde(default = "default_verify_timeout_secs")]
+    pub verify_timeout_secs: u64,
+
+    #[serde(default = "default_ip_types")]
+    pub ip_types: Vec<IpType>,
+
+    #[serde(default)]
+    pub log_level: Option<LevelFilter>,
+
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+/// Several cloudflare zones that are kept current with a single token
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct CloudflareMultiConfig {
+    pub token: String,
+    pub zones: Vec<CloudflareZoneEntry>,
+
+    #[serde(default)]
+    pub request_timeout_secs: Option<u64>,
+
+    #[serde(default)]
+    pub log_level: Option<LevelFilter>,
+
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct CloudflareZoneEntry {
+    pub name: String,
+    pub records: Vec<String>,
+
+    #[serde(default = "default_ip_types")]
+    pub ip_types: Vec<IpType>,
+}
+
+impl CloudflareMultiConfig {
+    pub fn zone_names(&self) -> Vec<&str> {
+        self.zones.iter().map(|x| x.name.as_str()).collect()
+    }
+
+    /// The address types of any of the zones
+    pub fn zone_ip_types(&self) -> Vec<IpType> {
+        [IpType::V4, IpType::V6]
+            .iter()
+            .copied()
+            .filter(|x| self.zones.iter().any(|zone| zone.ip_types.contains(x)))
+            .collect()
+    }
+
+    /// The zone as a cloudflare domain of its own, so that it is updated like any other
+    pub fn zone_config(&self, zone: &CloudflareZoneEntry) -> CloudflareConfig {
+        CloudflareConfig {
+            email: None,
+            key: None,
+            token: Some(self.token.clone()),
+            zone: zone.name.clone(),
+            records: zone.records.clone(),
+            request_timeout_secs: self.request_timeout_secs,
+            create_missing: false,
+            ttl: None,
+            proxied: None,
+            use_batch_updates: default_use_batch_updates(),
+            use_per_record_queries: default_use_per_record_queries(),
+            zone_cache_ttl_secs: default_zone_cache_ttl_secs(),
+            verify_after_update: false,
+            verify_timeout_secs: default_verify_timeout_secs(),
+            ip_types: zone.ip_types.clone(),
+            log_level: self.log_level,
+            name: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct GoDaddyConfig {
+    #[serde(default = "godaddy_base_url")]
+    pub base_url: String,
+    pub key: String,
+    pub secret: String,
+    pub domain: String,
+    pub records: Vec<String>,
+
+    #[serde(default)]
+    pub request_timeout_secs: Option<u64>,
+
+    #[serde(default = "default_ip_types")]
+    pub ip_types: Vec<IpType>,
+
+    #[serde(default)]
+    pub create_missing: bool,
+
+    #[serde(default)]
+    pub log_level: Option<LevelFilter>,
+
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct NamecheapConfig {
+    #[serde(default = "namecheap_base_url")]
+    pub base_url: String,
+    pub domain: String,
+    pub ddns_password: String,
+    pub records: Vec<String>,
+
+    #[serde(default)]
+    pub request_timeout_secs: Option<u64>,
+
+    #[serde(default = "default_ip_types")]
+    pub ip_types: Vec<IpType>,
+
+    #[serde(default)]
+    pub log_level: Option<LevelFilter>,
+
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct NamecheapApiConfig {
+    #[serde(default = "namecheap_api_base_url")]
+    pub base_url: String,
+    pub api_user: String,
+    pub api_key: String,
+    pub username: String,
+    pub client_ip: String,
+    pub domain: String,
+    pub records: Vec<String>,
+
+    #[serde(default)]
+    pub request_timeout_secs: Option<u64>,
+
+    #[serde(default)]
+    pub log_level: Option<LevelFilter>,
+
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct HeConfig {
+    #[serde(default = "he_base_url")]
+    pub base_url: String,
+    pub hostname: String,
+    pub password: String,
+    pub records: Vec<String>,
+
+    #[serde(default)]
+    pub request_timeout_secs: Option<u64>,
+
+    #[serde(default = "default_ip_types")]
+    pub ip_types: Vec<IpType>,
+
+    #[serde(default)]
+    pub log_level: Option<LevelFilter>,
+
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct NoIpConfig {
+    #[serde(default = "noip_base_url")]
+    pub base_url: String,
+    pub username: String,
+    pub password: String,
+    pub hostname: String,
+
+    #[serde(default)]
+    pub request_timeout_secs: Option<u64>,
+
+    #[serde(default = "default_ip_types")]
+    pub ip_types: Vec<IpType>,
+
+    #[serde(default)]
+    pub log_level: Option<LevelFilter>,
+
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct DynuConfig {
+    #[serde(default = "dynu_base_url")]
+    pub base_url: String,
+    pub hostname: String,
+    pub username: String,
+    pub password: String,
+    pub records: Vec<DynuRecord>,
+
+    #[serde(default = "default_ip_types")]
+    pub ip_types: Vec<IpType>,
+
+    #[serde(default)]
+    pub request_timeout_secs: Option<u64>,
+
+    #[serde(default)]
+    pub log_level: Option<LevelFilter>,
+
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+impl DynuConfig {
+    /// The address types of the records, as a record can ask for types beyond the domain's
+    pub fn record_ip_types(&self) -> Vec<IpType> {
+        let mut result = self.ip_types.clone();
+        for ip_type in self.records.iter().flat_map(|x| x.ip_types(&self.ip_types)) {
+            if !result.contains(ip_type) {
+                result.push(*ip_type);
+            }
+        }
+        result
+    }
+}
+
+/// A dynu record, which is written as just the name when it receives the domain's address types
+#[derive(Deserialize, Serialize, Clone, PartialEq, Eq, Debug)]
+#[serde(from = "DynuRecordRepr", into = "DynuRecordRepr")]
+pub struct DynuRecord {
+    pub name: String,
+    pub ip_types: Option<Vec<IpType>>,
+}
+
+impl DynuRecord {
+    /// The address types the record receives, which default to those of the domain
+    pub fn ip_types<'a>(&'a self, default: &'a [IpType]) -> &'a [IpType] {
+        self.ip_types.as_deref().unwrap_or(default)
+    }
+}
+
+impl From<String> for DynuRecord {
+    fn from(name: String) -> Self {
+        DynuRecord {
+            name,
+            ip_types: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Eq, Debug)]
+#[serde(deny_unknown_fields)]
+struct DynuRecordTable {
+    name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ip_types: Option<Vec<IpType>>,
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Eq, Debug)]
+#[serde(untagged)]
+enum DynuRecordRepr {
+    Name(String),
+    Table(DynuRecordTable),
+}
+
+impl From<DynuRecordRepr> for DynuRecord {
+    fn from(repr: DynuRecordRepr) -> Self {
+        match repr {
+            DynuRecordRepr::Name(name) => DynuRecord::from(name),
+            DynuRecordRepr::Table(x) => DynuRecord {
+                name: x.name,
+                ip_types: x.ip_types,
+            },
+        }
+    }
+}
+
+impl From<DynuRecord> for DynuRecordRepr {
+    fn from(record: DynuRecord) -> Self {
+        match record.ip_types {
+            None => DynuRecordRepr::Name(record.name),
+            ip_types => DynuRecordRepr::Table(DynuRecordTable {
+                name: record.name,
+                ip_types,
+            }),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct PorkbunConfig {
+    #[serde(default = "porkbun_base_url")]
+    pub base_url: String,
+    pub domain: String,
+    pub key: String,
+    pub secret: String,
+    pub records: Vec<String>,
+
+    #[serde(default = "default_ip_types")]
+    pub ip_types: Vec<IpType>,
+
+    #[serde(default)]
+    pub request_timeout_secs: Option<u64>,
+
+    #[serde(default)]
+    pub create_missing: bool,
+
+    /// Update the records with the ip that the porkbun api sees instead of the resolved ip
+    #[serde(default)]
+    pub use_api_ip: bool,
+
+    #[serde(default)]
+    pub ttl: Option<String>,
+
+    #[serde(default)]
+    pub log_level: Option<LevelFilter>,
+
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct DnsOMaticConfig {
+    #[serde(default = "dnsomatic_base_url")]
+    pub base_url: String,
+    pub username: String,
+    pub password: String,
+    pub hostnames: Vec<String>,
+
+    #[serde(default)]
+    pub request_timeout_secs: Option<u64>,
+
+    #[serde(default)]
+    pub log_level: Option<LevelFilter>,
+
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct OvhConfig {
+    #[serde(default = "ovh_base_url")]
+    pub base_url: String,
+    pub hostname: String,
+    pub username: String,
+    pub password: String,
+    pub records: Vec<String>,
+
+    #[serde(default)]
+    pub request_timeout_secs: Option<u64>,
+
+    #[serde(default)]
+    pub log_level: Option<LevelFilter>,
+
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Route53Config {
+    #[serde(default = "route53_base_url")]
+    pub base_url: String,
+    pub hosted_zone_id: String,
+    pub records: Vec<String>,
+
+    #[serde(default = "default_ip_types")]
+    pub ip_types: Vec<IpType>,
+
+    #[serde(default)]
+    pub region: Option<String>,
+
+    #[serde(default)]
+    pub request_timeout_secs: Option<u64>,
+
+    #[serde(default)]
+    pub log_level: Option<LevelFilter>,
+
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+/// Route 53 configs are still parsed when dness is built without the feature, so the error is
+/// reported when the domain is updated
+#[cfg(not(feature = "route53"))]
+#[async_trait::async_trait]
+impl DnsProvider for Route53Config {
+    async fn update(
+        &self,
+        _ctx: &crate::core::UpdateContext<'_>,
+        _addr: IpAddr,
+    ) -> Result<crate::core::Updates, Box<dyn error::Error>> {
+        Err(Box::new(crate::errors::DnessError::message(String::from(
+            "dness was built without route53 support",
+        ))))
+    }
+
+    fn name(&self) -> &str {
+        "route53"
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct LinodeConfig {
+    #[serde(default = "linode_base_url")]
+    pub base_url: String,
+    pub token: String,
+    pub domain: String,
+    pub records: Vec<String>,
+
+    #[serde(default = "default_ip_types")]
+    pub ip_types: Vec<IpType>,
+
+    #[serde(default)]
+    pub request_timeout_secs: Option<u64>,
+
+    #[serde(default)]
+    pub log_level: Option<LevelFilter>,
+
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct NameComConfig {
+    #[serde(default = "namecom_base_url")]
+    pub base_url: String,
+    pub username: String,
+    pub token: String,
+    pub domain: String,
+    pub records: Vec<String>,
+
+    #[serde(default = "default_ip_types")]
+    pub ip_types: Vec<IpType>,
+
+    #[serde(default)]
+    pub request_timeout_secs: Option<u64>,
+
+    #[serde(default)]
+    pub create_missing: bool,
+
+    #[serde(default)]
+    pub log_level: Option<LevelFilter>,
+
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct InwxConfig {
+    #[serde(default = "inwx_base_url")]
+    pub base_url: String,
+    pub username: String,
+    pub password: String,
+    pub hostname: String,
+    pub records: Vec<String>,
+
+    #[serde(default = "default_ip_types")]
+    pub ip_types: Vec<IpType>,
+
+    #[serde(default)]
+    pub request_timeout_secs: Option<u64>,
+
+    #[serde(default)]
+    pub log_level: Option<LevelFilter>,
+
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct InfomaniakConfig {
+    #[serde(default = "infomaniak_base_url")]
+    pub base_url: String,
+    pub username: String,
+    pub password: String,
+    pub hostname: String,
+    pub records: Vec<String>,
+
+    #[serde(default = "default_ip_types")]
+    pub ip_types: Vec<IpType>,
+
+    #[serde(default)]
+    pub request_timeout_secs: Option<u64>,
+
+    #[serde(default)]
+    pub log_level: Option<LevelFilter>,
+
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct StratoConfig {
+    #[serde(default = "strato_base_url")]
+    pub base_url: String,
+    pub domain: String,
+    pub password: String,
+    pub records: Vec<String>,
+
+    #[serde(default = "default_ip_types")]
+    pub ip_types: Vec<IpType>,
+
+    #[serde(default)]
+    pub request_timeout_secs: Option<u64>,
+
+    #[serde(default)]
+    pub log_level: Option<LevelFilter>,
+
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct DdnssConfig {
+    #[serde(default = "ddnss_base_url")]
+    pub base_url: String,
+    pub api_key: String,
+    pub hostname: String,
+
+    #[serde(default = "default_ip_types")]
+    pub ip_types: Vec<IpType>,
+
+    #[serde(default)]
+    pub request_timeout_secs: Option<u64>,
+
+    #[serde(default)]
+    pub log_level: Option<LevelFilter>,
+
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct BunnyConfig {
+    #[serde(default = "bunny_base_url")]
+    pub base_url: String,
+    pub api_key: String,
+    pub zone: String,
+    pub records: Vec<String>,
+
+    #[serde(default = "default_ip_types")]
+    pub ip_types: Vec<IpType>,
+
+    #[serde(default)]
+    pub request_timeout_secs: Option<u64>,
+
+    #[serde(default)]
+    pub create_missing: bool,
+
+    #[serde(default)]
+    pub log_level: Option<LevelFilter>,
+
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ZoneEditConfig {
+    #[serde(default = "zoneedit_base_url")]
+    pub base_url: String,
+    pub hostname: String,
+    pub username: String,
+    pub password: String,
+    pub records: Vec<String>,
+
+    #[serde(default = "default_ip_types")]
+    pub ip_types: Vec<IpType>,
+
+    #[serde(default)]
+    pub request_timeout_secs: Option<u64>,
+
+    #[serde(default)]
+    pub log_level: Option<LevelFilter>,
+
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Ipv64Config {
+    #[serde(default = "ipv64_base_url")]
+    pub base_url: String,
+    pub api_key: String,
+    pub domain: String,
+    pub records: Vec<String>,
+
+    #[serde(default = "default_ip_types")]
+    pub ip_types: Vec<IpType>,
+
+    #[serde(default)]
+    pub request_timeout_secs: Option<u64>,
+
+    #[serde(default)]
+    pub log_level: Option<LevelFilter>,
+
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct DnspodConfig {
+    #[serde(default = "dnspod_base_url")]
+    pub base_url: String,
+
+    /// The id and token of an api token joined by a comma: "<id>,<token>"
+    pub login_token: String,
+    pub domain: String,
+    pub records: Vec<String>,
+
+    #[serde(default = "default_ip_types")]
+    pub ip_types: Vec<IpType>,
+
+    #[serde(default)]
+    pub request_timeout_secs: Option<u64>,
+
+    #[serde(default)]
+    pub log_level: Option<LevelFilter>,
+
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct NjallaConfig {
+    #[serde(default = "njalla_base_url")]
+    pub base_url: String,
+    pub api_key: String,
+    pub hostname: String,
+    pub records: Vec<String>,
+
+    #[serde(default = "default_ip_types")]
+    pub ip_types: Vec<IpType>,
+
+    #[serde(default)]
+    pub request_timeout_secs: Option<u64>,
+
+    #[serde(default)]
+    pub log_level: Option<LevelFilter>,
+
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RegfishConfig {
+    #[serde(default = "regfish_base_url")]
+    pub base_url: String,
+    pub token: String,
+    pub domain: String,
+    pub records: Vec<String>,
+
+    #[serde(default = "default_ip_types")]
+    pub ip_types: Vec<IpType>,
+
+    #[serde(default)]
+    pub request_timeout_secs: Option<u64>,
+
+    #[serde(default)]
+    pub log_level: Option<LevelFilter>,
+
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+fn default_use_batch_updates() -> bool {
+    true
+}
+
+fn default_use_per_record_queries() -> bool {
+    true
+}
+
+pub fn default_zone_cache_ttl_secs() -> u64 {
+    86400
+}
+
+pub fn default_verify_timeout_secs() -> u64 {
+    10
+}
+
+pub fn godaddy_base_url() -> String {
+    String::from("https://api.godaddy.com")
+}
+
+pub fn namecheap_base_url() -> String {
+    String::from("https://dynamicdns.park-your-domain.com")
+}
+
+pub fn namecheap_api_base_url() -> String {
+    String::from("https://api.namecheap.com/xml.response")
+}
+
+pub fn he_base_url() -> String {
+    String::from("https://dyn.dns.he.net")
+}
+
+pub fn noip_base_url() -> String {
+    String::from("https://dynupdate.no-ip.com")
+}
+
+pub fn dynu_base_url() -> String {
+    String::from("https://api.dynu.com")
+}
+
+pub fn porkbun_base_url() -> String {
+    String::from("https://api.porkbun.com/api/json/v3")
+}
+
+pub fn dnsomatic_base_url() -> String {
+    String::from("https://updates.dnsomatic.com")
+}
+
+pub fn ovh_base_url() -> String {
+    String::from("https://www.ovh.com")
+}
+
+pub fn route53_base_url() -> String {
+    String::from("https://route53.amazonaws.com")
+}
+
+pub fn linode_base_url() -> String {
+    String::from("https://api.linode.com")
+}
+
+pub fn namecom_base_url() -> String {
+    String::from("https://api.name.com")
+}
+
+pub fn inwx_base_url() -> String {
+    String::from("https://dyndns.inwx.com")
+}
+
+pub fn infomaniak_base_url() -> String {
+    String::from("https://infomaniak.com")
+}
+
+pub fn strato_base_url() -> String {
+    String::from("https://dyndns.strato.com")
+}
+
+pub fn ddnss_base_url() -> String {
+    String::from("https://www.ddnss.de")
+}
+
+pub fn bunny_base_url() -> String {
+    String::from("https://api.bunny.net")
+}
+
+pub fn zoneedit_base_url() -> String {
+    String::from("https://dynamic.zoneedit.com")
+}
+
+pub fn ipv64_base_url() -> String {
+    String::from("https://ipv64.net")
+}
+
+pub fn dnspod_base_url() -> String {
+    String::from("https://dnsapi.cn")
+}
+
+pub fn njalla_base_url() -> String {
+    String::from("https://njal.la")
+}
+
+pub fn regfish_base_url() -> String {
+    String::from("https://dyndns.regfish.de")
+}
+
+/// GoDaddy's OTE test environment, which has its own set of credentials
+fn is_godaddy_ote(base_url: &str) -> bool {
+    base_url.contains("ote-godaddy.com")
+}
+
+/// Patterns in the records of providers that support them must parse
+fn record_pattern_errors(name: &str, records: &[String]) -> Vec<String> {
+    records
+        .iter()
+        .filter(|x| crate::core::is_record_pattern(x))
+        .filter_map(|x| glob::Pattern::new(x).err().map(|e| (x, e)))
+        .map(|(x, e)| format!("{}: record {} is not a valid pattern: {}", name, x, e))
+        .collect()
+}
+
+/// Whether the name refers to the record, either as written in the config or fully qualified with
+/// the domain (eg: "sub.example.com" for the "sub" record and "example.com" for "@")
+fn is_record_name(name: &str, record: &str, domain: &str) -> bool {
+    let name = name.trim_end_matches('.');
+    let record = record.trim_end_matches('.');
+    let qualified = match record {
+        "@" => String::from(domain),
+        _ => format!("{}.{}", record, domain),
+    };
+    name.eq_ignore_ascii_case(record) || name.eq_ignore_ascii_case(&qualified)
+}
+
+/// The record qualified with the domain, unless it already is
+fn qualified_name(record: &str, domain: &str) -> String {
+    let lower = record.to_ascii_lowercase();
+    let domain_lower = domain.to_ascii_lowercase();
+    if record == "@" {
+        String::from(domain)
+    } else if lower == domain_lower || lower.ends_with(&format!(".{}", domain_lower)) {
+        String::from(record)
+    } else {
+        format!("{}.{}", record, domain)
+    }
+}
+
+/// The names of a domain's records
+fn record_names(records: &[String]) -> Vec<&str> {
+    records.iter().map(String::as_str).collect()
+}
+
+/// Checks the config for mistakes that deserialization can't catch
+pub fn validate_config(config: &DnsConfig) -> Vec<String> {
+    let mut errors = Vec::new();
+    let ipv6_resolver = config.ip_resolver_for(IpType::V6);
+    let supports_ipv6_resolver = !ipv6_resolver.ipv6_resolvers().is_empty();
+
+    if let Some(proxy_url) = config.proxy_url.as_deref() {
+        // The url is left out of the error as it may contain credentials
+        if reqwest::Proxy::all(proxy_url).is_err() {
+            errors.push(String::from("proxy_url is not a valid proxy url"));
+        }
+    }
+
+    if let Some(user_agent) = config.user_agent.as_deref() {
+        if reqwest::header::HeaderValue::from_str(user_agent).is_err() {
+            errors.push(String::from("user_agent is not a valid header value"));
+        }
+    }
+
+    for domain in &config.domains {
+        let name = domain.display_name();
+        let ip_types = domain.get_ip_types();
+        if ip_types.is_empty() {
+            errors.push(format!("{}: ip_types must not be empty", name));
+        }
+
+        if ip_types.contains(&IpType::V6) {
+            if !domain.supports_ipv6() {
+                errors.push(format!("{}: provider does not support ipv6", name));
+            } else if !supports_ipv6_resolver {
+                errors.push(format!(
+                    "{}: ipv6 requested but the {} ip resolver does not support ipv6",
+                    name, ipv6_resolver
+                ));
+            }
+        }
+
+        let records = match domain {
+            DomainConfig::Cloudflare(c) => {
+                errors.extend(record_pattern_errors(&name, &c.records));
+                match (&c.token, &c.email, &c.key) {
+                    (Some(_), None, None) | (None, Some(_), Some(_)) => {}
+                    (Some(_), _, _) => errors.push(format!(
+                        "{}: token can't be combined with email and key",
+                        name
+                    )),
+                    _ => errors.push(format!(
+                        "{}: either token or both email and key are required",
+                        name
+                    )),
+                }
+                Some(record_names(&c.records))
+            }
+            DomainConfig::GoDaddy(c) => {
+                if c.key.is_empty() || c.secret.is_empty() {
+                    errors.push(format!("{}: key and secret must not be empty", name));
+                }
+                errors.extend(record_pattern_errors(&name, &c.records));
+                if is_godaddy_ote(&c.base_url) {
+                    warn!("{}: using GoDaddy OTE test environment", name);
+                }
+                Some(record_names(&c.records))
+            }
+            DomainConfig::Porkbun(c) => {
+                if c.key.is_empty() || c.secret.is_empty() {
+                    errors.push(format!("{}: key and secret must not be empty", name));
+                }
+                Some(record_names(&c.records))
+            }
+            DomainConfig::NamecheapApi(c) => {
+                if c.client_ip.parse::<Ipv4Addr>().is_err() {
+                    errors.push(format!("{}: client_ip must be an ipv4 address", name));
+                }
+                Some(record_names(&c.records))
+            }
+            DomainConfig::Namecheap(c) => Some(record_names(&c.records)),
+            DomainConfig::He(c) => Some(record_names(&c.records)),
+            DomainConfig::Inwx(c) => Some(record_names(&c.records)),
+            DomainConfig::ZoneEdit(c) => Some(record_names(&c.records)),
+            DomainConfig::Ipv64(c) => {
+                if c.api_key.is_empty() {
+                    errors.push(format!("{}: api_key must not be empty", name));
+                }
+                Some(record_names(&c.records))
+            }
+            DomainConfig::Dnspod(c) => {
+                if c.login_token.is_empty() {
+                    errors.push(format!("{}: login_token must not be empty", name));
+                }
+                Some(record_names(&c.records))
+            }
+            DomainConfig::Njalla(c) => {
+                if c.api_key.is_empty() {
+                    errors.push(format!("{}: api_key must not be empty", name));
+                }
+                Some(record_names(&c.records))
+            }
+            DomainConfig::Regfish(c) => {
+                if c.token.is_empty() {
+                    errors.push(format!("{}: token must not be empty", name));
+                }
+                Some(record_names(&c.records))
+            }
+            DomainConfig::Infomaniak(c) => Some(record_names(&c.records)),
+            DomainConfig::Strato(c) => Some(record_names(&c.records)),
+            DomainConfig::Ddnss(c) => {
+                if c.api_key.is_empty() {
+                    errors.push(format!("{}: api_key must not be empty", name));
+                }
+                None
+            }
+            DomainConfig::Bunny(c) => {
+                if c.api_key.is_empty() {
+                    errors.push(format!("{}: api_key must not be empty", name));
+                }
+                Some(record_names(&c.records))
+            }
+            DomainConfig::Dynu(c) => {
+                for record in &c.records {
+                    if record.ip_types.as_ref().is_some_and(|x| x.is_empty()) {
+                        errors.push(format!(
+                            "{}: ip_types of record {} must not be empty",
+                            name, record.name
+                        ));
+                    }
+                }
+                Some(c.records.iter().map(|x| x.name.as_str()).collect())
+            }
+            DomainConfig::DnsOMatic(c) => Some(record_names(&c.hostnames)),
+            DomainConfig::Ovh(c) => Some(record_names(&c.records)),
+            DomainConfig::Route53(c) => Some(record_names(&c.records)),
+            DomainConfig::Linode(c) => {
+                if c.token.is_empty() {
+                    errors.push(format!("{}: token must not be empty", name));
+                }
+                Some(record_names(&c.records))
+            }
+            DomainConfig::NameCom(c) => {
+                if c.username.is_empty() || c.token.is_empty() {
+                    errors.push(format!("{}: username and token must not be empty", name));
+                }
+                Some(record_names(&c.records))
+            }
+            DomainConfig::CloudflareMulti(c) => {
+                if c.token.is_empty() {
+                    errors.push(format!("{}: token must not be empty", name));
+                }
+                if c.zones.is_empty() {
+                    errors.push(format!("{}: zones must not be empty", name));
+                }
+                for zone in &c.zones {
+                    if zone.records.is_empty() {
+                        errors.push(format!(
+                            "{}: records of zone {} must not be empty",
+                            name, zone.name
+                        ));
+                    }
+                    errors.extend(record_pattern_errors(&name, &zone.records));
+                }
+                Some(
+                    c.zones
+                        .iter()
+                        .flat_map(|x| record_names(&x.records))
+                        .collect(),
+                )
+            }
+            DomainConfig::NoIp(_) => None,
+        };
+
+        // Providers without records update the domain itself
+        let Some(records) = records else { continue };
+        if records.is_empty() {
+            errors.push(format!("{}: records must not be empty", name));
+        }
+
+        // Names copied from a zone file are often fully qualified with a trailing dot
+        for record in records.iter().filter(|x| x.ends_with('.')) {
+            errors.push(format!(
+                "{}: record {} must not end with a dot",
+                name, record
+            ));
+        }
+    }
+
+    errors
+}
+
+/// The renderer of the config files, which is shared between the files so that helpers can cache
+/// their lookups for the whole parse
+fn config_renderer() -> Handlebars<'static> {
+    let mut handlebars = Handlebars::new();
+    handlebars.register_escape_fn(handlebars::no_escape);
+    handlebars.set_strict_mode(true);
+
+    handlebars.register_helper("file", Box::new(file_helper));
+
+    #[cfg(feature = "aws-secrets")]
+    handlebars.register_helper("secret", Box::new(crate::aws_secrets::SecretHelper::new()));
+
+    handlebars
+}
+
+/// The `{{file "/run/secrets/token"}}` config helper, which substitutes the contents of the file
+/// without trailing whitespace
+fn file_helper(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let path = h
+        .param(0)
+        .and_then(|x| x.value().as_str())
+        .ok_or(RenderErrorReason::ParamNotFoundForIndex("file", 0))?;
+
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| RenderErrorReason::Other(format!("could not read {}: {}", path, e)))?;
+
+    out.write(contents.trim_end())?;
+    Ok(())
+}
+
+fn render_config(handlebars: &mut Handlebars, path: &Path) -> Result<String, ConfigError> {
+    let mut f = File::open(path).map_err(|e| ConfigError {
         kind: ConfigErrorKind::FileNotFound(e),
     })?;
 
@@ -227,107 +2192,727 @@ pub fn parse_config<P: AsRef<Path>>(path: P) -> Result<DnsConfig, ConfigError> {
         kind: ConfigErrorKind::Misread(e),
     })?;
 
-    let mut handlebars = Handlebars::new();
-
     handlebars
         .register_template_string("dness_config", contents)
         .map_err(|e| ConfigError {
             kind: ConfigErrorKind::Template(e),
         })?;
-    handlebars.register_escape_fn(handlebars::no_escape);
-    handlebars.set_strict_mode(true);
 
-    let data: HashMap<_, _> = std::env::vars().collect();
-    let config_contents = handlebars
-        .render("dness_config", &data)
-        .map_err(|e| ConfigError {
-            kind: ConfigErrorKind::Render(e),
-        })?;
+    let data: HashMap<_, _> = std::env::vars().collect();
+    handlebars
+        .render("dness_config", &data)
+        .map_err(|e| ConfigError {
+            kind: ConfigErrorKind::Render(e),
+        })
+}
+
+/// Replaces every `*_file` key of the table with the trimmed contents of the file under the key
+/// without the suffix (eg: `token_file` becomes `token`), returning the number of files read
+fn read_secret_files(table: &mut toml::Table) -> Result<usize, ConfigError> {
+    let keys = table
+        .iter()
+        .filter(|(key, value)| key.ends_with("_file") && value.is_str())
+        .map(|(key, _)| key.clone())
+        .collect::<Vec<_>>();
+
+    for key in &keys {
+        let field = key.trim_end_matches("_file");
+        if table.contains_key(field) {
+            return Err(ConfigError {
+                kind: ConfigErrorKind::Invalid(format!(
+                    "{} and {} can't both be given",
+                    field, key
+                )),
+            });
+        }
+
+        let path = table.remove(key).and_then(|x| x.as_str().map(String::from));
+        let path = path.unwrap_or_default();
+        let contents = std::fs::read_to_string(&path).map_err(|e| ConfigError {
+            kind: ConfigErrorKind::FileNotFound(IoError::new(
+                e.kind(),
+                format!("could not read {} ({}): {}", key, path, e),
+            )),
+        })?;
+        table.insert(
+            String::from(field),
+            toml::Value::String(String::from(contents.trim())),
+        );
+    }
+
+    Ok(keys.len())
+}
+
+/// Reads the secrets of the domains and notifications that are given as files, like docker
+/// secrets or systemd credentials, so that they don't need to be in the environment
+fn read_config_secrets(table: &mut toml::Table) -> Result<usize, ConfigError> {
+    let mut read = 0;
+    if let Some(toml::Value::Array(domains)) = table.get_mut("domains") {
+        for domain in domains.iter_mut().filter_map(|x| x.as_table_mut()) {
+            read += read_secret_files(domain)?;
+        }
+    }
+
+    if let Some(toml::Value::Table(notifications)) = table.get_mut("notifications") {
+        read += read_secret_files(notifications)?;
+        if let Some(toml::Value::Table(smtp)) = notifications.get_mut("smtp") {
+            read += read_secret_files(smtp)?;
+        }
+    }
+
+    Ok(read)
+}
+
+/// Parses and merges the config files, where the domains of every file are kept and any other
+/// top level value is taken from the last file that has it
+pub fn parse_configs(paths: &[&Path]) -> Result<DnsConfig, ConfigError> {
+    let mut handlebars = config_renderer();
+    let mut merged = toml::Table::new();
+    for path in paths {
+        let config_contents = render_config(&mut handlebars, path)?;
+        let parse_err = |e| ConfigError {
+            kind: ConfigErrorKind::Parse(e),
+        };
+        let mut table: toml::Table = toml::from_str(&config_contents).map_err(parse_err)?;
+
+        // Each file is deserialized on its own first so that mistakes are reported with their
+        // location, as a top level value is never split across files. A file with secrets read
+        // from other files no longer matches its text, so it is only checked once merged.
+        if read_config_secrets(&mut table)? == 0 {
+            toml::from_str::<DnsConfig>(&config_contents).map_err(parse_err)?;
+        }
+
+        for (key, value) in table {
+            match (merged.get_mut(&key), value) {
+                (Some(toml::Value::Array(domains)), toml::Value::Array(more))
+                    if key == "domains" =>
+                {
+                    domains.extend(more)
+                }
+                (_, value) => {
+                    merged.insert(key, value);
+                }
+            }
+        }
+    }
+
+    let config: DnsConfig = toml::Value::Table(merged)
+        .try_into()
+        .map_err(|e| ConfigError {
+            kind: ConfigErrorKind::Parse(e),
+        })?;
+
+    config.validate_resolver()?;
+    config.validate_interval()?;
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_config(path: &str) -> Result<DnsConfig, ConfigError> {
+        parse_configs(&[Path::new(path)])
+    }
+
+    #[test]
+    fn test_ip_type_conversions() {
+        let v4 = IpType::from(IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2)));
+        let v6 = IpType::from("2001:db8::1".parse::<IpAddr>().unwrap());
+        assert_eq!(v4, IpType::V4);
+        assert_eq!(v6, IpType::V6);
+        assert_eq!(v4.record_type(), "A");
+        assert_eq!(v6.record_type(), "AAAA");
+        assert_eq!(v4.opposite(), IpType::V6);
+        assert_eq!(v6.opposite(), IpType::V4);
+    }
+
+    #[test]
+    fn deserialize_config_empty() {
+        let config: DnsConfig = toml::from_str("").unwrap();
+        assert_eq!(
+            config,
+            DnsConfig {
+                ip_resolver: IpResolverConfig::Single(String::from("opendns")),
+                ip_resolver_v4: None,
+                ip_resolver_v6: None,
+                ip_resolver_url: None,
+                ip_resolver_interface: None,
+                ip_allowlist: Vec::new(),
+                ip_blocklist: Vec::new(),
+                reject_private_ips: false,
+                reject_private_ips_exit_code: false,
+                allow_private_ips: false,
+                proxy_url: None,
+                user_agent: None,
+                max_retries: 3,
+                retry_base_delay_ms: 500,
+                request_timeout_secs: None,
+                connect_timeout_secs: None,
+                read_timeout_secs: None,
+                max_concurrent_updates: 4,
+                interval_secs: None,
+                systemd_notify: false,
+                fail_on_missing: false,
+                state_file: None,
+                state_ttl_secs: 3600,
+                min_update_interval_secs: 0,
+                history_file: None,
+                history_max_entries: 1000,
+                notifications: NotificationsConfig::default(),
+                metrics: MetricsConfig::default(),
+                circuit_breaker: CircuitBreakerConfig::default(),
+                output_format: OutputFormat::Text,
+                log: LogConfig {
+                    level: LevelFilter::Info,
+                    log_format: LogFormat::Text,
+                    target: LogTarget::Stdout,
+                    log_http: false,
+                },
+                domains: vec![]
+            }
+        )
+    }
+
+    #[test]
+    fn deserialize_config_deny_unknown() {
+        let err = toml::from_str::<DnsConfig>(r#"log_info = "DEBUG""#).unwrap_err();
+        let msg = format!("{}", err);
+        assert!(msg.contains("unknown field `log_info`"));
+    }
+
+    #[test]
+    fn test_rejected_ip_reason() {
+        let config: DnsConfig = toml::from_str(
+            r#"
+            ip_allowlist = ["2.0.0.0/8"]
+            ip_blocklist = ["2.2.2.0/24"]
+            reject_private_ips = true
+            "#,
+        )
+        .unwrap();
+
+        let reason = |ip: &str| {
+            config
+                .rejected_ip_reason(ip.parse().unwrap())
+                .map(|x| x.to_string())
+        };
+        assert_eq!(reason("2.3.3.3"), None);
+        assert_eq!(
+            reason("2.2.2.2"),
+            Some(String::from("it is within the blocklisted 2.2.2.0/24"))
+        );
+        assert_eq!(
+            reason("100.64.1.1"),
+            Some(String::from("it is within the private 100.64.0.0/10"))
+        );
+        assert_eq!(
+            reason("3.3.3.3"),
+            Some(String::from("it is outside the allowlist"))
+        );
+
+        // The allowlist only has ipv4 networks, so ipv6 addresses are only checked if private
+        assert_eq!(reason("2001:db8::1"), None);
+        assert_eq!(
+            reason("fd00::1"),
+            Some(String::from("it is within the private fc00::/7"))
+        );
+    }
+
+    #[test]
+    fn test_qualified_records() {
+        let config: DnsConfig = toml::from_str(
+            r#"
+            [[domains]]
+            type = "godaddy"
+            key = "key"
+            secret = "secret"
+            domain = "example.com"
+            records = ["@", "a", "b.example.com"]
+
+            [[domains]]
+            type = "noip"
+            hostname = "home.example.com"
+            username = "user"
+            password = "pass"
+        "#,
+        )
+        .unwrap();
+
+        let records = config.domains[0].qualified_records();
+        assert_eq!(
+            records,
+            vec![
+                ("@", String::from("example.com")),
+                ("a", String::from("a.example.com")),
+                ("b.example.com", String::from("b.example.com")),
+            ]
+        );
+        assert_eq!(
+            config.domains[1].qualified_records(),
+            vec![("home.example.com", String::from("home.example.com"))]
+        );
+    }
+
+    #[test]
+    fn test_rejected_private_ip() {
+        let mut config = DnsConfig::default();
+        let reason = |config: &DnsConfig, ip: &str| config.rejected_ip_reason(ip.parse().unwrap());
+        let private = Some(IpRejection::Private);
+        for ip in [
+            "127.0.0.1",
+            "169.254.1.1",
+            "10.1.1.1",
+            "172.16.1.1",
+            "192.168.1.1",
+            "::1",
+            "fe80::1",
+        ] {
+            assert_eq!(reason(&config, ip), private, "{}", ip);
+        }
+        assert_eq!(reason(&config, "2.2.2.2"), None);
+        assert_eq!(reason(&config, "2001:db8::1"), None);
+
+        config.allow_private_ips = true;
+        assert_eq!(reason(&config, "192.168.1.1"), None);
+
+        // Explicitly rejected networks win over allowing private addresses
+        config.reject_private_ips = true;
+        assert_eq!(
+            reason(&config, "192.168.1.1"),
+            Some(IpRejection::Untrusted(String::from(
+                "it is within the private 192.168.0.0/16"
+            )))
+        );
+        assert_eq!(reason(&config, "169.254.1.1"), None);
+    }
+
+    #[test]
+    fn deserialize_config_max_concurrent_providers() {
+        let config: DnsConfig = toml::from_str("max_concurrent_providers = 2").unwrap();
+        assert_eq!(config.max_concurrent_updates, 2);
+    }
+
+    #[test]
+    fn deserialize_config_simple() {
+        let toml_str = &include_str!("../assets/base-config.toml");
+        let config: DnsConfig = toml::from_str(toml_str).unwrap();
+        assert_eq!(
+            config,
+            DnsConfig {
+                ip_resolver: IpResolverConfig::Single(String::from("opendns")),
+                ip_resolver_v4: None,
+                ip_resolver_v6: None,
+                ip_resolver_url: None,
+                ip_resolver_interface: None,
+                ip_allowlist: Vec::new(),
+                ip_blocklist: Vec::new(),
+                reject_private_ips: false,
+                reject_private_ips_exit_code: false,
+                allow_private_ips: false,
+                proxy_url: None,
+                user_agent: None,
+                max_retries: 3,
+                retry_base_delay_ms: 500,
+                request_timeout_secs: None,
+                connect_timeout_secs: None,
+                read_timeout_secs: None,
+                max_concurrent_updates: 4,
+                interval_secs: None,
+                systemd_notify: false,
+                fail_on_missing: false,
+                state_file: None,
+                state_ttl_secs: 3600,
+                min_update_interval_secs: 0,
+                history_file: None,
+                history_max_entries: 1000,
+                notifications: NotificationsConfig::default(),
+                metrics: MetricsConfig::default(),
+                circuit_breaker: CircuitBreakerConfig::default(),
+                output_format: OutputFormat::Text,
+                log: LogConfig {
+                    level: LevelFilter::Info,
+                    log_format: LogFormat::Text,
+                    target: LogTarget::Stdout,
+                    log_http: false,
+                },
+                domains: vec![DomainConfig::Cloudflare(CloudflareConfig {
+                    email: None,
+                    key: None,
+                    token: Some(String::from("dec0de")),
+                    zone: String::from("example.com"),
+                    records: vec![String::from("n.example.com")],
+                    request_timeout_secs: None,
+                    create_missing: false,
+                    ttl: None,
+                    proxied: None,
+                    use_batch_updates: true,
+                    use_per_record_queries: true,
+                    zone_cache_ttl_secs: 86400,
+                    verify_after_update: false,
+                    verify_timeout_secs: 10,
+                    ip_types: vec![IpType::V4],
+                    log_level: None,
+                    name: None,
+                })]
+            }
+        );
+    }
+
+    #[test]
+    fn deserialize_config_godaddy() {
+        let toml_str = &include_str!("../assets/godaddy-config.toml");
+        let config: DomainConfig = toml::from_str(toml_str).unwrap();
+        assert_eq!(
+            config,
+            DomainConfig::GoDaddy(GoDaddyConfig {
+                base_url: String::from("https://api.godaddy.com"),
+                domain: String::from("example.com"),
+                key: String::from("abc123"),
+                secret: String::from("ef"),
+                records: vec![String::from("@")],
+                request_timeout_secs: None,
+                ip_types: vec![IpType::V4],
+                create_missing: false,
+                log_level: None,
+                name: None,
+            })
+        );
+    }
+
+    #[test]
+    fn deserialize_config_namecheap() {
+        let toml_str = &include_str!("../assets/namecheap-config.toml");
+        let config: DomainConfig = toml::from_str(toml_str).unwrap();
+        assert_eq!(
+            config,
+            DomainConfig::Namecheap(NamecheapConfig {
+                base_url: String::from("https://dynamicdns.park-your-domain.com"),
+                domain: String::from("test-dness-1.xyz"),
+                ddns_password: String::from("super_secret_password"),
+                records: vec![String::from("@"), String::from("*"), String::from("sub")],
+                request_timeout_secs: None,
+                ip_types: vec![IpType::V4],
+                log_level: None,
+                name: None,
+            })
+        );
+    }
+
+    #[test]
+    fn deserialize_config_namecheap_api() {
+        let toml_str = &include_str!("../assets/namecheap-api-config.toml");
+        let config: DomainConfig = toml::from_str(toml_str).unwrap();
+        assert_eq!(
+            config,
+            DomainConfig::NamecheapApi(NamecheapApiConfig {
+                base_url: String::from("https://api.namecheap.com/xml.response"),
+                api_user: String::from("dness"),
+                api_key: String::from("deadbeef"),
+                username: String::from("dness"),
+                client_ip: String::from("1.1.1.1"),
+                domain: String::from("example.com"),
+                records: vec![String::from("@"), String::from("sub")],
+                request_timeout_secs: None,
+                log_level: None,
+                name: None,
+            })
+        );
+    }
+
+    #[test]
+    fn deserialize_config_route53() {
+        let toml_str = &include_str!("../assets/route53-config.toml");
+        let config: DomainConfig = toml::from_str(toml_str).unwrap();
+        assert_eq!(
+            config,
+            DomainConfig::Route53(Route53Config {
+                base_url: String::from("https://route53.amazonaws.com"),
+                hosted_zone_id: String::from("Z0123456789ABCDEFGHIJ"),
+                records: vec![String::from("example.com"), String::from("sub.example.com")],
+                ip_types: vec![IpType::V4, IpType::V6],
+                region: None,
+                request_timeout_secs: None,
+                log_level: None,
+                name: None,
+            })
+        );
+    }
+
+    #[test]
+    fn deserialize_config_linode() {
+        let toml_str = &include_str!("../assets/linode-config.toml");
+        let config: DomainConfig = toml::from_str(toml_str).unwrap();
+        assert_eq!(
+            config,
+            DomainConfig::Linode(LinodeConfig {
+                base_url: String::from("https://api.linode.com"),
+                token: String::from("deadbeef"),
+                domain: String::from("example.com"),
+                records: vec![String::from("@"), String::from("sub")],
+                ip_types: vec![IpType::V4],
+                request_timeout_secs: None,
+                log_level: None,
+                name: None,
+            })
+        );
+    }
+
+    #[test]
+    fn deserialize_config_namecom() {
+        let toml_str = &include_str!("../assets/namecom-config.toml");
+        let config: DomainConfig = toml::from_str(toml_str).unwrap();
+        assert_eq!(
+            config,
+            DomainConfig::NameCom(NameComConfig {
+                base_url: String::from("https://api.name.com"),
+                username: String::from("dness"),
+                token: String::from("deadbeef"),
+                domain: String::from("example.com"),
+                records: vec![String::from("@"), String::from("sub")],
+                ip_types: vec![IpType::V4, IpType::V6],
+                request_timeout_secs: None,
+                create_missing: true,
+                log_level: None,
+                name: None,
+            })
+        );
+    }
+
+    #[test]
+    fn deserialize_config_inwx() {
+        let toml_str = &include_str!("../assets/inwx-config.toml");
+        let config: DomainConfig = toml::from_str(toml_str).unwrap();
+        assert_eq!(
+            config,
+            DomainConfig::Inwx(InwxConfig {
+                base_url: String::from("https://dyndns.inwx.com"),
+                username: String::from("dness"),
+                password: String::from("super_secret_password"),
+                hostname: String::from("example.com"),
+                records: vec![String::from("@"), String::from("sub")],
+                ip_types: vec![IpType::V4, IpType::V6],
+                request_timeout_secs: None,
+                log_level: None,
+                name: None,
+            })
+        );
+    }
+
+    #[test]
+    fn deserialize_config_infomaniak() {
+        let toml_str = &include_str!("../assets/infomaniak-config.toml");
+        let config: DomainConfig = toml::from_str(toml_str).unwrap();
+        assert_eq!(
+            config,
+            DomainConfig::Infomaniak(InfomaniakConfig {
+                base_url: String::from("https://infomaniak.com"),
+                username: String::from("dness"),
+                password: String::from("super_secret_password"),
+                hostname: String::from("example.com"),
+                records: vec![String::from("@"), String::from("sub")],
+                ip_types: vec![IpType::V4],
+                request_timeout_secs: None,
+                log_level: None,
+                name: None,
+            })
+        );
+    }
 
-    toml::from_str(&config_contents).map_err(|e| ConfigError {
-        kind: ConfigErrorKind::Parse(e),
-    })
-}
+    #[test]
+    fn deserialize_config_strato() {
+        let toml_str = &include_str!("../assets/strato-config.toml");
+        let config: DomainConfig = toml::from_str(toml_str).unwrap();
+        assert_eq!(
+            config,
+            DomainConfig::Strato(StratoConfig {
+                base_url: String::from("https://dyndns.strato.com"),
+                domain: String::from("example.com"),
+                password: String::from("super_secret_password"),
+                records: vec![String::from("@"), String::from("sub")],
+                ip_types: vec![IpType::V4, IpType::V6],
+                request_timeout_secs: None,
+                log_level: None,
+                name: None,
+            })
+        );
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn deserialize_config_ddnss() {
+        let toml_str = &include_str!("../assets/ddnss-config.toml");
+        let config: DomainConfig = toml::from_str(toml_str).unwrap();
+        assert_eq!(
+            config,
+            DomainConfig::Ddnss(DdnssConfig {
+                base_url: String::from("https://www.ddnss.de"),
+                api_key: String::from("super_secret_key"),
+                hostname: String::from("example.ddnss.de"),
+                ip_types: vec![IpType::V4, IpType::V6],
+                request_timeout_secs: None,
+                log_level: None,
+                name: None,
+            })
+        );
+    }
 
     #[test]
-    fn deserialize_config_empty() {
-        let config: DnsConfig = toml::from_str("").unwrap();
+    fn deserialize_config_bunny() {
+        let toml_str = &include_str!("../assets/bunny-config.toml");
+        let config: DomainConfig = toml::from_str(toml_str).unwrap();
         assert_eq!(
             config,
-            DnsConfig {
-                ip_resolver: String::from("opendns"),
-                log: LogConfig {
-                    level: LevelFilter::Info,
-                },
-                domains: vec![]
-            }
-        )
+            DomainConfig::Bunny(BunnyConfig {
+                base_url: String::from("https://api.bunny.net"),
+                api_key: String::from("deadbeef"),
+                zone: String::from("example.com"),
+                records: vec![String::from("@"), String::from("sub")],
+                ip_types: vec![IpType::V4],
+                request_timeout_secs: None,
+                create_missing: false,
+                log_level: None,
+                name: None,
+            })
+        );
     }
 
     #[test]
-    fn deserialize_config_deny_unknown() {
-        let err = toml::from_str::<DnsConfig>(r#"log_info = "DEBUG""#).unwrap_err();
-        let msg = format!("{}", err);
-        assert!(msg.contains("unknown field `log_info`"));
+    fn deserialize_config_zoneedit() {
+        let toml_str = &include_str!("../assets/zoneedit-config.toml");
+        let config: DomainConfig = toml::from_str(toml_str).unwrap();
+        assert_eq!(
+            config,
+            DomainConfig::ZoneEdit(ZoneEditConfig {
+                base_url: String::from("https://dynamic.zoneedit.com"),
+                hostname: String::from("example.com"),
+                username: String::from("dness"),
+                password: String::from("super_secret_password"),
+                records: vec![String::from("@"), String::from("sub")],
+                ip_types: vec![IpType::V4, IpType::V6],
+                request_timeout_secs: None,
+                log_level: None,
+                name: None,
+            })
+        );
     }
 
     #[test]
-    fn deserialize_config_simple() {
-        let toml_str = &include_str!("../assets/base-config.toml");
-        let config: DnsConfig = toml::from_str(toml_str).unwrap();
+    fn deserialize_config_ipv64() {
+        let toml_str = &include_str!("../assets/ipv64-config.toml");
+        let config: DomainConfig = toml::from_str(toml_str).unwrap();
         assert_eq!(
             config,
-            DnsConfig {
-                ip_resolver: String::from("opendns"),
-                log: LogConfig {
-                    level: LevelFilter::Info,
-                },
-                domains: vec![DomainConfig::Cloudflare(CloudflareConfig {
-                    email: None,
-                    key: None,
-                    token: Some(String::from("dec0de")),
-                    zone: String::from("example.com"),
-                    records: vec![String::from("n.example.com")]
-                })]
-            }
+            DomainConfig::Ipv64(Ipv64Config {
+                base_url: String::from("https://ipv64.net"),
+                api_key: String::from("super_secret_key"),
+                domain: String::from("example.ipv64.net"),
+                records: vec![String::from("@"), String::from("sub")],
+                ip_types: vec![IpType::V4, IpType::V6],
+                request_timeout_secs: None,
+                log_level: None,
+                name: None,
+            })
         );
     }
 
     #[test]
-    fn deserialize_config_godaddy() {
-        let toml_str = &include_str!("../assets/godaddy-config.toml");
+    fn deserialize_config_dnspod() {
+        let toml_str = &include_str!("../assets/dnspod-config.toml");
         let config: DomainConfig = toml::from_str(toml_str).unwrap();
         assert_eq!(
             config,
-            DomainConfig::GoDaddy(GoDaddyConfig {
-                base_url: String::from("https://api.godaddy.com"),
+            DomainConfig::Dnspod(DnspodConfig {
+                base_url: String::from("https://dnsapi.cn"),
+                login_token: String::from("12345,super_secret_token"),
                 domain: String::from("example.com"),
-                key: String::from("abc123"),
-                secret: String::from("ef"),
-                records: vec![String::from("@")]
+                records: vec![String::from("@"), String::from("sub")],
+                ip_types: vec![IpType::V4],
+                request_timeout_secs: None,
+                log_level: None,
+                name: None,
             })
         );
     }
 
     #[test]
-    fn deserialize_config_namecheap() {
-        let toml_str = &include_str!("../assets/namecheap-config.toml");
+    fn deserialize_config_njalla() {
+        let toml_str = &include_str!("../assets/njalla-config.toml");
         let config: DomainConfig = toml::from_str(toml_str).unwrap();
         assert_eq!(
             config,
-            DomainConfig::Namecheap(NamecheapConfig {
-                base_url: String::from("https://dynamicdns.park-your-domain.com"),
-                domain: String::from("test-dness-1.xyz"),
-                ddns_password: String::from("super_secret_password"),
-                records: vec![String::from("@"), String::from("*"), String::from("sub")]
+            DomainConfig::Njalla(NjallaConfig {
+                base_url: String::from("https://njal.la"),
+                api_key: String::from("super_secret_key"),
+                hostname: String::from("example.com"),
+                records: vec![String::from("home")],
+                ip_types: vec![IpType::V4, IpType::V6],
+                request_timeout_secs: None,
+                log_level: None,
+                name: None,
+            })
+        );
+    }
+
+    #[test]
+    fn deserialize_config_regfish() {
+        let toml_str = &include_str!("../assets/regfish-config.toml");
+        let config: DomainConfig = toml::from_str(toml_str).unwrap();
+        assert_eq!(
+            config,
+            DomainConfig::Regfish(RegfishConfig {
+                base_url: String::from("https://dyndns.regfish.de"),
+                token: String::from("super_secret_token"),
+                domain: String::from("example.com"),
+                records: vec![String::from("@"), String::from("sub")],
+                ip_types: vec![IpType::V4, IpType::V6],
+                request_timeout_secs: None,
+                log_level: None,
+                name: None,
             })
         );
     }
 
+    #[test]
+    fn deserialize_config_cloudflare_multi() {
+        let config = parse_config("assets/cloudflare-multi-config.toml").unwrap();
+        let DomainConfig::CloudflareMulti(c) = &config.domains[0] else {
+            panic!("expected a cloudflare multi domain");
+        };
+        assert_eq!(
+            c.zones,
+            vec![
+                CloudflareZoneEntry {
+                    name: String::from("example.com"),
+                    records: vec![String::from("n.example.com")],
+                    ip_types: vec![IpType::V4],
+                },
+                CloudflareZoneEntry {
+                    name: String::from("example.org"),
+                    records: vec![String::from("example.org"), String::from("n.example.org")],
+                    ip_types: vec![IpType::V6],
+                },
+            ]
+        );
+        assert_eq!(
+            config.domains[0].display_name(),
+            "example.com, example.org (cloudflaremulti)"
+        );
+        assert_eq!(
+            config.domains[0].get_ip_types(),
+            vec![IpType::V4, IpType::V6]
+        );
+
+        let zone = c.zone_config(&c.zones[1]);
+        assert_eq!(zone.token, Some(String::from("dec0de")));
+        assert_eq!(zone.zone, "example.org");
+        assert_eq!(zone.ip_types, vec![IpType::V6]);
+        assert!(validate_config(&config).is_empty());
+
+        let mut v6 = config.domains[0].clone();
+        assert!(v6.retain_ip_type(IpType::V6));
+        assert_eq!(v6.display_name(), "example.org (cloudflaremulti)");
+    }
+
     #[test]
     fn deserialize_config_he() {
         let toml_str = &include_str!("../assets/he-config.toml");
@@ -338,7 +2923,11 @@ mod tests {
                 base_url: String::from("https://dyn.dns.he.net"),
                 hostname: String::from("test-dness-1.xyz"),
                 password: String::from("super_secret_password"),
-                records: vec![String::from("@"), String::from("sub")]
+                records: vec![String::from("@"), String::from("sub")],
+                request_timeout_secs: None,
+                ip_types: vec![IpType::V4],
+                log_level: None,
+                name: None,
             })
         );
     }
@@ -350,9 +2939,41 @@ mod tests {
         assert_eq!(
             config,
             DnsConfig {
-                ip_resolver: String::from("opendns"),
+                ip_resolver: IpResolverConfig::Single(String::from("opendns")),
+                ip_resolver_v4: None,
+                ip_resolver_v6: None,
+                ip_resolver_url: None,
+                ip_resolver_interface: None,
+                ip_allowlist: Vec::new(),
+                ip_blocklist: Vec::new(),
+                reject_private_ips: false,
+                reject_private_ips_exit_code: false,
+                allow_private_ips: false,
+                proxy_url: None,
+                user_agent: None,
+                max_retries: 3,
+                retry_base_delay_ms: 500,
+                request_timeout_secs: None,
+                connect_timeout_secs: None,
+                read_timeout_secs: None,
+                max_concurrent_updates: 4,
+                interval_secs: None,
+                systemd_notify: false,
+                fail_on_missing: false,
+                state_file: None,
+                state_ttl_secs: 3600,
+                min_update_interval_secs: 0,
+                history_file: None,
+                history_max_entries: 1000,
+                notifications: NotificationsConfig::default(),
+                metrics: MetricsConfig::default(),
+                circuit_breaker: CircuitBreakerConfig::default(),
+                output_format: OutputFormat::Text,
                 log: LogConfig {
                     level: LevelFilter::Debug,
+                    log_format: LogFormat::Text,
+                    target: LogTarget::Stdout,
+                    log_http: false,
                 },
                 domains: vec![
                     DomainConfig::Cloudflare(CloudflareConfig {
@@ -360,7 +2981,19 @@ mod tests {
                         key: None,
                         token: Some(String::from("dec0de")),
                         zone: String::from("example.com"),
-                        records: vec![String::from("n.example.com")]
+                        records: vec![String::from("n.example.com")],
+                        request_timeout_secs: None,
+                        create_missing: false,
+                        ttl: None,
+                        proxied: None,
+                        use_batch_updates: true,
+                        use_per_record_queries: true,
+                        zone_cache_ttl_secs: 86400,
+                        verify_after_update: false,
+                        verify_timeout_secs: 10,
+                        ip_types: vec![IpType::V4],
+                        log_level: None,
+                        name: None,
                     }),
                     DomainConfig::Cloudflare(CloudflareConfig {
                         email: Some(String::from("admin@example.com")),
@@ -370,7 +3003,19 @@ mod tests {
                         records: vec![
                             String::from("n.example2.com"),
                             String::from("n2.example2.com")
-                        ]
+                        ],
+                        request_timeout_secs: None,
+                        create_missing: false,
+                        ttl: None,
+                        proxied: None,
+                        use_batch_updates: true,
+                        use_per_record_queries: true,
+                        zone_cache_ttl_secs: 86400,
+                        verify_after_update: false,
+                        verify_timeout_secs: 10,
+                        ip_types: vec![IpType::V4],
+                        log_level: None,
+                        name: None,
                     })
                 ]
             }
@@ -391,9 +3036,41 @@ mod tests {
         assert_eq!(
             config,
             DnsConfig {
-                ip_resolver: String::from("ipify"),
+                ip_resolver: IpResolverConfig::Single(String::from("ipify")),
+                ip_resolver_v4: None,
+                ip_resolver_v6: None,
+                ip_resolver_url: None,
+                ip_resolver_interface: None,
+                ip_allowlist: Vec::new(),
+                ip_blocklist: Vec::new(),
+                reject_private_ips: false,
+                reject_private_ips_exit_code: false,
+                allow_private_ips: false,
+                proxy_url: None,
+                user_agent: None,
+                max_retries: 3,
+                retry_base_delay_ms: 500,
+                request_timeout_secs: None,
+                connect_timeout_secs: None,
+                read_timeout_secs: None,
+                max_concurrent_updates: 4,
+                interval_secs: None,
+                systemd_notify: false,
+                fail_on_missing: false,
+                state_file: None,
+                state_ttl_secs: 3600,
+                min_update_interval_secs: 0,
+                history_file: None,
+                history_max_entries: 1000,
+                notifications: NotificationsConfig::default(),
+                metrics: MetricsConfig::default(),
+                circuit_breaker: CircuitBreakerConfig::default(),
+                output_format: OutputFormat::Text,
                 log: LogConfig {
                     level: LevelFilter::Info,
+                    log_format: LogFormat::Text,
+                    target: LogTarget::Stdout,
+                    log_http: false,
                 },
                 domains: vec![]
             }
@@ -411,10 +3088,115 @@ mod tests {
                 username: String::from("myemail@example.org"),
                 hostname: String::from("dnesstest.hopto.org"),
                 password: String::from("super_secret_password"),
+                request_timeout_secs: None,
+                ip_types: vec![IpType::V4],
+                log_level: None,
+                name: None,
             })
         );
     }
 
+    #[test]
+    fn deserialize_noip_ipv6_config() {
+        let toml_str = &include_str!("../assets/noip-ipv6-config.toml");
+        let config: DomainConfig = toml::from_str(toml_str).unwrap();
+        assert_eq!(config.get_ip_types(), vec![IpType::V4, IpType::V6]);
+    }
+
+    #[test]
+    fn deserialize_named_domain_config() {
+        let toml_str = &include_str!("../assets/named-domain-config.toml");
+        let config: DomainConfig = toml::from_str(toml_str).unwrap();
+        assert_eq!(config.display_name(), "home");
+
+        let toml_str = &include_str!("../assets/noip-config.toml");
+        let config: DomainConfig = toml::from_str(toml_str).unwrap();
+        assert_eq!(config.display_name(), "dnesstest.hopto.org (noip)");
+    }
+
+    #[test]
+    fn validate_config_readme() {
+        std::env::set_var("MY_CLOUDFLARE_TOKEN", "dec0de");
+        let config = parse_config("assets/readme-config.toml").unwrap();
+        assert!(validate_config(&config).is_empty());
+    }
+
+    #[test]
+    fn validate_config_errors() {
+        let config = parse_config("assets/invalid-semantics-config.toml").unwrap();
+        assert_eq!(
+            validate_config(&config),
+            vec![
+                String::from("proxy_url is not a valid proxy url"),
+                String::from("user_agent is not a valid header value"),
+                String::from(
+                    "example.com (cloudflare): ipv6 requested but the doh ip resolver does not support ipv6"
+                ),
+                String::from("example.com (cloudflare): token can't be combined with email and key"),
+                String::from("example.com (cloudflare): records must not be empty"),
+                String::from("example.com (godaddy): ip_types must not be empty"),
+                String::from("example.com (godaddy): key and secret must not be empty"),
+                String::from(
+                    "example.com (godaddy): record home-[a is not a valid pattern: Pattern syntax error near position 5: invalid range pattern"
+                ),
+                String::from("example.com (godaddy): record www. must not end with a dot"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_is_record_name() {
+        assert!(is_record_name("sub.example.com", "sub", "example.com"));
+        assert!(is_record_name("Sub.Example.com.", "sub", "example.com"));
+        assert!(is_record_name("example.com", "@", "example.com"));
+        assert!(is_record_name("sub", "sub", "example.com"));
+        assert!(is_record_name(
+            "n.example.com",
+            "n.example.com",
+            "example.com"
+        ));
+        assert!(!is_record_name("sub.example.org", "sub", "example.com"));
+        assert!(!is_record_name("example.com", "sub", "example.com"));
+    }
+
+    #[test]
+    fn test_is_godaddy_ote() {
+        assert!(is_godaddy_ote("https://api.ote-godaddy.com"));
+        assert!(!is_godaddy_ote(&godaddy_base_url()));
+    }
+
+    #[test]
+    fn deserialize_config_json_log() {
+        let config = parse_config("assets/json-log-config.toml").unwrap();
+        assert_eq!(
+            config.log,
+            LogConfig {
+                level: LevelFilter::Warn,
+                log_format: LogFormat::Json,
+                target: LogTarget::Stdout,
+                log_http: true,
+            }
+        );
+    }
+
+    #[test]
+    fn deserialize_config_syslog() {
+        let toml_str = r#"
+            [log]
+            target = "syslog"
+        "#;
+        let config: DnsConfig = toml::from_str(toml_str).unwrap();
+        assert_eq!(
+            config.log,
+            LogConfig {
+                level: LevelFilter::Info,
+                log_format: LogFormat::Text,
+                target: LogTarget::Syslog,
+                log_http: false,
+            }
+        );
+    }
+
     #[test]
     fn deserialize_config_dynu() {
         let toml_str = &include_str!("../assets/dynu-config.toml");
@@ -426,8 +3208,305 @@ mod tests {
                 hostname: String::from("test-dness-1.xyz"),
                 username: String::from("MyUserName"),
                 password: String::from("IpUpdatePassword"),
-                records: vec![String::from("@"), String::from("sub")]
+                records: vec![
+                    DynuRecord::from(String::from("@")),
+                    DynuRecord::from(String::from("sub")),
+                    DynuRecord {
+                        name: String::from("v6"),
+                        ip_types: Some(vec![IpType::V6]),
+                    },
+                ],
+                ip_types: vec![IpType::V4],
+                request_timeout_secs: None,
+                log_level: None,
+                name: None,
+            })
+        );
+    }
+
+    #[test]
+    fn deserialize_config_dnsomatic() {
+        let toml_str = &include_str!("../assets/dnsomatic-config.toml");
+        let config: DomainConfig = toml::from_str(toml_str).unwrap();
+        assert_eq!(
+            config,
+            DomainConfig::DnsOMatic(DnsOMaticConfig {
+                base_url: String::from("https://updates.dnsomatic.com"),
+                username: String::from("myemail@example.org"),
+                password: String::from("super_secret_password"),
+                hostnames: vec![String::from("all.dnsomatic.com")],
+                request_timeout_secs: None,
+                log_level: None,
+                name: None,
+            })
+        );
+    }
+
+    #[test]
+    fn deserialize_config_ovh() {
+        let toml_str = &include_str!("../assets/ovh-config.toml");
+        let config: DomainConfig = toml::from_str(toml_str).unwrap();
+        assert_eq!(
+            config,
+            DomainConfig::Ovh(OvhConfig {
+                base_url: String::from("https://www.ovh.com"),
+                hostname: String::from("example.com"),
+                username: String::from("example.com-dynhost"),
+                password: String::from("super_secret_password"),
+                records: vec![String::from("@"), String::from("sub")],
+                request_timeout_secs: None,
+                log_level: None,
+                name: None,
             })
         );
     }
+
+    #[test]
+    fn deserialize_config_http_resolver() {
+        let config = parse_config("assets/http-resolver-config.toml").unwrap();
+        assert_eq!(
+            config,
+            DnsConfig {
+                ip_resolver: IpResolverConfig::Single(String::from("http")),
+                ip_resolver_v4: None,
+                ip_resolver_v6: None,
+                ip_resolver_url: Some(String::from("https://icanhazip.com")),
+                ip_resolver_interface: None,
+                ip_allowlist: Vec::new(),
+                ip_blocklist: Vec::new(),
+                reject_private_ips: false,
+                reject_private_ips_exit_code: false,
+                allow_private_ips: false,
+                proxy_url: None,
+                user_agent: None,
+                max_retries: 3,
+                retry_base_delay_ms: 500,
+                request_timeout_secs: None,
+                connect_timeout_secs: None,
+                read_timeout_secs: None,
+                max_concurrent_updates: 4,
+                interval_secs: None,
+                systemd_notify: false,
+                fail_on_missing: false,
+                state_file: None,
+                state_ttl_secs: 3600,
+                min_update_interval_secs: 0,
+                history_file: None,
+                history_max_entries: 1000,
+                notifications: NotificationsConfig::default(),
+                metrics: MetricsConfig::default(),
+                circuit_breaker: CircuitBreakerConfig::default(),
+                output_format: OutputFormat::Text,
+                log: LogConfig {
+                    level: LevelFilter::Info,
+                    log_format: LogFormat::Text,
+                    target: LogTarget::Stdout,
+                    log_http: false,
+                },
+                domains: vec![]
+            }
+        );
+    }
+
+    #[test]
+    fn deserialize_config_http_resolver_bad() {
+        let err = parse_config("assets/http-resolver-config-bad.toml").unwrap_err();
+        let msg = format!("{}", err);
+        assert!(msg.contains("ip_resolver_url is only valid"));
+    }
+
+    #[test]
+    fn deserialize_config_resolver_chain() {
+        let config = parse_config("assets/resolver-chain-config.toml").unwrap();
+        assert_eq!(
+            config.ip_resolver,
+            IpResolverConfig::Chain(vec![
+                String::from("opendns"),
+                String::from("ipify"),
+                String::from("http")
+            ])
+        );
+        assert_eq!(
+            config.ip_resolver_url,
+            Some(String::from("https://icanhazip.com"))
+        );
+        assert_eq!(config.ip_resolver.ipv6_resolvers().len(), 2);
+    }
+
+    #[test]
+    fn deserialize_config_resolver_per_ip_type() {
+        let config = parse_config("assets/resolver-per-ip-type-config.toml").unwrap();
+        assert_eq!(
+            config.ip_resolver_for(IpType::V4),
+            &IpResolverConfig::Single(String::from("opendns"))
+        );
+        assert_eq!(
+            config.ip_resolver_for(IpType::V6),
+            &IpResolverConfig::Chain(vec![String::from("interface"), String::from("ipify")])
+        );
+        assert!(validate_config(&config).is_empty());
+    }
+
+    #[test]
+    fn deserialize_config_interface_resolver() {
+        let config = parse_config("assets/interface-resolver-config.toml").unwrap();
+        assert_eq!(
+            config.ip_resolver,
+            IpResolverConfig::Single(String::from("interface"))
+        );
+        assert_eq!(config.ip_resolver_interface, Some(String::from("eth0")));
+    }
+
+    #[test]
+    fn deserialize_config_interface_resolver_bad() {
+        let err = parse_config("assets/interface-resolver-config-bad.toml").unwrap_err();
+        let msg = format!("{}", err);
+        assert!(msg.contains("ip_resolver_interface is required"));
+    }
+
+    #[test]
+    fn deserialize_config_secret_files() {
+        let config = parse_config("assets/secret-file-config.toml").unwrap();
+        match &config.domains[0] {
+            DomainConfig::Cloudflare(c) => assert_eq!(c.token, Some(String::from("dec0de"))),
+            x => panic!("unexpected domain: {:?}", x),
+        }
+    }
+
+    #[test]
+    fn deserialize_config_secret_file_missing() {
+        let err = parse_config("assets/secret-file-config-bad.toml").unwrap_err();
+        assert!(matches!(err.kind, ConfigErrorKind::FileNotFound(_)));
+        let source = error::Error::source(&err).unwrap().to_string();
+        assert!(source.contains("could not read secret_file (assets/does-not-exist.secret)"));
+    }
+
+    #[test]
+    fn parse_config_file_helper() {
+        let dir = std::env::temp_dir();
+        let secret = dir.join(format!("dness-file-helper-{}", std::process::id()));
+        let path = dir.join(format!("dness-file-helper-{}.toml", std::process::id()));
+        std::fs::write(&secret, "dec0de\n").unwrap();
+        let template = format!(
+            "[[domains]]\n\
+             type = \"cloudflare\"\n\
+             token = \"{{{{file \"{}\"}}}}\"\n\
+             zone = \"example.com\"\n\
+             records = [\"n.example.com\"]\n",
+            secret.display()
+        );
+        std::fs::write(&path, template).unwrap();
+
+        let config = parse_configs(&[&path]);
+        std::fs::remove_file(&secret).unwrap();
+        let missing = parse_configs(&[&path]);
+        std::fs::remove_file(&path).unwrap();
+
+        match &config.unwrap().domains[0] {
+            DomainConfig::Cloudflare(c) => assert_eq!(c.token, Some(String::from("dec0de"))),
+            d => panic!("unexpected domain: {:?}", d),
+        }
+
+        let err = missing.unwrap_err();
+        assert!(matches!(err.kind, ConfigErrorKind::Render(_)));
+        let source = error::Error::source(&err).unwrap().to_string();
+        assert!(source.contains(&secret.display().to_string()));
+    }
+
+    #[test]
+    fn parse_configs_merged() {
+        let config = parse_configs(&[
+            Path::new("assets/merge-base-config.toml"),
+            Path::new("assets/merge-override-config.toml"),
+        ])
+        .unwrap();
+        assert_eq!(
+            config.ip_resolver,
+            IpResolverConfig::Single(String::from("ipify"))
+        );
+        assert_eq!(config.max_retries, 5);
+        assert_eq!(config.log.level, LevelFilter::Warn);
+        assert_eq!(config.domains.len(), 2);
+        assert_eq!(config.domains[0].provider_name(), "cloudflare");
+        assert_eq!(config.domains[1].provider_name(), "he");
+    }
+
+    #[test]
+    fn parse_configs_reports_bad_file() {
+        let err = parse_configs(&[
+            Path::new("assets/merge-base-config.toml"),
+            Path::new("assets/readme-config-bad.toml"),
+        ])
+        .unwrap_err();
+        assert!(format!("{:?}", err).contains("I_DO_NOT_EXIST"));
+    }
+
+    #[test]
+    fn deserialize_config_notifications() {
+        let config = parse_config("assets/notifications-config.toml").unwrap();
+        assert_eq!(
+            config.notifications,
+            NotificationsConfig {
+                webhook_url: Some(String::from("https://example.com/dness")),
+                webhook_method: String::from("PUT"),
+                slack_webhook_url: Some(String::from(
+                    "https://hooks.slack.com/services/T0/B0/XXXX"
+                )),
+                discord_webhook_url: Some(String::from("https://discord.com/api/webhooks/0/XXXX")),
+                ntfy_url: Some(String::from("https://ntfy.sh/my-dness-alerts")),
+                ntfy_token: Some(String::from("tk_1")),
+                smtp: Some(SmtpConfig {
+                    smtp_host: String::from("smtp.example.com"),
+                    smtp_port: Some(465),
+                    smtp_username: Some(String::from("dness@example.com")),
+                    smtp_password: Some(String::from("hunter2")),
+                    smtp_tls: true,
+                    from: String::from("dness <dness@example.com>"),
+                    to: String::from("admin@example.com"),
+                }),
+            }
+        );
+    }
+
+    #[test]
+    fn deserialize_config_metrics() {
+        let config = parse_config("assets/metrics-config.toml").unwrap();
+        assert_eq!(
+            config.metrics,
+            MetricsConfig {
+                enabled: true,
+                listen_addr: String::from("127.0.0.1:9100"),
+            }
+        );
+    }
+
+    #[test]
+    fn deserialize_config_cloudflare_ipv6() {
+        let config = parse_config("assets/cloudflare-ipv6-config.toml").unwrap();
+        assert_eq!(
+            config.domains[0].get_ip_types(),
+            vec![IpType::V4, IpType::V6]
+        );
+    }
+
+    #[test]
+    fn deserialize_config_cloudflare_verify() {
+        let toml_str = r#"
+            [[domains]]
+            type = "cloudflare"
+            token = "dec0de"
+            zone = "example.com"
+            records = ["n.example.com"]
+            verify_after_update = true
+            verify_timeout_secs = 30
+        "#;
+        let config: DnsConfig = toml::from_str(toml_str).unwrap();
+        match &config.domains[0] {
+            DomainConfig::Cloudflare(c) => {
+                assert!(c.verify_after_update);
+                assert_eq!(c.verify_timeout_secs, 30);
+            }
+            _ => panic!("expected a cloudflare config"),
+        }
+    }
 }
@@ -1,13 +1,26 @@
-use log::warn;
+use crate::config::{DnsConfig, IpType};
+use crate::dns::DnsResolver;
+use crate::errors::DnessError;
+use crate::http_log;
+use crate::retry::RetryPolicy;
+use crate::state::ZoneCache;
+use async_trait::async_trait;
+use glob::Pattern;
 use std::collections::HashSet;
 use std::fmt;
+use std::future::Future;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::ops::{Add, AddAssign};
+use std::time::Instant;
+use tracing::{field, info, info_span, warn, Instrument};
 
 #[derive(Clone, Debug, Copy, Default, PartialEq, Eq)]
 pub struct Updates {
     pub updated: i32,
     pub current: i32,
     pub missing: i32,
+    pub failed: i32,
+    pub dry_run: bool,
 }
 
 impl AddAssign for Updates {
@@ -15,6 +28,8 @@ impl AddAssign for Updates {
         self.updated += other.updated;
         self.current += other.current;
         self.missing += other.missing;
+        self.failed += other.failed;
+        self.dry_run |= other.dry_run;
     }
 }
 
@@ -32,9 +47,154 @@ impl fmt::Display for Updates {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "updated: {}, already current: {}, missing: {}",
-            self.updated, self.current, self.missing
-        )
+            "updated: {}, already current: {}, missing: {}, failed: {}",
+            self.updated, self.current, self.missing, self.failed
+        )?;
+
+        if self.dry_run {
+            write!(f, " (dry-run)")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// What a provider needs to know, beyond its own config, to update its records
+#[derive(Debug)]
+pub struct UpdateContext<'a> {
+    pub client: &'a reqwest::Client,
+    pub policy: &'a RetryPolicy,
+
+    /// The name of the domain being updated, which labels retries
+    pub name: &'a str,
+    pub dry_run: bool,
+    pub force: bool,
+
+    /// The ipv6 address given alongside the ipv4 address to providers that update both address
+    /// types in a single request
+    pub addr6: Option<Ipv6Addr>,
+
+    /// The cloudflare zone ids from previous runs
+    pub zones: &'a ZoneCache,
+
+    /// The global config, which vets the addresses that a provider reports in place of the
+    /// resolved address
+    pub config: &'a DnsConfig,
+}
+
+/// A dns service that can keep records pointed at an address
+#[async_trait]
+pub trait DnsProvider: Send + Sync {
+    /// Ensures the records of the provider point to the address, retrying failed requests
+    async fn update(
+        &self,
+        ctx: &UpdateContext<'_>,
+        addr: IpAddr,
+    ) -> Result<Updates, Box<dyn std::error::Error>>;
+
+    /// The name of the provider, as it is written in the config
+    fn name(&self) -> &str;
+}
+
+/// The ipv4 address for providers that can only keep A records current
+pub fn require_ipv4(addr: IpAddr, name: &str) -> Result<Ipv4Addr, DnessError> {
+    match addr {
+        IpAddr::V4(addr) => Ok(addr),
+        IpAddr::V6(_) => Err(DnessError::message(format!(
+            "ipv6 is not supported for {}",
+            name
+        ))),
+    }
+}
+
+/// Sends requests within a span that records the http fields, so that provider calls can be
+/// correlated with the trace
+#[async_trait]
+pub trait SendTraced {
+    async fn send_traced(self) -> reqwest::Result<reqwest::Response>;
+}
+
+#[async_trait]
+impl SendTraced for reqwest::RequestBuilder {
+    async fn send_traced(self) -> reqwest::Result<reqwest::Response> {
+        let (client, request) = self.build_split();
+        let request = request?;
+
+        // The query is left out as some providers authenticate with it
+        let url = request.url();
+        let span = info_span!(
+            "http",
+            http.url = %format!("{}{}", url.origin().ascii_serialization(), url.path()),
+            http.method = %request.method(),
+            http.status_code = field::Empty,
+            http.response_time_ms = field::Empty,
+        );
+
+        let log_http = http_log::is_enabled();
+        if log_http {
+            span.in_scope(|| http_log::log_request(&request));
+        }
+
+        let start = Instant::now();
+        let response = client.execute(request).instrument(span.clone()).await;
+        span.record("http.response_time_ms", start.elapsed().as_millis() as u64);
+        if let Ok(response) = &response {
+            span.record("http.status_code", response.status().as_u16());
+        }
+
+        match response {
+            Ok(response) if log_http => http_log::log_response(response).instrument(span).await,
+            response => response,
+        }
+    }
+}
+
+/// Prefix for log messages that describe changes that were not applied due to a dry run
+pub fn dry_run_prefix(dry_run: bool) -> &'static str {
+    if dry_run {
+        "[dry-run] "
+    } else {
+        ""
+    }
+}
+
+/// Whether a configured record is a glob pattern (eg: "*.example.com") that matches any of the
+/// records listed by the provider, rather than the name of a single record
+pub fn is_record_pattern(record: &str) -> bool {
+    record.contains(['*', '?', '['])
+}
+
+/// Splits the configured records into the names of records and glob patterns. A pattern that
+/// doesn't parse is kept as a name, as it is already reported when the config is validated.
+pub fn split_record_patterns(records: &[String]) -> (HashSet<String>, Vec<Pattern>) {
+    let mut names = HashSet::new();
+    let mut patterns = Vec::new();
+    for record in records {
+        match Pattern::new(record) {
+            Ok(pattern) if is_record_pattern(record) => patterns.push(pattern),
+            _ => {
+                names.insert(record.clone());
+            }
+        }
+    }
+    (names, patterns)
+}
+
+/// Patterns that don't match any record are only warned about, as they aren't counted as
+/// missing records
+pub fn log_unmatched_patterns(
+    patterns: &[Pattern],
+    actual: &HashSet<String>,
+    provider: &str,
+    domain: &str,
+) {
+    for pattern in patterns {
+        if !actual.iter().any(|x| pattern.matches(x)) {
+            warn!(
+                "record pattern {} did not match any records in {} domain {}",
+                pattern, provider, domain
+            );
+        }
     }
 }
 
@@ -60,3 +220,375 @@ pub fn log_missing_domains(
 
     missing_domains.len()
 }
+
+/// The outcomes of an update request sent with the dyndns2 protocol that aren't errors
+#[derive(Clone, Debug, Copy, PartialEq, Eq)]
+pub enum UpdateOutcome {
+    Updated,
+    Current,
+    Missing,
+}
+
+impl Updates {
+    /// Tallies the outcome of a single update request
+    pub fn record(&mut self, outcome: UpdateOutcome) {
+        match outcome {
+            UpdateOutcome::Updated => self.updated += 1,
+            UpdateOutcome::Current => self.current += 1,
+            UpdateOutcome::Missing => self.missing += 1,
+        }
+    }
+}
+
+/// Interprets the return code of the dyndns2 protocol (originally from dyn.com and adopted by
+/// many other services) where the first word of the response body denotes the outcome.
+pub fn parse_dyndns2_response(response: &str) -> Result<UpdateOutcome, DnessError> {
+    let code = response.split_whitespace().next().unwrap_or_default();
+    let reason = match code {
+        "good" => return Ok(UpdateOutcome::Updated),
+        "nochg" => return Ok(UpdateOutcome::Current),
+        "nohost" => return Ok(UpdateOutcome::Missing),
+        "911" | "dnserr" => {
+            return Err(DnessError::transient(format!(
+                "dyndns2 update failed (server error, try again later): {}",
+                response.trim()
+            )))
+        }
+        "notfqdn" => "hostname is not a fully qualified domain name",
+        "badauth" => "invalid username and password combination",
+        "badagent" => "the service rejected the user agent or request",
+        "!donator" => "the requested feature is only available to paid accounts",
+        "abuse" => "hostname is blocked for update abuse",
+        "blocked" => "update client has been blocked",
+        _ => "unrecognized response",
+    };
+
+    Err(DnessError::message(format!(
+        "dyndns2 update failed ({}): {}",
+        reason,
+        response.trim()
+    )))
+}
+
+/// The records of a provider that are updated one host at a time and that can't be listed, so
+/// the address of each record is looked up through dns to know whether it needs an update
+#[derive(Debug)]
+pub struct DnsCheckedRecords<'a> {
+    /// The name of the provider, which labels the logs
+    pub provider: &'a str,
+    pub domain: &'a str,
+    pub records: &'a [String],
+    pub dry_run: bool,
+    pub force: bool,
+}
+
+impl DnsCheckedRecords<'_> {
+    /// Points the records at the address, where the provider sends the update request for a
+    /// fully qualified host
+    pub async fn update<U, F>(&self, wan: IpAddr, update: U) -> Result<Updates, DnessError>
+    where
+        U: Fn(String) -> F,
+        F: Future<Output = Result<UpdateOutcome, DnessError>>,
+    {
+        let resolver = DnsResolver::create_cloudflare().await?;
+        let resolver = &resolver;
+        let ip_type = IpType::from(wan);
+        let lookup = move |query: String| async move { resolver.ip_lookup(&query, ip_type).await };
+        self.update_with(wan, lookup, update).await
+    }
+
+    async fn update_with<L, LF, E, U, F>(
+        &self,
+        wan: IpAddr,
+        lookup: L,
+        update: U,
+    ) -> Result<Updates, DnessError>
+    where
+        L: Fn(String) -> LF,
+        LF: Future<Output = Result<IpAddr, E>>,
+        E: fmt::Display,
+        U: Fn(String) -> F,
+        F: Future<Output = Result<UpdateOutcome, DnessError>>,
+    {
+        let mut results = Updates::default();
+
+        for record in self.records {
+            let host = if record == "@" {
+                self.domain.to_string()
+            } else {
+                format!("{}.{}", record, self.domain)
+            };
+
+            let previous = if self.force {
+                None
+            } else {
+                match lookup(format!("{}.", host)).await {
+                    Ok(ip) if ip == wan => {
+                        results.current += 1;
+                        continue;
+                    }
+                    Ok(ip) => Some(ip),
+                    Err(e) => {
+                        // Could be a network issue or it could be that the record didn't exist.
+                        warn!(
+                            "resolving {} record ({}) encountered an error: {}",
+                            self.provider, record, e
+                        );
+                        results.missing += 1;
+                        continue;
+                    }
+                }
+            };
+
+            let outcome = if self.dry_run {
+                UpdateOutcome::Updated
+            } else {
+                update(host).await?
+            };
+
+            if outcome == UpdateOutcome::Updated {
+                match previous {
+                    Some(ip) => info!(
+                        "{}{} from domain {} updated from {} to {}",
+                        dry_run_prefix(self.dry_run),
+                        record,
+                        self.domain,
+                        ip,
+                        wan
+                    ),
+                    None => info!(
+                        "{}{} from domain {} forcibly updated to {}",
+                        dry_run_prefix(self.dry_run),
+                        record,
+                        self.domain,
+                        wan
+                    ),
+                }
+            }
+            results.record(outcome);
+        }
+
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::retry::Retryable;
+    use std::sync::Mutex;
+
+    fn records() -> Vec<String> {
+        ["@", "stale", "gone", "nohost"]
+            .iter()
+            .map(|x| x.to_string())
+            .collect()
+    }
+
+    const WAN: IpAddr = IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2));
+
+    async fn lookup(query: String) -> Result<IpAddr, String> {
+        match query.as_str() {
+            "example.com." => Ok(WAN),
+            "stale.example.com." | "nohost.example.com." => {
+                Ok(IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1)))
+            }
+            _ => Err(String::from("no records found")),
+        }
+    }
+
+    async fn update_hosts(
+        records: &DnsCheckedRecords<'_>,
+        requested: &Mutex<Vec<String>>,
+    ) -> Result<Updates, DnessError> {
+        records
+            .update_with(WAN, lookup, |host| async move {
+                requested.lock().unwrap().push(host.clone());
+                match host.as_str() {
+                    "nohost.example.com" => Ok(UpdateOutcome::Missing),
+                    "bad.example.com" => Err(DnessError::message(String::from("badauth"))),
+                    _ => Ok(UpdateOutcome::Updated),
+                }
+            })
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_dns_checked_records_update() {
+        let records = records();
+        let checked = DnsCheckedRecords {
+            provider: "test",
+            domain: "example.com",
+            records: &records,
+            dry_run: false,
+            force: false,
+        };
+
+        let requested = Mutex::new(Vec::new());
+        let summary = update_hosts(&checked, &requested).await.unwrap();
+
+        // The current record is skipped and a record that doesn't resolve isn't sent
+        assert_eq!(
+            requested.into_inner().unwrap(),
+            vec!["stale.example.com", "nohost.example.com"]
+        );
+        assert_eq!(
+            summary,
+            Updates {
+                updated: 1,
+                current: 1,
+                missing: 2,
+                ..Updates::default()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dns_checked_records_force_update() {
+        let records = records();
+        let checked = DnsCheckedRecords {
+            provider: "test",
+            domain: "example.com",
+            records: &records,
+            dry_run: false,
+            force: true,
+        };
+
+        let requested = Mutex::new(Vec::new());
+        let summary = update_hosts(&checked, &requested).await.unwrap();
+
+        // Every record is sent without being looked up, with "@" as the domain itself
+        assert_eq!(
+            requested.into_inner().unwrap(),
+            vec![
+                "example.com",
+                "stale.example.com",
+                "gone.example.com",
+                "nohost.example.com"
+            ]
+        );
+        assert_eq!(
+            summary,
+            Updates {
+                updated: 3,
+                missing: 1,
+                ..Updates::default()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dns_checked_records_dry_run() {
+        let records = records();
+        let checked = DnsCheckedRecords {
+            provider: "test",
+            domain: "example.com",
+            records: &records,
+            dry_run: true,
+            force: false,
+        };
+
+        let requested = Mutex::new(Vec::new());
+        let summary = update_hosts(&checked, &requested).await.unwrap();
+
+        assert!(requested.into_inner().unwrap().is_empty());
+        assert_eq!(
+            summary,
+            Updates {
+                updated: 2,
+                current: 1,
+                missing: 1,
+                ..Updates::default()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dns_checked_records_update_error() {
+        let records = vec![String::from("bad"), String::from("stale")];
+        let checked = DnsCheckedRecords {
+            provider: "test",
+            domain: "example.com",
+            records: &records,
+            dry_run: false,
+            force: true,
+        };
+
+        let requested = Mutex::new(Vec::new());
+        let err = update_hosts(&checked, &requested).await.unwrap_err();
+
+        // The first failed request stops the rest of the records from being sent
+        assert_eq!(requested.into_inner().unwrap(), vec!["bad.example.com"]);
+        assert!(err.to_string().contains("badauth"));
+    }
+
+    #[test]
+    fn test_updates_sum() {
+        let failed = Updates {
+            failed: 1,
+            ..Updates::default()
+        };
+        let total = Updates {
+            updated: 2,
+            missing: 1,
+            ..Updates::default()
+        } + failed;
+        assert_eq!(
+            total.to_string(),
+            "updated: 2, already current: 0, missing: 1, failed: 1"
+        );
+    }
+
+    #[test]
+    fn test_split_record_patterns() {
+        let records = vec![
+            String::from("example.com"),
+            String::from("*.example.com"),
+            String::from("[a.example.com"),
+        ];
+        let (names, patterns) = split_record_patterns(&records);
+
+        let mut expected = HashSet::new();
+        expected.insert(String::from("example.com"));
+        expected.insert(String::from("[a.example.com"));
+        assert_eq!(names, expected);
+        assert_eq!(patterns.len(), 1);
+        assert!(patterns[0].matches("a.example.com"));
+        assert!(!patterns[0].matches("example.com"));
+    }
+
+    #[test]
+    fn test_parse_dyndns2_response() {
+        assert_eq!(
+            parse_dyndns2_response("good 2.2.2.2").unwrap(),
+            UpdateOutcome::Updated
+        );
+        assert_eq!(
+            parse_dyndns2_response("nochg 2.2.2.2\n").unwrap(),
+            UpdateOutcome::Current
+        );
+
+        assert_eq!(
+            parse_dyndns2_response("nohost").unwrap(),
+            UpdateOutcome::Missing
+        );
+
+        let msg = format!("{}", parse_dyndns2_response("abuse").unwrap_err());
+        assert!(msg.contains("blocked for update abuse"));
+
+        let msg = format!("{}", parse_dyndns2_response("badauth").unwrap_err());
+        assert!(msg.contains("invalid username and password"));
+
+        let msg = format!("{}", parse_dyndns2_response("badagent").unwrap_err());
+        assert!(msg.contains("rejected the user agent"));
+
+        let err = parse_dyndns2_response("911").unwrap_err();
+        assert!(err.is_retryable());
+        assert!(!parse_dyndns2_response("badauth")
+            .unwrap_err()
+            .is_retryable());
+
+        let msg = format!("{}", parse_dyndns2_response("").unwrap_err());
+        assert!(msg.contains("unrecognized response"));
+    }
+}
@@ -0,0 +1,292 @@
+use crate::{
+    config::{DdnssConfig, IpType},
+    core::{dry_run_prefix, DnsProvider, SendTraced, UpdateContext, UpdateOutcome, Updates},
+    dns::DnsResolver,
+    errors::DnessError,
+    retry::retry,
+};
+use async_trait::async_trait;
+use std::net::IpAddr;
+use tracing::{info, warn};
+
+#[derive(Debug)]
+pub struct DdnssProvider<'a> {
+    client: &'a reqwest::Client,
+    config: &'a DdnssConfig,
+}
+
+impl<'a> DdnssProvider<'a> {
+    /// https://ddnss.de/info.php
+    ///
+    /// The A and AAAA records are updated together when both addresses are given
+    pub async fn update_domain(&self, addrs: &[IpAddr]) -> Result<UpdateOutcome, DnessError> {
+        let base = self.config.base_url.trim_end_matches('/').to_string();
+        let get_url = format!("{}/upd.php", base);
+
+        let mut query = vec![
+            ("key", self.config.api_key.clone()),
+            ("host", self.config.hostname.clone()),
+        ];
+        for addr in addrs {
+            let param = match addr {
+                IpAddr::V4(_) => "ip",
+                IpAddr::V6(_) => "ip6",
+            };
+            query.push((param, addr.to_string()));
+        }
+
+        let response = self
+            .client
+            .get(&get_url)
+            .query(&query)
+            .send_traced()
+            .await
+            .map_err(|e| DnessError::send_http(&get_url, "ddnss update", e))?
+            .error_for_status()
+            .map_err(|e| DnessError::bad_response(&get_url, "ddnss update", e))?
+            .text()
+            .await
+            .map_err(|e| DnessError::deserialize(&get_url, "ddnss update", e))?;
+
+        parse_ddnss_response(&response)
+    }
+}
+
+/// DDNSS responds with the number of hostnames updated (eg: "Updated 1 hostname(s)") instead of
+/// a dyndns2 return code
+fn parse_ddnss_response(response: &str) -> Result<UpdateOutcome, DnessError> {
+    let updated = response
+        .split("Updated ")
+        .nth(1)
+        .and_then(|x| x.split_whitespace().next())
+        .and_then(|x| x.parse::<u32>().ok());
+
+    match updated {
+        Some(0) => Ok(UpdateOutcome::Missing),
+        Some(_) => Ok(UpdateOutcome::Updated),
+        None if response.contains("badauth") => Err(DnessError::message(String::from(
+            "ddnss rejected the update key",
+        ))),
+        None => Err(DnessError::message(format!(
+            "unexpected ddnss response: {}",
+            response.trim()
+        ))),
+    }
+}
+
+fn join_addrs(addrs: &[IpAddr]) -> String {
+    addrs
+        .iter()
+        .map(|x| x.to_string())
+        .collect::<Vec<_>>()
+        .join(" and ")
+}
+
+#[tracing::instrument(skip(client, config), fields(domain = %config.hostname))]
+pub async fn update_domains(
+    client: &reqwest::Client,
+    config: &DdnssConfig,
+    addrs: &[IpAddr],
+    dry_run: bool,
+    force: bool,
+) -> Result<Updates, DnessError> {
+    let provider = DdnssProvider { client, config };
+    let mut results = Updates::default();
+
+    // Only the addresses whose records are out of date are sent, so that both records are
+    // updated in a single request when needed
+    let mut stale = Vec::new();
+    if force {
+        stale.extend_from_slice(addrs);
+    } else {
+        let resolver = DnsResolver::create_cloudflare().await?;
+        let dns_query = format!("{}.", &config.hostname);
+        for &addr in addrs {
+            match resolver.ip_lookup(&dns_query, IpType::from(addr)).await {
+                Ok(ip) if ip == addr => results.current += 1,
+                Ok(_) => stale.push(addr),
+                Err(e) => {
+                    // Could be a network issue or it could be that the record didn't exist.
+                    warn!(
+                        "resolving ddnss ({}) encountered an error: {}",
+                        config.hostname, e
+                    );
+                    results.missing += 1;
+                }
+            }
+        }
+    }
+
+    if stale.is_empty() {
+        return Ok(results);
+    }
+
+    let outcome = if dry_run {
+        UpdateOutcome::Updated
+    } else {
+        provider.update_domain(&stale).await?
+    };
+
+    if outcome == UpdateOutcome::Updated {
+        info!(
+            "{}{} updated to {}",
+            dry_run_prefix(dry_run),
+            config.hostname,
+            join_addrs(&stale)
+        );
+    }
+
+    for _ in &stale {
+        results.record(outcome);
+    }
+
+    Ok(results)
+}
+
+#[async_trait]
+impl DnsProvider for DdnssConfig {
+    async fn update(
+        &self,
+        ctx: &UpdateContext<'_>,
+        addr: IpAddr,
+    ) -> Result<Updates, Box<dyn std::error::Error>> {
+        let mut addrs = vec![addr];
+        addrs.extend(ctx.addr6.map(IpAddr::V6));
+        retry(ctx.policy, ctx.name, || {
+            update_domains(ctx.client, self, &addrs, ctx.dry_run, ctx.force)
+        })
+        .await
+        .map_err(|e| e.into())
+    }
+
+    fn name(&self) -> &str {
+        "ddnss"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    macro_rules! ddnss_server {
+        () => {{
+            use rouille::Response;
+            use rouille::Server;
+
+            let requests = Arc::new(AtomicUsize::new(0));
+            let counter = requests.clone();
+            let server = Server::new("localhost:0", move |request| {
+                if request.url() != "/upd.php" {
+                    return Response::empty_404();
+                }
+
+                counter.fetch_add(1, Ordering::SeqCst);
+                if request.get_param("key").as_deref() != Some("my-key") {
+                    return Response::text("badauth");
+                }
+
+                let host = request.get_param("host");
+                let ip = request.get_param("ip");
+                let ip6 = request.get_param("ip6");
+                match (host.as_deref(), ip.as_deref(), ip6.as_deref()) {
+                    (Some("gone.ddnss.de"), _, _) => Response::text("Updated 0 hostname(s)"),
+                    (Some("example.ddnss.de"), Some("2.2.2.2"), Some("2001:db8::1"))
+                    | (Some("example.ddnss.de"), Some("2.2.2.2"), None)
+                    | (Some("example.ddnss.de"), None, Some("2001:db8::1")) => {
+                        Response::text("Updated 1 hostname(s)")
+                    }
+                    _ => Response::text("Error"),
+                }
+            })
+            .unwrap();
+
+            let (tx, rx) = std::sync::mpsc::sync_channel(1);
+            let addr = server.server_addr().clone();
+            std::thread::spawn(move || {
+                while let Err(_) = rx.try_recv() {
+                    server.poll();
+                    std::thread::sleep(std::time::Duration::from_millis(50))
+                }
+            });
+            (tx, addr, requests)
+        }};
+    }
+
+    fn config(base_url: String, hostname: &str) -> DdnssConfig {
+        DdnssConfig {
+            base_url,
+            api_key: String::from("my-key"),
+            hostname: String::from(hostname),
+            ip_types: vec![IpType::V4, IpType::V6],
+            request_timeout_secs: None,
+            log_level: None,
+            name: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ddnss_update_both_in_one_request() {
+        let (tx, addr, requests) = ddnss_server!();
+        let http_client = reqwest::Client::new();
+        let config = config(format!("http://{}", addr), "example.ddnss.de");
+        let addrs = [
+            IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2)),
+            IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)),
+        ];
+
+        let summary = update_domains(&http_client, &config, &addrs, false, true)
+            .await
+            .unwrap();
+        tx.send(()).unwrap();
+
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            summary,
+            Updates {
+                updated: 2,
+                ..Updates::default()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ddnss_update_missing() {
+        let (tx, addr, _) = ddnss_server!();
+        let http_client = reqwest::Client::new();
+        let config = config(format!("http://{}", addr), "gone.ddnss.de");
+
+        let v4 = [IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2))];
+        let summary = update_domains(&http_client, &config, &v4, false, true)
+            .await
+            .unwrap();
+        tx.send(()).unwrap();
+
+        assert_eq!(
+            summary,
+            Updates {
+                missing: 1,
+                ..Updates::default()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ddnss_bad_key() {
+        let (tx, addr, _) = ddnss_server!();
+        let http_client = reqwest::Client::new();
+        let config = DdnssConfig {
+            api_key: String::from("wrong"),
+            ..config(format!("http://{}", addr), "example.ddnss.de")
+        };
+
+        let v4 = [IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2))];
+        let result = update_domains(&http_client, &config, &v4, false, true).await;
+        tx.send(()).unwrap();
+
+        let err = result.unwrap_err().to_string();
+        assert!(err.contains("ddnss rejected the update key"));
+    }
+}
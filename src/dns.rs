@@ -1,7 +1,8 @@
+use crate::config::IpType;
 use crate::errors::{DnsError, DnsErrorKind};
-use std::net::{IpAddr, Ipv4Addr};
 use hickory_resolver::config::{NameServerConfigGroup, ResolverConfig, ResolverOpts};
 use hickory_resolver::TokioAsyncResolver;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 
 #[derive(Debug)]
 pub struct DnsResolver {
@@ -28,6 +29,24 @@ impl DnsResolver {
         Self::from_config(config).await
     }
 
+    pub async fn create_opendns_ipv6() -> Result<Self, DnsError> {
+        let config = ResolverConfig::from_parts(
+            None,
+            vec![],
+            NameServerConfigGroup::from_ips_clear(
+                &[
+                    // OpenDNS nameservers that answer "myip.opendns.com" with our ipv6 address
+                    IpAddr::V6(Ipv6Addr::new(0x2620, 0x119, 0x35, 0, 0, 0, 0, 0x35)),
+                    IpAddr::V6(Ipv6Addr::new(0x2620, 0x119, 0x53, 0, 0, 0, 0, 0x53)),
+                ],
+                53,
+                false,
+            ),
+        );
+
+        Self::from_config(config).await
+    }
+
     pub async fn create_cloudflare() -> Result<Self, DnsError> {
         Self::from_config(ResolverConfig::cloudflare()).await
     }
@@ -57,6 +76,32 @@ impl DnsResolver {
                 kind: Box::new(DnsErrorKind::UnexpectedResponse(0)),
             })
     }
+
+    pub async fn ipv6_lookup(&self, host: &str) -> Result<Ipv6Addr, DnsError> {
+        let response = self
+            .resolver
+            .ipv6_lookup(host)
+            .await
+            .map_err(|e| DnsError {
+                kind: Box::new(DnsErrorKind::DnsResolve(e)),
+            })?;
+
+        response
+            .iter()
+            .next()
+            .map(|address| address.0)
+            .ok_or_else(|| DnsError {
+                kind: Box::new(DnsErrorKind::UnexpectedResponse(0)),
+            })
+    }
+
+    /// Looks up the address of the given type for the host
+    pub async fn ip_lookup(&self, host: &str, ip_type: IpType) -> Result<IpAddr, DnsError> {
+        match ip_type {
+            IpType::V4 => self.ipv4_lookup(host).await.map(IpAddr::V4),
+            IpType::V6 => self.ipv6_lookup(host).await.map(IpAddr::V6),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -80,6 +125,13 @@ pub async fn wan_lookup_ip() -> Result<Ipv4Addr, DnsError> {
     opendns.wan_lookup().await
 }
 
+/// Resolves the ipv6 WAN address by asking OpenDNS over ipv6, as OpenDNS answers with the address
+/// the query was sent from
+pub async fn wan_lookup_ipv6() -> Result<Ipv6Addr, DnsError> {
+    let resolver = DnsResolver::create_opendns_ipv6().await?;
+    resolver.ipv6_lookup("myip.opendns.com.").await
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
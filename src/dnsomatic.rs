@@ -0,0 +1,202 @@
+use crate::config::DnsOMaticConfig;
+use crate::core::{
+    dry_run_prefix, parse_dyndns2_response, require_ipv4, DnsProvider, SendTraced, UpdateContext,
+    UpdateOutcome, Updates,
+};
+use crate::errors::DnessError;
+use crate::retry::retry;
+use async_trait::async_trait;
+use std::net::{IpAddr, Ipv4Addr};
+use tracing::{debug, info, warn};
+
+#[derive(Debug)]
+pub struct DnsOMaticProvider<'a> {
+    client: &'a reqwest::Client,
+    config: &'a DnsOMaticConfig,
+}
+
+impl<'a> DnsOMaticProvider<'a> {
+    /// https://www.dnsomatic.com/docs/api
+    pub async fn update_domain(
+        &self,
+        hostname: &str,
+        wan: Ipv4Addr,
+    ) -> Result<UpdateOutcome, DnessError> {
+        let base = self.config.base_url.trim_end_matches('/').to_string();
+        let get_url = format!("{}/nic/update", base);
+        let response = self
+            .client
+            .get(&get_url)
+            .query(&[("hostname", hostname), ("myip", &wan.to_string())])
+            .basic_auth(&self.config.username, Some(&self.config.password))
+            .send_traced()
+            .await
+            .map_err(|e| DnessError::send_http(&get_url, "dnsomatic update", e))?
+            .error_for_status()
+            .map_err(|e| DnessError::bad_response(&get_url, "dnsomatic update", e))?
+            .text()
+            .await
+            .map_err(|e| DnessError::deserialize(&get_url, "dnsomatic update", e))?;
+
+        parse_dyndns2_response(&response)
+    }
+}
+
+/// DNS-O-Matic is an aggregator that forwards updates to the services configured in the account.
+/// As the aggregated hostnames may not resolve (eg: "all.dnsomatic.com"), there is no dns
+/// pre-check, and we instead rely on the dyndns2 response code to know if a hostname was current.
+#[tracing::instrument(skip(client, config), fields(domain = ?config.hostnames))]
+pub async fn update_domains(
+    client: &reqwest::Client,
+    config: &DnsOMaticConfig,
+    wan: Ipv4Addr,
+    dry_run: bool,
+) -> Result<Updates, DnessError> {
+    let provider = DnsOMaticProvider { client, config };
+    let mut results = Updates::default();
+
+    for hostname in &config.hostnames {
+        // Without a pre-check, a dry run can only assume that every hostname would be updated
+        let outcome = if dry_run {
+            UpdateOutcome::Updated
+        } else {
+            provider.update_domain(hostname, wan).await?
+        };
+
+        match outcome {
+            UpdateOutcome::Updated => {
+                info!("{}{} updated to {}", dry_run_prefix(dry_run), hostname, wan);
+                results.updated += 1;
+            }
+            UpdateOutcome::Current => {
+                debug!("{} is already current", hostname);
+                results.current += 1;
+            }
+            UpdateOutcome::Missing => {
+                warn!("{} does not exist in the dns-o-matic account", hostname);
+                results.missing += 1;
+            }
+        }
+    }
+
+    Ok(results)
+}
+
+#[async_trait]
+impl DnsProvider for DnsOMaticConfig {
+    async fn update(
+        &self,
+        ctx: &UpdateContext<'_>,
+        addr: IpAddr,
+    ) -> Result<Updates, Box<dyn std::error::Error>> {
+        let addr = require_ipv4(addr, ctx.name)?;
+        retry(ctx.policy, ctx.name, || {
+            update_domains(ctx.client, self, addr, ctx.dry_run)
+        })
+        .await
+        .map_err(|e| e.into())
+    }
+
+    fn name(&self) -> &str {
+        "dnsomatic"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! dnsomatic_server {
+        () => {{
+            use rouille::Response;
+            use rouille::Server;
+
+            let server = Server::new("localhost:0", |request| {
+                if request.url() != "/nic/update" {
+                    return Response::empty_404();
+                }
+
+                match request.get_param("hostname").as_deref() {
+                    Some("a.example.com") => Response::text("good 2.2.2.2"),
+                    Some("b.example.com") => Response::text("nochg 2.2.2.2"),
+                    _ => Response::text("nohost"),
+                }
+            })
+            .unwrap();
+
+            let (tx, rx) = std::sync::mpsc::sync_channel(1);
+            let addr = server.server_addr().clone();
+            std::thread::spawn(move || {
+                while let Err(_) = rx.try_recv() {
+                    server.poll();
+                    std::thread::sleep(std::time::Duration::from_millis(50))
+                }
+            });
+            (tx, addr)
+        }};
+    }
+
+    #[tokio::test]
+    async fn test_dnsomatic_update() {
+        let (tx, addr) = dnsomatic_server!();
+        let http_client = reqwest::Client::new();
+        let new_ip = Ipv4Addr::new(2, 2, 2, 2);
+        let config = DnsOMaticConfig {
+            base_url: format!("http://{}", addr),
+            username: String::from("me@example.com"),
+            password: String::from("my-pass"),
+            hostnames: vec![String::from("a.example.com"), String::from("b.example.com")],
+            request_timeout_secs: None,
+            log_level: None,
+            name: None,
+        };
+
+        let summary = update_domains(&http_client, &config, new_ip, false)
+            .await
+            .unwrap();
+        tx.send(()).unwrap();
+
+        assert_eq!(
+            summary,
+            Updates {
+                current: 1,
+                updated: 1,
+                missing: 0,
+                failed: 0,
+                dry_run: false,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dnsomatic_nohost() {
+        let (tx, addr) = dnsomatic_server!();
+        let http_client = reqwest::Client::new();
+        let new_ip = Ipv4Addr::new(2, 2, 2, 2);
+        let config = DnsOMaticConfig {
+            base_url: format!("http://{}", addr),
+            username: String::from("me@example.com"),
+            password: String::from("my-pass"),
+            hostnames: vec![String::from("c.example.com")],
+            request_timeout_secs: None,
+            log_level: None,
+            name: None,
+        };
+
+        let summary = update_domains(&http_client, &config, new_ip, false)
+            .await
+            .unwrap();
+        tx.send(()).unwrap();
+
+        assert_eq!(
+            summary,
+            Updates {
+                current: 0,
+                updated: 0,
+                missing: 1,
+                failed: 0,
+                dry_run: false,
+            }
+        );
+    }
+}
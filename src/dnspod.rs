@@ -0,0 +1,472 @@
+use crate::config::{DnspodConfig, IpType};
+use crate::core::{dry_run_prefix, DnsProvider, SendTraced, UpdateContext, Updates};
+use crate::errors::DnessError;
+use crate::retry::retry;
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::net::IpAddr;
+use tracing::{debug, info, warn};
+
+#[derive(Deserialize, PartialEq, Clone, Debug)]
+struct DnspodStatus {
+    code: String,
+    message: String,
+}
+
+#[derive(Deserialize, PartialEq, Clone, Debug)]
+struct DnspodListResponse {
+    status: DnspodStatus,
+
+    #[serde(default)]
+    records: Vec<DnspodRecord>,
+}
+
+#[derive(Deserialize, PartialEq, Clone, Debug)]
+struct DnspodRecord {
+    id: String,
+    name: String,
+    r#type: String,
+    value: String,
+    ttl: String,
+    line_id: String,
+}
+
+#[derive(Deserialize, PartialEq, Clone, Debug)]
+struct DnspodModifyResponse {
+    status: DnspodStatus,
+}
+
+/// The status code of a successful action
+const SUCCESS: &str = "1";
+
+/// The status code of a record list without any records of the requested type
+const NO_RECORDS: &str = "10";
+
+/// DNSPod responds with a 200 even when an action fails, so the status code in the body
+/// determines the outcome. Rate limiting is worth retrying while the other errors are not.
+fn check_status(status: &DnspodStatus, context: &str, domain: &str) -> Result<(), DnessError> {
+    match status.code.as_str() {
+        SUCCESS => Ok(()),
+        "6" => Err(DnessError::message(format!(
+            "{}: domain {} not found in dnspod: {}",
+            context, domain, status.message
+        ))),
+        "70" => Err(DnessError::transient(format!(
+            "{}: rate limited by dnspod: {}",
+            context, status.message
+        ))),
+        code => Err(DnessError::message(format!(
+            "{} failed [{}]: {}",
+            context, code, status.message
+        ))),
+    }
+}
+
+#[derive(Clone, Debug)]
+struct DnspodClient<'a> {
+    base_url: String,
+    domain: String,
+    login_token: String,
+    records: HashSet<String>,
+    client: &'a reqwest::Client,
+    dry_run: bool,
+}
+
+impl<'a> DnspodClient<'a> {
+    fn log_missing_domains(&self, remote_domains: &[DnspodRecord]) -> usize {
+        let actual = remote_domains
+            .iter()
+            .map(|x| x.name.clone())
+            .collect::<HashSet<String>>();
+        crate::core::log_missing_domains(&self.records, &actual, "DNSPod", &self.domain)
+    }
+
+    /// https://docs.dnspod.cn/api/record-list/
+    async fn fetch_records(&self, ip_type: IpType) -> Result<Vec<DnspodRecord>, DnessError> {
+        let post_url = format!("{}/Record.List", self.base_url);
+        let response = self
+            .client
+            .post(&post_url)
+            .form(&[
+                ("login_token", self.login_token.as_str()),
+                ("format", "json"),
+                ("domain", self.domain.as_str()),
+                ("record_type", ip_type.record_type()),
+            ])
+            .send_traced()
+            .await
+            .map_err(|e| DnessError::send_http(&post_url, "dnspod fetch records", e))?
+            .error_for_status()
+            .map_err(|e| DnessError::bad_response(&post_url, "dnspod fetch records", e))?
+            .json::<DnspodListResponse>()
+            .await
+            .map_err(|e| DnessError::deserialize(&post_url, "dnspod fetch records", e))?;
+
+        if response.status.code == NO_RECORDS {
+            return Ok(Vec::new());
+        }
+
+        check_status(&response.status, "dnspod fetch records", &self.domain)?;
+        let records = response
+            .records
+            .into_iter()
+            .filter(|r| r.r#type == ip_type.record_type())
+            .collect();
+        Ok(records)
+    }
+
+    /// https://docs.dnspod.cn/api/modify-records/
+    async fn update_record(&self, record: &DnspodRecord, addr: IpAddr) -> Result<(), DnessError> {
+        if self.dry_run {
+            return Ok(());
+        }
+
+        let post_url = format!("{}/Record.Modify", self.base_url);
+        let value = addr.to_string();
+        let response = self
+            .client
+            .post(&post_url)
+            .form(&[
+                ("login_token", self.login_token.as_str()),
+                ("format", "json"),
+                ("domain", self.domain.as_str()),
+                ("record_id", record.id.as_str()),
+                ("sub_domain", record.name.as_str()),
+                ("record_type", record.r#type.as_str()),
+                ("record_line_id", record.line_id.as_str()),
+                ("value", value.as_str()),
+                ("ttl", record.ttl.as_str()),
+            ])
+            .send_traced()
+            .await
+            .map_err(|e| DnessError::send_http(&post_url, "dnspod update record", e))?
+            .error_for_status()
+            .map_err(|e| DnessError::bad_response(&post_url, "dnspod update record", e))?
+            .json::<DnspodModifyResponse>()
+            .await
+            .map_err(|e| DnessError::deserialize(&post_url, "dnspod update record", e))?;
+
+        check_status(&response.status, "dnspod update record", &self.domain)
+    }
+
+    async fn ensure_current_ip(
+        &self,
+        record: &DnspodRecord,
+        addr: IpAddr,
+    ) -> Result<Updates, DnessError> {
+        let mut current = 0;
+        let mut updated = 0;
+        match record.value.parse::<IpAddr>() {
+            Ok(ip) => {
+                if ip != addr {
+                    updated += 1;
+                    self.update_record(record, addr).await?;
+
+                    info!(
+                        "{}{} from domain {} updated from {} to {}",
+                        dry_run_prefix(self.dry_run),
+                        record.name,
+                        self.domain,
+                        record.value,
+                        addr
+                    )
+                } else {
+                    current += 1;
+                    debug!(
+                        "{} from domain {} is already current",
+                        record.name, self.domain
+                    )
+                }
+            }
+            Err(ref e) => {
+                updated += 1;
+                warn!("could not parse domain {} address {} as an ip -- will replace it. Original error: {}", record.name, record.value, e);
+                self.update_record(record, addr).await?;
+
+                info!(
+                    "{}{} from domain {} updated from {} to {}",
+                    dry_run_prefix(self.dry_run),
+                    record.name,
+                    self.domain,
+                    record.value,
+                    addr
+                )
+            }
+        }
+
+        Ok(Updates {
+            updated,
+            current,
+            ..Updates::default()
+        })
+    }
+}
+
+/// DNSPod dynamic dns service works as the following:
+///
+/// 1. Send a POST request to list the records of the address type in the domain: "A" for ipv4
+///    and "AAAA" for ipv6
+/// 2. Find all the expected records (and log those that are missing) and check their current IP
+/// 3. Update the remote IP as needed, ensuring that the line and TTL of the record are preserved
+#[tracing::instrument(skip(client, config), fields(domain = %config.domain))]
+pub async fn update_domains(
+    client: &reqwest::Client,
+    config: &DnspodConfig,
+    addr: IpAddr,
+    dry_run: bool,
+) -> Result<Updates, DnessError> {
+    let dnspod_client = DnspodClient {
+        base_url: config.base_url.trim_end_matches('/').to_string(),
+        domain: config.domain.clone(),
+        login_token: config.login_token.clone(),
+        records: config.records.iter().cloned().collect(),
+        client,
+        dry_run,
+    };
+
+    let records = dnspod_client.fetch_records(IpType::from(addr)).await?;
+    let missing = dnspod_client.log_missing_domains(&records) as i32;
+    let mut summary = Updates {
+        missing,
+        ..Updates::default()
+    };
+
+    for record in records {
+        if dnspod_client.records.contains(&record.name) {
+            summary += dnspod_client.ensure_current_ip(&record, addr).await?;
+        }
+    }
+
+    Ok(summary)
+}
+
+#[async_trait]
+impl DnsProvider for DnspodConfig {
+    async fn update(
+        &self,
+        ctx: &UpdateContext<'_>,
+        addr: IpAddr,
+    ) -> Result<Updates, Box<dyn std::error::Error>> {
+        retry(ctx.policy, ctx.name, || {
+            update_domains(ctx.client, self, addr, ctx.dry_run)
+        })
+        .await
+        .map_err(|e| e.into())
+    }
+
+    fn name(&self) -> &str {
+        "dnspod"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::retry::Retryable;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    #[test]
+    fn deserialize_dnspod_response() {
+        let json_str = &include_str!("../assets/dnspod-record-list.json");
+        let response: DnspodListResponse = serde_json::from_str(json_str).unwrap();
+        assert_eq!(response.status.code, "1");
+        assert_eq!(
+            response.records[0],
+            DnspodRecord {
+                id: String::from("16894439"),
+                name: String::from("@"),
+                r#type: String::from("A"),
+                value: String::from("2.2.2.2"),
+                ttl: String::from("600"),
+                line_id: String::from("0"),
+            }
+        );
+    }
+
+    #[test]
+    fn test_dnspod_check_status() {
+        let status = |code: &str| DnspodStatus {
+            code: String::from(code),
+            message: String::from("message"),
+        };
+
+        assert!(check_status(&status("1"), "ctx", "example.com").is_ok());
+
+        let err = check_status(&status("6"), "ctx", "example.com").unwrap_err();
+        assert!(err.to_string().contains("domain example.com not found"));
+        assert!(!err.is_retryable());
+
+        let err = check_status(&status("70"), "ctx", "example.com").unwrap_err();
+        assert!(err.to_string().contains("rate limited"));
+        assert!(err.is_retryable());
+
+        let err = check_status(&status("-1"), "ctx", "example.com").unwrap_err();
+        assert!(err.to_string().contains("ctx failed [-1]: message"));
+        assert!(!err.is_retryable());
+    }
+
+    fn dnspod_response(code: &str) -> rouille::Response {
+        let body = format!(r#"{{"status":{{"code":"{}","message":"msg"}}}}"#, code);
+        rouille::Response::from_data("application/json", body)
+    }
+
+    macro_rules! dnspod_rouille_server {
+        () => {{
+            use rouille::Response;
+            use rouille::Server;
+
+            let server = Server::new("localhost:0", |request| {
+                let body = rouille::input::post::raw_urlencoded_post_input(request).unwrap();
+                let param = |name: &str| {
+                    body.iter()
+                        .find(|(k, _)| k == name)
+                        .map(|(_, v)| v.clone())
+                        .unwrap_or_default()
+                };
+
+                if param("login_token") != "12345,my-token" {
+                    return dnspod_response("-1");
+                }
+
+                match request.url().as_str() {
+                    "/Record.List" => {
+                        match (param("domain").as_str(), param("record_type").as_str()) {
+                            ("example.com", "A") => Response::from_data(
+                                "application/json",
+                                include_bytes!("../assets/dnspod-record-list.json").to_vec(),
+                            ),
+                            ("example.com", _) => dnspod_response("10"),
+                            _ => dnspod_response("6"),
+                        }
+                    }
+                    "/Record.Modify" => {
+                        // The line and ttl of the record are preserved
+                        let expected = match param("record_id").as_str() {
+                            "16894439" => ("@", "600"),
+                            "16894440" => ("sub", "300"),
+                            _ => return dnspod_response("8"),
+                        };
+                        if (param("sub_domain").as_str(), param("ttl").as_str()) == expected
+                            && param("record_line_id") == "0"
+                            && param("value") == "2.2.2.1"
+                        {
+                            dnspod_response("1")
+                        } else {
+                            dnspod_response("2")
+                        }
+                    }
+                    _ => Response::empty_404(),
+                }
+            })
+            .unwrap();
+
+            let (tx, rx) = std::sync::mpsc::sync_channel(1);
+            let addr = server.server_addr().clone();
+            std::thread::spawn(move || {
+                while let Err(_) = rx.try_recv() {
+                    server.poll();
+                    std::thread::sleep(std::time::Duration::from_millis(50))
+                }
+            });
+            (tx, addr)
+        }};
+    }
+
+    fn config(addr: std::net::SocketAddr, records: Vec<String>) -> DnspodConfig {
+        DnspodConfig {
+            base_url: format!("http://{}", addr),
+            login_token: String::from("12345,my-token"),
+            domain: String::from("example.com"),
+            records,
+            ip_types: vec![IpType::V4],
+            request_timeout_secs: None,
+            log_level: None,
+            name: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dnspod_update() {
+        let (tx, addr) = dnspod_rouille_server!();
+        let http_client = reqwest::Client::new();
+        let new_ip = IpAddr::V4(Ipv4Addr::new(2, 2, 2, 1));
+        let config = config(addr, vec![String::from("@"), String::from("sub")]);
+
+        let summary = update_domains(&http_client, &config, new_ip, false)
+            .await
+            .unwrap();
+        tx.send(()).unwrap();
+
+        assert_eq!(
+            summary,
+            Updates {
+                updated: 2,
+                ..Updates::default()
+            }
+        )
+    }
+
+    #[tokio::test]
+    async fn test_dnspod_current_and_missing() {
+        let (tx, addr) = dnspod_rouille_server!();
+        let http_client = reqwest::Client::new();
+        let new_ip = IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2));
+        let config = config(
+            addr,
+            vec![String::from("@"), String::from("sub"), String::from("sub2")],
+        );
+
+        let summary = update_domains(&http_client, &config, new_ip, false)
+            .await
+            .unwrap();
+        tx.send(()).unwrap();
+
+        assert_eq!(
+            summary,
+            Updates {
+                current: 2,
+                missing: 1,
+                ..Updates::default()
+            }
+        )
+    }
+
+    #[tokio::test]
+    async fn test_dnspod_no_records() {
+        let (tx, addr) = dnspod_rouille_server!();
+        let http_client = reqwest::Client::new();
+        let new_ip = IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1));
+        let config = config(addr, vec![String::from("@")]);
+
+        let summary = update_domains(&http_client, &config, new_ip, false)
+            .await
+            .unwrap();
+        tx.send(()).unwrap();
+
+        assert_eq!(
+            summary,
+            Updates {
+                missing: 1,
+                ..Updates::default()
+            }
+        )
+    }
+
+    #[tokio::test]
+    async fn test_dnspod_domain_not_found() {
+        let (tx, addr) = dnspod_rouille_server!();
+        let http_client = reqwest::Client::new();
+        let new_ip = IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2));
+        let config = DnspodConfig {
+            domain: String::from("example.org"),
+            ..config(addr, vec![String::from("@")])
+        };
+
+        let result = update_domains(&http_client, &config, new_ip, false).await;
+        tx.send(()).unwrap();
+
+        let err = result.unwrap_err().to_string();
+        assert!(err.contains("domain example.org not found"));
+    }
+}
@@ -0,0 +1,119 @@
+use crate::core::SendTraced;
+use crate::errors::DnessError;
+use hickory_proto::op::{Message, MessageType, OpCode, Query};
+use hickory_proto::rr::{Name, RData, RecordType};
+use std::net::Ipv4Addr;
+
+/// OpenDNS's DNS over HTTPS endpoint. The endpoint must be OpenDNS as only their resolvers will
+/// answer "myip.opendns.com" with the address of the client that sent the query.
+pub const OPENDNS_DOH_URL: &str = "https://doh.opendns.com/dns-query";
+
+fn wan_query() -> Result<Vec<u8>, DnessError> {
+    let name = Name::from_ascii("myip.opendns.com.")
+        .map_err(|e| DnessError::message(format!("unable to construct doh query: {}", e)))?;
+
+    // RFC 8484 recommends an id of 0 so that responses are cache friendly
+    let mut msg = Message::new();
+    msg.set_id(0)
+        .set_message_type(MessageType::Query)
+        .set_op_code(OpCode::Query)
+        .set_recursion_desired(true)
+        .add_query(Query::query(name, RecordType::A));
+
+    msg.to_vec()
+        .map_err(|e| DnessError::message(format!("unable to encode doh query: {}", e)))
+}
+
+/// Resolves the WAN IP by sending a wire format dns query over https (RFC 8484), so that the IP
+/// can be resolved on networks where outbound port 53 is blocked.
+pub async fn doh_lookup_ip(client: &reqwest::Client, url: &str) -> Result<Ipv4Addr, DnessError> {
+    let body = client
+        .post(url)
+        .header("Accept", "application/dns-message")
+        .header("Content-Type", "application/dns-message")
+        .body(wan_query()?)
+        .send_traced()
+        .await
+        .map_err(|e| DnessError::send_http(url, "doh get ip", e))?
+        .error_for_status()
+        .map_err(|e| DnessError::bad_response(url, "doh get ip", e))?
+        .bytes()
+        .await
+        .map_err(|e| DnessError::deserialize(url, "doh get ip", e))?;
+
+    let response = Message::from_vec(&body)
+        .map_err(|e| DnessError::message(format!("unable to decode doh response: {}", e)))?;
+
+    response
+        .answers()
+        .iter()
+        .find_map(|record| match record.data() {
+            Some(RData::A(a)) => Some(a.0),
+            _ => None,
+        })
+        .ok_or_else(|| {
+            DnessError::message(format!(
+                "doh response contained no A records (response code: {})",
+                response.response_code()
+            ))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hickory_proto::rr::rdata::A;
+    use hickory_proto::rr::Record;
+    use std::io::Read;
+
+    macro_rules! doh_server {
+        () => {{
+            use rouille::Response;
+            use rouille::Server;
+
+            let server = Server::new("localhost:0", |request| {
+                if request.url() != "/dns-query" {
+                    return Response::empty_404();
+                }
+
+                let mut body = Vec::new();
+                request.data().unwrap().read_to_end(&mut body).unwrap();
+                let query = Message::from_vec(&body).unwrap();
+
+                let mut response = Message::new();
+                response
+                    .set_id(query.id())
+                    .set_message_type(MessageType::Response)
+                    .add_queries(query.queries().to_vec())
+                    .add_answer(Record::from_rdata(
+                        query.queries()[0].name().clone(),
+                        0,
+                        RData::A(A(Ipv4Addr::new(2, 2, 2, 2))),
+                    ));
+
+                Response::from_data("application/dns-message", response.to_vec().unwrap())
+            })
+            .unwrap();
+
+            let (tx, rx) = std::sync::mpsc::sync_channel(1);
+            let addr = server.server_addr().clone();
+            std::thread::spawn(move || {
+                while let Err(_) = rx.try_recv() {
+                    server.poll();
+                    std::thread::sleep(std::time::Duration::from_millis(50))
+                }
+            });
+            (tx, addr)
+        }};
+    }
+
+    #[tokio::test]
+    async fn test_doh_lookup_ip() {
+        let (tx, addr) = doh_server!();
+        let http_client = reqwest::Client::new();
+        let url = format!("http://{}/dns-query", addr);
+        let ip = doh_lookup_ip(&http_client, &url).await.unwrap();
+        tx.send(()).unwrap();
+        assert_eq!(ip, Ipv4Addr::new(2, 2, 2, 2));
+    }
+}
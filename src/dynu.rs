@@ -1,9 +1,14 @@
-use crate::config::DynuConfig;
-use crate::core::Updates;
+use crate::config::{DynuConfig, IpType};
+use crate::core::{
+    dry_run_prefix, parse_dyndns2_response, DnsProvider, SendTraced, UpdateContext, UpdateOutcome,
+    Updates,
+};
 use crate::dns::DnsResolver;
 use crate::errors::DnessError;
-use log::{info, warn};
-use std::net::Ipv4Addr;
+use crate::retry::retry;
+use async_trait::async_trait;
+use std::net::IpAddr;
+use tracing::{info, warn};
 
 #[derive(Debug)]
 pub struct DynuProvider<'a> {
@@ -12,12 +17,23 @@ pub struct DynuProvider<'a> {
 }
 
 impl<'a> DynuProvider<'a> {
-    pub async fn update_domain(&self, host: &str, wan: Ipv4Addr) -> Result<(), DnessError> {
+    /// Updates the address of the given type, where "no" tells dynu to leave the address of the
+    /// other type alone: https://www.dynu.com/DynamicDNS/IP-Update-Protocol
+    pub async fn update_domain(
+        &self,
+        host: &str,
+        wan: IpAddr,
+    ) -> Result<UpdateOutcome, DnessError> {
         let base = self.config.base_url.trim_end_matches('/').to_string();
         let get_url = format!("{}/nic/update", base);
+        let (myip, myipv6) = match wan {
+            IpAddr::V4(ip) => (ip.to_string(), String::from("no")),
+            IpAddr::V6(ip) => (String::from("no"), ip.to_string()),
+        };
         let mut params = vec![
             ("hostname", self.config.hostname.clone()),
-            ("myip", wan.to_string()),
+            ("myip", myip),
+            ("myipv6", myipv6),
         ];
 
         if host != "@" {
@@ -32,7 +48,7 @@ impl<'a> DynuProvider<'a> {
                 self.config.username.clone(),
                 Some(self.config.password.clone()),
             )
-            .send()
+            .send_traced()
             .await
             .map_err(|e| DnessError::send_http(&get_url, "dynu update", e))?
             .error_for_status()
@@ -41,47 +57,81 @@ impl<'a> DynuProvider<'a> {
             .await
             .map_err(|e| DnessError::deserialize(&get_url, "dynu update", e))?;
 
-        if !response.contains("nochg") && !response.contains("good") {
-            Err(DnessError::message(format!(
-                "expected zero errors, but received: {}",
-                response
-            )))
-        } else {
-            Ok(())
-        }
+        parse_dyndns2_response(&response)
     }
 }
 
+#[tracing::instrument(skip(client, config), fields(domain = %config.hostname))]
 pub async fn update_domains(
     client: &reqwest::Client,
     config: &DynuConfig,
-    wan: Ipv4Addr,
+    wan: IpAddr,
+    dry_run: bool,
+    force: bool,
 ) -> Result<Updates, DnessError> {
     let resolver = DnsResolver::create_cloudflare().await?;
     let dynu_provider = DynuProvider { client, config };
 
     let mut results = Updates::default();
 
-    for record in &config.records {
+    let ip_type = IpType::from(wan);
+    let records = config
+        .records
+        .iter()
+        .filter(|x| x.ip_types(&config.ip_types).contains(&ip_type))
+        .map(|x| &x.name);
+
+    for record in records {
         let dns_query = if record == "@" {
             format!("{}.", config.hostname)
         } else {
             format!("{}.{}.", record, config.hostname)
         };
 
-        let response = resolver.ipv4_lookup(&dns_query).await;
+        if force {
+            let outcome = if dry_run {
+                UpdateOutcome::Updated
+            } else {
+                dynu_provider.update_domain(record, wan).await?
+            };
+
+            if outcome == UpdateOutcome::Updated {
+                info!(
+                    "{}{} from domain {} forcibly updated to {}",
+                    dry_run_prefix(dry_run),
+                    record,
+                    config.hostname,
+                    wan
+                );
+            }
+            results.record(outcome);
+            continue;
+        }
+
+        let response = resolver.ip_lookup(&dns_query, ip_type).await;
 
         match response {
             Ok(ip) => {
                 if ip == wan {
                     results.current += 1;
                 } else {
-                    dynu_provider.update_domain(record, wan).await?;
-                    info!(
-                        "{} from domain {} updated from {} to {}",
-                        record, config.hostname, ip, wan
-                    );
-                    results.updated += 1;
+                    let outcome = if dry_run {
+                        UpdateOutcome::Updated
+                    } else {
+                        dynu_provider.update_domain(record, wan).await?
+                    };
+
+                    if outcome == UpdateOutcome::Updated {
+                        info!(
+                            "{}{} from domain {} updated from {} to {}",
+                            dry_run_prefix(dry_run),
+                            record,
+                            config.hostname,
+                            ip,
+                            wan
+                        );
+                    }
+                    results.record(outcome);
                 }
             }
             Err(e) => {
@@ -98,18 +148,49 @@ pub async fn update_domains(
     Ok(results)
 }
 
+#[async_trait]
+impl DnsProvider for DynuConfig {
+    async fn update(
+        &self,
+        ctx: &UpdateContext<'_>,
+        addr: IpAddr,
+    ) -> Result<Updates, Box<dyn std::error::Error>> {
+        retry(ctx.policy, ctx.name, || {
+            update_domains(ctx.client, self, addr, ctx.dry_run, ctx.force)
+        })
+        .await
+        .map_err(|e| e.into())
+    }
+
+    fn name(&self) -> &str {
+        "dynu"
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::DynuRecord;
+    use std::net::{Ipv4Addr, Ipv6Addr};
 
     macro_rules! dynu_server {
         () => {{
             use rouille::Response;
             use rouille::Server;
 
-            let server = Server::new("localhost:0", |request| match request.url().as_str() {
-                "/nic/update" => Response::from_data("text/plain", b"good 2.2.2.2".to_vec()),
-                _ => Response::empty_404(),
+            let server = Server::new("localhost:0", |request| {
+                // Only the address of a single type is sent, so that the other is left alone
+                let myip = request.get_param("myip").unwrap_or_default();
+                let myipv6 = request.get_param("myipv6").unwrap_or_default();
+                match (request.url().as_str(), myip.as_str(), myipv6.as_str()) {
+                    ("/nic/update", "2.2.2.2", "no") => {
+                        Response::from_data("text/plain", b"good 2.2.2.2".to_vec())
+                    }
+                    ("/nic/update", "no", "2001:db8::1") => {
+                        Response::from_data("text/plain", b"good 2001:db8::1".to_vec())
+                    }
+                    _ => Response::empty_404(),
+                }
             })
             .unwrap();
 
@@ -129,16 +210,22 @@ mod tests {
     async fn test_dynu_update() {
         let (tx, addr) = dynu_server!();
         let http_client = reqwest::Client::new();
-        let new_ip = Ipv4Addr::new(2, 2, 2, 2);
+        let new_ip = IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2));
         let config = DynuConfig {
             base_url: format!("http://{}", addr),
             hostname: String::from("example.com"),
             username: String::from("myusername"),
             password: String::from("secret-1"),
-            records: vec![String::from("@")],
+            records: vec![DynuRecord::from(String::from("@"))],
+            ip_types: vec![IpType::V4],
+            request_timeout_secs: None,
+            log_level: None,
+            name: None,
         };
 
-        let summary = update_domains(&http_client, &config, new_ip).await.unwrap();
+        let summary = update_domains(&http_client, &config, new_ip, false, false)
+            .await
+            .unwrap();
         tx.send(()).unwrap();
 
         assert_eq!(
@@ -147,7 +234,50 @@ mod tests {
                 current: 0,
                 updated: 1,
                 missing: 0,
+                failed: 0,
+                dry_run: false,
             }
         );
     }
+
+    #[tokio::test]
+    async fn test_dynu_record_ip_types() {
+        let (tx, addr) = dynu_server!();
+        let http_client = reqwest::Client::new();
+        let config = DynuConfig {
+            base_url: format!("http://{}", addr),
+            hostname: String::from("example.com"),
+            username: String::from("myusername"),
+            password: String::from("secret-1"),
+            records: vec![
+                DynuRecord::from(String::from("@")),
+                DynuRecord {
+                    name: String::from("v6"),
+                    ip_types: Some(vec![IpType::V6]),
+                },
+                DynuRecord {
+                    name: String::from("both"),
+                    ip_types: Some(vec![IpType::V4, IpType::V6]),
+                },
+            ],
+            ip_types: vec![IpType::V4],
+            request_timeout_secs: None,
+            log_level: None,
+            name: None,
+        };
+
+        let new_ip = IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2));
+        let summary = update_domains(&http_client, &config, new_ip, false, true)
+            .await
+            .unwrap();
+        assert_eq!(summary.updated, 2);
+
+        let new_ip = IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1));
+        let summary = update_domains(&http_client, &config, new_ip, false, true)
+            .await
+            .unwrap();
+        tx.send(()).unwrap();
+        assert_eq!(summary.updated, 2);
+        assert_eq!(config.record_ip_types(), vec![IpType::V4, IpType::V6]);
+    }
 }
@@ -1,6 +1,7 @@
+use crate::retry::Retryable;
+use hickory_resolver::error::ResolveError;
 use std::error;
 use std::fmt;
-use hickory_resolver::error::ResolveError;
 
 #[derive(Debug)]
 pub enum DnessErrorKind {
@@ -20,6 +21,7 @@ pub enum DnessErrorKind {
         source: reqwest::Error,
     },
     Message(String),
+    Transient(String),
     Dns {
         source: DnsError,
     },
@@ -66,6 +68,24 @@ impl DnessError {
             kind: DnessErrorKind::Message(msg),
         }
     }
+
+    /// An error reported by the remote server that is expected to resolve itself
+    pub fn transient(msg: String) -> DnessError {
+        DnessError {
+            kind: DnessErrorKind::Transient(msg),
+        }
+    }
+}
+
+impl Retryable for DnessError {
+    fn is_retryable(&self) -> bool {
+        // Only failures to send and transient server errors are retried as other errors, like a
+        // bad response, are unlikely to be fixed by another attempt
+        matches!(
+            self.kind,
+            DnessErrorKind::SendHttp { .. } | DnessErrorKind::Transient(_)
+        )
+    }
 }
 
 impl From<DnsError> for DnessError {
@@ -108,6 +128,7 @@ impl fmt::Display for DnessError {
             ),
             DnessErrorKind::Dns { .. } => write!(f, "dns lookup"),
             DnessErrorKind::Message(msg) => write!(f, "{}", msg),
+            DnessErrorKind::Transient(msg) => write!(f, "{}", msg),
         }
     }
 }
@@ -0,0 +1,545 @@
+use crate::config::{
+    bunny_base_url, ddnss_base_url, default_ip_types, default_verify_timeout_secs,
+    default_zone_cache_ttl_secs, dnsomatic_base_url, dnspod_base_url, dynu_base_url,
+    godaddy_base_url, he_base_url, infomaniak_base_url, inwx_base_url, ipv64_base_url,
+    linode_base_url, namecheap_api_base_url, namecheap_base_url, namecom_base_url, njalla_base_url,
+    noip_base_url, ovh_base_url, porkbun_base_url, regfish_base_url, route53_base_url,
+    strato_base_url, zoneedit_base_url, BunnyConfig, CloudflareConfig, CloudflareMultiConfig,
+    CloudflareZoneEntry, DdnssConfig, DnsOMaticConfig, DnspodConfig, DomainConfig, DynuConfig,
+    DynuRecord, GoDaddyConfig, HeConfig, InfomaniakConfig, InwxConfig, IpResolverConfig,
+    Ipv64Config, LinodeConfig, NameComConfig, NamecheapApiConfig, NamecheapConfig, NjallaConfig,
+    NoIpConfig, OvhConfig, PorkbunConfig, RegfishConfig, Route53Config, StratoConfig,
+    ZoneEditConfig, PROVIDER_TYPES,
+};
+use dialoguer::{Confirm, Input, Password, Select};
+use serde::Serialize;
+use std::error;
+
+const RESOLVERS: [&str; 5] = ["opendns", "ipify", "doh", "http", "interface"];
+
+/// The subset of the config that is asked for, where everything else is left to the defaults
+#[derive(Serialize, Clone, PartialEq, Debug)]
+struct GeneratedConfig {
+    ip_resolver: IpResolverConfig,
+    ip_resolver_url: Option<String>,
+    ip_resolver_interface: Option<String>,
+    domains: Vec<DomainConfig>,
+}
+
+fn input(prompt: &str) -> dialoguer::Result<String> {
+    Input::new().with_prompt(prompt).interact_text()
+}
+
+fn password(prompt: &str) -> dialoguer::Result<String> {
+    Password::new().with_prompt(prompt).interact()
+}
+
+fn records(prompt: &str) -> dialoguer::Result<Vec<String>> {
+    let records: String = Input::new()
+        .with_prompt(format!("{} (comma separated)", prompt))
+        .default(String::from("@"))
+        .interact_text()?;
+
+    Ok(records
+        .split(',')
+        .map(|x| x.trim())
+        .filter(|x| !x.is_empty())
+        .map(String::from)
+        .collect())
+}
+
+/// Asks for the next provider to add, where `None` denotes that no more are wanted
+fn ask_provider() -> dialoguer::Result<Option<&'static str>> {
+    let mut items = PROVIDER_TYPES.to_vec();
+    items.push("done");
+    let selection = Select::new()
+        .with_prompt("Add a domain from provider")
+        .items(&items)
+        .default(0)
+        .interact()?;
+    Ok(PROVIDER_TYPES.get(selection).copied())
+}
+
+fn prompt_cloudflare() -> dialoguer::Result<DomainConfig> {
+    Ok(DomainConfig::Cloudflare(CloudflareConfig {
+        email: None,
+        key: None,
+        token: Some(password("API token (with the \"Edit zone DNS\" template)")?),
+        zone: input("Zone (eg: example.com)")?,
+        records: records("A records")?,
+        request_timeout_secs: None,
+        create_missing: false,
+        ttl: None,
+        proxied: None,
+        use_batch_updates: true,
+        use_per_record_queries: true,
+        zone_cache_ttl_secs: default_zone_cache_ttl_secs(),
+        verify_after_update: false,
+        verify_timeout_secs: default_verify_timeout_secs(),
+        ip_types: default_ip_types(),
+        log_level: None,
+        name: None,
+    }))
+}
+
+fn prompt_cloudflaremulti() -> dialoguer::Result<DomainConfig> {
+    let token = password("API token (with the \"Edit zone DNS\" template for each zone)")?;
+    let mut zones = Vec::new();
+    loop {
+        zones.push(CloudflareZoneEntry {
+            name: input("Zone (eg: example.com)")?,
+            records: records("A records")?,
+            ip_types: default_ip_types(),
+        });
+
+        let another = Confirm::new()
+            .with_prompt("Add another zone?")
+            .default(false)
+            .interact()?;
+        if !another {
+            break;
+        }
+    }
+
+    Ok(DomainConfig::CloudflareMulti(CloudflareMultiConfig {
+        token,
+        zones,
+        request_timeout_secs: None,
+        log_level: None,
+        name: None,
+    }))
+}
+
+fn prompt_godaddy() -> dialoguer::Result<DomainConfig> {
+    Ok(DomainConfig::GoDaddy(GoDaddyConfig {
+        base_url: godaddy_base_url(),
+        key: input("API key")?,
+        secret: password("API secret")?,
+        domain: input("Domain (eg: example.com)")?,
+        records: records("A records")?,
+        request_timeout_secs: None,
+        ip_types: default_ip_types(),
+        create_missing: false,
+        log_level: None,
+        name: None,
+    }))
+}
+
+fn prompt_namecheap() -> dialoguer::Result<DomainConfig> {
+    Ok(DomainConfig::Namecheap(NamecheapConfig {
+        base_url: namecheap_base_url(),
+        domain: input("Domain (eg: example.com)")?,
+        ddns_password: password("Dynamic DNS password")?,
+        records: records("Records")?,
+        request_timeout_secs: None,
+        ip_types: default_ip_types(),
+        log_level: None,
+        name: None,
+    }))
+}
+
+fn prompt_namecheapapi() -> dialoguer::Result<DomainConfig> {
+    Ok(DomainConfig::NamecheapApi(NamecheapApiConfig {
+        base_url: namecheap_api_base_url(),
+        api_user: input("API user")?,
+        api_key: password("API key")?,
+        username: input("Username")?,
+        client_ip: input("Whitelisted client IP")?,
+        domain: input("Domain (eg: example.com)")?,
+        records: records("A records")?,
+        request_timeout_secs: None,
+        log_level: None,
+        name: None,
+    }))
+}
+
+fn prompt_he() -> dialoguer::Result<DomainConfig> {
+    Ok(DomainConfig::He(HeConfig {
+        base_url: he_base_url(),
+        hostname: input("Hostname (eg: example.com)")?,
+        password: password("Dynamic DNS key")?,
+        records: records("Records")?,
+        request_timeout_secs: None,
+        ip_types: default_ip_types(),
+        log_level: None,
+        name: None,
+    }))
+}
+
+fn prompt_noip() -> dialoguer::Result<DomainConfig> {
+    Ok(DomainConfig::NoIp(NoIpConfig {
+        base_url: noip_base_url(),
+        username: input("Username")?,
+        password: password("Password")?,
+        hostname: input("Hostname (eg: example.hopto.org)")?,
+        request_timeout_secs: None,
+        ip_types: default_ip_types(),
+        log_level: None,
+        name: None,
+    }))
+}
+
+fn prompt_dynu() -> dialoguer::Result<DomainConfig> {
+    Ok(DomainConfig::Dynu(DynuConfig {
+        base_url: dynu_base_url(),
+        hostname: input("Hostname (eg: example.com)")?,
+        username: input("Username")?,
+        password: password("IP update password")?,
+        records: records("Records")?
+            .into_iter()
+            .map(DynuRecord::from)
+            .collect(),
+        ip_types: default_ip_types(),
+        request_timeout_secs: None,
+        log_level: None,
+        name: None,
+    }))
+}
+
+fn prompt_porkbun() -> dialoguer::Result<DomainConfig> {
+    Ok(DomainConfig::Porkbun(PorkbunConfig {
+        base_url: porkbun_base_url(),
+        domain: input("Domain (eg: example.com)")?,
+        key: password("API key")?,
+        secret: password("Secret API key")?,
+        records: records("A records")?,
+        ip_types: default_ip_types(),
+        request_timeout_secs: None,
+        create_missing: false,
+        use_api_ip: false,
+        ttl: None,
+        log_level: None,
+        name: None,
+    }))
+}
+
+fn prompt_dnsomatic() -> dialoguer::Result<DomainConfig> {
+    Ok(DomainConfig::DnsOMatic(DnsOMaticConfig {
+        base_url: dnsomatic_base_url(),
+        username: input("Username")?,
+        password: password("Password")?,
+        hostnames: records("Hostnames")?,
+        request_timeout_secs: None,
+        log_level: None,
+        name: None,
+    }))
+}
+
+fn prompt_ovh() -> dialoguer::Result<DomainConfig> {
+    Ok(DomainConfig::Ovh(OvhConfig {
+        base_url: ovh_base_url(),
+        hostname: input("Hostname (eg: example.com)")?,
+        username: input("DynHost username")?,
+        password: password("DynHost password")?,
+        records: records("Records")?,
+        request_timeout_secs: None,
+        log_level: None,
+        name: None,
+    }))
+}
+
+fn prompt_route53() -> dialoguer::Result<DomainConfig> {
+    Ok(DomainConfig::Route53(Route53Config {
+        base_url: route53_base_url(),
+        hosted_zone_id: input("Hosted zone id")?,
+        records: input("Fully qualified records (comma separated)")?
+            .split(',')
+            .map(|x| x.trim())
+            .filter(|x| !x.is_empty())
+            .map(String::from)
+            .collect(),
+        ip_types: default_ip_types(),
+        region: None,
+        request_timeout_secs: None,
+        log_level: None,
+        name: None,
+    }))
+}
+
+fn prompt_linode() -> dialoguer::Result<DomainConfig> {
+    Ok(DomainConfig::Linode(LinodeConfig {
+        base_url: linode_base_url(),
+        token: password("Personal access token (with read/write domains access)")?,
+        domain: input("Domain (eg: example.com)")?,
+        records: records("Records")?,
+        ip_types: default_ip_types(),
+        request_timeout_secs: None,
+        log_level: None,
+        name: None,
+    }))
+}
+
+fn prompt_namecom() -> dialoguer::Result<DomainConfig> {
+    Ok(DomainConfig::NameCom(NameComConfig {
+        base_url: namecom_base_url(),
+        username: input("Username")?,
+        token: password("API token")?,
+        domain: input("Domain (eg: example.com)")?,
+        records: records("Records")?,
+        ip_types: default_ip_types(),
+        request_timeout_secs: None,
+        create_missing: false,
+        log_level: None,
+        name: None,
+    }))
+}
+
+fn prompt_inwx() -> dialoguer::Result<DomainConfig> {
+    Ok(DomainConfig::Inwx(InwxConfig {
+        base_url: inwx_base_url(),
+        username: input("DynDNS username")?,
+        password: password("DynDNS password")?,
+        hostname: input("Hostname (eg: example.com)")?,
+        records: records("Records")?,
+        ip_types: default_ip_types(),
+        request_timeout_secs: None,
+        log_level: None,
+        name: None,
+    }))
+}
+
+fn prompt_infomaniak() -> dialoguer::Result<DomainConfig> {
+    Ok(DomainConfig::Infomaniak(InfomaniakConfig {
+        base_url: infomaniak_base_url(),
+        username: input("Dynamic DNS username")?,
+        password: password("Dynamic DNS password")?,
+        hostname: input("Hostname (eg: example.com)")?,
+        records: records("Records")?,
+        ip_types: default_ip_types(),
+        request_timeout_secs: None,
+        log_level: None,
+        name: None,
+    }))
+}
+
+fn prompt_strato() -> dialoguer::Result<DomainConfig> {
+    Ok(DomainConfig::Strato(StratoConfig {
+        base_url: strato_base_url(),
+        domain: input("Domain (eg: example.com)")?,
+        password: password("DynDNS password")?,
+        records: records("Records")?,
+        ip_types: default_ip_types(),
+        request_timeout_secs: None,
+        log_level: None,
+        name: None,
+    }))
+}
+
+fn prompt_ddnss() -> dialoguer::Result<DomainConfig> {
+    Ok(DomainConfig::Ddnss(DdnssConfig {
+        base_url: ddnss_base_url(),
+        api_key: password("Update key")?,
+        hostname: input("Hostname (eg: example.ddnss.de)")?,
+        ip_types: default_ip_types(),
+        request_timeout_secs: None,
+        log_level: None,
+        name: None,
+    }))
+}
+
+fn prompt_bunny() -> dialoguer::Result<DomainConfig> {
+    Ok(DomainConfig::Bunny(BunnyConfig {
+        base_url: bunny_base_url(),
+        api_key: password("API key (found in the account settings)")?,
+        zone: input("Zone (eg: example.com)")?,
+        records: records("Records")?,
+        ip_types: default_ip_types(),
+        request_timeout_secs: None,
+        create_missing: false,
+        log_level: None,
+        name: None,
+    }))
+}
+
+fn prompt_zoneedit() -> dialoguer::Result<DomainConfig> {
+    Ok(DomainConfig::ZoneEdit(ZoneEditConfig {
+        base_url: zoneedit_base_url(),
+        hostname: input("Domain (eg: example.com)")?,
+        username: input("Username")?,
+        password: password("Dynamic authentication token")?,
+        records: records("Records")?,
+        ip_types: default_ip_types(),
+        request_timeout_secs: None,
+        log_level: None,
+        name: None,
+    }))
+}
+
+fn prompt_ipv64() -> dialoguer::Result<DomainConfig> {
+    Ok(DomainConfig::Ipv64(Ipv64Config {
+        base_url: ipv64_base_url(),
+        api_key: password("Update key")?,
+        domain: input("Domain (eg: example.ipv64.net)")?,
+        records: records("Records")?,
+        ip_types: default_ip_types(),
+        request_timeout_secs: None,
+        log_level: None,
+        name: None,
+    }))
+}
+
+fn prompt_dnspod() -> dialoguer::Result<DomainConfig> {
+    let id = input("API token ID")?;
+    let token = password("API token")?;
+    Ok(DomainConfig::Dnspod(DnspodConfig {
+        base_url: dnspod_base_url(),
+        login_token: format!("{},{}", id, token),
+        domain: input("Domain (eg: example.com)")?,
+        records: records("Records")?,
+        ip_types: default_ip_types(),
+        request_timeout_secs: None,
+        log_level: None,
+        name: None,
+    }))
+}
+
+fn prompt_njalla() -> dialoguer::Result<DomainConfig> {
+    Ok(DomainConfig::Njalla(NjallaConfig {
+        base_url: njalla_base_url(),
+        api_key: password("Dynamic record key")?,
+        hostname: input("Domain (eg: example.com)")?,
+        records: records("Records")?,
+        ip_types: default_ip_types(),
+        request_timeout_secs: None,
+        log_level: None,
+        name: None,
+    }))
+}
+
+fn prompt_regfish() -> dialoguer::Result<DomainConfig> {
+    Ok(DomainConfig::Regfish(RegfishConfig {
+        base_url: regfish_base_url(),
+        token: password("DynDNS token")?,
+        domain: input("Domain (eg: example.com)")?,
+        records: records("Records")?,
+        ip_types: default_ip_types(),
+        request_timeout_secs: None,
+        log_level: None,
+        name: None,
+    }))
+}
+
+fn prompt_provider(provider: &str) -> dialoguer::Result<DomainConfig> {
+    match provider {
+        "cloudflare" => prompt_cloudflare(),
+        "godaddy" => prompt_godaddy(),
+        "namecheap" => prompt_namecheap(),
+        "namecheapapi" => prompt_namecheapapi(),
+        "he" => prompt_he(),
+        "noip" => prompt_noip(),
+        "dynu" => prompt_dynu(),
+        "porkbun" => prompt_porkbun(),
+        "dnsomatic" => prompt_dnsomatic(),
+        "route53" => prompt_route53(),
+        "linode" => prompt_linode(),
+        "namecom" => prompt_namecom(),
+        "inwx" => prompt_inwx(),
+        "infomaniak" => prompt_infomaniak(),
+        "strato" => prompt_strato(),
+        "ddnss" => prompt_ddnss(),
+        "bunny" => prompt_bunny(),
+        "zoneedit" => prompt_zoneedit(),
+        "ipv64" => prompt_ipv64(),
+        "dnspod" => prompt_dnspod(),
+        "njalla" => prompt_njalla(),
+        "regfish" => prompt_regfish(),
+        "cloudflaremulti" => prompt_cloudflaremulti(),
+        "ovh" => prompt_ovh(),
+        _ => Err(dialoguer::Error::IO(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("unknown provider: {}", provider),
+        ))),
+    }
+}
+
+/// Asks a series of questions on the terminal to build a config, returned as TOML
+pub fn generate_config() -> Result<String, Box<dyn error::Error>> {
+    let selection = Select::new()
+        .with_prompt("WAN IP resolver")
+        .items(&RESOLVERS)
+        .default(0)
+        .interact()?;
+    let resolver = RESOLVERS[selection];
+
+    let mut config = GeneratedConfig {
+        ip_resolver: IpResolverConfig::Single(String::from(resolver)),
+        ip_resolver_url: None,
+        ip_resolver_interface: None,
+        domains: Vec::new(),
+    };
+
+    match resolver {
+        "http" => config.ip_resolver_url = Some(input("URL that responds with the WAN IP")?),
+        "interface" => config.ip_resolver_interface = Some(input("WAN network interface")?),
+        _ => {}
+    }
+
+    while let Some(provider) = ask_provider()? {
+        config.domains.push(prompt_provider(provider)?);
+    }
+
+    Ok(toml::to_string(&config)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::parse_configs;
+
+    #[test]
+    fn test_prompt_unknown_provider() {
+        let err = prompt_provider("dyndns").unwrap_err();
+        assert_eq!(err.to_string(), "IO error: unknown provider: dyndns");
+    }
+
+    #[test]
+    fn test_generated_config_round_trip() {
+        let config = GeneratedConfig {
+            ip_resolver: IpResolverConfig::Single(String::from("http")),
+            ip_resolver_url: Some(String::from("https://icanhazip.com")),
+            ip_resolver_interface: None,
+            domains: vec![
+                DomainConfig::Cloudflare(CloudflareConfig {
+                    email: None,
+                    key: None,
+                    token: Some(String::from("dec0de")),
+                    zone: String::from("example.com"),
+                    records: vec![String::from("@"), String::from("sub")],
+                    request_timeout_secs: None,
+                    create_missing: false,
+                    ttl: None,
+                    proxied: None,
+                    use_batch_updates: true,
+                    use_per_record_queries: true,
+                    zone_cache_ttl_secs: default_zone_cache_ttl_secs(),
+                    verify_after_update: false,
+                    verify_timeout_secs: default_verify_timeout_secs(),
+                    ip_types: default_ip_types(),
+                    log_level: None,
+                    name: None,
+                }),
+                DomainConfig::NoIp(NoIpConfig {
+                    base_url: noip_base_url(),
+                    username: String::from("me@example.com"),
+                    password: String::from("my-pass"),
+                    hostname: String::from("example.hopto.org"),
+                    request_timeout_secs: None,
+                    ip_types: default_ip_types(),
+                    log_level: None,
+                    name: None,
+                }),
+            ],
+        };
+
+        let path =
+            std::env::temp_dir().join(format!("dness-generated-{}.toml", std::process::id()));
+        std::fs::write(&path, toml::to_string(&config).unwrap()).unwrap();
+        let parsed = parse_configs(&[&path]);
+        std::fs::remove_file(&path).unwrap();
+
+        let parsed = parsed.unwrap();
+        assert_eq!(parsed.ip_resolver, config.ip_resolver);
+        assert_eq!(parsed.ip_resolver_url, config.ip_resolver_url);
+        assert_eq!(parsed.domains, config.domains);
+    }
+}
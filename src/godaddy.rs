@@ -1,12 +1,18 @@
-use crate::config::GoDaddyConfig;
-use crate::core::Updates;
+use crate::config::{GoDaddyConfig, IpType};
+use crate::core::{
+    dry_run_prefix, log_unmatched_patterns, split_record_patterns, DnsProvider, SendTraced,
+    UpdateContext, Updates,
+};
 use crate::errors::DnessError;
-use log::{debug, info, warn};
+use crate::retry::retry;
+use async_trait::async_trait;
+use glob::Pattern;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::BTreeMap as Map;
 use std::collections::HashSet;
-use std::net::Ipv4Addr;
+use std::net::IpAddr;
+use tracing::{debug, info, warn};
 
 #[derive(Deserialize, Serialize, PartialEq, Clone, Debug)]
 struct GoRecord {
@@ -17,6 +23,12 @@ struct GoRecord {
     other: Map<String, Value>,
 }
 
+#[derive(Serialize, PartialEq, Clone, Debug)]
+struct GoNewRecord {
+    data: String,
+    ttl: u32,
+}
+
 #[derive(Clone, Debug)]
 struct GoClient<'a> {
     base_url: String,
@@ -24,7 +36,9 @@ struct GoClient<'a> {
     key: String,
     secret: String,
     records: HashSet<String>,
+    patterns: Vec<Pattern>,
     client: &'a reqwest::Client,
+    dry_run: bool,
 }
 
 impl<'a> GoClient<'a> {
@@ -34,20 +48,47 @@ impl<'a> GoClient<'a> {
             .map(|x| &x.name)
             .cloned()
             .collect::<HashSet<String>>();
+        log_unmatched_patterns(&self.patterns, &actual, "GoDaddy", &self.domain);
         crate::core::log_missing_domains(&self.records, &actual, "GoDaddy", &self.domain)
     }
 
+    /// Whether the record is one of the configured records or matches one of the patterns
+    fn is_configured(&self, name: &str) -> bool {
+        self.records.contains(name) || self.patterns.iter().any(|x| x.matches(name))
+    }
+
+    // The expected records that aren't in GoDaddy, in a stable order
+    fn missing_records(&self, remote_domains: &[GoRecord]) -> Vec<String> {
+        let actual = remote_domains
+            .iter()
+            .map(|x| &x.name)
+            .collect::<HashSet<&String>>();
+        let mut missing = self
+            .records
+            .iter()
+            .filter(|x| !actual.contains(x))
+            .cloned()
+            .collect::<Vec<_>>();
+        missing.sort();
+        missing
+    }
+
     fn auth_header(&self) -> String {
         format!("sso-key {}:{}", self.key, self.secret)
     }
 
-    async fn fetch_records(&self) -> Result<Vec<GoRecord>, DnessError> {
-        let get_url = format!("{}/v1/domains/{}/records/A", self.base_url, self.domain);
+    async fn fetch_records(&self, ip_type: IpType) -> Result<Vec<GoRecord>, DnessError> {
+        let get_url = format!(
+            "{}/v1/domains/{}/records/{}",
+            self.base_url,
+            self.domain,
+            ip_type.record_type()
+        );
         let response = self
             .client
             .get(&get_url)
             .header("Authorization", self.auth_header())
-            .send()
+            .send_traced()
             .await
             .map_err(|e| DnessError::send_http(&get_url, "godaddy fetch records", e))?
             .error_for_status()
@@ -58,12 +99,19 @@ impl<'a> GoClient<'a> {
         Ok(response)
     }
 
-    async fn update_record(&self, record: &GoRecord, addr: Ipv4Addr) -> Result<(), DnessError> {
+    async fn update_record(&self, record: &GoRecord, addr: IpAddr) -> Result<(), DnessError> {
         let put_url = format!(
-            "{}/v1/domains/{}/records/A/{}",
-            self.base_url, self.domain, record.name
+            "{}/v1/domains/{}/records/{}/{}",
+            self.base_url,
+            self.domain,
+            IpType::from(addr).record_type(),
+            record.name
         );
 
+        if self.dry_run {
+            return Ok(());
+        }
+
         self.client
             .put(&put_url)
             .header("Authorization", self.auth_header())
@@ -71,7 +119,7 @@ impl<'a> GoClient<'a> {
                 data: addr.to_string(),
                 ..record.clone()
             }])
-            .send()
+            .send_traced()
             .await
             .map_err(|e| DnessError::send_http(&put_url, "godaddy update records", e))?
             .error_for_status()
@@ -80,22 +128,56 @@ impl<'a> GoClient<'a> {
         Ok(())
     }
 
+    // GoDaddy creates the record when replacing the records of a name that has none
+    async fn create_record(&self, name: &str, addr: IpAddr) -> Result<(), DnessError> {
+        let put_url = format!(
+            "{}/v1/domains/{}/records/{}/{}",
+            self.base_url,
+            self.domain,
+            IpType::from(addr).record_type(),
+            name
+        );
+
+        if self.dry_run {
+            return Ok(());
+        }
+
+        self.client
+            .put(&put_url)
+            .header("Authorization", self.auth_header())
+            .json(&vec![GoNewRecord {
+                data: addr.to_string(),
+                ttl: 600,
+            }])
+            .send_traced()
+            .await
+            .map_err(|e| DnessError::send_http(&put_url, "godaddy create record", e))?
+            .error_for_status()
+            .map_err(|e| DnessError::bad_response(&put_url, "godaddy create record", e))?;
+
+        Ok(())
+    }
+
     async fn ensure_current_ip(
         &self,
         record: &GoRecord,
-        addr: Ipv4Addr,
+        addr: IpAddr,
     ) -> Result<Updates, DnessError> {
         let mut current = 0;
         let mut updated = 0;
-        match record.data.parse::<Ipv4Addr>() {
+        match record.data.parse::<IpAddr>() {
             Ok(ip) => {
                 if ip != addr {
                     updated += 1;
                     self.update_record(record, addr).await?;
 
                     info!(
-                        "{} from domain {} updated from {} to {}",
-                        record.name, self.domain, record.data, addr
+                        "{}{} from domain {} updated from {} to {}",
+                        dry_run_prefix(self.dry_run),
+                        record.name,
+                        self.domain,
+                        record.data,
+                        addr
                     )
                 } else {
                     current += 1;
@@ -107,12 +189,16 @@ impl<'a> GoClient<'a> {
             }
             Err(ref e) => {
                 updated += 1;
-                warn!("could not parse domain {} address {} as ipv4 -- will replace it. Original error: {}", record.name, record.data, e);
+                warn!("could not parse domain {} address {} as {} -- will replace it. Original error: {}", record.name, record.data, IpType::from(addr), e);
                 self.update_record(record, addr).await?;
 
                 info!(
-                    "{} from domain {} updated from {} to {}",
-                    record.name, self.domain, record.data, addr
+                    "{}{} from domain {} updated from {} to {}",
+                    dry_run_prefix(self.dry_run),
+                    record.name,
+                    self.domain,
+                    record.data,
+                    addr
                 )
             }
         }
@@ -128,32 +214,54 @@ impl<'a> GoClient<'a> {
 /// GoDaddy dynamic dns service works as the following:
 ///
 /// 1. Send a GET request to find all records in the domain
-/// 2. Find all the expected records (and log those that are missing) and check their current IP
+/// 2. Find all the expected records (and log those that are missing) and check their current IP.
+///    Records can also be matched with glob patterns against the names that GoDaddy lists,
+///    which are relative to the domain (eg: "home-*")
 /// 3. Update the remote IP as needed, ensuring that original properties are preserved in the
 ///    upload, so that we don't overwrite a property like TTL.
+#[tracing::instrument(skip(client, config), fields(domain = %config.domain))]
 pub async fn update_domains(
     client: &reqwest::Client,
     config: &GoDaddyConfig,
-    addr: Ipv4Addr,
+    addr: IpAddr,
+    dry_run: bool,
 ) -> Result<Updates, DnessError> {
+    let (records, patterns) = split_record_patterns(&config.records);
     let go_client = GoClient {
         base_url: config.base_url.trim_end_matches('/').to_string(),
         domain: config.domain.clone(),
         key: config.key.clone(),
         secret: config.secret.clone(),
-        records: config.records.iter().cloned().collect(),
+        records,
+        patterns,
         client,
+        dry_run,
     };
 
-    let records = go_client.fetch_records().await?;
+    let records = go_client.fetch_records(IpType::from(addr)).await?;
     let missing = go_client.log_missing_domains(&records) as i32;
     let mut summary = Updates {
         missing,
         ..Updates::default()
     };
 
+    if config.create_missing {
+        for name in go_client.missing_records(&records) {
+            go_client.create_record(&name, addr).await?;
+            summary.missing -= 1;
+            summary.updated += 1;
+            info!(
+                "{}{} from domain {} created with {}",
+                dry_run_prefix(dry_run),
+                name,
+                config.domain,
+                addr
+            );
+        }
+    }
+
     for record in records {
-        if go_client.records.contains(&record.name) {
+        if go_client.is_configured(&record.name) {
             summary += go_client.ensure_current_ip(&record, addr).await?;
         }
     }
@@ -161,10 +269,30 @@ pub async fn update_domains(
     Ok(summary)
 }
 
+#[async_trait]
+impl DnsProvider for GoDaddyConfig {
+    async fn update(
+        &self,
+        ctx: &UpdateContext<'_>,
+        addr: IpAddr,
+    ) -> Result<Updates, Box<dyn std::error::Error>> {
+        retry(ctx.policy, ctx.name, || {
+            update_domains(ctx.client, self, addr, ctx.dry_run)
+        })
+        .await
+        .map_err(|e| e.into())
+    }
+
+    fn name(&self) -> &str {
+        "godaddy"
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use serde_json::json;
+    use std::net::{Ipv4Addr, Ipv6Addr};
 
     #[test]
     fn deserialize_go_records() {
@@ -220,6 +348,33 @@ mod tests {
                 ),
                 "/v1/domains/domain-2.com/records/A/@" => Response::text("Nice job!"),
                 "/v1/domains/domain-2.com/records/A/a" => Response::text("Nice job!"),
+                "/v1/domains/domain-3.com/records/A" => {
+                    Response::from_data("application/json", r#"[{"name": "@", "data": "1.1.1.1"}]"#)
+                }
+                "/v1/domains/domain-4.com/records/AAAA" => Response::from_data(
+                    "application/json",
+                    r#"[{"name": "@", "data": "2001:db8::1"}, {"name": "a", "data": "2001:db8::2"}]"#,
+                ),
+                "/v1/domains/domain-4.com/records/AAAA/a" => Response::text("Nice job!"),
+                "/v1/domains/domain-5.com/records/A" => {
+                    Response::from_data("application/json", r#"[{"name": "@", "data": "2.2.2.2"}]"#)
+                }
+                "/v1/domains/domain-5.com/records/A/b" if request.method() == "PUT" => {
+                    Response::text("Nice job!")
+                }
+                "/v1/domains/domain-6.com/records/AAAA" => {
+                    Response::from_data("application/json", "[]")
+                }
+                "/v1/domains/domain-6.com/records/AAAA/@" if request.method() == "PUT" => {
+                    let mut body = String::new();
+                    std::io::Read::read_to_string(&mut request.data().unwrap(), &mut body)
+                        .unwrap();
+                    if body == r#"[{"data":"2001:db8::1","ttl":600}]"# {
+                        Response::text("Nice job!")
+                    } else {
+                        Response::empty_400()
+                    }
+                }
                 _ => Response::empty_404(),
             })
             .unwrap();
@@ -240,16 +395,23 @@ mod tests {
     async fn test_godaddy_unparseable_ipv4() {
         let (tx, addr) = godaddy_rouille_server!();
         let http_client = reqwest::Client::new();
-        let new_ip = Ipv4Addr::new(2, 2, 2, 2);
+        let new_ip = IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2));
         let config = GoDaddyConfig {
             base_url: format!("http://{}", addr),
             domain: String::from("domain-1.com"),
             key: String::from("key-1"),
             secret: String::from("secret-1"),
             records: vec![String::from("@")],
+            request_timeout_secs: None,
+            ip_types: vec![IpType::V4],
+            create_missing: false,
+            log_level: None,
+            name: None,
         };
 
-        let summary = update_domains(&http_client, &config, new_ip).await.unwrap();
+        let summary = update_domains(&http_client, &config, new_ip, false)
+            .await
+            .unwrap();
         tx.send(()).unwrap();
 
         assert_eq!(
@@ -258,6 +420,8 @@ mod tests {
                 current: 0,
                 updated: 1,
                 missing: 0,
+                failed: 0,
+                dry_run: false,
             }
         );
     }
@@ -266,16 +430,23 @@ mod tests {
     async fn test_godaddy_grabbag() {
         let (tx, addr) = godaddy_rouille_server!();
         let http_client = reqwest::Client::new();
-        let new_ip = Ipv4Addr::new(2, 2, 2, 2);
+        let new_ip = IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2));
         let config = GoDaddyConfig {
             base_url: format!("http://{}", addr),
             domain: String::from("domain-2.com"),
             key: String::from("key-1"),
             secret: String::from("secret-1"),
             records: vec![String::from("@"), String::from("a"), String::from("b")],
+            request_timeout_secs: None,
+            ip_types: vec![IpType::V4],
+            create_missing: false,
+            log_level: None,
+            name: None,
         };
 
-        let summary = update_domains(&http_client, &config, new_ip).await.unwrap();
+        let summary = update_domains(&http_client, &config, new_ip, false)
+            .await
+            .unwrap();
         tx.send(()).unwrap();
 
         assert_eq!(
@@ -284,6 +455,183 @@ mod tests {
                 current: 1,
                 updated: 1,
                 missing: 1,
+                failed: 0,
+                dry_run: false,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_godaddy_pattern() {
+        let (tx, addr) = godaddy_rouille_server!();
+        let http_client = reqwest::Client::new();
+        let new_ip = IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2));
+        let config = GoDaddyConfig {
+            base_url: format!("http://{}", addr),
+            domain: String::from("domain-2.com"),
+            key: String::from("key-1"),
+            secret: String::from("secret-1"),
+            records: vec![String::from("*"), String::from("b*")],
+            request_timeout_secs: None,
+            ip_types: vec![IpType::V4],
+            create_missing: false,
+            log_level: None,
+            name: None,
+        };
+
+        // The unmatched pattern isn't a missing record
+        let summary = update_domains(&http_client, &config, new_ip, false)
+            .await
+            .unwrap();
+        tx.send(()).unwrap();
+
+        assert_eq!(
+            summary,
+            Updates {
+                current: 1,
+                updated: 1,
+                ..Updates::default()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_godaddy_dry_run() {
+        // domain-3 has no update endpoint, so the test fails if an update is sent
+        let (tx, addr) = godaddy_rouille_server!();
+        let http_client = reqwest::Client::new();
+        let new_ip = IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2));
+        let config = GoDaddyConfig {
+            base_url: format!("http://{}", addr),
+            domain: String::from("domain-3.com"),
+            key: String::from("key-1"),
+            secret: String::from("secret-1"),
+            records: vec![String::from("@")],
+            request_timeout_secs: None,
+            ip_types: vec![IpType::V4],
+            create_missing: false,
+            log_level: None,
+            name: None,
+        };
+
+        let summary = update_domains(&http_client, &config, new_ip, true)
+            .await
+            .unwrap();
+        tx.send(()).unwrap();
+
+        assert_eq!(
+            summary,
+            Updates {
+                current: 0,
+                updated: 1,
+                missing: 0,
+                failed: 0,
+                dry_run: false,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_godaddy_ipv6() {
+        let (tx, addr) = godaddy_rouille_server!();
+        let http_client = reqwest::Client::new();
+        let new_ip = IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1));
+        let config = GoDaddyConfig {
+            base_url: format!("http://{}", addr),
+            domain: String::from("domain-4.com"),
+            key: String::from("key-1"),
+            secret: String::from("secret-1"),
+            records: vec![String::from("@"), String::from("a")],
+            request_timeout_secs: None,
+            ip_types: vec![IpType::V6],
+            create_missing: false,
+            log_level: None,
+            name: None,
+        };
+
+        let summary = update_domains(&http_client, &config, new_ip, false)
+            .await
+            .unwrap();
+        tx.send(()).unwrap();
+
+        assert_eq!(
+            summary,
+            Updates {
+                current: 1,
+                updated: 1,
+                missing: 0,
+                failed: 0,
+                dry_run: false,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_godaddy_create_missing() {
+        let (tx, addr) = godaddy_rouille_server!();
+        let http_client = reqwest::Client::new();
+        let new_ip = IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2));
+        let config = GoDaddyConfig {
+            base_url: format!("http://{}", addr),
+            domain: String::from("domain-5.com"),
+            key: String::from("key-1"),
+            secret: String::from("secret-1"),
+            records: vec![String::from("@"), String::from("b")],
+            request_timeout_secs: None,
+            ip_types: vec![IpType::V4],
+            create_missing: true,
+            log_level: None,
+            name: None,
+        };
+
+        let summary = update_domains(&http_client, &config, new_ip, false)
+            .await
+            .unwrap();
+        tx.send(()).unwrap();
+
+        assert_eq!(
+            summary,
+            Updates {
+                current: 1,
+                updated: 1,
+                missing: 0,
+                failed: 0,
+                dry_run: false,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_godaddy_create_missing_ipv6() {
+        let (tx, addr) = godaddy_rouille_server!();
+        let http_client = reqwest::Client::new();
+        let new_ip = IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1));
+        let config = GoDaddyConfig {
+            base_url: format!("http://{}", addr),
+            domain: String::from("domain-6.com"),
+            key: String::from("key-1"),
+            secret: String::from("secret-1"),
+            records: vec![String::from("@")],
+            request_timeout_secs: None,
+            ip_types: vec![IpType::V6],
+            create_missing: true,
+            log_level: None,
+            name: None,
+        };
+
+        let summary = update_domains(&http_client, &config, new_ip, false)
+            .await
+            .unwrap();
+        tx.send(()).unwrap();
+
+        assert_eq!(
+            summary,
+            Updates {
+                current: 0,
+                updated: 1,
+                missing: 0,
+                failed: 0,
+                dry_run: false,
             }
         );
     }
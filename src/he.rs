@@ -1,18 +1,43 @@
-use crate::config::HeConfig;
-use crate::core::Updates;
+use crate::config::{HeConfig, IpType};
+use crate::core::{
+    dry_run_prefix, parse_dyndns2_response, DnsProvider, SendTraced, UpdateContext, UpdateOutcome,
+    Updates,
+};
 use crate::dns::DnsResolver;
 use crate::errors::DnessError;
-use log::{info, warn};
-use std::net::Ipv4Addr;
+use crate::retry::retry;
+use async_trait::async_trait;
+use std::error::Error;
+use std::net::IpAddr;
+use tracing::{debug, info, warn};
 
 #[derive(Debug)]
 pub struct HeProvider<'a> {
+    client: &'a reqwest::Client,
     config: &'a HeConfig,
 }
 
+/// Walks the error chain looking for the peer resetting the connection
+fn is_connection_reset(err: &reqwest::Error) -> bool {
+    let mut source = err.source();
+    while let Some(e) = source {
+        if let Some(io) = e.downcast_ref::<std::io::Error>() {
+            if io.kind() == std::io::ErrorKind::ConnectionReset {
+                return true;
+            }
+        }
+        source = e.source();
+    }
+    false
+}
+
 impl<'a> HeProvider<'a> {
     /// https://dns.he.net/docs.html
-    pub async fn update_domain(&self, host: &str, wan: Ipv4Addr) -> Result<(), DnessError> {
+    pub async fn update_domain(
+        &self,
+        host: &str,
+        wan: IpAddr,
+    ) -> Result<UpdateOutcome, DnessError> {
         let base = self.config.base_url.trim_end_matches('/').to_string();
         let url = format!("{}/nic/update", base);
         let params = [
@@ -21,14 +46,19 @@ impl<'a> HeProvider<'a> {
             ("myip", &wan.to_string()),
         ];
 
-        // annoyingly it looks like he closes the connection on every update
-        // so we have to allocate a new client for every request
-        let client = reqwest::Client::new();
-        let response = client
-            .post(&url)
-            .form(&params)
-            .send()
-            .await
+        // he has been known to close the connection after every update, so
+        // a pooled connection may be reset. The reset connection is evicted
+        // from the pool, so retrying with the same client (and its proxy and
+        // timeout settings) opens a new connection
+        let response = match self.client.post(&url).form(&params).send_traced().await {
+            Err(e) if is_connection_reset(&e) => {
+                debug!("he reset the connection, retrying with a new connection");
+                self.client.post(&url).form(&params).send_traced().await
+            }
+            response => response,
+        };
+
+        let response = response
             .map_err(|e| DnessError::send_http(&url, "he update", e))?
             .error_for_status()
             .map_err(|e| DnessError::bad_response(&url, "he update", e))?
@@ -36,26 +66,22 @@ impl<'a> HeProvider<'a> {
             .await
             .map_err(|e| DnessError::deserialize(&url, "he update", e))?;
 
-        if !response.contains("good") && !response.contains("nochg") {
-            Err(DnessError::message(format!(
-                "expected zero errors, but received: {}",
-                response
-            )))
-        } else {
-            Ok(())
-        }
+        parse_dyndns2_response(&response)
     }
 }
 
+#[tracing::instrument(skip(client, config), fields(domain = %config.hostname))]
 pub async fn update_domains(
-    _client: &reqwest::Client,
+    client: &reqwest::Client,
     config: &HeConfig,
-    wan: Ipv4Addr,
+    wan: IpAddr,
+    dry_run: bool,
+    force: bool,
 ) -> Result<Updates, DnessError> {
     // uses the same strategy as namecheap where we get the current records
     // via dns and check if they need to be updated
     let resolver = DnsResolver::create_cloudflare().await?;
-    let he = HeProvider { config };
+    let he = HeProvider { client, config };
 
     let mut results = Updates::default();
 
@@ -66,20 +92,51 @@ pub async fn update_domains(
             format!("{}.{}", record, &config.hostname)
         };
 
+        if force {
+            let outcome = if dry_run {
+                UpdateOutcome::Updated
+            } else {
+                he.update_domain(&host_record, wan).await?
+            };
+
+            if outcome == UpdateOutcome::Updated {
+                info!(
+                    "{}{} from domain {} forcibly updated to {}",
+                    dry_run_prefix(dry_run),
+                    record,
+                    config.hostname,
+                    wan
+                );
+            }
+            results.record(outcome);
+            continue;
+        }
+
         let dns_query = format!("{}.", &host_record);
-        let response = resolver.ipv4_lookup(&dns_query).await;
+        let response = resolver.ip_lookup(&dns_query, IpType::from(wan)).await;
 
         match response {
             Ok(ip) => {
                 if ip == wan {
                     results.current += 1;
                 } else {
-                    he.update_domain(&host_record, wan).await?;
-                    info!(
-                        "{} from domain {} updated from {} to {}",
-                        record, config.hostname, ip, wan
-                    );
-                    results.updated += 1;
+                    let outcome = if dry_run {
+                        UpdateOutcome::Updated
+                    } else {
+                        he.update_domain(&host_record, wan).await?
+                    };
+
+                    if outcome == UpdateOutcome::Updated {
+                        info!(
+                            "{}{} from domain {} updated from {} to {}",
+                            dry_run_prefix(dry_run),
+                            record,
+                            config.hostname,
+                            ip,
+                            wan
+                        );
+                    }
+                    results.record(outcome);
                 }
             }
             Err(e) => {
@@ -96,9 +153,29 @@ pub async fn update_domains(
     Ok(results)
 }
 
+#[async_trait]
+impl DnsProvider for HeConfig {
+    async fn update(
+        &self,
+        ctx: &UpdateContext<'_>,
+        addr: IpAddr,
+    ) -> Result<Updates, Box<dyn std::error::Error>> {
+        retry(ctx.policy, ctx.name, || {
+            update_domains(ctx.client, self, addr, ctx.dry_run, ctx.force)
+        })
+        .await
+        .map_err(|e| e.into())
+    }
+
+    fn name(&self) -> &str {
+        "he"
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::net::Ipv4Addr;
 
     macro_rules! he_server {
         () => {{
@@ -127,15 +204,21 @@ mod tests {
     async fn test_he_update() {
         let (tx, addr) = he_server!();
         let http_client = reqwest::Client::new();
-        let new_ip = Ipv4Addr::new(2, 2, 2, 2);
+        let new_ip = IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2));
         let config = HeConfig {
             base_url: format!("http://{}", addr),
             hostname: String::from("example.com"),
             password: String::from("secret-1"),
             records: vec![String::from("@")],
+            request_timeout_secs: None,
+            ip_types: vec![IpType::V4],
+            log_level: None,
+            name: None,
         };
 
-        let summary = update_domains(&http_client, &config, new_ip).await.unwrap();
+        let summary = update_domains(&http_client, &config, new_ip, false, false)
+            .await
+            .unwrap();
         tx.send(()).unwrap();
 
         assert_eq!(
@@ -144,6 +227,41 @@ mod tests {
                 current: 0,
                 updated: 1,
                 missing: 0,
+                failed: 0,
+                dry_run: false,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_he_force_update() {
+        let (tx, addr) = he_server!();
+        let http_client = reqwest::Client::new();
+        let new_ip = IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2));
+        let config = HeConfig {
+            base_url: format!("http://{}", addr),
+            hostname: String::from("example.com"),
+            password: String::from("secret-1"),
+            records: vec![String::from("@"), String::from("sub")],
+            request_timeout_secs: None,
+            ip_types: vec![IpType::V4],
+            log_level: None,
+            name: None,
+        };
+
+        let summary = update_domains(&http_client, &config, new_ip, false, true)
+            .await
+            .unwrap();
+        tx.send(()).unwrap();
+
+        assert_eq!(
+            summary,
+            Updates {
+                current: 0,
+                updated: 2,
+                missing: 0,
+                failed: 0,
+                dry_run: false,
             }
         );
     }
@@ -0,0 +1,162 @@
+use crate::state::temp_path;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io::{Error as IoError, ErrorKind};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+
+#[derive(Debug)]
+pub struct HistoryError {
+    kind: HistoryErrorKind,
+}
+
+#[derive(Debug)]
+pub enum HistoryErrorKind {
+    Read(IoError),
+    Serialize(serde_json::Error),
+    Write(IoError),
+}
+
+impl error::Error for HistoryError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self.kind {
+            HistoryErrorKind::Read(ref e) => Some(e),
+            HistoryErrorKind::Serialize(ref e) => Some(e),
+            HistoryErrorKind::Write(ref e) => Some(e),
+        }
+    }
+}
+
+impl fmt::Display for HistoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "history file issue: ")?;
+        match self.kind {
+            HistoryErrorKind::Read(ref _e) => write!(f, "unable to read file"),
+            HistoryErrorKind::Serialize(ref _e) => write!(f, "unable to serialize entry"),
+            HistoryErrorKind::Write(ref _e) => write!(f, "unable to write file"),
+        }
+    }
+}
+
+/// How many records of a domain were updated in a run
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct ProviderHistory {
+    pub name: String,
+    pub updated: i32,
+}
+
+/// A line in the history file that describes a single run
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct HistoryEntry {
+    pub ts: DateTime<Utc>,
+    pub ipv4: Ipv4Addr,
+    pub ipv6: Option<Ipv6Addr>,
+    pub updated: i32,
+    pub current: i32,
+    pub missing: i32,
+    pub providers: Vec<ProviderHistory>,
+}
+
+/// Appends the entry as a json line to the history file, pruning the oldest entries so that at
+/// most `max_entries` remain. The file is rewritten to a temporary file that is renamed into
+/// place, so a crash mid-write can't leave a truncated history behind.
+pub fn append_history<P: AsRef<Path>>(
+    path: P,
+    entry: &HistoryEntry,
+    max_entries: usize,
+) -> Result<(), HistoryError> {
+    let path = path.as_ref();
+    let existing = match fs::read_to_string(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+        Err(e) => {
+            return Err(HistoryError {
+                kind: HistoryErrorKind::Read(e),
+            })
+        }
+    };
+
+    let line = serde_json::to_string(entry).map_err(|e| HistoryError {
+        kind: HistoryErrorKind::Serialize(e),
+    })?;
+
+    let mut lines = existing
+        .lines()
+        .filter(|x| !x.trim().is_empty())
+        .collect::<Vec<_>>();
+    lines.push(&line);
+    let skip = lines.len().saturating_sub(max_entries);
+
+    let mut data = String::new();
+    for line in &lines[skip..] {
+        data.push_str(line);
+        data.push('\n');
+    }
+
+    let tmp = temp_path(path);
+    fs::write(&tmp, data)
+        .and_then(|_| fs::rename(&tmp, path))
+        .map_err(|e| HistoryError {
+            kind: HistoryErrorKind::Write(e),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn entry(updated: i32) -> HistoryEntry {
+        HistoryEntry {
+            ts: Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+            ipv4: Ipv4Addr::new(2, 2, 2, 2),
+            ipv6: None,
+            updated,
+            current: 1,
+            missing: 0,
+            providers: vec![ProviderHistory {
+                name: String::from("example.com (cloudflare)"),
+                updated,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_history_format() {
+        let line = serde_json::to_value(entry(1)).unwrap();
+        assert_eq!(
+            line,
+            serde_json::json!({
+                "ts": "2024-01-01T00:00:00Z",
+                "ipv4": "2.2.2.2",
+                "ipv6": null,
+                "updated": 1,
+                "current": 1,
+                "missing": 0,
+                "providers": [{"name": "example.com (cloudflare)", "updated": 1}]
+            })
+        );
+    }
+
+    #[test]
+    fn test_history_prune() {
+        let path = std::env::temp_dir().join(format!("dness-history-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        for updated in 0..5 {
+            append_history(&path, &entry(updated), 3).unwrap();
+        }
+
+        let data = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let entries = data
+            .lines()
+            .map(|x| serde_json::from_str::<HistoryEntry>(x).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(entries, vec![entry(2), entry(3), entry(4)]);
+    }
+}
@@ -0,0 +1,184 @@
+use reqwest::header::HeaderMap;
+use reqwest::ResponseBuilderExt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::{debug, Level};
+
+/// Set from the `log_http` option of the log config
+static LOG_HTTP: AtomicBool = AtomicBool::new(false);
+
+/// How much of a response body is logged
+const BODY_PREVIEW_CHARS: usize = 500;
+
+/// Headers that carry credentials for one of the providers
+const SENSITIVE_HEADERS: [&str; 7] = [
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "x-auth-key",
+    "accesskey",
+    "x-amz-security-token",
+    "x-ovh-signature",
+];
+
+/// Query parameters that carry credentials for one of the providers
+const SENSITIVE_PARAMS: [&str; 7] = ["key", "k", "token", "password", "pass", "secret", "apikey"];
+
+const REDACTED: &str = "[REDACTED]";
+
+pub fn enable(enabled: bool) {
+    LOG_HTTP.store(enabled, Ordering::Relaxed);
+}
+
+/// Requests are only logged when asked for and debug logs are enabled, as reading the response
+/// body up front isn't free
+pub fn is_enabled() -> bool {
+    LOG_HTTP.load(Ordering::Relaxed) && tracing::enabled!(Level::DEBUG)
+}
+
+fn redact_url(url: &reqwest::Url) -> reqwest::Url {
+    let mut result = url.clone();
+    if url.query().is_none() {
+        return result;
+    }
+
+    let pairs = url.query_pairs().map(|(name, value)| {
+        if SENSITIVE_PARAMS.contains(&name.to_ascii_lowercase().as_str()) {
+            (name, REDACTED.into())
+        } else {
+            (name, value)
+        }
+    });
+    result.query_pairs_mut().clear().extend_pairs(pairs);
+    result
+}
+
+fn redact_headers(headers: &HeaderMap) -> String {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if SENSITIVE_HEADERS.contains(&name.as_str()) {
+                REDACTED
+            } else {
+                value.to_str().unwrap_or("[binary]")
+            };
+            format!("{}: {}", name, value)
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The start of the body, where json is pretty printed
+fn body_preview(body: &[u8], is_json: bool) -> String {
+    let text = Some(body)
+        .filter(|_| is_json)
+        .and_then(|x| serde_json::from_slice::<serde_json::Value>(x).ok())
+        .and_then(|x| serde_json::to_string_pretty(&x).ok())
+        .unwrap_or_else(|| String::from_utf8_lossy(body).into_owned());
+
+    match text.char_indices().nth(BODY_PREVIEW_CHARS) {
+        Some((end, _)) => format!("{}... ({} bytes total)", &text[..end], body.len()),
+        None => text,
+    }
+}
+
+pub fn log_request(request: &reqwest::Request) {
+    debug!("→ {} {}", request.method(), redact_url(request.url()));
+    if !request.headers().is_empty() {
+        debug!("→ headers: {}", redact_headers(request.headers()));
+    }
+}
+
+/// Logs the response, which requires reading the body, so the response is rebuilt from its
+/// parts afterwards
+pub async fn log_response(response: reqwest::Response) -> reqwest::Result<reqwest::Response> {
+    let url = response.url().clone();
+    let status = response.status();
+    let version = response.version();
+    let headers = response.headers().clone();
+    let body = response.bytes().await?;
+
+    let is_json = headers
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|x| x.to_str().ok())
+        .is_some_and(|x| x.contains("json"));
+    debug!("← {} body: {}", status, body_preview(&body, is_json));
+
+    let mut builder = http::Response::builder()
+        .status(status)
+        .version(version)
+        .url(url);
+    if let Some(builder_headers) = builder.headers_mut() {
+        *builder_headers = headers;
+    }
+
+    let response = builder
+        .body(body)
+        .expect("response parts to be valid as they are from a response");
+    Ok(reqwest::Response::from(response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn test_redact_url() {
+        let url = reqwest::Url::parse(
+            "https://ipv64.net/nic/update?key=dec0de&domain=example.com&ip=2.2.2.2",
+        )
+        .unwrap();
+        assert_eq!(
+            redact_url(&url).as_str(),
+            "https://ipv64.net/nic/update?key=%5BREDACTED%5D&domain=example.com&ip=2.2.2.2"
+        );
+
+        let url = reqwest::Url::parse("https://api.bunny.net/dnszone").unwrap();
+        assert_eq!(redact_url(&url), url);
+    }
+
+    #[test]
+    fn test_redact_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("Authorization", HeaderValue::from_static("Bearer dec0de"));
+        headers.insert("AccessKey", HeaderValue::from_static("dec0de"));
+        headers.insert("Accept", HeaderValue::from_static("application/json"));
+        assert_eq!(
+            redact_headers(&headers),
+            "authorization: [REDACTED], accesskey: [REDACTED], accept: application/json"
+        );
+    }
+
+    #[test]
+    fn test_body_preview() {
+        assert_eq!(
+            body_preview(br#"{"status":"ok"}"#, true),
+            "{\n  \"status\": \"ok\"\n}"
+        );
+        assert_eq!(body_preview(b"good 2.2.2.2", false), "good 2.2.2.2");
+
+        let long = "a".repeat(600);
+        let preview = body_preview(long.as_bytes(), false);
+        assert_eq!(
+            preview,
+            format!("{}... (600 bytes total)", "a".repeat(BODY_PREVIEW_CHARS))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_log_response_keeps_response() {
+        let response = http::Response::builder()
+            .status(404)
+            .url(reqwest::Url::parse("https://example.com/missing").unwrap())
+            .header("Content-Type", "application/json")
+            .body(r#"{"error":"not found"}"#)
+            .unwrap();
+
+        let response = log_response(reqwest::Response::from(response))
+            .await
+            .unwrap();
+        assert_eq!(response.url().as_str(), "https://example.com/missing");
+        assert_eq!(response.status(), 404);
+        assert_eq!(response.text().await.unwrap(), r#"{"error":"not found"}"#);
+    }
+}
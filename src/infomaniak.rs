@@ -0,0 +1,182 @@
+use crate::config::InfomaniakConfig;
+use crate::core::{
+    parse_dyndns2_response, DnsCheckedRecords, DnsProvider, SendTraced, UpdateContext,
+    UpdateOutcome, Updates,
+};
+use crate::errors::DnessError;
+use crate::retry::retry;
+use async_trait::async_trait;
+use std::net::IpAddr;
+
+#[derive(Debug)]
+pub struct InfomaniakProvider<'a> {
+    client: &'a reqwest::Client,
+    config: &'a InfomaniakConfig,
+}
+
+impl<'a> InfomaniakProvider<'a> {
+    /// https://www.infomaniak.com/en/support/faq/2376
+    pub async fn update_domain(
+        &self,
+        host: &str,
+        wan: IpAddr,
+    ) -> Result<UpdateOutcome, DnessError> {
+        let base = self.config.base_url.trim_end_matches('/').to_string();
+        let get_url = format!("{}/nic/update", base);
+        let response = self
+            .client
+            .get(&get_url)
+            .query(&[("hostname", host), ("myip", &wan.to_string())])
+            .basic_auth(&self.config.username, Some(&self.config.password))
+            .send_traced()
+            .await
+            .map_err(|e| DnessError::send_http(&get_url, "infomaniak update", e))?
+            .error_for_status()
+            .map_err(|e| DnessError::bad_response(&get_url, "infomaniak update", e))?
+            .text()
+            .await
+            .map_err(|e| DnessError::deserialize(&get_url, "infomaniak update", e))?;
+
+        parse_dyndns2_response(&response)
+    }
+}
+
+#[tracing::instrument(skip(client, config), fields(domain = %config.hostname))]
+pub async fn update_domains(
+    client: &reqwest::Client,
+    config: &InfomaniakConfig,
+    wan: IpAddr,
+    dry_run: bool,
+    force: bool,
+) -> Result<Updates, DnessError> {
+    // uses the same strategy as namecheap where we get the current records
+    // via dns and check if they need to be updated. Infomaniak expects the
+    // fully qualified name of each record as the hostname
+    let infomaniak = &InfomaniakProvider { client, config };
+    DnsCheckedRecords {
+        provider: "infomaniak",
+        domain: &config.hostname,
+        records: &config.records,
+        dry_run,
+        force,
+    }
+    .update(wan, |host| async move {
+        infomaniak.update_domain(&host, wan).await
+    })
+    .await
+}
+
+#[async_trait]
+impl DnsProvider for InfomaniakConfig {
+    async fn update(
+        &self,
+        ctx: &UpdateContext<'_>,
+        addr: IpAddr,
+    ) -> Result<Updates, Box<dyn std::error::Error>> {
+        retry(ctx.policy, ctx.name, || {
+            update_domains(ctx.client, self, addr, ctx.dry_run, ctx.force)
+        })
+        .await
+        .map_err(|e| e.into())
+    }
+
+    fn name(&self) -> &str {
+        "infomaniak"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::IpType;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    macro_rules! infomaniak_server {
+        () => {{
+            use rouille::Response;
+            use rouille::Server;
+
+            let server = Server::new("localhost:0", |request| {
+                if request.url() != "/nic/update" {
+                    return Response::empty_404();
+                }
+
+                // "Basic ZG5lc3M6bXktcGFzcw==" is the basic auth of dness:my-pass
+                if request.header("Authorization") != Some("Basic ZG5lc3M6bXktcGFzcw==") {
+                    return Response::text("badauth");
+                }
+
+                // the hostname is the fully qualified name of the record and both address types
+                // are given in the same parameter
+                let hostname = request.get_param("hostname");
+                let myip = request.get_param("myip");
+                match (hostname.as_deref(), myip.as_deref()) {
+                    (Some("example.com"), Some("2.2.2.2"))
+                    | (Some("sub.example.com"), Some("2.2.2.2")) => Response::text("good 2.2.2.2"),
+                    (Some("example.com"), Some("2001:db8::1")) => {
+                        Response::text("good 2001:db8::1")
+                    }
+                    _ => Response::text("notfqdn"),
+                }
+            })
+            .unwrap();
+
+            let (tx, rx) = std::sync::mpsc::sync_channel(1);
+            let addr = server.server_addr().clone();
+            std::thread::spawn(move || {
+                while let Err(_) = rx.try_recv() {
+                    server.poll();
+                    std::thread::sleep(std::time::Duration::from_millis(50))
+                }
+            });
+            (tx, addr)
+        }};
+    }
+
+    #[tokio::test]
+    async fn test_infomaniak_update_fqdn() {
+        let (tx, addr) = infomaniak_server!();
+        let http_client = reqwest::Client::new();
+        let config = InfomaniakConfig {
+            base_url: format!("http://{}", addr),
+            username: String::from("dness"),
+            password: String::from("my-pass"),
+            hostname: String::from("example.com"),
+            records: vec![String::from("@"), String::from("sub")],
+            ip_types: vec![IpType::V4, IpType::V6],
+            request_timeout_secs: None,
+            log_level: None,
+            name: None,
+        };
+
+        let v4 = IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2));
+        let summary = update_domains(&http_client, &config, v4, false, true)
+            .await
+            .unwrap();
+
+        let v6 = IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1));
+        let config = InfomaniakConfig {
+            records: vec![String::from("@")],
+            ..config
+        };
+        let summary6 = update_domains(&http_client, &config, v6, false, true)
+            .await
+            .unwrap();
+        tx.send(()).unwrap();
+
+        assert_eq!(
+            summary,
+            Updates {
+                updated: 2,
+                ..Updates::default()
+            }
+        );
+        assert_eq!(
+            summary6,
+            Updates {
+                updated: 1,
+                ..Updates::default()
+            }
+        );
+    }
+}
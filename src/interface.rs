@@ -0,0 +1,105 @@
+use crate::config::IpType;
+use crate::errors::DnessError;
+use std::net::IpAddr;
+
+/// Whether the address could be the WAN IP of the interface, which excludes loopback and
+/// link-local addresses
+fn is_qualifying(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => !ip.is_loopback() && !ip.is_link_local(),
+        IpAddr::V6(ip) => !ip.is_loopback() && !ip.is_unicast_link_local(),
+    }
+}
+
+fn select_ip<'a, I>(interface: &str, ip_type: IpType, addrs: I) -> Result<IpAddr, DnessError>
+where
+    I: IntoIterator<Item = (&'a str, IpAddr)>,
+{
+    let mut found = false;
+    for (name, ip) in addrs {
+        if name != interface {
+            continue;
+        }
+
+        found = true;
+        let matches_type = match ip_type {
+            IpType::V4 => ip.is_ipv4(),
+            IpType::V6 => ip.is_ipv6(),
+        };
+
+        if matches_type && is_qualifying(&ip) {
+            return Ok(ip);
+        }
+    }
+
+    let reason = if found {
+        format!("it has no non-loopback, non-link-local {} address", ip_type)
+    } else {
+        String::from("it was not found")
+    };
+
+    Err(DnessError::message(format!(
+        "unable to resolve ip from interface {}: {}",
+        interface, reason
+    )))
+}
+
+/// Returns the first address of the requested type found on the named local network interface.
+/// Useful when dness runs on the router, as the address of the WAN interface is the WAN IP.
+pub fn interface_ip(interface: &str, ip_type: IpType) -> Result<IpAddr, DnessError> {
+    let interfaces = if_addrs::get_if_addrs().map_err(|e| {
+        DnessError::message(format!(
+            "unable to list network interfaces to find {}: {}",
+            interface, e
+        ))
+    })?;
+
+    select_ip(
+        interface,
+        ip_type,
+        interfaces.iter().map(|x| (x.name.as_str(), x.ip())),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addrs() -> Vec<(&'static str, IpAddr)> {
+        vec![
+            ("lo", "127.0.0.1".parse().unwrap()),
+            ("eth0", "169.254.10.1".parse().unwrap()),
+            ("eth0", "fe80::1".parse().unwrap()),
+            ("eth0", "2.2.2.2".parse().unwrap()),
+            ("eth0", "2001:db8::2".parse().unwrap()),
+            ("eth1", "fe80::2".parse().unwrap()),
+        ]
+    }
+
+    #[test]
+    fn test_select_interface_ip() {
+        assert_eq!(
+            select_ip("eth0", IpType::V4, addrs()).unwrap(),
+            "2.2.2.2".parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(
+            select_ip("eth0", IpType::V6, addrs()).unwrap(),
+            "2001:db8::2".parse::<IpAddr>().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_select_interface_ip_errors() {
+        let err = select_ip("wan", IpType::V4, addrs()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unable to resolve ip from interface wan: it was not found"
+        );
+
+        let err = select_ip("eth1", IpType::V6, addrs()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unable to resolve ip from interface eth1: it has no non-loopback, non-link-local ipv6 address"
+        );
+    }
+}
@@ -0,0 +1,93 @@
+use crate::config::InwxConfig;
+use crate::core::{
+    parse_dyndns2_response, DnsCheckedRecords, DnsProvider, SendTraced, UpdateContext,
+    UpdateOutcome, Updates,
+};
+use crate::errors::DnessError;
+use crate::retry::retry;
+use async_trait::async_trait;
+use std::net::IpAddr;
+
+#[derive(Debug)]
+pub struct InwxProvider<'a> {
+    client: &'a reqwest::Client,
+    config: &'a InwxConfig,
+}
+
+impl<'a> InwxProvider<'a> {
+    /// https://kb.inwx.com/en-us/3-nameserver/131
+    pub async fn update_domain(
+        &self,
+        host: &str,
+        wan: IpAddr,
+    ) -> Result<UpdateOutcome, DnessError> {
+        let base = self.config.base_url.trim_end_matches('/').to_string();
+        let get_url = format!("{}/nic/update", base);
+
+        // ipv6 addresses are given in their own parameter
+        let ip_param = match wan {
+            IpAddr::V4(_) => "myip",
+            IpAddr::V6(_) => "myipv6",
+        };
+
+        let response = self
+            .client
+            .get(&get_url)
+            .query(&[("hostname", host), (ip_param, &wan.to_string())])
+            .basic_auth(&self.config.username, Some(&self.config.password))
+            .send_traced()
+            .await
+            .map_err(|e| DnessError::send_http(&get_url, "inwx update", e))?
+            .error_for_status()
+            .map_err(|e| DnessError::bad_response(&get_url, "inwx update", e))?
+            .text()
+            .await
+            .map_err(|e| DnessError::deserialize(&get_url, "inwx update", e))?;
+
+        parse_dyndns2_response(&response)
+    }
+}
+
+#[tracing::instrument(skip(client, config), fields(domain = %config.hostname))]
+pub async fn update_domains(
+    client: &reqwest::Client,
+    config: &InwxConfig,
+    wan: IpAddr,
+    dry_run: bool,
+    force: bool,
+) -> Result<Updates, DnessError> {
+    // uses the same strategy as namecheap where we get the current records
+    // via dns and check if they need to be updated
+    let inwx = &InwxProvider { client, config };
+    DnsCheckedRecords {
+        provider: "inwx",
+        domain: &config.hostname,
+        records: &config.records,
+        dry_run,
+        force,
+    }
+    .update(
+        wan,
+        |host| async move { inwx.update_domain(&host, wan).await },
+    )
+    .await
+}
+
+#[async_trait]
+impl DnsProvider for InwxConfig {
+    async fn update(
+        &self,
+        ctx: &UpdateContext<'_>,
+        addr: IpAddr,
+    ) -> Result<Updates, Box<dyn std::error::Error>> {
+        retry(ctx.policy, ctx.name, || {
+            update_domains(ctx.client, self, addr, ctx.dry_run, ctx.force)
+        })
+        .await
+        .map_err(|e| e.into())
+    }
+
+    fn name(&self) -> &str {
+        "inwx"
+    }
+}
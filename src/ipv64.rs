@@ -0,0 +1,96 @@
+use crate::config::Ipv64Config;
+use crate::core::{
+    parse_dyndns2_response, DnsCheckedRecords, DnsProvider, SendTraced, UpdateContext,
+    UpdateOutcome, Updates,
+};
+use crate::errors::DnessError;
+use crate::retry::retry;
+use async_trait::async_trait;
+use std::net::IpAddr;
+
+#[derive(Debug)]
+pub struct Ipv64Provider<'a> {
+    client: &'a reqwest::Client,
+    config: &'a Ipv64Config,
+}
+
+impl<'a> Ipv64Provider<'a> {
+    /// https://ipv64.net/dyndns_updater_api
+    pub async fn update_domain(
+        &self,
+        host: &str,
+        wan: IpAddr,
+    ) -> Result<UpdateOutcome, DnessError> {
+        let base = self.config.base_url.trim_end_matches('/').to_string();
+        let get_url = format!("{}/nic/update", base);
+
+        // ipv6 addresses are given in their own parameter
+        let ip_param = match wan {
+            IpAddr::V4(_) => "ip",
+            IpAddr::V6(_) => "ip6",
+        };
+
+        let response = self
+            .client
+            .get(&get_url)
+            .query(&[
+                ("key", self.config.api_key.as_str()),
+                ("domain", host),
+                (ip_param, &wan.to_string()),
+            ])
+            .send_traced()
+            .await
+            .map_err(|e| DnessError::send_http(&get_url, "ipv64 update", e))?
+            .error_for_status()
+            .map_err(|e| DnessError::bad_response(&get_url, "ipv64 update", e))?
+            .text()
+            .await
+            .map_err(|e| DnessError::deserialize(&get_url, "ipv64 update", e))?;
+
+        parse_dyndns2_response(&response)
+    }
+}
+
+#[tracing::instrument(skip(client, config), fields(domain = %config.domain))]
+pub async fn update_domains(
+    client: &reqwest::Client,
+    config: &Ipv64Config,
+    wan: IpAddr,
+    dry_run: bool,
+    force: bool,
+) -> Result<Updates, DnessError> {
+    // uses the same strategy as namecheap where we get the current records
+    // via dns and check if they need to be updated
+    let ipv64 = &Ipv64Provider { client, config };
+    DnsCheckedRecords {
+        provider: "ipv64",
+        domain: &config.domain,
+        records: &config.records,
+        dry_run,
+        force,
+    }
+    .update(
+        wan,
+        |host| async move { ipv64.update_domain(&host, wan).await },
+    )
+    .await
+}
+
+#[async_trait]
+impl DnsProvider for Ipv64Config {
+    async fn update(
+        &self,
+        ctx: &UpdateContext<'_>,
+        addr: IpAddr,
+    ) -> Result<Updates, Box<dyn std::error::Error>> {
+        retry(ctx.policy, ctx.name, || {
+            update_domains(ctx.client, self, addr, ctx.dry_run, ctx.force)
+        })
+        .await
+        .map_err(|e| e.into())
+    }
+
+    fn name(&self) -> &str {
+        "ipv64"
+    }
+}
@@ -0,0 +1,402 @@
+use crate::config::{IpType, LinodeConfig};
+use crate::core::{dry_run_prefix, DnsProvider, SendTraced, UpdateContext, Updates};
+use crate::errors::DnessError;
+use crate::retry::retry;
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::net::IpAddr;
+use tracing::{debug, info};
+
+/// The envelope of every paginated list in the linode api
+#[derive(Deserialize, PartialEq, Clone, Debug)]
+struct LinodeResponse<T> {
+    data: Vec<T>,
+    page: i32,
+    pages: i32,
+    results: i32,
+}
+
+#[derive(Deserialize, PartialEq, Clone, Debug)]
+struct LinodeDomain {
+    id: i64,
+    domain: String,
+}
+
+#[derive(Deserialize, PartialEq, Clone, Debug)]
+struct LinodeRecord {
+    id: i64,
+    name: String,
+    r#type: String,
+    target: String,
+}
+
+#[derive(Serialize, PartialEq, Clone, Debug)]
+struct LinodeUpdateRecord<'a> {
+    target: &'a str,
+}
+
+// Linode denotes the root of the domain with an empty name
+fn record_name(name: &str) -> &str {
+    if name == "@" {
+        ""
+    } else {
+        name
+    }
+}
+
+#[derive(Debug)]
+struct LinodeClient<'a> {
+    base_url: &'a str,
+    config: &'a LinodeConfig,
+    client: &'a reqwest::Client,
+    dry_run: bool,
+}
+
+impl<'a> LinodeClient<'a> {
+    /// Collects the data of every page of a list endpoint
+    async fn get_all<T: DeserializeOwned>(
+        &self,
+        url: &str,
+        filter: Option<&str>,
+        context: &str,
+    ) -> Result<Vec<T>, DnessError> {
+        let mut results = Vec::new();
+        let mut page = 1;
+        loop {
+            let mut request = self
+                .client
+                .get(url)
+                .bearer_auth(&self.config.token)
+                .query(&[("page", page)]);
+
+            if let Some(filter) = filter {
+                request = request.header("X-Filter", filter);
+            }
+
+            let response: LinodeResponse<T> = request
+                .send_traced()
+                .await
+                .map_err(|e| DnessError::send_http(url, context, e))?
+                .error_for_status()
+                .map_err(|e| DnessError::bad_response(url, context, e))?
+                .json()
+                .await
+                .map_err(|e| DnessError::deserialize(url, context, e))?;
+
+            debug!(
+                "{}: page {} of {} ({} results)",
+                context, response.page, response.pages, response.results
+            );
+
+            results.extend(response.data);
+            if response.page >= response.pages {
+                break;
+            }
+            page = response.page + 1;
+        }
+
+        Ok(results)
+    }
+
+    /// https://techdocs.akamai.com/linode-api/reference/get-domains
+    async fn domain_id(&self) -> Result<i64, DnessError> {
+        let url = format!("{}/v4/domains", self.base_url);
+        let domains: Vec<LinodeDomain> = self.get_all(&url, None, "linode get domains").await?;
+        domains
+            .iter()
+            .find(|x| x.domain.eq_ignore_ascii_case(&self.config.domain))
+            .map(|x| x.id)
+            .ok_or_else(|| {
+                DnessError::message(format!(
+                    "unable to find linode domain {}",
+                    self.config.domain
+                ))
+            })
+    }
+
+    /// https://techdocs.akamai.com/linode-api/reference/get-domain-records
+    async fn records(
+        &self,
+        domain_id: i64,
+        ip_type: IpType,
+    ) -> Result<Vec<LinodeRecord>, DnessError> {
+        let url = format!("{}/v4/domains/{}/records", self.base_url, domain_id);
+        let filter = format!("{{\"type\":\"{}\"}}", ip_type.record_type());
+        let records: Vec<LinodeRecord> = self
+            .get_all(&url, Some(&filter), "linode get records")
+            .await?;
+
+        // The filter is only a hint to the api, so records of other types are ignored
+        Ok(records
+            .into_iter()
+            .filter(|x| x.r#type == ip_type.record_type())
+            .collect())
+    }
+
+    /// https://techdocs.akamai.com/linode-api/reference/put-domain-record
+    async fn update_record(
+        &self,
+        domain_id: i64,
+        record: &LinodeRecord,
+        addr: IpAddr,
+    ) -> Result<(), DnessError> {
+        if self.dry_run {
+            return Ok(());
+        }
+
+        let url = format!(
+            "{}/v4/domains/{}/records/{}",
+            self.base_url, domain_id, record.id
+        );
+        self.client
+            .put(&url)
+            .bearer_auth(&self.config.token)
+            .json(&LinodeUpdateRecord {
+                target: &addr.to_string(),
+            })
+            .send_traced()
+            .await
+            .map_err(|e| DnessError::send_http(&url, "linode update record", e))?
+            .error_for_status()
+            .map_err(|e| DnessError::bad_response(&url, "linode update record", e))?;
+
+        Ok(())
+    }
+}
+
+/// Linode dns is updated as follows:
+///
+/// 1. Page through the account's domains to find the id of the configured domain
+/// 2. Page through the domain's records of the address type
+/// 3. Find all the expected records (and log those that are missing) and check their target
+/// 4. Update the target of the records that are out of date
+#[tracing::instrument(skip(client, config), fields(domain = %config.domain))]
+pub async fn update_domains(
+    client: &reqwest::Client,
+    config: &LinodeConfig,
+    addr: IpAddr,
+    dry_run: bool,
+) -> Result<Updates, DnessError> {
+    let linode = LinodeClient {
+        base_url: config.base_url.trim_end_matches('/'),
+        config,
+        client,
+        dry_run,
+    };
+
+    let domain_id = linode.domain_id().await?;
+    let records = linode.records(domain_id, IpType::from(addr)).await?;
+
+    let expected = config
+        .records
+        .iter()
+        .map(|x| String::from(record_name(x)))
+        .collect::<HashSet<_>>();
+    let actual = records
+        .iter()
+        .map(|x| x.name.clone())
+        .collect::<HashSet<_>>();
+    let missing = crate::core::log_missing_domains(&expected, &actual, "Linode", &config.domain);
+
+    let mut summary = Updates {
+        missing: missing as i32,
+        ..Updates::default()
+    };
+
+    for record in records.iter().filter(|x| expected.contains(&x.name)) {
+        if record.target.parse::<IpAddr>().ok() == Some(addr) {
+            summary.current += 1;
+            debug!(
+                "{} from domain {} is already current",
+                record.name, config.domain
+            );
+        } else {
+            linode.update_record(domain_id, record, addr).await?;
+            summary.updated += 1;
+            info!(
+                "{}{} from domain {} updated from {} to {}",
+                dry_run_prefix(dry_run),
+                record.name,
+                config.domain,
+                record.target,
+                addr
+            );
+        }
+    }
+
+    Ok(summary)
+}
+
+#[async_trait]
+impl DnsProvider for LinodeConfig {
+    async fn update(
+        &self,
+        ctx: &UpdateContext<'_>,
+        addr: IpAddr,
+    ) -> Result<Updates, Box<dyn std::error::Error>> {
+        retry(ctx.policy, ctx.name, || {
+            update_domains(ctx.client, self, addr, ctx.dry_run)
+        })
+        .await
+        .map_err(|e| e.into())
+    }
+
+    fn name(&self) -> &str {
+        "linode"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    macro_rules! linode_server {
+        () => {{
+            use rouille::Response;
+            use rouille::Server;
+
+            let server = Server::new("localhost:0", |request| {
+                if request.header("Authorization") != Some("Bearer deadbeef") {
+                    return Response::text("unauthorized").with_status_code(401);
+                }
+
+                let page = request.get_param("page");
+                let filter = request.header("X-Filter").unwrap_or_default();
+                match (request.method(), request.url().as_str(), page.as_deref()) {
+                    ("GET", "/v4/domains", Some("1")) => Response::from_data(
+                        "application/json",
+                        include_bytes!("../assets/linode-domains-page-1.json").to_vec(),
+                    ),
+                    ("GET", "/v4/domains", Some("2")) => Response::from_data(
+                        "application/json",
+                        include_bytes!("../assets/linode-domains-page-2.json").to_vec(),
+                    ),
+                    ("GET", "/v4/domains/1002/records", Some("1"))
+                        if filter.contains("\"AAAA\"") =>
+                    {
+                        Response::from_data(
+                            "application/json",
+                            r#"{"data": [{"id": 3, "name": "", "type": "AAAA", "target": "2001:db8::1"}], "page": 1, "pages": 1, "results": 1}"#,
+                        )
+                    }
+                    ("GET", "/v4/domains/1002/records", Some("1")) => Response::from_data(
+                        "application/json",
+                        include_bytes!("../assets/linode-records.json").to_vec(),
+                    ),
+                    ("PUT", "/v4/domains/1002/records/1", _) => {
+                        Response::from_data("application/json", "{}")
+                    }
+                    _ => Response::empty_404(),
+                }
+            })
+            .unwrap();
+
+            let (tx, rx) = std::sync::mpsc::sync_channel(1);
+            let addr = server.server_addr().clone();
+            std::thread::spawn(move || {
+                while let Err(_) = rx.try_recv() {
+                    server.poll();
+                    std::thread::sleep(std::time::Duration::from_millis(50))
+                }
+            });
+            (tx, addr)
+        }};
+    }
+
+    fn config(addr: std::net::SocketAddr, domain: &str, records: &[&str]) -> LinodeConfig {
+        LinodeConfig {
+            base_url: format!("http://{}", addr),
+            token: String::from("deadbeef"),
+            domain: String::from(domain),
+            records: records.iter().map(|x| String::from(*x)).collect(),
+            ip_types: vec![IpType::V4, IpType::V6],
+            request_timeout_secs: None,
+            log_level: None,
+            name: None,
+        }
+    }
+
+    #[test]
+    fn deserialize_linode_response() {
+        let json_str = &include_str!("../assets/linode-domains-page-1.json");
+        let response: LinodeResponse<LinodeDomain> = serde_json::from_str(json_str).unwrap();
+        assert_eq!(
+            response,
+            LinodeResponse {
+                data: vec![LinodeDomain {
+                    id: 1001,
+                    domain: String::from("example.org"),
+                }],
+                page: 1,
+                pages: 2,
+                results: 2,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_linode_update() {
+        let (tx, addr) = linode_server!();
+        let http_client = reqwest::Client::new();
+        let new_ip = IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2));
+        let config = config(addr, "example.com", &["@", "sub", "www"]);
+
+        let summary = update_domains(&http_client, &config, new_ip, false)
+            .await
+            .unwrap();
+        tx.send(()).unwrap();
+
+        assert_eq!(
+            summary,
+            Updates {
+                current: 1,
+                updated: 1,
+                missing: 1,
+                failed: 0,
+                dry_run: false,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_linode_ipv6() {
+        let (tx, addr) = linode_server!();
+        let http_client = reqwest::Client::new();
+        let new_ip = IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1));
+        let config = config(addr, "example.com", &["@"]);
+
+        let summary = update_domains(&http_client, &config, new_ip, false)
+            .await
+            .unwrap();
+        tx.send(()).unwrap();
+
+        assert_eq!(
+            summary,
+            Updates {
+                current: 1,
+                updated: 0,
+                missing: 0,
+                failed: 0,
+                dry_run: false,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_linode_unknown_domain() {
+        let (tx, addr) = linode_server!();
+        let http_client = reqwest::Client::new();
+        let new_ip = IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2));
+        let config = config(addr, "example.net", &["@"]);
+
+        let err = update_domains(&http_client, &config, new_ip, false)
+            .await
+            .unwrap_err();
+        tx.send(()).unwrap();
+
+        assert_eq!(err.to_string(), "unable to find linode domain example.net");
+    }
+}
@@ -0,0 +1,437 @@
+use crate::config::{DomainConfig, LogFormat, LogTarget};
+use log::LevelFilter;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::IsTerminal;
+use std::sync::{Arc, Mutex};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Metadata, Subscriber};
+use tracing_subscriber::filter::{EnvFilter, LevelFilter as TracingLevelFilter};
+use tracing_subscriber::fmt::format::{JsonFields, Writer};
+use tracing_subscriber::fmt::writer::{BoxMakeWriter, MakeWriter};
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields, FormattedFields};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{Layer, Registry};
+
+/// Splits the fields of an event into its message and the remaining fields
+#[derive(Default)]
+struct JsonVisitor {
+    message: String,
+    fields: serde_json::Map<String, serde_json::Value>,
+}
+
+impl JsonVisitor {
+    fn insert(&mut self, field: &Field, value: serde_json::Value) {
+        self.fields.insert(String::from(field.name()), value);
+    }
+}
+
+impl Visit for JsonVisitor {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field, value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{:?}", value);
+        } else {
+            self.insert(field, format!("{:?}", value).into());
+        }
+    }
+}
+
+/// Formats each event as a json line of `{"level","ts","msg","fields","spans"}`
+struct JsonFormat;
+
+impl<S, N> FormatEvent<S, N> for JsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let mut visitor = JsonVisitor::default();
+        event.record(&mut visitor);
+
+        // The span fields were already formatted as json by the JsonFields formatter
+        let mut spans = Vec::new();
+        if let Some(scope) = ctx.event_scope() {
+            for span in scope.from_root() {
+                let extensions = span.extensions();
+                let fields = extensions
+                    .get::<FormattedFields<N>>()
+                    .and_then(|x| serde_json::from_str::<serde_json::Value>(x).ok())
+                    .unwrap_or_else(|| serde_json::json!({}));
+                spans.push(serde_json::json!({ "name": span.name(), "fields": fields }));
+            }
+        }
+
+        let line = serde_json::json!({
+            "level": event.metadata().level().as_str().to_ascii_lowercase(),
+            "ts": chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            "msg": visitor.message,
+            "fields": visitor.fields,
+            "spans": spans,
+        });
+        writeln!(writer, "{}", line)
+    }
+}
+
+type SyslogLogger = syslog::Logger<syslog::LoggerBackend, syslog::Formatter3164>;
+
+/// Sends each formatted event to the local syslog daemon at the severity of the event's level
+#[derive(Clone)]
+struct SyslogMakeWriter(Arc<Mutex<SyslogLogger>>);
+
+impl SyslogMakeWriter {
+    fn connect() -> Result<SyslogMakeWriter, syslog::Error> {
+        let formatter = syslog::Formatter3164 {
+            facility: syslog::Facility::LOG_DAEMON,
+            hostname: None,
+            process: String::from("dness"),
+            pid: std::process::id(),
+        };
+        let logger = syslog::unix(formatter)?;
+        Ok(SyslogMakeWriter(Arc::new(Mutex::new(logger))))
+    }
+
+    fn writer(&self, level: Level) -> SyslogWriter {
+        SyslogWriter {
+            logger: self.0.clone(),
+            level,
+            buf: Vec::new(),
+        }
+    }
+}
+
+impl<'a> MakeWriter<'a> for SyslogMakeWriter {
+    type Writer = SyslogWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.writer(Level::INFO)
+    }
+
+    fn make_writer_for(&'a self, meta: &Metadata<'_>) -> Self::Writer {
+        self.writer(*meta.level())
+    }
+}
+
+/// An event is formatted with several writes, so it is sent as a single message once the
+/// writer is dropped
+struct SyslogWriter {
+    logger: Arc<Mutex<SyslogLogger>>,
+    level: Level,
+    buf: Vec<u8>,
+}
+
+impl std::io::Write for SyslogWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for SyslogWriter {
+    fn drop(&mut self) {
+        let msg = String::from_utf8_lossy(&self.buf);
+        let msg = msg.trim_end();
+        if msg.is_empty() {
+            return;
+        }
+
+        if let Ok(mut logger) = self.logger.lock() {
+            let result = match self.level {
+                Level::ERROR => logger.err(msg),
+                Level::WARN => logger.warning(msg),
+                Level::INFO => logger.info(msg),
+                _ => logger.debug(msg),
+            };
+
+            if let Err(e) = result {
+                eprintln!("could not write to syslog: {}", e);
+            }
+        }
+    }
+}
+
+fn to_tracing_level(lvl: LevelFilter) -> TracingLevelFilter {
+    match lvl {
+        LevelFilter::Off => TracingLevelFilter::OFF,
+        LevelFilter::Error => TracingLevelFilter::ERROR,
+        LevelFilter::Warn => TracingLevelFilter::WARN,
+        LevelFilter::Info => TracingLevelFilter::INFO,
+        LevelFilter::Debug => TracingLevelFilter::DEBUG,
+        LevelFilter::Trace => TracingLevelFilter::TRACE,
+    }
+}
+
+fn fmt_layer(format: LogFormat, target: LogTarget) -> Box<dyn Layer<Registry> + Send + Sync> {
+    let (writer, is_terminal) = match target {
+        LogTarget::Stdout => (
+            BoxMakeWriter::new(std::io::stdout),
+            std::io::stdout().is_terminal(),
+        ),
+        LogTarget::Stderr => (
+            BoxMakeWriter::new(std::io::stderr),
+            std::io::stderr().is_terminal(),
+        ),
+        LogTarget::Syslog => match SyslogMakeWriter::connect() {
+            Ok(writer) => (BoxMakeWriter::new(writer), false),
+            Err(e) => {
+                eprintln!(
+                    "could not connect to syslog, logging to stderr instead: {}",
+                    e
+                );
+                (BoxMakeWriter::new(std::io::stderr), false)
+            }
+        },
+    };
+
+    match format {
+        // Timestamps are left out as mechanisms like systemd / journald will show the timestamp
+        // that received the log line at.
+        LogFormat::Text => tracing_subscriber::fmt::layer()
+            .without_time()
+            .with_ansi(is_terminal)
+            .with_writer(writer)
+            .boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .fmt_fields(JsonFields::new())
+            .event_format(JsonFormat)
+            .with_writer(writer)
+            .boxed(),
+    }
+}
+
+/// The log levels of the provider modules that override the global level. When domains of the
+/// same provider disagree, the most verbose level wins.
+pub fn module_levels(domains: &[DomainConfig]) -> BTreeMap<&'static str, LevelFilter> {
+    let mut result = BTreeMap::new();
+    for domain in domains {
+        if let Some(level) = domain.log_level() {
+            let entry = result.entry(domain.module_path()).or_insert(level);
+            *entry = (*entry).max(level);
+        }
+    }
+    result
+}
+
+/// Installs the global subscriber. The level can be overridden with the RUST_LOG environment
+/// variable, and log records from dependencies are forwarded to the subscriber. Events from the
+/// given modules are filtered at their own level instead.
+pub fn init_logging(
+    lvl: LevelFilter,
+    modules: &BTreeMap<&str, LevelFilter>,
+    format: LogFormat,
+    target: LogTarget,
+) {
+    let mut filter = EnvFilter::builder()
+        .with_default_directive(to_tracing_level(lvl).into())
+        .from_env_lossy();
+
+    for (module, level) in modules {
+        match format!("{}={}", module, to_tracing_level(*level)).parse() {
+            Ok(directive) => filter = filter.add_directive(directive),
+            Err(e) => eprintln!("could not set the log level of {}: {}", module, e),
+        }
+    }
+
+    let registry = tracing_subscriber::registry()
+        .with(fmt_layer(format, target))
+        .with(filter);
+
+    #[cfg(feature = "telemetry")]
+    let registry = registry.with(telemetry::layer());
+
+    registry.init();
+}
+
+/// Flushes any telemetry that has yet to be exported
+pub fn shutdown() {
+    #[cfg(feature = "telemetry")]
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+#[cfg(feature = "telemetry")]
+mod telemetry {
+    use opentelemetry::trace::TracerProvider as _;
+    use tracing::Subscriber;
+    use tracing_subscriber::registry::LookupSpan;
+    use tracing_subscriber::Layer;
+
+    /// Exports spans over OTLP to the collector configured by the standard OTEL_* environment
+    /// variables, like OTEL_EXPORTER_OTLP_ENDPOINT
+    pub fn layer<S>() -> Option<impl Layer<S>>
+    where
+        S: Subscriber + for<'span> LookupSpan<'span>,
+    {
+        let exporter = match opentelemetry_otlp::SpanExporter::builder()
+            .with_http()
+            .build()
+        {
+            Ok(exporter) => exporter,
+            Err(e) => {
+                eprintln!("could not create the OTLP exporter: {}", e);
+                return None;
+            }
+        };
+
+        let provider = opentelemetry_sdk::trace::TracerProvider::builder()
+            .with_batch_exporter(exporter, opentelemetry_sdk::runtime::Tokio)
+            .build();
+        let tracer = provider.tracer(env!("CARGO_PKG_NAME"));
+        opentelemetry::global::set_tracer_provider(provider);
+        Some(tracing_opentelemetry::layer().with_tracer(tracer))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing::info;
+    use tracing_subscriber::fmt::MakeWriter;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for Buffer {
+        type Writer = Buffer;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    #[test]
+    fn test_module_levels() {
+        let toml_str = r#"
+            [[domains]]
+            type = "cloudflare"
+            token = "dec0de"
+            zone = "example.com"
+            records = ["n.example.com"]
+            log_level = "debug"
+
+            [[domains]]
+            type = "cloudflare"
+            token = "dec0de"
+            zone = "example.org"
+            records = ["n.example.org"]
+            log_level = "trace"
+
+            [[domains]]
+            type = "godaddy"
+            key = "key"
+            secret = "secret"
+            domain = "example.com"
+            records = ["@"]
+        "#;
+        let config: crate::config::DnsConfig = toml::from_str(toml_str).unwrap();
+
+        let mut expected = BTreeMap::new();
+        expected.insert("dness::cloudflare", LevelFilter::Trace);
+        assert_eq!(module_levels(&config.domains), expected);
+    }
+
+    #[test]
+    fn test_json_format() {
+        let buffer = Buffer::default();
+        let subscriber = tracing_subscriber::registry().with(
+            tracing_subscriber::fmt::layer()
+                .fmt_fields(JsonFields::new())
+                .event_format(JsonFormat)
+                .with_writer(buffer.clone()),
+        );
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("update_domains", domain = "example.com");
+            let _guard = span.enter();
+            info!(total_updated = 2, elapsed_ms = 15, "processed all");
+        });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let line: serde_json::Value = serde_json::from_str(output.trim()).unwrap();
+        assert_eq!(line["level"], "info");
+        assert_eq!(line["msg"], "processed all");
+        assert_eq!(
+            line["fields"],
+            serde_json::json!({"total_updated": 2, "elapsed_ms": 15})
+        );
+        assert_eq!(
+            line["spans"],
+            serde_json::json!([{"name": "update_domains", "fields": {"domain": "example.com"}}])
+        );
+        assert!(line["ts"].as_str().is_some());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_syslog_writer() {
+        let dir = std::env::temp_dir().join(format!("dness-syslog-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("log.sock");
+        let _ = std::fs::remove_file(&path);
+        let socket = std::os::unix::net::UnixDatagram::bind(&path).unwrap();
+
+        let formatter = syslog::Formatter3164 {
+            facility: syslog::Facility::LOG_DAEMON,
+            hostname: None,
+            process: String::from("dness"),
+            pid: 1234,
+        };
+        let logger = syslog::unix_custom(formatter, &path).unwrap();
+        let writer = SyslogMakeWriter(Arc::new(Mutex::new(logger)));
+        let subscriber = tracing_subscriber::registry().with(
+            tracing_subscriber::fmt::layer()
+                .without_time()
+                .with_ansi(false)
+                .with_writer(writer),
+        );
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::warn!("could not resolve");
+        });
+
+        let mut buf = [0u8; 1024];
+        let len = socket.recv(&mut buf).unwrap();
+        let msg = String::from_utf8_lossy(&buf[..len]).into_owned();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // The daemon facility (3) with the warning severity (4) is a priority of 3 * 8 + 4
+        assert!(msg.starts_with("<28>"), "{}", msg);
+        assert!(msg.contains("dness[1234]"), "{}", msg);
+        assert!(msg.contains("could not resolve"), "{}", msg);
+    }
+}
@@ -1,34 +1,189 @@
+#[cfg(feature = "aws-secrets")]
+mod aws_secrets;
+mod bunny;
+mod check;
+mod circuit_breaker;
 mod cloudflare;
 mod config;
 mod core;
+mod ddnss;
 mod dns;
+mod dnsomatic;
+mod dnspod;
+mod doh;
 mod dynu;
 mod errors;
+mod generate;
 mod godaddy;
 mod he;
+mod history;
+mod http_log;
+mod infomaniak;
+mod interface;
+mod inwx;
+mod ipv64;
+mod linode;
+mod logging;
+mod metrics;
 mod namecheap;
+mod namecheap_api;
+mod namecom;
+mod njalla;
 mod noip;
+mod notify;
+mod ovh;
 mod porkbun;
+mod regfish;
+mod resolver;
+mod retry;
+#[cfg(feature = "route53")]
+mod route53;
+mod state;
+mod strato;
+mod summary;
+#[cfg(unix)]
+mod systemd;
+mod zoneedit;
 
-use crate::config::{parse_config, DnsConfig, DomainConfig};
-use crate::core::Updates;
-use crate::dns::wan_lookup_ip;
+use crate::check::{CheckReport, DomainStatus};
+use crate::circuit_breaker::{CircuitBreaker, Circuits};
+use crate::config::{
+    parse_configs, validate_config, ConfigError, DnsConfig, DomainConfig, IpRejection, IpType,
+    LogFormat, LogTarget, OutputFormat, PROVIDER_TYPES,
+};
+use crate::core::{DnsProvider, UpdateContext, Updates};
 use crate::errors::DnessError;
+use crate::history::{append_history, HistoryEntry, ProviderHistory};
+use crate::logging::{init_logging, module_levels};
+use crate::metrics::SharedMetrics;
+use crate::notify::{notifiers, FailureEvent, UpdateEvent};
+use crate::resolver::{resolve_ip, resolve_ipv6, wan_resolvers, WanResolver};
+use crate::retry::RetryPolicy;
+use crate::state::{read_state, recent_updates, write_state, LastUpdated, State, ZoneCache};
+use crate::summary::RunSummary;
 use chrono::Duration;
 use clap::Parser;
-use log::{error, info, LevelFilter};
+use futures::stream::{self, StreamExt};
+use log::LevelFilter;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::error;
 use std::fmt::Write;
-use std::net::Ipv4Addr;
-use std::path::{Path, PathBuf};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
 use std::time::Instant;
+use tracing::{debug, error, info, warn};
+
+/// The request timeout used when one is not configured
+const DEFAULT_REQUEST_TIMEOUT_SECS: u64 = 30;
+
+/// The user agent sent with requests when one is not configured
+const DEFAULT_USER_AGENT: &str = concat!(
+    "dness/",
+    env!("CARGO_PKG_VERSION"),
+    " (https://github.com/nickbabcock/dness)"
+);
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Opt {
-    /// Sets a custom config file
+    /// Sets a custom config file. When given more than once, the files are merged: the domains of
+    /// every file are kept and other settings are taken from the last file that has them
+    #[structopt(short, long)]
+    config: Vec<PathBuf>,
+
+    /// Reports the changes that would be made without applying them
+    #[structopt(long)]
+    dry_run: bool,
+
+    /// Skips the dns lookup of current records and always sends an update for providers that
+    /// otherwise rely on the lookup
+    #[structopt(long)]
+    force: bool,
+
+    /// Runs continuously, processing the domains every given number of seconds
+    #[structopt(long, value_parser = clap::value_parser!(u64).range(1..))]
+    interval: Option<u64>,
+
+    /// Only keeps A records current, skipping domains that only have AAAA records
+    #[structopt(long, conflicts_with = "ipv6_only")]
+    ipv4_only: bool,
+
+    /// Only keeps AAAA records current, skipping domains that only have A records
+    #[structopt(long)]
+    ipv6_only: bool,
+
+    /// Only updates the domains of the given providers, separated by commas (eg:
+    /// cloudflare,godaddy)
+    #[structopt(long, value_delimiter = ',')]
+    providers: Vec<String>,
+
+    /// Only updates the given records, which can be repeated or separated by commas. A record is
+    /// named as in the config or fully qualified (eg: sub.example.com), and domains left without
+    /// records are skipped.
+    #[structopt(long = "record", value_delimiter = ',')]
+    records: Vec<String>,
+
+    /// Prints the WAN IP found by the configured resolver and exits without updating any domains
+    #[structopt(long)]
+    print_ip: bool,
+
+    /// Reports which records are current, stale, or missing without updating any. Exits with 0
+    /// when all are current, 1 when any are stale or missing, and 2 when a domain can't be checked
+    #[structopt(long)]
+    check: bool,
+
+    /// The format of the report written by --check and of the summary of a run. With json, a run
+    /// writes its summary to stdout as a line of JSON and logs to stderr. Overrides the config's
+    /// output_format.
+    #[structopt(long, value_enum)]
+    output: Option<OutputFormat>,
+
+    /// Turns off logging, which is useful when only the output of --output json is wanted
     #[structopt(short, long)]
-    config: Option<PathBuf>,
+    quiet: bool,
+
+    /// Checks the config file for mistakes and exits without updating any domains
+    #[structopt(long, requires = "config")]
+    validate_config: bool,
+
+    /// Lists the names and ids of the zones that the cloudflare api token given with --token has
+    /// access to and exits, which helps find the zone name for the config
+    #[structopt(long, requires = "token")]
+    cloudflare_list_zones: bool,
+
+    /// The cloudflare api token for --cloudflare-list-zones
+    #[structopt(long, requires = "cloudflare_list_zones")]
+    token: Option<String>,
+
+    /// Asks a series of questions to build a config, which is written to stdout
+    #[structopt(long)]
+    generate_config: bool,
+}
+
+impl Opt {
+    /// The only address type to keep current, if one was given
+    fn only_ip_type(&self) -> Option<IpType> {
+        if self.ipv4_only {
+            Some(IpType::V4)
+        } else if self.ipv6_only {
+            Some(IpType::V6)
+        } else {
+            None
+        }
+    }
+
+    fn domain_filter(&self) -> DomainFilter {
+        DomainFilter {
+            ip_type: self.only_ip_type(),
+            providers: self
+                .providers
+                .iter()
+                .map(|x| x.trim().to_string())
+                .collect(),
+            records: self.records.iter().map(|x| x.trim().to_string()).collect(),
+        }
+    }
 }
 
 fn log_err(context: &str, err: Box<dyn error::Error>) {
@@ -45,70 +200,170 @@ fn log_err(context: &str, err: Box<dyn error::Error>) {
     error!("{}", msg);
 }
 
-fn init_logging(lvl: LevelFilter) {
-    env_logger::Builder::from_default_env()
-        .filter_level(lvl)
-        .target(env_logger::Target::Stdout)
-        .init();
+/// Lists the config files for logs
+fn display_paths(paths: &[PathBuf]) -> String {
+    paths
+        .iter()
+        .map(|x| x.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Parses and merges the config files
+fn parse_config_files(paths: &[PathBuf]) -> Result<DnsConfig, ConfigError> {
+    let paths = paths.iter().map(|x| x.as_path()).collect::<Vec<_>>();
+    parse_configs(&paths)
 }
 
 /// Parses the TOML configuration. If no configuration file is present, the default configuration
 /// is returned so that the WAN IP can still be logged on execution. If there is an error parsing
 /// the configuration file, exit with a non-zero status code.
-fn init_configuration<T: AsRef<Path>>(file: Option<T>) -> DnsConfig {
-    if let Some(config_file) = file {
-        let path = config_file.as_ref();
-        match parse_config(path) {
-            Ok(c) => c,
-            Err(e) => {
-                // If there is an error during configuration, we assume a log level of Warn so that
-                // the user will see the error printed.
-                init_logging(LevelFilter::Warn);
-                let desc = format!("could not configure application from: {}", path.display());
-                log_err(&desc, Box::new(e));
-                std::process::exit(1)
-            }
+fn init_configuration(files: &[PathBuf]) -> DnsConfig {
+    if files.is_empty() {
+        return Default::default();
+    }
+
+    match parse_config_files(files) {
+        Ok(c) => c,
+        Err(e) => {
+            // If there is an error during configuration, we assume a log level of Warn so that
+            // the user will see the error printed.
+            init_logging(
+                LevelFilter::Warn,
+                &BTreeMap::new(),
+                LogFormat::Text,
+                LogTarget::Stdout,
+            );
+            let desc = format!(
+                "could not configure application from: {}",
+                display_paths(files)
+            );
+            log_err(&desc, Box::new(e));
+            std::process::exit(1)
         }
-    } else {
-        Default::default()
     }
 }
 
-async fn ipify_resolve_ip(client: &reqwest::Client) -> Result<Ipv4Addr, DnessError> {
-    let ipify_url = "https://api.ipify.org/";
-    let ip_text = client
-        .get(ipify_url)
-        .send()
-        .await
-        .map_err(|e| DnessError::send_http(ipify_url, "ipify get ip", e))?
-        .error_for_status()
-        .map_err(|e| DnessError::bad_response(ipify_url, "ipify get ip", e))?
-        .text()
-        .await
-        .map_err(|e| DnessError::deserialize(ipify_url, "ipify get ip", e))?;
+/// The domains to keep current out of those configured, as given on the command line. It is kept
+/// around so that it can be applied again whenever the config is reloaded.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct DomainFilter {
+    ip_type: Option<IpType>,
+    providers: Vec<String>,
+    records: Vec<String>,
+}
 
-    let ip = ip_text
-        .parse::<Ipv4Addr>()
-        .map_err(|_| DnessError::message(format!("unable to parse {} as an ip", &ip_text)))?;
-    Ok(ip)
+impl DomainFilter {
+    fn apply(&self, config: &mut DnsConfig) {
+        restrict_ip_type(config, self.ip_type);
+        restrict_providers(config, &self.providers);
+        restrict_records(config, &self.records);
+    }
 }
 
-/// Resolves the WAN IP or exits with a non-zero status code
-async fn resolve_ip(client: &reqwest::Client, config: &DnsConfig) -> Ipv4Addr {
-    let res = match config.ip_resolver.to_ascii_lowercase().as_str() {
-        "opendns" => wan_lookup_ip().await.map_err(|x| x.into()),
-        "ipify" => ipify_resolve_ip(client).await,
-        _ => {
-            error!("unrecognized ip resolver: {}", config.ip_resolver);
-            std::process::exit(1)
+/// Restricts the domains to the given providers, matched against their `type` regardless of
+/// case. An empty list keeps every domain.
+fn restrict_providers(config: &mut DnsConfig, providers: &[String]) {
+    if !providers.is_empty() {
+        config.domains.retain(|d| {
+            providers
+                .iter()
+                .any(|x| x.eq_ignore_ascii_case(d.provider_name()))
+        });
+    }
+}
+
+/// Restricts the domains to the given records. Domains left without records are dropped. An empty
+/// list keeps every record.
+fn restrict_records(config: &mut DnsConfig, records: &[String]) {
+    if !records.is_empty() {
+        config.domains.retain_mut(|d| d.retain_records(records));
+    }
+}
+
+/// Providers given to --providers that aren't the `type` of any provider
+fn unknown_providers(providers: &[String]) -> Vec<&String> {
+    providers
+        .iter()
+        .filter(|x| !PROVIDER_TYPES.iter().any(|t| t.eq_ignore_ascii_case(x)))
+        .collect()
+}
+
+/// Restricts the domains to the given address type. Domains left without an address type are
+/// dropped, so that they are skipped instead of failing validation.
+fn restrict_ip_type(config: &mut DnsConfig, ip_type: Option<IpType>) {
+    if let Some(ip_type) = ip_type {
+        config.domains.retain_mut(|d| d.retain_ip_type(ip_type));
+    }
+}
+
+/// Parses and validates the config files, returning the exit code
+fn validate_configuration(paths: &[PathBuf]) -> i32 {
+    init_logging(
+        LevelFilter::Warn,
+        &BTreeMap::new(),
+        LogFormat::Text,
+        LogTarget::Stderr,
+    );
+    let errors = match parse_config_files(paths) {
+        Ok(config) => validate_config(&config),
+        Err(e) => {
+            let desc = format!("could not parse config: {}", display_paths(paths));
+            log_err(&desc, Box::new(e));
+            return 1;
         }
     };
 
-    match res {
-        Ok(c) => c,
+    if errors.is_empty() {
+        println!("Config is valid");
+        0
+    } else {
+        for e in &errors {
+            eprintln!("{}", e);
+        }
+        1
+    }
+}
+
+/// Reads the state left behind by the previous run, if a state file is configured
+fn load_state(config: &DnsConfig) -> Option<State> {
+    let path = config.state_file.as_ref()?;
+    match read_state(path) {
+        Ok(state) => state,
         Err(e) => {
-            log_err("could not successfully resolve IP", Box::new(e));
-            std::process::exit(1)
+            warn!("ignoring state file {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Writes the state file with the addresses that are now current for all domains. When some
+/// domains aren't current, no address is cached so that the next run doesn't skip them, even if
+/// the WAN IP flips back to the address of an earlier run.
+fn save_state(
+    config: &DnsConfig,
+    current: Option<(Ipv4Addr, Option<Ipv6Addr>)>,
+    zones: &ZoneCache,
+    circuits: Circuits,
+    last_updated_at: LastUpdated,
+) {
+    if let Some(path) = config.state_file.as_ref() {
+        let (ipv4, ipv6) = match current {
+            Some((addr, addr6)) => (Some(addr), addr6),
+            None => (None, None),
+        };
+
+        let state = State {
+            ipv4,
+            ipv6,
+            updated_at: chrono::Utc::now(),
+            zones: zones.zones(),
+            circuits,
+            last_updated_at,
+        };
+
+        if let Err(e) = write_state(path, &state) {
+            warn!("could not write state file {}: {}", path.display(), e);
         }
     }
 }
@@ -119,88 +374,1715 @@ fn elapsed(start: Instant) -> String {
         .unwrap_or_else(|_| String::from("<error>"))
 }
 
-async fn update_provider(
-    http_client: &reqwest::Client,
-    addr: Ipv4Addr,
-    domain: &DomainConfig,
-) -> Result<Updates, Box<dyn std::error::Error>> {
-    match domain {
-        DomainConfig::Cloudflare(domain_config) => {
-            cloudflare::update_domains(http_client, domain_config, addr)
-                .await
-                .map_err(|e| e.into())
+/// Logs the totals of a run, which are attached as fields for structured logs
+fn log_summary(total: &Updates, start: Instant) {
+    info!(
+        total_updated = total.updated,
+        total_current = total.current,
+        total_missing = total.missing,
+        total_failed = total.failed,
+        elapsed_ms = start.elapsed().as_millis() as u64,
+        "processed all: ({}) in {}",
+        total,
+        elapsed(start)
+    );
+}
+
+/// The outcome of processing all the configured domains once
+struct RunOutcome {
+    /// The resolved WAN IP, if resolution succeeded
+    addr: Option<Ipv4Addr>,
+
+    /// The resolved ipv6 WAN IP, if any domain needed it and resolution succeeded
+    addr6: Option<Ipv6Addr>,
+
+    /// The totals of the updates
+    updates: Updates,
+
+    /// Whether the WAN IP could not be resolved, any domain failed to update, or (when configured)
+    /// any record is missing
+    failure: bool,
+}
+
+impl RunOutcome {
+    /// The outcome of a run that stopped before any domain was processed
+    fn aborted(failure: bool) -> RunOutcome {
+        RunOutcome {
+            addr: None,
+            addr6: None,
+            updates: Updates::default(),
+            failure,
         }
-        DomainConfig::GoDaddy(domain_config) => {
-            godaddy::update_domains(http_client, domain_config, addr)
-                .await
-                .map_err(|e| e.into())
+    }
+}
+
+/// Writes the summary of a run to stdout when a machine readable format is requested
+fn print_summary(format: OutputFormat, outcome: &RunOutcome, start: Instant) {
+    if format == OutputFormat::Json {
+        let summary = RunSummary::new(
+            &outcome.updates,
+            start.elapsed(),
+            outcome.addr,
+            outcome.addr6,
+        );
+        println!("{}", summary.to_json());
+    }
+}
+
+/// The client shared by all requests. Proxies from the HTTP_PROXY, HTTPS_PROXY, and NO_PROXY
+/// environment variables are used unless a proxy is configured.
+fn http_client(config: &DnsConfig) -> Result<reqwest::Client, reqwest::Error> {
+    let global_timeout = config
+        .request_timeout_secs
+        .unwrap_or(DEFAULT_REQUEST_TIMEOUT_SECS);
+    let user_agent = config.user_agent.as_deref().unwrap_or(DEFAULT_USER_AGENT);
+    debug!("sending requests with user agent: {}", user_agent);
+    let mut builder = reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(global_timeout))
+        .user_agent(user_agent);
+    if let Some(connect_timeout) = config.connect_timeout_secs {
+        builder = builder.connect_timeout(std::time::Duration::from_secs(connect_timeout));
+    }
+    if let Some(read_timeout) = config.read_timeout_secs {
+        builder = builder.read_timeout(std::time::Duration::from_secs(read_timeout));
+    }
+    if let Some(proxy_url) = config.proxy_url.as_deref() {
+        builder = builder.proxy(reqwest::Proxy::all(proxy_url)?);
+    }
+    builder.build()
+}
+
+/// Prints the bare WAN IP so that it can be consumed by scripts
+async fn print_ip(config: &DnsConfig) -> Result<(), Box<dyn error::Error>> {
+    let client = http_client(config)?;
+    let resolvers = wan_resolvers(&client, config)?;
+    let addr = resolve_ip(&resolvers).await?;
+    println!("{}", addr);
+    Ok(())
+}
+
+/// Writes the zones of a cloudflare account to stdout
+async fn print_cloudflare_zones(token: &str) -> Result<(), Box<dyn error::Error>> {
+    let client = http_client(&DnsConfig::default())?;
+    for zone in cloudflare::cloudflare_list_zones(&client, token).await? {
+        println!("{} {}", zone.name, zone.id);
+    }
+    Ok(())
+}
+
+/// Errors when the config doesn't trust the resolved address as the WAN IP
+fn check_wan_ip(config: &DnsConfig, ip: IpAddr) -> Result<(), DnessError> {
+    match config.rejected_ip_reason(ip) {
+        Some(rejection) => Err(rejected_ip_error(ip, &rejection)),
+        None => Ok(()),
+    }
+}
+
+fn rejected_ip_error(ip: IpAddr, rejection: &IpRejection) -> DnessError {
+    match rejection {
+        IpRejection::Private => DnessError::message(format!(
+            "resolved IP {} looks like a private address; refusing to update DNS",
+            ip
+        )),
+        IpRejection::Untrusted(_) => {
+            DnessError::message(format!("rejected {} as {}", ip, rejection))
         }
-        DomainConfig::Namecheap(domain_config) => {
-            namecheap::update_domains(http_client, domain_config, addr)
+    }
+}
+
+fn wants_ipv6(config: &DnsConfig) -> bool {
+    config
+        .domains
+        .iter()
+        .any(|d| d.get_ip_types().contains(&IpType::V6))
+}
+
+/// The result of updating a domain for one of its address types
+type DomainResult<'a> = (
+    &'a DomainConfig,
+    String,
+    Result<Updates, Box<dyn error::Error>>,
+    String,
+);
+
+/// An update of a provider to an address, where the item identifies the update in the results
+struct UpdateJob<'a, T> {
+    item: T,
+    provider: &'a dyn DnsProvider,
+    addr: IpAddr,
+
+    /// The ipv6 address for providers that update both address types in a single request
+    addr6: Option<Ipv6Addr>,
+    name: String,
+
+    /// The timeout of each request, when the domain overrides the timeout of the shared client
+    timeout_secs: Option<u64>,
+
+    /// The client with the overridden timeout, instead of the shared client
+    client: Option<reqwest::Client>,
+}
+
+/// The key of a record in the state file, as its provider and fully qualified name
+fn record_key(d: &DomainConfig, record: &str) -> String {
+    format!("{}/{}", d.provider_name(), record)
+}
+
+/// Runs the updates with at most the given number at the same time, returning the result and
+/// elapsed time of each update in the order of the jobs
+async fn run_updates<T>(
+    http_client: &reqwest::Client,
+    config: &DnsConfig,
+    zones: &ZoneCache,
+    jobs: Vec<UpdateJob<'_, T>>,
+    concurrency: usize,
+    dry_run: bool,
+    force: bool,
+) -> Vec<(T, String, Result<Updates, Box<dyn error::Error>>, String)> {
+    let policy = &RetryPolicy {
+        max_retries: config.max_retries,
+        base_delay: std::time::Duration::from_millis(config.retry_base_delay_ms),
+    };
+
+    // Buffering (instead of buffering unordered) keeps the log output in the order of the config
+    stream::iter(jobs)
+        .map(|job| async move {
+            let ctx = UpdateContext {
+                client: job.client.as_ref().unwrap_or(http_client),
+                policy,
+                name: &job.name,
+                dry_run,
+                force,
+                addr6: job.addr6,
+                zones,
+                config,
+            };
+
+            let start_update = Instant::now();
+            let result = job
+                .provider
+                .update(&ctx, job.addr)
                 .await
-                .map_err(|e| e.into())
+                .map(|updates| Updates { dry_run, ..updates });
+            (job.item, job.name, result, elapsed(start_update))
+        })
+        .buffered(concurrency)
+        .collect()
+        .await
+}
+
+/// The updates of the domains, where a domain kept current for multiple address types has an
+/// update for each type unless its provider updates both in a single request. Domains that need
+/// an ipv6 address are skipped when one isn't given.
+fn update_jobs(
+    config: &DnsConfig,
+    addr: Ipv4Addr,
+    addr6: Option<Ipv6Addr>,
+) -> Vec<UpdateJob<'_, &DomainConfig>> {
+    config
+        .domains
+        .iter()
+        .flat_map(|d| {
+            let mut ip_types = d.get_ip_types();
+            let combined = ip_types.contains(&IpType::V4)
+                && ip_types.contains(&IpType::V6)
+                && d.updates_ip_types_together()
+                && addr6.is_some();
+            if combined {
+                ip_types.retain(|x| *x == IpType::V4);
+            }
+
+            let label = ip_types.len() > 1;
+            ip_types.into_iter().filter_map(move |ip_type| {
+                let ip = match ip_type {
+                    IpType::V4 => IpAddr::V4(addr),
+                    IpType::V6 => IpAddr::V6(addr6?),
+                };
+
+                // Domains kept current for multiple address types are labelled with the type
+                let name = if label {
+                    format!("{} [{}]", d.display_name(), ip_type)
+                } else {
+                    d.display_name()
+                };
+
+                Some(UpdateJob {
+                    item: d,
+                    provider: d.provider(),
+                    addr: ip,
+                    addr6: addr6.filter(|_| combined),
+                    name,
+                    timeout_secs: d.request_timeout_secs(),
+                    client: None,
+                })
+            })
+        })
+        .collect()
+}
+
+async fn update_domains<'a>(
+    http_client: &reqwest::Client,
+    zones: &ZoneCache,
+    config: &DnsConfig,
+    mut jobs: Vec<UpdateJob<'a, &'a DomainConfig>>,
+    dry_run: bool,
+    force: bool,
+) -> Vec<DomainResult<'a>> {
+    // Providers are independent of each other, so they are updated concurrently
+    let concurrency = match config.max_concurrent_updates {
+        0 => jobs.len().max(1),
+        n => n,
+    };
+
+    // The timeout applies to each request, so a domain that overrides it has a client of its own
+    let global_timeout = config
+        .request_timeout_secs
+        .unwrap_or(DEFAULT_REQUEST_TIMEOUT_SECS);
+    let mut clients = HashMap::new();
+    for timeout in jobs.iter().filter_map(|x| x.timeout_secs) {
+        if timeout == global_timeout || clients.contains_key(&timeout) {
+            continue;
         }
-        DomainConfig::He(domain_config) => he::update_domains(http_client, domain_config, addr)
-            .await
-            .map_err(|e| e.into()),
-        DomainConfig::NoIp(domain_config) => noip::update_domains(http_client, domain_config, addr)
-            .await
-            .map_err(|e| e.into()),
-        DomainConfig::Dynu(domain_config) => dynu::update_domains(http_client, domain_config, addr)
-            .await
-            .map_err(|e| e.into()),
-        DomainConfig::Porkbun(domain_config) => {
-            porkbun::update_domains(http_client, domain_config, addr)
-                .await
-                .map_err(|e| e.into())
+
+        let timeout_config = DnsConfig {
+            request_timeout_secs: Some(timeout),
+            ..config.clone()
+        };
+        match crate::http_client(&timeout_config) {
+            Ok(client) => {
+                clients.insert(timeout, client);
+            }
+            Err(e) => warn!(
+                "could not create http client with a {}s timeout: {}",
+                timeout, e
+            ),
         }
     }
+    for job in &mut jobs {
+        job.client = job.timeout_secs.and_then(|x| clients.get(&x)).cloned();
+    }
+
+    run_updates(
+        http_client,
+        config,
+        zones,
+        jobs,
+        concurrency,
+        dry_run,
+        force,
+    )
+    .await
 }
 
-#[tokio::main]
-async fn main() {
-    let start = Instant::now();
-    let opt = Opt::parse();
-    let config = init_configuration(opt.config.as_ref());
+/// Performs a dry run of every domain and reports the state of their records, returning the exit
+/// code
+async fn run_check(config: &DnsConfig, format: OutputFormat) -> i32 {
+    let http_client = match http_client(config) {
+        Ok(client) => client,
+        Err(e) => {
+            log_err("could not create http client", Box::new(e));
+            return 2;
+        }
+    };
+
+    let resolvers = match wan_resolvers(&http_client, config) {
+        Ok(resolvers) => resolvers,
+        Err(e) => {
+            log_err("could not create ip resolvers", Box::new(e));
+            return 2;
+        }
+    };
 
-    init_logging(config.log.level);
+    let addr = resolve_ip(&resolvers)
+        .await
+        .and_then(|addr| check_wan_ip(config, IpAddr::V4(addr)).map(|_| addr));
+    let addr = match addr {
+        Ok(addr) => addr,
+        Err(e) => {
+            log_err("could not successfully resolve IP", Box::new(e));
+            return 2;
+        }
+    };
 
+    let addr6 = if wants_ipv6(config) {
+        let addr6 = resolve_ipv6(&resolvers)
+            .await
+            .and_then(|addr6| check_wan_ip(config, IpAddr::V6(addr6)).map(|_| addr6));
+        match addr6 {
+            Ok(addr6) => Some(addr6),
+            Err(e) => {
+                log_err("could not successfully resolve ipv6 address", Box::new(e));
+                return 2;
+            }
+        }
+    } else {
+        None
+    };
+
+    // A check reads the cached zone ids but, like a dry run, doesn't write the state file
+    let zones = ZoneCache::new(load_state(config).map(|x| x.zones).unwrap_or_default());
+    let jobs = update_jobs(config, addr, addr6);
+    let results = update_domains(&http_client, &zones, config, jobs, true, false).await;
+    let report = CheckReport {
+        addr,
+        addr6,
+        domains: results
+            .iter()
+            .map(|(_, name, result, _)| DomainStatus::new(name.clone(), result))
+            .collect(),
+    };
+
+    println!("{}", report.render(format).trim_end());
+    report.exit_code()
+}
+
+#[tracing::instrument(skip_all)]
+async fn run_once(opt: &Opt, config: &DnsConfig, metrics: &SharedMetrics) -> RunOutcome {
     // Use a single HTTP client when updating dns records so that connections can be reused
-    let http_client = reqwest::Client::new();
+    let http_client = match http_client(config) {
+        Ok(client) => client,
+        Err(e) => {
+            log_err("could not create http client", Box::new(e));
+            return RunOutcome::aborted(true);
+        }
+    };
 
+    let resolvers = match wan_resolvers(&http_client, config) {
+        Ok(resolvers) => resolvers,
+        Err(e) => {
+            log_err("could not create ip resolvers", Box::new(e));
+            return RunOutcome::aborted(true);
+        }
+    };
+
+    run(opt, config, metrics, &http_client, &resolvers).await
+}
+
+/// Resolves the WAN IP with the resolvers and brings the configured domains up to date with it
+async fn run(
+    opt: &Opt,
+    config: &DnsConfig,
+    metrics: &SharedMetrics,
+    http_client: &reqwest::Client,
+    resolvers: &[Box<dyn WanResolver + '_>],
+) -> RunOutcome {
+    let start = Instant::now();
     let start_resolve = Instant::now();
-    let addr = resolve_ip(&http_client, &config).await;
+    let addr = match resolve_ip(resolvers).await {
+        Ok(addr) => addr,
+        Err(e) => {
+            log_err("could not successfully resolve IP", Box::new(e));
+            return RunOutcome::aborted(true);
+        }
+    };
+
     info!("resolved address to {} in {}", addr, elapsed(start_resolve));
+    if let Ok(mut m) = metrics.lock() {
+        m.observe_resolution(start_resolve.elapsed());
+    }
+
+    // Updates are skipped for a rejected address, but it's up to the config whether an untrusted
+    // address fails the run
+    if let Some(rejection) = config.rejected_ip_reason(IpAddr::V4(addr)) {
+        let e = rejected_ip_error(IpAddr::V4(addr), &rejection);
+        match rejection {
+            IpRejection::Private => error!("{}", e),
+            IpRejection::Untrusted(_) => log_err("could not accept the resolved IP", Box::new(e)),
+        }
+        return RunOutcome::aborted(rejection.fails_run(config));
+    }
 
     // Keep track of any failures in ensuring current DNS records. We don't want to fail on the
     // first error, as subsequent domains listed in the config can still be valid, but if there
     // were any failures, we still need to exit with a non-zero exit code
-    let mut failure = false;
-    let mut total_updates = Updates::default();
+    let mut errors: Vec<(String, Box<dyn error::Error>)> = Vec::new();
 
-    for d in config.domains {
-        let start_update = Instant::now();
-        match update_provider(&http_client, addr, &d).await {
-            Ok(updates) => {
+    // The ipv6 address is only resolved when a domain asks for it, as not every network has ipv6
+    let addr6 = if wants_ipv6(config) {
+        let start_resolve = Instant::now();
+        let addr6 = resolve_ipv6(resolvers).await;
+        match addr6 {
+            Ok(addr6) => {
                 info!(
-                    "processed {}: ({}) in {}",
-                    d.display_name(),
-                    updates,
-                    elapsed(start_update)
+                    "resolved ipv6 address to {} in {}",
+                    addr6,
+                    elapsed(start_resolve)
                 );
+                match config.rejected_ip_reason(IpAddr::V6(addr6)) {
+                    None => Some(addr6),
+                    Some(rejection) if rejection.fails_run(config) => {
+                        let e = rejected_ip_error(IpAddr::V6(addr6), &rejection);
+                        let msg = String::from("ipv6 address (domains needing it are skipped)");
+                        errors.push((msg, Box::new(e)));
+                        None
+                    }
+                    Some(rejection) => {
+                        let e = rejected_ip_error(IpAddr::V6(addr6), &rejection);
+                        let msg = "could not accept the resolved ipv6 address (domains needing it are skipped)";
+                        log_err(msg, Box::new(e));
+                        None
+                    }
+                }
+            }
+            Err(e) => {
+                let msg = String::from("ipv6 address (domains needing it are skipped)");
+                errors.push((msg, Box::new(e)));
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let mut total_updates = Updates {
+        dry_run: opt.dry_run,
+        ..Updates::default()
+    };
+
+    // The state file is bypassed on a forced run as the point is to push the address regardless.
+    // A domain with failures on record, like one whose circuit is open, isn't current.
+    let previous = load_state(config);
+    let ttl = Duration::seconds(config.state_ttl_secs as i64);
+    let is_cached = previous.as_ref().is_some_and(|state| {
+        state.circuits.is_empty() && state.is_current(addr, addr6, ttl, chrono::Utc::now())
+    });
+    if !opt.force && is_cached && errors.is_empty() {
+        debug!("already current (cached)");
+        log_summary(&total_updates, start);
+        return RunOutcome {
+            addr: Some(addr),
+            addr6,
+            updates: total_updates,
+            failure: false,
+        };
+    }
+
+    let zones = ZoneCache::new(
+        previous
+            .as_ref()
+            .map(|state| state.zones.clone())
+            .unwrap_or_default(),
+    );
+
+    // A domain that keeps failing is skipped until its cooldown elapses, unless forced
+    let mut breaker = CircuitBreaker::new(
+        previous
+            .as_ref()
+            .map(|state| state.circuits.clone())
+            .unwrap_or_default(),
+        &config.circuit_breaker,
+    );
+    // A record updated moments ago is left alone, so that an address flipping back and forth
+    // doesn't update records on every run. The rest of its domain's records are still updated.
+    let now = chrono::Utc::now();
+    let min_interval = Duration::seconds(config.min_update_interval_secs as i64);
+    let mut last_updated = previous
+        .as_ref()
+        .map(|state| recent_updates(&state.last_updated_at, min_interval, now))
+        .unwrap_or_default();
+    let mut held_back = false;
+    let due_config;
+    let job_config = if opt.force || last_updated.is_empty() {
+        config
+    } else {
+        let mut domains = config.domains.clone();
+        domains.retain_mut(|d| {
+            let mut due = Vec::new();
+            for (record, name) in d.qualified_records() {
+                if last_updated.contains_key(&record_key(d, &name)) {
+                    debug!("skipping update for {} -- updated too recently", name);
+                    held_back = true;
+                } else {
+                    due.push(String::from(record));
+                }
+            }
+            d.retain_records(&due)
+        });
+        due_config = DnsConfig {
+            domains,
+            ..config.clone()
+        };
+        &due_config
+    };
+
+    let (jobs, suspended): (Vec<_>, Vec<_>) = update_jobs(job_config, addr, addr6)
+        .into_iter()
+        .partition(|job| opt.force || breaker.suspended_for(&job.name, now).is_none());
+    for job in suspended {
+        let remaining = breaker.suspended_for(&job.name, now).unwrap_or_default();
+        warn!(
+            "skipping {} as it has failed repeatedly, trying again in {}s",
+            job.name,
+            remaining.num_seconds()
+        );
+        total_updates.failed += 1;
+    }
+
+    let results = update_domains(http_client, &zones, config, jobs, opt.dry_run, opt.force).await;
+
+    let mut updated_domains = Vec::new();
+    let mut providers = Vec::new();
+    for (d, name, result, took) in results {
+        if result.is_ok() {
+            breaker.record_success(&name);
+        } else if breaker.record_failure(&name, chrono::Utc::now()) {
+            warn!(
+                "{} keeps failing, so it is skipped for the next {}s",
+                name, config.circuit_breaker.cooldown_secs
+            );
+        }
+
+        providers.push(ProviderHistory {
+            name: name.clone(),
+            updated: result.as_ref().map(|x| x.updated).unwrap_or_default(),
+        });
+        match result {
+            Ok(updates) => {
+                info!("processed {}: ({}) in {}", name, updates, took);
+                // Providers only count their updates, so the records updated together are
+                // all held back from the next runs
+                if updates.updated > 0 && config.min_update_interval_secs > 0 && !opt.dry_run {
+                    for (_, record) in d.qualified_records() {
+                        last_updated.insert(record_key(d, &record), chrono::Utc::now());
+                    }
+                }
+                if updates.updated > 0 {
+                    updated_domains.push(name);
+                }
+                if let Ok(mut m) = metrics.lock() {
+                    m.record_updates(d.provider_name(), &d.domain_name(), &updates);
+                }
                 total_updates += updates;
             }
             Err(e) => {
-                failure = true;
-                let msg = format!("could not update {}", d.display_name(),);
-                log_err(&msg, e);
+                if let Ok(mut m) = metrics.lock() {
+                    m.record_error(d.provider_name());
+                }
+                total_updates.failed += 1;
+                errors.push((name, e));
+            }
+        }
+    }
+
+    if let Some(history_file) = config.history_file.as_ref().filter(|_| !opt.dry_run) {
+        let entry = HistoryEntry {
+            ts: chrono::Utc::now(),
+            ipv4: addr,
+            ipv6: addr6,
+            updated: total_updates.updated,
+            current: total_updates.current,
+            missing: total_updates.missing,
+            providers,
+        };
+        if let Err(e) = append_history(history_file, &entry, config.history_max_entries) {
+            log_err("could not append to history file", Box::new(e));
+        }
+    }
+
+    // A notification is a courtesy, so failing to send one doesn't fail the run
+    let notifiers = notifiers(http_client, &config.notifications);
+    if !opt.dry_run {
+        for (name, e) in &errors {
+            let event = FailureEvent {
+                provider: name.clone(),
+                error: e.to_string(),
+            };
+            for notifier in &notifiers {
+                if let Err(e) = notifier.notify_failure(&event).await {
+                    warn!("could not send notification: {}", e);
+                }
+            }
+        }
+    }
+
+    // Missing records are only a failure when asked for, as they may be created later. Failed
+    // counts reported by a provider that succeeded, like unverified records, aren't a failure.
+    let missing_failure = config.fail_on_missing && total_updates.missing > 0;
+    let failure = !errors.is_empty() || missing_failure;
+    for (name, e) in errors {
+        log_err(&format!("could not update {}", name), e);
+    }
+
+    if missing_failure {
+        error!("{} records are missing", total_updates.missing);
+    }
+
+    if total_updates.updated > 0 && !opt.dry_run {
+        let event = UpdateEvent {
+            old_ip: previous
+                .as_ref()
+                .and_then(|state| state.ipv4)
+                .map(IpAddr::V4),
+            new_ip: IpAddr::V4(addr),
+            domains: updated_domains,
+            updated: total_updates.updated,
+            timestamp: chrono::Utc::now(),
+        };
+
+        for notifier in &notifiers {
+            if let Err(e) = notifier.notify(&event).await {
+                warn!("could not send notification: {}", e);
+            }
+        }
+    }
+
+    if !failure {
+        if let Ok(mut m) = metrics.lock() {
+            m.record_success(chrono::Utc::now());
+        }
+    }
+
+    // The state is written after a failed run too, so that failures are counted across runs, but
+    // the addresses are only cached once every domain is current
+    if !opt.dry_run {
+        let all_current = !failure && total_updates.failed == 0 && !held_back;
+        let current = all_current.then_some((addr, addr6));
+        save_state(config, current, &zones, breaker.circuits(), last_updated);
+    }
+
+    log_summary(&total_updates, start);
+    RunOutcome {
+        addr: Some(addr),
+        addr6,
+        updates: total_updates,
+        failure,
+    }
+}
+
+/// The config shared between the daemon loop and the task that reloads it
+type SharedConfig = Arc<RwLock<DnsConfig>>;
+
+#[cfg(unix)]
+struct ShutdownSignals {
+    terminate: tokio::signal::unix::Signal,
+    interrupt: tokio::signal::unix::Signal,
+}
+
+#[cfg(unix)]
+impl ShutdownSignals {
+    fn new() -> std::io::Result<ShutdownSignals> {
+        use tokio::signal::unix::{signal, SignalKind};
+        Ok(ShutdownSignals {
+            terminate: signal(SignalKind::terminate())?,
+            interrupt: signal(SignalKind::interrupt())?,
+        })
+    }
+
+    async fn recv(&mut self) {
+        tokio::select! {
+            _ = self.terminate.recv() => {},
+            _ = self.interrupt.recv() => {},
+        }
+    }
+}
+
+#[cfg(not(unix))]
+struct ShutdownSignals;
+
+#[cfg(not(unix))]
+impl ShutdownSignals {
+    fn new() -> std::io::Result<ShutdownSignals> {
+        Ok(ShutdownSignals)
+    }
+
+    async fn recv(&mut self) {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+/// Re-reads the config files, keeping the current config if the new one is invalid
+fn reload_configuration(
+    paths: &[PathBuf],
+    filter: &DomainFilter,
+    config: &RwLock<DnsConfig>,
+) -> Result<(), ConfigError> {
+    let mut new_config = parse_config_files(paths)?;
+    filter.apply(&mut new_config);
+    if let Ok(mut current) = config.write() {
+        *current = new_config;
+        info!("config reloaded from {}", display_paths(paths));
+    }
+    Ok(())
+}
+
+/// How long the config file must go without changes before it is reloaded, as editors may
+/// write a file several times on a single save
+const RELOAD_DEBOUNCE: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// Watches the config files in the background and reloads them once changes settle. The
+/// directories are watched instead of the files, as editors often save by renaming a new file over
+/// the old one.
+fn spawn_reload_on_change(
+    paths: Vec<PathBuf>,
+    filter: DomainFilter,
+    config: SharedConfig,
+) -> ::notify::Result<tokio::task::JoinHandle<()>> {
+    use ::notify::{EventKind, RecursiveMode, Watcher};
+
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let file_names = paths
+        .iter()
+        .filter_map(|x| x.file_name().map(|x| x.to_os_string()))
+        .collect::<Vec<_>>();
+    let mut watcher =
+        ::notify::recommended_watcher(move |event: ::notify::Result<::notify::Event>| {
+            let Ok(event) = event else { return };
+            let is_change = matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_));
+            let is_config = event
+                .paths
+                .iter()
+                .filter_map(|x| x.file_name())
+                .any(|x| file_names.iter().any(|name| name == x));
+            if is_change && is_config {
+                let _ = tx.send(());
+            }
+        })?;
+
+    let dirs = paths
+        .iter()
+        .map(|path| match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        })
+        .collect::<BTreeSet<_>>();
+    for dir in &dirs {
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+    }
+
+    Ok(tokio::spawn(async move {
+        // The watcher stops once dropped, so it is owned by the task
+        let _watcher = watcher;
+        while rx.recv().await.is_some() {
+            while let Ok(Some(())) = tokio::time::timeout(RELOAD_DEBOUNCE, rx.recv()).await {}
+            if let Err(e) = reload_configuration(&paths, &filter, &config) {
+                warn!(
+                    "could not reload config from {}: {}",
+                    display_paths(&paths),
+                    e
+                );
+            }
+        }
+    }))
+}
+
+/// Listens for SIGHUP in the background and reloads the config files on each one
+#[cfg(unix)]
+fn spawn_reload_on_hangup(
+    paths: Vec<PathBuf>,
+    filter: DomainFilter,
+    config: SharedConfig,
+) -> std::io::Result<tokio::task::JoinHandle<()>> {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut hangup = signal(SignalKind::hangup())?;
+    Ok(tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            if paths.is_empty() {
+                info!("no config file to reload");
+            } else if let Err(e) = reload_configuration(&paths, &filter, &config) {
+                let msg = format!("could not reload config from {}", display_paths(&paths));
+                log_err(&msg, Box::new(e));
+            }
+        }
+    }))
+}
+
+/// Exits the process once any pending telemetry is flushed
+fn exit(code: i32) -> ! {
+    logging::shutdown();
+    std::process::exit(code)
+}
+
+/// Processes the domains every interval until a shutdown signal is received. A shutdown signal
+/// received mid-run is acted upon once the run completes.
+async fn run_daemon(opt: &Opt, config: DnsConfig, interval_secs: u64, metrics: &SharedMetrics) {
+    #[cfg(unix)]
+    let mut notifier = config.systemd_notify.then(systemd::SystemdNotifier::new);
+
+    let config = SharedConfig::new(RwLock::new(config));
+    let mut signals = match ShutdownSignals::new() {
+        Ok(signals) => signals,
+        Err(e) => {
+            log_err("could not listen for signals", Box::new(e));
+            exit(1)
+        }
+    };
+
+    #[cfg(unix)]
+    if let Err(e) = spawn_reload_on_hangup(opt.config.clone(), opt.domain_filter(), config.clone())
+    {
+        log_err("could not listen for SIGHUP", Box::new(e));
+        exit(1)
+    }
+
+    if !opt.config.is_empty() {
+        if let Err(e) =
+            spawn_reload_on_change(opt.config.clone(), opt.domain_filter(), config.clone())
+        {
+            warn!("could not watch the config file for changes: {}", e);
+        }
+    }
+
+    info!("running every {}s", interval_secs);
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(interval_secs));
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    let mut last_addr = None;
+    let mut runs = 0;
+    let mut totals = Updates::default();
+    loop {
+        // A signal received during a run is only seen here, so that the run's updates and the
+        // state file aren't cut short
+        tokio::select! {
+            biased;
+            _ = signals.recv() => {
+                info!("shutting down (runs: {}, {})", runs, totals);
+                #[cfg(unix)]
+                if let Some(notifier) = &notifier {
+                    notifier.stopping();
+                }
+                break;
+            }
+            _ = interval.tick() => {}
+        }
+
+        // Take a snapshot so that a reload mid-run doesn't mix two configs
+        let current = match config.read() {
+            Ok(current) => current.clone(),
+            Err(_) => break,
+        };
+
+        let start = Instant::now();
+        let outcome = run_once(opt, &current, metrics).await;
+        print_summary(opt.output.unwrap_or(current.output_format), &outcome, start);
+        runs += 1;
+        totals += outcome.updates;
+        #[cfg(unix)]
+        if let Some(notifier) = notifier.as_mut() {
+            notifier.run_finished(&outcome.updates);
+        }
+
+        if let (Some(previous), Some(addr)) = (last_addr, outcome.addr) {
+            if previous != addr {
+                info!("WAN IP changed from {} to {}", previous, addr);
+            }
+        }
+
+        last_addr = outcome.addr.or(last_addr);
+        if outcome.failure {
+            warn!(
+                "at least one update failed, trying again in {}s",
+                interval_secs
+            );
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let opt = Opt::parse();
+    if opt.generate_config {
+        match generate::generate_config() {
+            Ok(config) => print!("{}", config),
+            Err(e) => {
+                eprintln!("could not generate config: {}", e);
+                std::process::exit(1)
+            }
+        }
+        return;
+    }
+
+    if opt.validate_config {
+        std::process::exit(validate_configuration(&opt.config))
+    }
+
+    if let Some(token) = opt.token.as_deref().filter(|_| opt.cloudflare_list_zones) {
+        init_logging(
+            LevelFilter::Warn,
+            &BTreeMap::new(),
+            LogFormat::Text,
+            LogTarget::Stderr,
+        );
+        if let Err(e) = print_cloudflare_zones(token).await {
+            log_err("could not list cloudflare zones", e);
+            exit(1)
+        }
+        return;
+    }
+
+    let mut config = init_configuration(&opt.config);
+    let filter = opt.domain_filter();
+    filter.apply(&mut config);
+
+    // A check, printing the IP, and a JSON summary reserve stdout for their output
+    let output = opt.output.unwrap_or(config.output_format);
+    let reserves_stdout = opt.check || opt.print_ip || output == OutputFormat::Json;
+    let target = match config.log.target {
+        LogTarget::Stdout if reserves_stdout => LogTarget::Stderr,
+        target => target,
+    };
+    let (level, modules) = if opt.quiet {
+        (LevelFilter::Off, BTreeMap::new())
+    } else {
+        (config.log.level, module_levels(&config.domains))
+    };
+    init_logging(level, &modules, config.log.log_format, target);
+    http_log::enable(config.log.log_http);
+
+    if !filter.providers.is_empty() {
+        info!("filtering to providers: {:?}", filter.providers);
+        for provider in unknown_providers(&filter.providers) {
+            warn!("{} given to --providers is not a known provider", provider);
+        }
+    }
+
+    if !filter.records.is_empty() {
+        info!("filtering to records: {:?}", filter.records);
+    }
+
+    if opt.print_ip {
+        if let Err(e) = print_ip(&config).await {
+            log_err("could not resolve the WAN IP", e);
+            exit(1)
+        }
+        return;
+    }
+
+    if opt.check {
+        exit(run_check(&config, output).await)
+    }
+
+    if opt.dry_run {
+        info!("dry run enabled, no dns records will be changed");
+    }
+
+    if opt.force {
+        info!("force enabled, updates will be sent without checking current records");
+    }
+
+    if let Some(ip_type) = opt.only_ip_type() {
+        info!(
+            "only updating {} records, {} records will be left as is",
+            ip_type.record_type(),
+            ip_type.opposite().record_type()
+        );
+    }
+
+    match config.max_concurrent_updates {
+        0 => debug!("updating all domains at the same time"),
+        n => debug!("updating at most {} domains at the same time", n),
+    }
+
+    let metrics = SharedMetrics::default();
+    if config.metrics.enabled {
+        let listen_addr = config.metrics.listen_addr.clone();
+        let metrics = metrics.clone();
+        info!("serving metrics on {}", listen_addr);
+        tokio::spawn(async move {
+            if let Err(e) = metrics::serve(&listen_addr, metrics).await {
+                let msg = format!("could not serve metrics on {}", listen_addr);
+                log_err(&msg, Box::new(e));
+            }
+        });
+    }
+
+    match opt.interval.or(config.interval_secs) {
+        Some(interval_secs) => run_daemon(&opt, config, interval_secs, &metrics).await,
+        None => {
+            let start = Instant::now();
+            let outcome = run_once(&opt, &config, &metrics).await;
+            print_summary(output, &outcome, start);
+            if outcome.failure {
+                error!("at least one update failed, so exiting with non-zero status code");
+                exit(1)
+            }
+        }
+    }
+
+    logging::shutdown();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A provider that reports the given updates, or fails when there are none
+    struct MockProvider(Option<Updates>);
+
+    #[async_trait::async_trait]
+    impl DnsProvider for MockProvider {
+        async fn update(
+            &self,
+            ctx: &UpdateContext<'_>,
+            _addr: IpAddr,
+        ) -> Result<Updates, Box<dyn error::Error>> {
+            self.0.ok_or_else(|| {
+                let msg = format!("{} failed", ctx.name);
+                Box::new(DnessError::message(msg)) as Box<dyn error::Error>
+            })
+        }
+
+        fn name(&self) -> &str {
+            "mock"
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_updates() {
+        let updated = MockProvider(Some(Updates {
+            updated: 1,
+            current: 2,
+            ..Updates::default()
+        }));
+        let failed = MockProvider(None);
+        let config = DnsConfig {
+            max_retries: 0,
+            retry_base_delay_ms: 0,
+            ..DnsConfig::default()
+        };
+
+        let jobs = vec![
+            UpdateJob {
+                item: 1,
+                provider: &updated as &dyn DnsProvider,
+                addr: IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2)),
+                addr6: None,
+                name: String::from("first"),
+                timeout_secs: None,
+                client: None,
+            },
+            UpdateJob {
+                item: 2,
+                provider: &failed as &dyn DnsProvider,
+                addr: IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2)),
+                addr6: None,
+                name: String::from("second"),
+                timeout_secs: None,
+                client: None,
+            },
+        ];
+
+        let client = reqwest::Client::new();
+        let results = run_updates(
+            &client,
+            &config,
+            &ZoneCache::default(),
+            jobs,
+            1,
+            true,
+            false,
+        )
+        .await;
+        assert_eq!(results.len(), 2);
+
+        let (item, name, result, _) = &results[0];
+        assert_eq!((*item, name.as_str()), (1, "first"));
+        assert_eq!(
+            result.as_ref().unwrap(),
+            &Updates {
+                updated: 1,
+                current: 2,
+                missing: 0,
+                failed: 0,
+                dry_run: true,
+            }
+        );
+
+        let (item, name, result, _) = &results[1];
+        assert_eq!((*item, name.as_str()), (2, "second"));
+        assert_eq!(result.as_ref().unwrap_err().to_string(), "second failed");
+    }
+
+    /// A resolver that always returns the given address, or fails when there is none
+    struct MockWanResolver(Option<IpAddr>);
+
+    #[async_trait::async_trait]
+    impl WanResolver for MockWanResolver {
+        async fn resolve(&self, _ip_type: IpType) -> Result<IpAddr, DnessError> {
+            self.0
+                .ok_or_else(|| DnessError::message(String::from("mock failed")))
+        }
+
+        fn name(&self) -> &str {
+            "mock"
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_with_mock_resolver() {
+        let opt = Opt::parse_from(["dness", "--dry-run"]);
+        let config = DnsConfig::default();
+        let metrics = SharedMetrics::default();
+        let http_client = reqwest::Client::new();
+        let resolvers: Vec<Box<dyn WanResolver>> = vec![
+            Box::new(MockWanResolver(None)),
+            Box::new(MockWanResolver(Some(IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2))))),
+        ];
+
+        let outcome = run(&opt, &config, &metrics, &http_client, &resolvers).await;
+        assert_eq!(outcome.addr, Some(Ipv4Addr::new(2, 2, 2, 2)));
+        assert!(!outcome.failure);
+
+        let resolvers: Vec<Box<dyn WanResolver>> = vec![Box::new(MockWanResolver(None))];
+        let outcome = run(&opt, &config, &metrics, &http_client, &resolvers).await;
+        assert_eq!(outcome.addr, None);
+        assert!(outcome.failure);
+    }
+
+    #[tokio::test]
+    async fn test_run_circuit_breaker() {
+        let path = std::env::temp_dir().join(format!("dness-circuit-{}.json", std::process::id()));
+        let toml_str = format!(
+            r#"
+            max_retries = 0
+            state_file = '{}'
+
+            [circuit_breaker]
+            max_failures = 2
+
+            [[domains]]
+            type = "godaddy"
+            base_url = "http://127.0.0.1:1"
+            key = "key"
+            secret = "secret"
+            domain = "example.com"
+            records = ["@"]
+        "#,
+            path.display()
+        );
+        let config: DnsConfig = toml::from_str(&toml_str).unwrap();
+        let opt = Opt::parse_from(["dness"]);
+        let metrics = SharedMetrics::default();
+        let http_client = reqwest::Client::new();
+        let resolvers: Vec<Box<dyn WanResolver>> = vec![Box::new(MockWanResolver(Some(
+            IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2)),
+        )))];
+
+        for failures in 1..=2 {
+            let outcome = run(&opt, &config, &metrics, &http_client, &resolvers).await;
+            assert!(outcome.failure);
+            let state = read_state(&path).unwrap().unwrap();
+            assert_eq!(state.ipv4, None);
+            assert_eq!(state.circuits["example.com (godaddy)"].failures, failures);
+        }
+
+        // The failing domain is now skipped instead of sent another request
+        let outcome = run(&opt, &config, &metrics, &http_client, &resolvers).await;
+        assert!(!outcome.failure);
+        assert_eq!(outcome.updates.failed, 1);
+
+        // Even with the address cached, the skipped domain keeps the run from being cached, so
+        // it is tried again once its circuit closes
+        let mut state = read_state(&path).unwrap().unwrap();
+        assert_eq!(state.ipv4, None);
+        state.ipv4 = Some(Ipv4Addr::new(2, 2, 2, 2));
+        write_state(&path, &state).unwrap();
+        let outcome = run(&opt, &config, &metrics, &http_client, &resolvers).await;
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(outcome.updates.failed, 1);
+    }
+
+    #[tokio::test]
+    async fn test_run_uncaches_after_failure() {
+        let path = std::env::temp_dir().join(format!("dness-uncache-{}.json", std::process::id()));
+        let state = State {
+            ipv4: Some(Ipv4Addr::new(1, 1, 1, 1)),
+            ipv6: None,
+            updated_at: chrono::Utc::now(),
+            zones: Default::default(),
+            circuits: Default::default(),
+            last_updated_at: Default::default(),
+        };
+        write_state(&path, &state).unwrap();
+
+        let toml_str = format!(
+            r#"
+            max_retries = 0
+            state_file = '{}'
+
+            [[domains]]
+            type = "godaddy"
+            base_url = "http://127.0.0.1:1"
+            key = "key"
+            secret = "secret"
+            domain = "example.com"
+            records = ["@"]
+        "#,
+            path.display()
+        );
+        let config: DnsConfig = toml::from_str(&toml_str).unwrap();
+        let opt = Opt::parse_from(["dness"]);
+        let metrics = SharedMetrics::default();
+        let http_client = reqwest::Client::new();
+        let resolver = |ip| -> Vec<Box<dyn WanResolver>> {
+            vec![Box::new(MockWanResolver(Some(IpAddr::V4(ip))))]
+        };
+
+        // The update to the new address fails, so the old address is no longer cached
+        let resolvers = resolver(Ipv4Addr::new(2, 2, 2, 2));
+        let outcome = run(&opt, &config, &metrics, &http_client, &resolvers).await;
+        assert!(outcome.failure);
+        assert_eq!(read_state(&path).unwrap().unwrap().ipv4, None);
+
+        // Once the address flips back, the records that may be left at the new address are updated
+        let resolvers = resolver(Ipv4Addr::new(1, 1, 1, 1));
+        let outcome = run(&opt, &config, &metrics, &http_client, &resolvers).await;
+        std::fs::remove_file(&path).unwrap();
+        assert!(outcome.failure);
+    }
+
+    #[tokio::test]
+    async fn test_update_domains_request_timeout() {
+        use rouille::{Response, Server};
+
+        // Every request is slow, but each is within the timeout
+        let server = Server::new("localhost:0", |request| {
+            std::thread::sleep(std::time::Duration::from_millis(600));
+            match request.url().as_str() {
+                "/v1/domains/example.com/records/A" => Response::from_data(
+                    "application/json",
+                    r#"[{"name": "@", "data": "1.1.1.1"}, {"name": "a", "data": "1.1.1.1"}]"#,
+                ),
+                "/v1/domains/example.com/records/A/@" => Response::text("Nice job!"),
+                "/v1/domains/example.com/records/A/a" => Response::text("Nice job!"),
+                _ => Response::empty_404(),
+            }
+        })
+        .unwrap();
+        let (tx, rx) = std::sync::mpsc::sync_channel::<()>(1);
+        let addr = server.server_addr();
+        std::thread::spawn(move || {
+            while rx.try_recv().is_err() {
+                server.poll();
+                std::thread::sleep(std::time::Duration::from_millis(50))
+            }
+        });
+
+        let toml_str = format!(
+            r#"
+            max_retries = 0
+
+            [[domains]]
+            type = "godaddy"
+            base_url = "http://{}"
+            key = "key"
+            secret = "secret"
+            domain = "example.com"
+            records = ["@", "a"]
+            request_timeout_secs = 1
+        "#,
+            addr
+        );
+        let config: DnsConfig = toml::from_str(&toml_str).unwrap();
+        let http_client = reqwest::Client::new();
+        let jobs = update_jobs(&config, Ipv4Addr::new(2, 2, 2, 2), None);
+        let zones = ZoneCache::default();
+
+        // The update as a whole takes longer than the timeout
+        let results = update_domains(&http_client, &zones, &config, jobs, false, false).await;
+        tx.send(()).unwrap();
+        let (_, _, result, _) = &results[0];
+        assert_eq!(result.as_ref().unwrap().updated, 2);
+    }
+
+    #[test]
+    fn test_update_jobs_combined_ip_types() {
+        let toml_str = r#"
+            ip_resolver = "ipify"
+
+            [[domains]]
+            type = "ddnss"
+            api_key = "key"
+            hostname = "example.ddnss.de"
+            ip_types = ["v4", "v6"]
+
+            [[domains]]
+            type = "noip"
+            username = "dness"
+            password = "pass"
+            hostname = "example.com"
+            ip_types = ["v4", "v6"]
+        "#;
+        let config: DnsConfig = toml::from_str(toml_str).unwrap();
+        let addr = Ipv4Addr::new(2, 2, 2, 2);
+        let addr6 = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1);
+
+        let jobs = update_jobs(&config, addr, Some(addr6))
+            .into_iter()
+            .map(|x| (x.name, x.addr, x.addr6))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            jobs,
+            vec![
+                (
+                    String::from("example.ddnss.de (ddnss)"),
+                    IpAddr::V4(addr),
+                    Some(addr6)
+                ),
+                (
+                    String::from("example.com (noip) [ipv4]"),
+                    IpAddr::V4(addr),
+                    None
+                ),
+                (
+                    String::from("example.com (noip) [ipv6]"),
+                    IpAddr::V6(addr6),
+                    None
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_restrict_ip_type() {
+        let toml_str = r#"
+            [[domains]]
+            type = "noip"
+            username = "dness"
+            password = "pass"
+            hostname = "example.com"
+            ip_types = ["v4", "v6"]
+
+            [[domains]]
+            type = "noip"
+            username = "dness"
+            password = "pass"
+            hostname = "v4.example.com"
+        "#;
+        let config: DnsConfig = toml::from_str(toml_str).unwrap();
+
+        let mut unrestricted = config.clone();
+        restrict_ip_type(&mut unrestricted, None);
+        assert_eq!(unrestricted, config);
+
+        let mut v4 = config.clone();
+        restrict_ip_type(&mut v4, Some(IpType::V4));
+        let v4_types = v4
+            .domains
+            .iter()
+            .map(|d| d.get_ip_types())
+            .collect::<Vec<_>>();
+        assert_eq!(v4_types, vec![vec![IpType::V4], vec![IpType::V4]]);
+
+        // The ipv4 only domain is skipped
+        let mut v6 = config.clone();
+        restrict_ip_type(&mut v6, Some(IpType::V6));
+        assert_eq!(v6.domains.len(), 1);
+        assert_eq!(v6.domains[0].display_name(), "example.com (noip)");
+        assert_eq!(v6.domains[0].get_ip_types(), vec![IpType::V6]);
+    }
+
+    #[test]
+    fn test_restrict_providers() {
+        let toml_str = r#"
+            [[domains]]
+            type = "noip"
+            username = "dness"
+            password = "pass"
+            hostname = "example.com"
+
+            [[domains]]
+            type = "he"
+            hostname = "example.com"
+            password = "pass"
+            records = ["@"]
+        "#;
+        let config: DnsConfig = toml::from_str(toml_str).unwrap();
+
+        let opt = Opt::parse_from(["dness", "--providers", "NoIp, cloudflare"]);
+        let mut filtered = config.clone();
+        opt.domain_filter().apply(&mut filtered);
+        assert_eq!(filtered.domains.len(), 1);
+        assert_eq!(filtered.domains[0].display_name(), "example.com (noip)");
+
+        let mut unfiltered = config.clone();
+        Opt::parse_from(["dness"])
+            .domain_filter()
+            .apply(&mut unfiltered);
+        assert_eq!(unfiltered, config);
+
+        let providers = vec![String::from("Cloudflare"), String::from("cloudflair")];
+        assert_eq!(unknown_providers(&providers), vec!["cloudflair"]);
+    }
+
+    #[test]
+    fn test_restrict_records() {
+        let toml_str = r#"
+            [[domains]]
+            type = "godaddy"
+            domain = "example.com"
+            key = "key"
+            secret = "secret"
+            records = ["@", "sub", "www"]
+
+            [[domains]]
+            type = "cloudflare"
+            token = "dec0de"
+            zone = "example.com"
+            records = ["n.example.com"]
+
+            [[domains]]
+            type = "noip"
+            username = "dness"
+            password = "pass"
+            hostname = "sub.example.com"
+        "#;
+        let config: DnsConfig = toml::from_str(toml_str).unwrap();
+
+        let opt = Opt::parse_from([
+            "dness",
+            "--record",
+            "sub.example.com,example.com",
+            "--record",
+            "other.example.com",
+        ]);
+        let mut filtered = config.clone();
+        opt.domain_filter().apply(&mut filtered);
+
+        // The cloudflare domain is skipped as none of its records are left
+        assert_eq!(filtered.domains.len(), 2);
+        match &filtered.domains[0] {
+            DomainConfig::GoDaddy(c) => assert_eq!(c.records, vec!["@", "sub"]),
+            d => panic!("unexpected domain: {:?}", d),
+        }
+        assert_eq!(filtered.domains[1].display_name(), "sub.example.com (noip)");
+
+        let mut unfiltered = config.clone();
+        Opt::parse_from(["dness"])
+            .domain_filter()
+            .apply(&mut unfiltered);
+        assert_eq!(unfiltered, config);
+    }
+
+    #[test]
+    fn test_only_ip_type_flags() {
+        let opt = Opt::parse_from(["dness", "--ipv6-only"]);
+        assert_eq!(opt.only_ip_type(), Some(IpType::V6));
+        assert_eq!(Opt::parse_from(["dness"]).only_ip_type(), None);
+        assert!(Opt::try_parse_from(["dness", "--ipv4-only", "--ipv6-only"]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_run_min_update_interval() {
+        let path = std::env::temp_dir().join(format!("dness-interval-{}.json", std::process::id()));
+        let updated_at = chrono::Utc::now() - Duration::seconds(60);
+        let state = State {
+            ipv4: Some(Ipv4Addr::new(1, 1, 1, 1)),
+            ipv6: None,
+            updated_at,
+            zones: Default::default(),
+            circuits: Default::default(),
+            last_updated_at: LastUpdated::from([(String::from("godaddy/example.com"), updated_at)]),
+        };
+        write_state(&path, &state).unwrap();
+
+        let toml_str = format!(
+            r#"
+            max_retries = 0
+            state_file = '{}'
+            min_update_interval_secs = 900
+
+            [[domains]]
+            type = "godaddy"
+            base_url = "http://127.0.0.1:1"
+            key = "key"
+            secret = "secret"
+            domain = "example.com"
+            records = ["@"]
+        "#,
+            path.display()
+        );
+        let config: DnsConfig = toml::from_str(&toml_str).unwrap();
+        let opt = Opt::parse_from(["dness"]);
+        let metrics = SharedMetrics::default();
+        let http_client = reqwest::Client::new();
+        let resolvers: Vec<Box<dyn WanResolver>> = vec![Box::new(MockWanResolver(Some(
+            IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2)),
+        )))];
+
+        // The unreachable domain isn't sent a request as it was updated a minute ago, and the new
+        // address isn't cached as the domain hasn't been updated to it
+        let outcome = run(&opt, &config, &metrics, &http_client, &resolvers).await;
+        let actual = read_state(&path).unwrap().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(!outcome.failure);
+        assert_eq!(outcome.updates, Updates::default());
+        assert_eq!(actual.ipv4, None);
+        assert_eq!(actual.last_updated_at, state.last_updated_at);
+    }
+
+    #[tokio::test]
+    async fn test_run_min_update_interval_by_record() {
+        use rouille::{Response, Server};
+
+        // Only the record that wasn't updated recently may be updated
+        let server = Server::new("localhost:0", |request| match request.url().as_str() {
+            "/v1/domains/example.com/records/A" => Response::from_data(
+                "application/json",
+                r#"[{"name": "@", "data": "1.1.1.1"}, {"name": "a", "data": "1.1.1.1"}]"#,
+            ),
+            "/v1/domains/example.com/records/A/a" => Response::text("Nice job!"),
+            _ => Response::empty_404(),
+        })
+        .unwrap();
+        let (tx, rx) = std::sync::mpsc::sync_channel::<()>(1);
+        let addr = server.server_addr();
+        std::thread::spawn(move || {
+            while rx.try_recv().is_err() {
+                server.poll();
+                std::thread::sleep(std::time::Duration::from_millis(50))
             }
+        });
+
+        let path = std::env::temp_dir().join(format!("dness-records-{}.json", std::process::id()));
+        let updated_at = chrono::Utc::now() - Duration::seconds(60);
+        let state = State {
+            ipv4: Some(Ipv4Addr::new(1, 1, 1, 1)),
+            ipv6: None,
+            updated_at,
+            zones: Default::default(),
+            circuits: Default::default(),
+            last_updated_at: LastUpdated::from([(String::from("godaddy/example.com"), updated_at)]),
+        };
+        write_state(&path, &state).unwrap();
+
+        let toml_str = format!(
+            r#"
+            max_retries = 0
+            state_file = '{}'
+            min_update_interval_secs = 900
+
+            [[domains]]
+            type = "godaddy"
+            base_url = "http://{}"
+            key = "key"
+            secret = "secret"
+            domain = "example.com"
+            records = ["@", "a"]
+        "#,
+            path.display(),
+            addr
+        );
+        let config: DnsConfig = toml::from_str(&toml_str).unwrap();
+        let opt = Opt::parse_from(["dness"]);
+        let metrics = SharedMetrics::default();
+        let http_client = reqwest::Client::new();
+        let resolvers: Vec<Box<dyn WanResolver>> = vec![Box::new(MockWanResolver(Some(
+            IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2)),
+        )))];
+
+        let outcome = run(&opt, &config, &metrics, &http_client, &resolvers).await;
+        tx.send(()).unwrap();
+        let actual = read_state(&path).unwrap().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(!outcome.failure);
+
+        // The record held back isn't counted as current
+        let expected = Updates {
+            updated: 1,
+            ..Updates::default()
+        };
+        assert_eq!(outcome.updates, expected);
+        assert_eq!(actual.ipv4, None);
+
+        let keys: Vec<_> = actual.last_updated_at.keys().collect();
+        assert_eq!(keys, vec!["godaddy/a.example.com", "godaddy/example.com"]);
+        assert_eq!(actual.last_updated_at["godaddy/example.com"], updated_at);
+        assert!(actual.last_updated_at["godaddy/a.example.com"] > updated_at);
+    }
+
+    #[tokio::test]
+    async fn test_run_refuses_private_ip() {
+        let opt = Opt::parse_from(["dness", "--dry-run"]);
+        let mut config = DnsConfig::default();
+        let metrics = SharedMetrics::default();
+        let http_client = reqwest::Client::new();
+        let addr = Ipv4Addr::new(192, 168, 1, 1);
+        let resolvers: Vec<Box<dyn WanResolver>> =
+            vec![Box::new(MockWanResolver(Some(IpAddr::V4(addr))))];
+
+        // A private address fails the run regardless of the exit code config
+        let outcome = run(&opt, &config, &metrics, &http_client, &resolvers).await;
+        assert_eq!(outcome.addr, None);
+        assert!(outcome.failure);
+
+        config.allow_private_ips = true;
+        let outcome = run(&opt, &config, &metrics, &http_client, &resolvers).await;
+        assert_eq!(outcome.addr, Some(addr));
+        assert!(!outcome.failure);
+
+        // The networks of reject_private_ips are rejected even when private addresses are
+        // allowed, but only fail the run when asked to
+        config.reject_private_ips = true;
+        let resolvers: Vec<Box<dyn WanResolver>> = vec![Box::new(MockWanResolver(Some(
+            IpAddr::V4(Ipv4Addr::new(100, 64, 1, 1)),
+        )))];
+        let outcome = run(&opt, &config, &metrics, &http_client, &resolvers).await;
+        assert_eq!(outcome.addr, None);
+        assert!(!outcome.failure);
+
+        config.reject_private_ips_exit_code = true;
+        let outcome = run(&opt, &config, &metrics, &http_client, &resolvers).await;
+        assert_eq!(outcome.addr, None);
+        assert!(outcome.failure);
+    }
+
+    #[tokio::test]
+    async fn test_reload_on_change() {
+        let dir = std::env::temp_dir().join(format!("dness-watch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("dness.toml");
+        std::fs::write(&path, "max_retries = 1").unwrap();
+        let config = SharedConfig::new(RwLock::new(
+            parse_config_files(std::slice::from_ref(&path)).unwrap(),
+        ));
+        spawn_reload_on_change(vec![path.clone()], DomainFilter::default(), config.clone())
+            .unwrap();
+
+        // An invalid config is ignored
+        std::fs::write(&path, "max_retries = \"many\"").unwrap();
+        tokio::time::sleep(RELOAD_DEBOUNCE * 2).await;
+        assert_eq!(config.read().unwrap().max_retries, 1);
+
+        std::fs::write(&path, "max_retries = 5").unwrap();
+        for _ in 0..100 {
+            if config.read().unwrap().max_retries == 5 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
         }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(config.read().unwrap().max_retries, 5);
     }
 
-    info!("processed all: ({}) in {}", total_updates, elapsed(start));
-    if failure {
-        error!("at least one update failed, so exiting with non-zero status code");
-        std::process::exit(1)
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_reload_on_hangup() {
+        use nix::sys::signal::{kill, Signal};
+        use nix::unistd::Pid;
+
+        let path = std::env::temp_dir().join(format!("dness-reload-{}.toml", std::process::id()));
+        std::fs::write(&path, "max_retries = 1").unwrap();
+        let config = SharedConfig::new(RwLock::new(
+            parse_config_files(std::slice::from_ref(&path)).unwrap(),
+        ));
+        spawn_reload_on_hangup(vec![path.clone()], DomainFilter::default(), config.clone())
+            .unwrap();
+
+        // An invalid config is ignored
+        std::fs::write(&path, "max_retries = \"many\"").unwrap();
+        kill(Pid::this(), Signal::SIGHUP).unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        assert_eq!(config.read().unwrap().max_retries, 1);
+
+        std::fs::write(&path, "max_retries = 5").unwrap();
+        kill(Pid::this(), Signal::SIGHUP).unwrap();
+        for _ in 0..50 {
+            if config.read().unwrap().max_retries == 5 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(config.read().unwrap().max_retries, 5);
     }
 }
@@ -0,0 +1,218 @@
+use crate::core::Updates;
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::{extract::State, Router};
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// The same default buckets as the official prometheus clients
+const RESOLUTION_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+pub type SharedMetrics = Arc<Mutex<MetricsState>>;
+
+#[derive(Debug, Default)]
+struct Histogram {
+    counts: [u64; RESOLUTION_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        for (bucket, count) in RESOLUTION_BUCKETS.iter().zip(self.counts.iter_mut()) {
+            if value <= *bucket {
+                *count += 1;
+            }
+        }
+
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+/// The metrics accumulated over the lifetime of the process
+#[derive(Debug, Default)]
+pub struct MetricsState {
+    /// Keyed by provider, domain, and update type
+    updates: BTreeMap<(String, String, &'static str), u64>,
+    errors: BTreeMap<String, u64>,
+    resolution: Histogram,
+    last_success: Option<DateTime<Utc>>,
+}
+
+impl MetricsState {
+    pub fn record_updates(&mut self, provider: &str, domain: &str, updates: &Updates) {
+        let counts = [
+            ("updated", updates.updated),
+            ("current", updates.current),
+            ("missing", updates.missing),
+        ];
+
+        for (kind, count) in counts {
+            let key = (String::from(provider), String::from(domain), kind);
+            *self.updates.entry(key).or_default() += count.max(0) as u64;
+        }
+    }
+
+    pub fn record_error(&mut self, provider: &str) {
+        *self.errors.entry(String::from(provider)).or_default() += 1;
+    }
+
+    pub fn observe_resolution(&mut self, elapsed: Duration) {
+        self.resolution.observe(elapsed.as_secs_f64());
+    }
+
+    pub fn record_success(&mut self, at: DateTime<Utc>) {
+        self.last_success = Some(at);
+    }
+
+    /// Renders the metrics in the prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        let _ = writeln!(
+            out,
+            "# HELP dness_updates_total Records processed by outcome"
+        );
+        let _ = writeln!(out, "# TYPE dness_updates_total counter");
+        for ((provider, domain, kind), count) in &self.updates {
+            let _ = writeln!(
+                out,
+                "dness_updates_total{{provider=\"{}\",domain=\"{}\",type=\"{}\"}} {}",
+                escape(provider),
+                escape(domain),
+                kind,
+                count
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP dness_update_errors_total Failed provider updates"
+        );
+        let _ = writeln!(out, "# TYPE dness_update_errors_total counter");
+        for (provider, count) in &self.errors {
+            let _ = writeln!(
+                out,
+                "dness_update_errors_total{{provider=\"{}\"}} {}",
+                escape(provider),
+                count
+            );
+        }
+
+        let name = "dness_wan_ip_resolution_duration_seconds";
+        let _ = writeln!(out, "# HELP {} Time taken to resolve the WAN IP", name);
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        for (bucket, count) in RESOLUTION_BUCKETS.iter().zip(self.resolution.counts.iter()) {
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bucket, count);
+        }
+        let count = self.resolution.count;
+        let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, count);
+        let _ = writeln!(out, "{}_sum {}", name, self.resolution.sum);
+        let _ = writeln!(out, "{}_count {}", name, count);
+
+        if let Some(at) = self.last_success {
+            let name = "dness_last_successful_update_timestamp_seconds";
+            let _ = writeln!(out, "# HELP {} When all domains were last processed", name);
+            let _ = writeln!(out, "# TYPE {} gauge", name);
+            let _ = writeln!(out, "{} {}", name, at.timestamp());
+        }
+
+        out
+    }
+}
+
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+async fn metrics_handler(State(metrics): State<SharedMetrics>) -> impl IntoResponse {
+    let body = metrics.lock().map(|m| m.render()).unwrap_or_default();
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body)
+}
+
+/// Serves the metrics at "/metrics" until the process exits
+pub async fn serve(listen_addr: &str, metrics: SharedMetrics) -> Result<(), std::io::Error> {
+    let app = Router::new()
+        .route("/metrics", get(metrics_handler))
+        .with_state(metrics);
+    let listener = tokio::net::TcpListener::bind(listen_addr).await?;
+    axum::serve(listener, app).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_metrics_render() {
+        let mut metrics = MetricsState::default();
+        let updates = Updates {
+            updated: 1,
+            current: 2,
+            ..Updates::default()
+        };
+        metrics.record_updates("cloudflare", "example.com", &updates);
+        metrics.record_updates("cloudflare", "example.com", &updates);
+        metrics.record_error("godaddy");
+        metrics.observe_resolution(Duration::from_millis(200));
+        metrics.record_success(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap());
+
+        let out = metrics.render();
+        let labels = "provider=\"cloudflare\",domain=\"example.com\"";
+        assert!(out.contains(&format!(
+            "dness_updates_total{{{},type=\"updated\"}} 2",
+            labels
+        )));
+        assert!(out.contains(&format!(
+            "dness_updates_total{{{},type=\"current\"}} 4",
+            labels
+        )));
+        assert!(out.contains(&format!(
+            "dness_updates_total{{{},type=\"missing\"}} 0",
+            labels
+        )));
+        assert!(out.contains("dness_update_errors_total{provider=\"godaddy\"} 1"));
+        assert!(out.contains("dness_wan_ip_resolution_duration_seconds_bucket{le=\"0.1\"} 0"));
+        assert!(out.contains("dness_wan_ip_resolution_duration_seconds_bucket{le=\"0.25\"} 1"));
+        assert!(out.contains("dness_wan_ip_resolution_duration_seconds_count 1"));
+        assert!(out.contains("dness_last_successful_update_timestamp_seconds 1704067200"));
+    }
+
+    #[tokio::test]
+    async fn test_metrics_serve() {
+        let metrics = SharedMetrics::default();
+        metrics.lock().unwrap().record_error("godaddy");
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+
+        let listen_addr = addr.to_string();
+        tokio::spawn(async move { serve(&listen_addr, metrics).await });
+
+        let url = format!("http://{}/metrics", addr);
+        let mut body = None;
+        for _ in 0..20 {
+            if let Ok(response) = reqwest::get(&url).await {
+                body = Some(response.text().await.unwrap());
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+
+        assert!(body
+            .unwrap()
+            .contains("dness_update_errors_total{provider=\"godaddy\"} 1"));
+    }
+}
@@ -1,9 +1,50 @@
-use crate::config::NamecheapConfig;
-use crate::core::Updates;
+use crate::config::{IpType, NamecheapConfig};
+use crate::core::{dry_run_prefix, DnsProvider, SendTraced, UpdateContext, Updates};
 use crate::dns::DnsResolver;
 use crate::errors::DnessError;
-use log::{info, warn};
-use std::net::Ipv4Addr;
+use crate::retry::retry;
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::net::IpAddr;
+use tracing::{info, warn};
+
+/// The subset of namecheap's xml interface response that denotes success
+#[derive(Deserialize, PartialEq, Clone, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct NamecheapResponse {
+    command: String,
+    err_count: u32,
+    #[serde(rename = "errors", default)]
+    errors: NamecheapErrors,
+}
+
+#[derive(Deserialize, PartialEq, Clone, Debug, Default)]
+struct NamecheapErrors {
+    #[serde(rename = "Err1")]
+    err1: Option<String>,
+}
+
+fn parse_response(body: &str) -> Result<(), DnessError> {
+    let response: NamecheapResponse = quick_xml::de::from_str(body).map_err(|e| {
+        DnessError::message(format!(
+            "unable to parse namecheap response ({}): {}",
+            e, body
+        ))
+    })?;
+
+    if response.err_count == 0 {
+        return Ok(());
+    }
+
+    let message = response
+        .errors
+        .err1
+        .unwrap_or_else(|| String::from("unknown error"));
+    Err(DnessError::message(format!(
+        "{} error [{}]: {}",
+        response.command, response.err_count, message
+    )))
+}
 
 #[derive(Debug)]
 pub struct NamecheapProvider<'a> {
@@ -13,9 +54,13 @@ pub struct NamecheapProvider<'a> {
 
 impl<'a> NamecheapProvider<'a> {
     /// https://www.namecheap.com/support/knowledgebase/article.aspx/29/11/how-do-i-use-a-browser-to-dynamically-update-the-hosts-ip
-    pub async fn update_domain(&self, host: &str, wan: Ipv4Addr) -> Result<(), DnessError> {
+    pub async fn update_domain(&self, host: &str, wan: IpAddr) -> Result<(), DnessError> {
         let base = self.config.base_url.trim_end_matches('/').to_string();
-        let get_url = format!("{}/update", base);
+        let endpoint = match wan {
+            IpAddr::V4(_) => "update",
+            IpAddr::V6(_) => "update6",
+        };
+        let get_url = format!("{}/{}", base, endpoint);
         let response = self
             .client
             .get(&get_url)
@@ -25,7 +70,7 @@ impl<'a> NamecheapProvider<'a> {
                 ("password", &self.config.ddns_password),
                 ("ip", &wan.to_string()),
             ])
-            .send()
+            .send_traced()
             .await
             .map_err(|e| DnessError::send_http(&get_url, "namecheap update", e))?
             .error_for_status()
@@ -34,21 +79,17 @@ impl<'a> NamecheapProvider<'a> {
             .await
             .map_err(|e| DnessError::deserialize(&get_url, "namecheap update", e))?;
 
-        if !response.contains("<ErrCount>0</ErrCount>") {
-            Err(DnessError::message(format!(
-                "expected zero errors, but received: {}",
-                response
-            )))
-        } else {
-            Ok(())
-        }
+        parse_response(&response)
     }
 }
 
+#[tracing::instrument(skip(client, config), fields(domain = %config.domain))]
 pub async fn update_domains(
     client: &reqwest::Client,
     config: &NamecheapConfig,
-    wan: Ipv4Addr,
+    wan: IpAddr,
+    dry_run: bool,
+    force: bool,
 ) -> Result<Updates, DnessError> {
     // Use cloudflare's DNS to query all the configured records. Ideally we'd use dns
     // over tls for privacy purposes but that feature is experimental and we don't want to rely on
@@ -71,17 +112,38 @@ pub async fn update_domains(
             format!("{}.{}.", record, config.domain)
         };
 
-        let response = resolver.ipv4_lookup(&dns_query).await;
+        if force {
+            if !dry_run {
+                namecheap.update_domain(record, wan).await?;
+            }
+            info!(
+                "{}{} from domain {} forcibly updated to {}",
+                dry_run_prefix(dry_run),
+                record,
+                config.domain,
+                wan
+            );
+            results.updated += 1;
+            continue;
+        }
+
+        let response = resolver.ip_lookup(&dns_query, IpType::from(wan)).await;
 
         match response {
             Ok(ip) => {
                 if ip == wan {
                     results.current += 1;
                 } else {
-                    namecheap.update_domain(record, wan).await?;
+                    if !dry_run {
+                        namecheap.update_domain(record, wan).await?;
+                    }
                     info!(
-                        "{} from domain {} updated from {} to {}",
-                        record, config.domain, ip, wan
+                        "{}{} from domain {} updated from {} to {}",
+                        dry_run_prefix(dry_run),
+                        record,
+                        config.domain,
+                        ip,
+                        wan
                     );
                     results.updated += 1;
                 }
@@ -100,9 +162,29 @@ pub async fn update_domains(
     Ok(results)
 }
 
+#[async_trait]
+impl DnsProvider for NamecheapConfig {
+    async fn update(
+        &self,
+        ctx: &UpdateContext<'_>,
+        addr: IpAddr,
+    ) -> Result<Updates, Box<dyn std::error::Error>> {
+        retry(ctx.policy, ctx.name, || {
+            update_domains(ctx.client, self, addr, ctx.dry_run, ctx.force)
+        })
+        .await
+        .map_err(|e| e.into())
+    }
+
+    fn name(&self) -> &str {
+        "namecheap"
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr};
 
     macro_rules! namecheap_server {
         () => {{
@@ -110,7 +192,7 @@ mod tests {
             use rouille::Server;
 
             let server = Server::new("localhost:0", |request| match request.url().as_str() {
-                "/update" => Response::from_data(
+                "/update" | "/update6" => Response::from_data(
                     "text/html",
                     include_bytes!("../assets/namecheap-update.xml").to_vec(),
                 ),
@@ -130,19 +212,74 @@ mod tests {
         }};
     }
 
+    #[test]
+    fn test_parse_success_response() {
+        let body = include_str!("../assets/namecheap-update.xml");
+        assert!(parse_response(body).is_ok());
+    }
+
+    #[test]
+    fn test_parse_error_response() {
+        let body = include_str!("../assets/namecheap-update-error.xml");
+        let err = parse_response(body).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "SETDNSHOST error [1]: Passwords do not match"
+        );
+    }
+
     #[tokio::test]
     async fn test_namecheap_update() {
         let (tx, addr) = namecheap_server!();
         let http_client = reqwest::Client::new();
-        let new_ip = Ipv4Addr::new(2, 2, 2, 2);
+        let new_ip = IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2));
         let config = NamecheapConfig {
             base_url: format!("http://{}", addr),
             domain: String::from("example.com"),
             ddns_password: String::from("secret-1"),
             records: vec![String::from("@")],
+            request_timeout_secs: None,
+            ip_types: vec![IpType::V4],
+            log_level: None,
+            name: None,
         };
 
-        let summary = update_domains(&http_client, &config, new_ip).await.unwrap();
+        let summary = update_domains(&http_client, &config, new_ip, false, false)
+            .await
+            .unwrap();
+        tx.send(()).unwrap();
+
+        assert_eq!(
+            summary,
+            Updates {
+                current: 0,
+                updated: 1,
+                missing: 0,
+                failed: 0,
+                dry_run: false,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_namecheap_ipv6_force_update() {
+        let (tx, addr) = namecheap_server!();
+        let http_client = reqwest::Client::new();
+        let new_ip = IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1));
+        let config = NamecheapConfig {
+            base_url: format!("http://{}", addr),
+            domain: String::from("example.com"),
+            ddns_password: String::from("secret-1"),
+            records: vec![String::from("@")],
+            request_timeout_secs: None,
+            ip_types: vec![IpType::V6],
+            log_level: None,
+            name: None,
+        };
+
+        let summary = update_domains(&http_client, &config, new_ip, false, true)
+            .await
+            .unwrap();
         tx.send(()).unwrap();
 
         assert_eq!(
@@ -151,6 +288,8 @@ mod tests {
                 current: 0,
                 updated: 1,
                 missing: 0,
+                failed: 0,
+                dry_run: false,
             }
         );
     }
@@ -0,0 +1,425 @@
+use crate::config::NamecheapApiConfig;
+use crate::core::{dry_run_prefix, require_ipv4, DnsProvider, SendTraced, UpdateContext, Updates};
+use crate::errors::DnessError;
+use crate::retry::retry;
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::net::{IpAddr, Ipv4Addr};
+use tracing::{debug, info};
+
+/// The envelope of every response from the namecheap xml api
+#[derive(Deserialize, PartialEq, Clone, Debug)]
+struct ApiResponse<T> {
+    #[serde(rename = "@Status")]
+    status: String,
+    #[serde(rename = "Errors", default)]
+    errors: ApiErrors,
+    #[serde(rename = "CommandResponse")]
+    command_response: Option<T>,
+}
+
+#[derive(Deserialize, PartialEq, Clone, Debug, Default)]
+struct ApiErrors {
+    #[serde(rename = "Error", default)]
+    error: Vec<ApiError>,
+}
+
+#[derive(Deserialize, PartialEq, Clone, Debug)]
+struct ApiError {
+    #[serde(rename = "@Number")]
+    number: String,
+    #[serde(rename = "$text", default)]
+    message: String,
+}
+
+#[derive(Deserialize, PartialEq, Clone, Debug)]
+struct GetHostsResponse {
+    #[serde(rename = "DomainDNSGetHostsResult")]
+    result: GetHostsResult,
+}
+
+#[derive(Deserialize, PartialEq, Clone, Debug)]
+struct GetHostsResult {
+    #[serde(rename = "@EmailType")]
+    email_type: Option<String>,
+    #[serde(rename = "host", default)]
+    hosts: Vec<NamecheapHost>,
+}
+
+#[derive(Deserialize, PartialEq, Clone, Debug)]
+struct NamecheapHost {
+    #[serde(rename = "@Name")]
+    name: String,
+    #[serde(rename = "@Type")]
+    r#type: String,
+    #[serde(rename = "@Address")]
+    address: String,
+    #[serde(rename = "@MXPref")]
+    mx_pref: String,
+    #[serde(rename = "@TTL")]
+    ttl: String,
+}
+
+#[derive(Deserialize, PartialEq, Clone, Debug)]
+struct SetHostsResponse {
+    #[serde(rename = "DomainDNSSetHostsResult")]
+    result: SetHostsResult,
+}
+
+#[derive(Deserialize, PartialEq, Clone, Debug)]
+struct SetHostsResult {
+    #[serde(rename = "@IsSuccess")]
+    is_success: bool,
+}
+
+fn parse_response<T: DeserializeOwned>(command: &str, body: &str) -> Result<T, DnessError> {
+    let response: ApiResponse<T> = quick_xml::de::from_str(body).map_err(|e| {
+        DnessError::message(format!(
+            "unable to parse namecheap {} response ({}): {}",
+            command, e, body
+        ))
+    })?;
+
+    if !response.status.eq_ignore_ascii_case("OK") {
+        let errors = response
+            .errors
+            .error
+            .iter()
+            .map(|e| format!("[{}] {}", e.number, e.message))
+            .collect::<Vec<_>>();
+        return Err(DnessError::message(format!(
+            "namecheap {} error: {}",
+            command,
+            errors.join(", ")
+        )));
+    }
+
+    response.command_response.ok_or_else(|| {
+        DnessError::message(format!(
+            "namecheap {} response is missing the command response: {}",
+            command, body
+        ))
+    })
+}
+
+#[derive(Debug)]
+struct NamecheapApiClient<'a> {
+    client: &'a reqwest::Client,
+    config: &'a NamecheapApiConfig,
+    dry_run: bool,
+}
+
+impl<'a> NamecheapApiClient<'a> {
+    // The api addresses a domain by its second level domain and the remaining tld (eg: "co.uk")
+    fn sld_tld(&self) -> (&str, &str) {
+        self.config
+            .domain
+            .split_once('.')
+            .unwrap_or((&self.config.domain, ""))
+    }
+
+    fn command(&self, method: reqwest::Method, command: &str) -> reqwest::RequestBuilder {
+        let (sld, tld) = self.sld_tld();
+        self.client.request(method, &self.config.base_url).query(&[
+            ("ApiUser", self.config.api_user.as_str()),
+            ("ApiKey", &self.config.api_key),
+            ("UserName", &self.config.username),
+            ("ClientIp", &self.config.client_ip),
+            ("Command", command),
+            ("SLD", sld),
+            ("TLD", tld),
+        ])
+    }
+
+    /// https://www.namecheap.com/support/api/methods/domains-dns/get-hosts/
+    async fn get_hosts(&self) -> Result<GetHostsResult, DnessError> {
+        let url = &self.config.base_url;
+        let body = self
+            .command(reqwest::Method::GET, "namecheap.domains.dns.getHosts")
+            .send_traced()
+            .await
+            .map_err(|e| DnessError::send_http(url, "namecheap get hosts", e))?
+            .error_for_status()
+            .map_err(|e| DnessError::bad_response(url, "namecheap get hosts", e))?
+            .text()
+            .await
+            .map_err(|e| DnessError::deserialize(url, "namecheap get hosts", e))?;
+
+        let response: GetHostsResponse = parse_response("get hosts", &body)?;
+        Ok(response.result)
+    }
+
+    /// Replaces every host in the domain, so hosts that aren't managed by dness must be sent back
+    /// unchanged. https://www.namecheap.com/support/api/methods/domains-dns/set-hosts/
+    async fn set_hosts(
+        &self,
+        hosts: &[NamecheapHost],
+        email_type: Option<&String>,
+    ) -> Result<(), DnessError> {
+        if self.dry_run {
+            return Ok(());
+        }
+
+        let mut form = Vec::new();
+        for (i, host) in hosts.iter().enumerate() {
+            let i = i + 1;
+            form.push((format!("HostName{}", i), host.name.clone()));
+            form.push((format!("RecordType{}", i), host.r#type.clone()));
+            form.push((format!("Address{}", i), host.address.clone()));
+            form.push((format!("MXPref{}", i), host.mx_pref.clone()));
+            form.push((format!("TTL{}", i), host.ttl.clone()));
+        }
+
+        if let Some(email_type) = email_type {
+            form.push((String::from("EmailType"), email_type.clone()));
+        }
+
+        let url = &self.config.base_url;
+        let body = self
+            .command(reqwest::Method::POST, "namecheap.domains.dns.setHosts")
+            .form(&form)
+            .send_traced()
+            .await
+            .map_err(|e| DnessError::send_http(url, "namecheap set hosts", e))?
+            .error_for_status()
+            .map_err(|e| DnessError::bad_response(url, "namecheap set hosts", e))?
+            .text()
+            .await
+            .map_err(|e| DnessError::deserialize(url, "namecheap set hosts", e))?;
+
+        let response: SetHostsResponse = parse_response("set hosts", &body)?;
+        if !response.result.is_success {
+            return Err(DnessError::message(format!(
+                "namecheap did not set hosts for {}",
+                self.config.domain
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// The namecheap xml api returns every host in the domain, so unlike the dynamic dns endpoint, no
+/// dns query is needed to know if a record is current:
+///
+/// 1. Fetch all hosts in the domain
+/// 2. Find all the expected A records (and log those that are missing) and check their address
+/// 3. If any are out of date, send all the hosts back with the new address, as namecheap only
+///    supports replacing every host at once
+#[tracing::instrument(skip(client, config), fields(domain = %config.domain))]
+pub async fn update_domains(
+    client: &reqwest::Client,
+    config: &NamecheapApiConfig,
+    addr: Ipv4Addr,
+    dry_run: bool,
+) -> Result<Updates, DnessError> {
+    let namecheap = NamecheapApiClient {
+        client,
+        config,
+        dry_run,
+    };
+
+    let GetHostsResult {
+        email_type,
+        mut hosts,
+    } = namecheap.get_hosts().await?;
+
+    let expected = config.records.iter().cloned().collect::<HashSet<_>>();
+    let actual = hosts
+        .iter()
+        .filter(|x| x.r#type == "A")
+        .map(|x| x.name.clone())
+        .collect::<HashSet<_>>();
+    let missing = crate::core::log_missing_domains(&expected, &actual, "Namecheap", &config.domain);
+
+    let mut summary = Updates {
+        missing: missing as i32,
+        ..Updates::default()
+    };
+
+    for host in hosts
+        .iter_mut()
+        .filter(|x| x.r#type == "A" && expected.contains(&x.name))
+    {
+        if host.address.parse::<Ipv4Addr>().ok() == Some(addr) {
+            summary.current += 1;
+            debug!(
+                "{} from domain {} is already current",
+                host.name, config.domain
+            );
+        } else {
+            summary.updated += 1;
+            info!(
+                "{}{} from domain {} updated from {} to {}",
+                dry_run_prefix(dry_run),
+                host.name,
+                config.domain,
+                host.address,
+                addr
+            );
+            host.address = addr.to_string();
+        }
+    }
+
+    if summary.updated > 0 {
+        namecheap.set_hosts(&hosts, email_type.as_ref()).await?;
+    }
+
+    Ok(summary)
+}
+
+#[async_trait]
+impl DnsProvider for NamecheapApiConfig {
+    async fn update(
+        &self,
+        ctx: &UpdateContext<'_>,
+        addr: IpAddr,
+    ) -> Result<Updates, Box<dyn std::error::Error>> {
+        let addr = require_ipv4(addr, ctx.name)?;
+        retry(ctx.policy, ctx.name, || {
+            update_domains(ctx.client, self, addr, ctx.dry_run)
+        })
+        .await
+        .map_err(|e| e.into())
+    }
+
+    fn name(&self) -> &str {
+        "namecheapapi"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! namecheap_api_server {
+        () => {{
+            use rouille::Response;
+            use rouille::Server;
+
+            let server = Server::new("localhost:0", |request| {
+                if request.url() != "/xml.response" {
+                    return Response::empty_404();
+                }
+
+                match request.get_param("Command").as_deref() {
+                    Some("namecheap.domains.dns.getHosts") => Response::from_data(
+                        "text/xml",
+                        include_bytes!("../assets/namecheap-api-get-hosts.xml").to_vec(),
+                    ),
+                    Some("namecheap.domains.dns.setHosts") => {
+                        // All hosts, including the MX and TXT records, must be sent back
+                        let form = rouille::input::post::raw_urlencoded_post_input(request)
+                            .unwrap_or_default();
+                        let has = |key: &str, value: &str| {
+                            form.iter().any(|(k, v)| k == key && v == value)
+                        };
+                        if has("Address1", "2.2.2.1")
+                            && has("Address2", "2.2.2.1")
+                            && has("TTL2", "1799")
+                            && has("RecordType4", "TXT")
+                            && has("EmailType", "MX")
+                        {
+                            Response::from_data(
+                                "text/xml",
+                                include_bytes!("../assets/namecheap-api-set-hosts.xml").to_vec(),
+                            )
+                        } else {
+                            Response::from_data(
+                                "text/xml",
+                                include_bytes!("../assets/namecheap-api-error.xml").to_vec(),
+                            )
+                        }
+                    }
+                    _ => Response::empty_404(),
+                }
+            })
+            .unwrap();
+
+            let (tx, rx) = std::sync::mpsc::sync_channel(1);
+            let addr = server.server_addr().clone();
+            std::thread::spawn(move || {
+                while let Err(_) = rx.try_recv() {
+                    server.poll();
+                    std::thread::sleep(std::time::Duration::from_millis(50))
+                }
+            });
+            (tx, addr)
+        }};
+    }
+
+    fn config(addr: std::net::SocketAddr, records: &[&str]) -> NamecheapApiConfig {
+        NamecheapApiConfig {
+            base_url: format!("http://{}/xml.response", addr),
+            api_user: String::from("dness"),
+            api_key: String::from("deadbeef"),
+            username: String::from("dness"),
+            client_ip: String::from("1.1.1.1"),
+            domain: String::from("example.com"),
+            records: records.iter().map(|x| String::from(*x)).collect(),
+            request_timeout_secs: None,
+            log_level: None,
+            name: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_error_response() {
+        let body = include_str!("../assets/namecheap-api-error.xml");
+        let err = parse_response::<GetHostsResponse>("get hosts", body).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "namecheap get hosts error: [1011150] Invalid request IP: 1.1.1.1"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_namecheap_api_update() {
+        let (tx, addr) = namecheap_api_server!();
+        let http_client = reqwest::Client::new();
+        let new_ip = Ipv4Addr::new(2, 2, 2, 1);
+        let config = config(addr, &["@", "sub"]);
+
+        let summary = update_domains(&http_client, &config, new_ip, false)
+            .await
+            .unwrap();
+        tx.send(()).unwrap();
+
+        assert_eq!(
+            summary,
+            Updates {
+                current: 0,
+                updated: 2,
+                missing: 0,
+                failed: 0,
+                dry_run: false,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_namecheap_api_current() {
+        let (tx, addr) = namecheap_api_server!();
+        let http_client = reqwest::Client::new();
+        let new_ip = Ipv4Addr::new(2, 2, 2, 2);
+        let config = config(addr, &["@", "sub", "www"]);
+
+        let summary = update_domains(&http_client, &config, new_ip, false)
+            .await
+            .unwrap();
+        tx.send(()).unwrap();
+
+        assert_eq!(
+            summary,
+            Updates {
+                current: 2,
+                updated: 0,
+                missing: 1,
+                failed: 0,
+                dry_run: false,
+            }
+        );
+    }
+}
@@ -0,0 +1,369 @@
+use crate::config::{IpType, NameComConfig};
+use crate::core::{dry_run_prefix, DnsProvider, SendTraced, UpdateContext, Updates};
+use crate::errors::DnessError;
+use crate::retry::retry;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::net::IpAddr;
+use tracing::{debug, info};
+
+/// The ttl of records that are created, which is the minimum name.com allows
+const DEFAULT_TTL: u32 = 300;
+
+#[derive(Deserialize, PartialEq, Clone, Debug)]
+struct ListRecordsResponse {
+    #[serde(default)]
+    records: Vec<NameComRecord>,
+}
+
+#[derive(Deserialize, PartialEq, Clone, Debug)]
+struct NameComRecord {
+    id: i64,
+    #[serde(default)]
+    host: String,
+    r#type: String,
+    answer: String,
+    ttl: u32,
+}
+
+/// The body used to create or replace a record
+#[derive(Serialize, PartialEq, Clone, Debug)]
+struct NameComRecordBody<'a> {
+    host: &'a str,
+    r#type: &'a str,
+    answer: String,
+    ttl: u32,
+}
+
+// Name.com denotes the root of the domain with an empty host
+fn host_name(name: &str) -> &str {
+    if name == "@" {
+        ""
+    } else {
+        name
+    }
+}
+
+#[derive(Debug)]
+struct NameComClient<'a> {
+    records_url: String,
+    config: &'a NameComConfig,
+    client: &'a reqwest::Client,
+    dry_run: bool,
+}
+
+impl<'a> NameComClient<'a> {
+    /// https://www.name.com/api-docs/DNS#ListRecords
+    async fn list_records(&self) -> Result<Vec<NameComRecord>, DnessError> {
+        let url = &self.records_url;
+        let response: ListRecordsResponse = self
+            .client
+            .get(url)
+            .basic_auth(&self.config.username, Some(&self.config.token))
+            .send_traced()
+            .await
+            .map_err(|e| DnessError::send_http(url, "namecom list records", e))?
+            .error_for_status()
+            .map_err(|e| DnessError::bad_response(url, "namecom list records", e))?
+            .json()
+            .await
+            .map_err(|e| DnessError::deserialize(url, "namecom list records", e))?;
+        Ok(response.records)
+    }
+
+    /// Replaces the record with the new address, sending back its host, type, and ttl as name.com
+    /// would otherwise reset them. https://www.name.com/api-docs/DNS#UpdateRecord
+    async fn update_record(&self, record: &NameComRecord, addr: IpAddr) -> Result<(), DnessError> {
+        if self.dry_run {
+            return Ok(());
+        }
+
+        let url = format!("{}/{}", self.records_url, record.id);
+        self.client
+            .put(&url)
+            .basic_auth(&self.config.username, Some(&self.config.token))
+            .json(&NameComRecordBody {
+                host: &record.host,
+                r#type: &record.r#type,
+                answer: addr.to_string(),
+                ttl: record.ttl,
+            })
+            .send_traced()
+            .await
+            .map_err(|e| DnessError::send_http(&url, "namecom update record", e))?
+            .error_for_status()
+            .map_err(|e| DnessError::bad_response(&url, "namecom update record", e))?;
+
+        Ok(())
+    }
+
+    /// https://www.name.com/api-docs/DNS#CreateRecord
+    async fn create_record(&self, host: &str, addr: IpAddr) -> Result<(), DnessError> {
+        if self.dry_run {
+            return Ok(());
+        }
+
+        let url = &self.records_url;
+        self.client
+            .post(url)
+            .basic_auth(&self.config.username, Some(&self.config.token))
+            .json(&NameComRecordBody {
+                host,
+                r#type: IpType::from(addr).record_type(),
+                answer: addr.to_string(),
+                ttl: DEFAULT_TTL,
+            })
+            .send_traced()
+            .await
+            .map_err(|e| DnessError::send_http(url, "namecom create record", e))?
+            .error_for_status()
+            .map_err(|e| DnessError::bad_response(url, "namecom create record", e))?;
+
+        Ok(())
+    }
+}
+
+/// Name.com is updated as follows:
+///
+/// 1. Send a GET request to list all records in the domain
+/// 2. Find all the expected records of the address type (and log those that are missing) and
+///    check their answer
+/// 3. Update the records that are out of date, preserving their ttl
+/// 4. Create the missing records when `create_missing` is set
+#[tracing::instrument(skip(client, config), fields(domain = %config.domain))]
+pub async fn update_domains(
+    client: &reqwest::Client,
+    config: &NameComConfig,
+    addr: IpAddr,
+    dry_run: bool,
+) -> Result<Updates, DnessError> {
+    let namecom = NameComClient {
+        records_url: format!(
+            "{}/v4/domains/{}/records",
+            config.base_url.trim_end_matches('/'),
+            config.domain
+        ),
+        config,
+        client,
+        dry_run,
+    };
+
+    let record_type = IpType::from(addr).record_type();
+    let records = namecom
+        .list_records()
+        .await?
+        .into_iter()
+        .filter(|x| x.r#type == record_type)
+        .collect::<Vec<_>>();
+
+    let expected = config
+        .records
+        .iter()
+        .map(|x| String::from(host_name(x)))
+        .collect::<HashSet<_>>();
+    let actual = records
+        .iter()
+        .map(|x| x.host.clone())
+        .collect::<HashSet<_>>();
+    let missing = crate::core::log_missing_domains(&expected, &actual, "Name.com", &config.domain);
+
+    let mut summary = Updates {
+        missing: missing as i32,
+        ..Updates::default()
+    };
+
+    if config.create_missing {
+        let mut missing = expected.difference(&actual).collect::<Vec<_>>();
+        missing.sort();
+        for host in missing {
+            namecom.create_record(host, addr).await?;
+            summary.missing -= 1;
+            summary.updated += 1;
+            info!(
+                "{}{} from domain {} created with {}",
+                dry_run_prefix(dry_run),
+                host,
+                config.domain,
+                addr
+            );
+        }
+    }
+
+    for record in records.iter().filter(|x| expected.contains(&x.host)) {
+        if record.answer.parse::<IpAddr>().ok() == Some(addr) {
+            summary.current += 1;
+            debug!(
+                "{} from domain {} is already current",
+                record.host, config.domain
+            );
+        } else {
+            namecom.update_record(record, addr).await?;
+            summary.updated += 1;
+            info!(
+                "{}{} from domain {} updated from {} to {}",
+                dry_run_prefix(dry_run),
+                record.host,
+                config.domain,
+                record.answer,
+                addr
+            );
+        }
+    }
+
+    Ok(summary)
+}
+
+#[async_trait]
+impl DnsProvider for NameComConfig {
+    async fn update(
+        &self,
+        ctx: &UpdateContext<'_>,
+        addr: IpAddr,
+    ) -> Result<Updates, Box<dyn std::error::Error>> {
+        retry(ctx.policy, ctx.name, || {
+            update_domains(ctx.client, self, addr, ctx.dry_run)
+        })
+        .await
+        .map_err(|e| e.into())
+    }
+
+    fn name(&self) -> &str {
+        "namecom"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    macro_rules! namecom_server {
+        () => {{
+            use rouille::Response;
+            use rouille::Server;
+
+            let server = Server::new("localhost:0", |request| {
+                // "dness:deadbeef" in base64
+                if request.header("Authorization") != Some("Basic ZG5lc3M6ZGVhZGJlZWY=") {
+                    return Response::text("unauthorized").with_status_code(401);
+                }
+
+                match (request.method(), request.url().as_str()) {
+                    ("GET", "/v4/domains/example.com/records") => Response::from_data(
+                        "application/json",
+                        include_bytes!("../assets/namecom-records.json").to_vec(),
+                    ),
+                    ("PUT", "/v4/domains/example.com/records/1") => {
+                        // The ttl of the record must be preserved
+                        let body: serde_json::Value = rouille::input::json_input(request).unwrap();
+                        if body["ttl"] == 600 && body["host"] == "" && body["type"] == "A" {
+                            Response::from_data("application/json", "{}")
+                        } else {
+                            Response::text("bad update").with_status_code(400)
+                        }
+                    }
+                    ("POST", "/v4/domains/example.com/records") => {
+                        let body: serde_json::Value = rouille::input::json_input(request).unwrap();
+                        if body["host"] == "www" && body["answer"] == "2.2.2.2" {
+                            Response::from_data("application/json", "{}")
+                        } else {
+                            Response::text("bad create").with_status_code(400)
+                        }
+                    }
+                    _ => Response::empty_404(),
+                }
+            })
+            .unwrap();
+
+            let (tx, rx) = std::sync::mpsc::sync_channel(1);
+            let addr = server.server_addr().clone();
+            std::thread::spawn(move || {
+                while let Err(_) = rx.try_recv() {
+                    server.poll();
+                    std::thread::sleep(std::time::Duration::from_millis(50))
+                }
+            });
+            (tx, addr)
+        }};
+    }
+
+    fn config(addr: std::net::SocketAddr, create_missing: bool) -> NameComConfig {
+        NameComConfig {
+            base_url: format!("http://{}", addr),
+            username: String::from("dness"),
+            token: String::from("deadbeef"),
+            domain: String::from("example.com"),
+            records: vec![String::from("@"), String::from("sub"), String::from("www")],
+            ip_types: vec![IpType::V4],
+            request_timeout_secs: None,
+            create_missing,
+            log_level: None,
+            name: None,
+        }
+    }
+
+    #[test]
+    fn deserialize_namecom_records() {
+        let json_str = &include_str!("../assets/namecom-records.json");
+        let response: ListRecordsResponse = serde_json::from_str(json_str).unwrap();
+        assert_eq!(
+            response.records[0],
+            NameComRecord {
+                id: 1,
+                host: String::from(""),
+                r#type: String::from("A"),
+                answer: String::from("1.1.1.1"),
+                ttl: 600,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_namecom_update() {
+        let (tx, addr) = namecom_server!();
+        let http_client = reqwest::Client::new();
+        let new_ip = IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2));
+        let config = config(addr, false);
+
+        let summary = update_domains(&http_client, &config, new_ip, false)
+            .await
+            .unwrap();
+        tx.send(()).unwrap();
+
+        assert_eq!(
+            summary,
+            Updates {
+                current: 1,
+                updated: 1,
+                missing: 1,
+                failed: 0,
+                dry_run: false,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_namecom_create_missing() {
+        let (tx, addr) = namecom_server!();
+        let http_client = reqwest::Client::new();
+        let new_ip = IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2));
+        let config = config(addr, true);
+
+        let summary = update_domains(&http_client, &config, new_ip, false)
+            .await
+            .unwrap();
+        tx.send(()).unwrap();
+
+        assert_eq!(
+            summary,
+            Updates {
+                current: 1,
+                updated: 2,
+                missing: 0,
+                failed: 0,
+                dry_run: false,
+            }
+        );
+    }
+}
@@ -0,0 +1,111 @@
+use crate::config::NjallaConfig;
+use crate::core::{
+    DnsCheckedRecords, DnsProvider, SendTraced, UpdateContext, UpdateOutcome, Updates,
+};
+use crate::errors::DnessError;
+use crate::retry::retry;
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::net::IpAddr;
+
+#[derive(Deserialize, PartialEq, Clone, Debug)]
+struct NjallaResponse {
+    status: String,
+
+    #[serde(default)]
+    message: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct NjallaProvider<'a> {
+    client: &'a reqwest::Client,
+    config: &'a NjallaConfig,
+}
+
+impl<'a> NjallaProvider<'a> {
+    /// https://njal.la/docs/ddns/
+    pub async fn update_domain(
+        &self,
+        host: &str,
+        wan: IpAddr,
+    ) -> Result<UpdateOutcome, DnessError> {
+        let base = self.config.base_url.trim_end_matches('/').to_string();
+        let get_url = format!("{}/update/", base);
+
+        let response = self
+            .client
+            .get(&get_url)
+            .query(&[
+                ("h", host),
+                ("k", self.config.api_key.as_str()),
+                ("v", &wan.to_string()),
+            ])
+            .send_traced()
+            .await
+            .map_err(|e| DnessError::send_http(&get_url, "njalla update", e))?
+            .error_for_status()
+            .map_err(|e| DnessError::bad_response(&get_url, "njalla update", e))?
+            .json::<NjallaResponse>()
+            .await
+            .map_err(|e| DnessError::deserialize(&get_url, "njalla update", e))?;
+
+        parse_njalla_response(&response)
+    }
+}
+
+/// Njalla only reports whether the update succeeded, as the records are checked via dns
+/// beforehand to know if they are current
+fn parse_njalla_response(response: &NjallaResponse) -> Result<UpdateOutcome, DnessError> {
+    match response.status.as_str() {
+        "ok" => Ok(UpdateOutcome::Updated),
+        status => Err(DnessError::message(format!(
+            "njalla update failed ({}): {}",
+            status,
+            response.message.as_deref().unwrap_or("no message given")
+        ))),
+    }
+}
+
+#[tracing::instrument(skip(client, config), fields(domain = %config.hostname))]
+pub async fn update_domains(
+    client: &reqwest::Client,
+    config: &NjallaConfig,
+    wan: IpAddr,
+    dry_run: bool,
+    force: bool,
+) -> Result<Updates, DnessError> {
+    // uses the same strategy as namecheap where we get the current records
+    // via dns and check if they need to be updated
+    let njalla = &NjallaProvider { client, config };
+    DnsCheckedRecords {
+        provider: "njalla",
+        domain: &config.hostname,
+        records: &config.records,
+        dry_run,
+        force,
+    }
+    .update(
+        wan,
+        |host| async move { njalla.update_domain(&host, wan).await },
+    )
+    .await
+}
+
+#[async_trait]
+impl DnsProvider for NjallaConfig {
+    async fn update(
+        &self,
+        ctx: &UpdateContext<'_>,
+        addr: IpAddr,
+    ) -> Result<Updates, Box<dyn std::error::Error>> {
+        retry(ctx.policy, ctx.name, || {
+            update_domains(ctx.client, self, addr, ctx.dry_run, ctx.force)
+        })
+        .await
+        .map_err(|e| e.into())
+    }
+
+    fn name(&self) -> &str {
+        "njalla"
+    }
+}
@@ -1,6 +1,16 @@
-use crate::{config::NoIpConfig, core::Updates, dns::DnsResolver, errors::DnessError};
-use log::{info, warn};
-use std::net::Ipv4Addr;
+use crate::{
+    config::{IpType, NoIpConfig},
+    core::{
+        dry_run_prefix, parse_dyndns2_response, DnsProvider, SendTraced, UpdateContext,
+        UpdateOutcome, Updates,
+    },
+    dns::DnsResolver,
+    errors::DnessError,
+    retry::retry,
+};
+use async_trait::async_trait;
+use std::net::IpAddr;
+use tracing::{info, warn};
 
 #[derive(Debug)]
 pub struct NoIpProvider<'a> {
@@ -10,7 +20,7 @@ pub struct NoIpProvider<'a> {
 
 impl<'a> NoIpProvider<'a> {
     /// https://www.noip.com/integrate/request
-    pub async fn update_domain(&self, wan: Ipv4Addr) -> Result<(), DnessError> {
+    pub async fn update_domain(&self, wan: IpAddr) -> Result<UpdateOutcome, DnessError> {
         let base = self.config.base_url.trim_end_matches('/').to_string();
         let get_url = format!("{}/nic/update", base);
         let response = self
@@ -21,7 +31,7 @@ impl<'a> NoIpProvider<'a> {
                 ("myip", &wan.to_string()),
             ])
             .basic_auth(&self.config.username, Some(&self.config.password))
-            .send()
+            .send_traced()
             .await
             .map_err(|e| DnessError::send_http(&get_url, "noip update", e))?
             .error_for_status()
@@ -30,26 +40,42 @@ impl<'a> NoIpProvider<'a> {
             .await
             .map_err(|e| DnessError::deserialize(&get_url, "noip update", e))?;
 
-        if !response.contains("good") {
-            Err(DnessError::message(format!(
-                "expected zero errors, but received: {}",
-                response
-            )))
-        } else {
-            Ok(())
-        }
+        parse_dyndns2_response(&response)
     }
 }
 
+#[tracing::instrument(skip(client, config), fields(domain = %config.hostname))]
 pub async fn update_domains(
     client: &reqwest::Client,
     config: &NoIpConfig,
-    wan: Ipv4Addr,
+    wan: IpAddr,
+    dry_run: bool,
+    force: bool,
 ) -> Result<Updates, DnessError> {
+    let provider = NoIpProvider { client, config };
+    let mut results = Updates::default();
+    if force {
+        let outcome = if dry_run {
+            UpdateOutcome::Updated
+        } else {
+            provider.update_domain(wan).await?
+        };
+
+        if outcome == UpdateOutcome::Updated {
+            info!(
+                "{}{} forcibly updated to {}",
+                dry_run_prefix(dry_run),
+                config.hostname,
+                wan
+            );
+        }
+        results.record(outcome);
+        return Ok(results);
+    }
+
     let resolver = DnsResolver::create_cloudflare().await?;
     let dns_query = format!("{}.", &config.hostname);
-    let response = resolver.ipv4_lookup(&dns_query).await;
-    let provider = NoIpProvider { client, config };
+    let response = resolver.ip_lookup(&dns_query, IpType::from(wan)).await;
     match response {
         Ok(ip) => {
             if ip == wan {
@@ -58,12 +84,23 @@ pub async fn update_domains(
                     ..Updates::default()
                 })
             } else {
-                provider.update_domain(wan).await?;
-                info!("{} updated from {} to {}", config.hostname, ip, wan);
-                Ok(Updates {
-                    updated: 1,
-                    ..Updates::default()
-                })
+                let outcome = if dry_run {
+                    UpdateOutcome::Updated
+                } else {
+                    provider.update_domain(wan).await?
+                };
+
+                if outcome == UpdateOutcome::Updated {
+                    info!(
+                        "{}{} updated from {} to {}",
+                        dry_run_prefix(dry_run),
+                        config.hostname,
+                        ip,
+                        wan
+                    );
+                }
+                results.record(outcome);
+                Ok(results)
             }
         }
         Err(e) => {
@@ -80,18 +117,46 @@ pub async fn update_domains(
     }
 }
 
+#[async_trait]
+impl DnsProvider for NoIpConfig {
+    async fn update(
+        &self,
+        ctx: &UpdateContext<'_>,
+        addr: IpAddr,
+    ) -> Result<Updates, Box<dyn std::error::Error>> {
+        retry(ctx.policy, ctx.name, || {
+            update_domains(ctx.client, self, addr, ctx.dry_run, ctx.force)
+        })
+        .await
+        .map_err(|e| e.into())
+    }
+
+    fn name(&self) -> &str {
+        "noip"
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::net::Ipv4Addr;
 
     macro_rules! noip_server {
         () => {{
             use rouille::Response;
             use rouille::Server;
 
-            let server = Server::new("localhost:0", |request| match request.url().as_str() {
-                "/nic/update" => Response::from_data("text/plain", b"good 2.2.2.2".to_vec()),
-                _ => Response::empty_404(),
+            let server = Server::new("localhost:0", |request| {
+                if request.url() != "/nic/update" {
+                    return Response::empty_404();
+                }
+
+                match request.get_param("hostname").as_deref() {
+                    Some("current.example.com") => Response::text("nochg 2.2.2.2"),
+                    Some("gone.example.com") => Response::text("nohost"),
+                    Some("banned.example.com") => Response::text("abuse"),
+                    _ => Response::text("good 2.2.2.2"),
+                }
             })
             .unwrap();
 
@@ -111,15 +176,21 @@ mod tests {
     async fn test_noip_update() {
         let (tx, addr) = noip_server!();
         let http_client = reqwest::Client::new();
-        let new_ip = Ipv4Addr::new(2, 2, 2, 2);
+        let new_ip = IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2));
         let config = NoIpConfig {
             base_url: format!("http://{}", addr),
             hostname: String::from("example.com"),
             username: String::from("me@example.com"),
             password: String::from("my-pass"),
+            request_timeout_secs: None,
+            ip_types: vec![IpType::V4],
+            log_level: None,
+            name: None,
         };
 
-        let summary = update_domains(&http_client, &config, new_ip).await.unwrap();
+        let summary = update_domains(&http_client, &config, new_ip, false, false)
+            .await
+            .unwrap();
         tx.send(()).unwrap();
 
         assert_eq!(
@@ -128,7 +199,55 @@ mod tests {
                 current: 0,
                 updated: 1,
                 missing: 0,
+                failed: 0,
+                dry_run: false,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_noip_force_response_codes() {
+        let (tx, addr) = noip_server!();
+        let http_client = reqwest::Client::new();
+        let new_ip = IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2));
+        let mut config = NoIpConfig {
+            base_url: format!("http://{}", addr),
+            hostname: String::from("current.example.com"),
+            username: String::from("me@example.com"),
+            password: String::from("my-pass"),
+            request_timeout_secs: None,
+            ip_types: vec![IpType::V4],
+            log_level: None,
+            name: None,
+        };
+
+        let current = update_domains(&http_client, &config, new_ip, false, true)
+            .await
+            .unwrap();
+
+        config.hostname = String::from("gone.example.com");
+        let missing = update_domains(&http_client, &config, new_ip, false, true)
+            .await
+            .unwrap();
+
+        config.hostname = String::from("banned.example.com");
+        let banned = update_domains(&http_client, &config, new_ip, false, true).await;
+        tx.send(()).unwrap();
+
+        assert_eq!(
+            current,
+            Updates {
+                current: 1,
+                ..Updates::default()
+            }
+        );
+        assert_eq!(
+            missing,
+            Updates {
+                missing: 1,
+                ..Updates::default()
             }
         );
+        assert!(banned.unwrap_err().to_string().contains("abuse"));
     }
 }
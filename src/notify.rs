@@ -0,0 +1,842 @@
+use crate::config::{NotificationsConfig, SmtpConfig};
+use crate::core::SendTraced;
+use crate::errors::DnessError;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use lettre::message::header::ContentType;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::time::Duration;
+use tracing::warn;
+
+/// Describes a change of the WAN IP that was pushed to at least one domain
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct UpdateEvent {
+    /// The previous WAN IP, which is only known when a state file is configured
+    pub old_ip: Option<IpAddr>,
+    pub new_ip: IpAddr,
+    pub domains: Vec<String>,
+
+    /// The number of records that were updated across all domains
+    pub updated: i32,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Describes a domain that could not be updated
+#[derive(Clone, PartialEq, Debug)]
+pub struct FailureEvent {
+    pub provider: String,
+    pub error: String,
+}
+
+#[async_trait]
+pub trait Notifier {
+    async fn notify(&self, event: &UpdateEvent) -> Result<(), DnessError>;
+
+    /// Notifies of a failed update, which is ignored unless the notifier reports failures
+    async fn notify_failure(&self, _event: &FailureEvent) -> Result<(), DnessError> {
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct WebhookNotifier<'a> {
+    client: &'a reqwest::Client,
+    url: String,
+    method: String,
+}
+
+#[async_trait]
+impl<'a> Notifier for WebhookNotifier<'a> {
+    async fn notify(&self, event: &UpdateEvent) -> Result<(), DnessError> {
+        let method = Method::from_bytes(self.method.to_ascii_uppercase().as_bytes())
+            .map_err(|_| DnessError::message(format!("invalid webhook method: {}", self.method)))?;
+
+        self.client
+            .request(method, &self.url)
+            .json(event)
+            .send_traced()
+            .await
+            .map_err(|e| DnessError::send_http(&self.url, "webhook notification", e))?
+            .error_for_status()
+            .map_err(|e| DnessError::bad_response(&self.url, "webhook notification", e))?;
+
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Clone, PartialEq, Debug)]
+struct SlackMessage {
+    text: String,
+}
+
+/// Posts messages to a slack incoming webhook
+#[derive(Debug)]
+pub struct SlackNotifier<'a> {
+    client: &'a reqwest::Client,
+    url: String,
+}
+
+impl<'a> SlackNotifier<'a> {
+    // The webhook url is a secret, so it is left out of errors
+    async fn post(&self, text: String) -> Result<(), DnessError> {
+        let response = self
+            .client
+            .post(&self.url)
+            .json(&SlackMessage { text })
+            .send_traced()
+            .await
+            .map_err(|e| {
+                DnessError::message(format!(
+                    "unable to send slack notification: {}",
+                    e.without_url()
+                ))
+            })?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(DnessError::message(format!(
+                "slack notification failed with status {}",
+                status
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<'a> Notifier for SlackNotifier<'a> {
+    async fn notify(&self, event: &UpdateEvent) -> Result<(), DnessError> {
+        let text = format!(
+            "dness: WAN IP changed to {}. Updated {} records.",
+            event.new_ip, event.updated
+        );
+        self.post(text).await
+    }
+
+    async fn notify_failure(&self, event: &FailureEvent) -> Result<(), DnessError> {
+        let text = format!("dness error: {} failed: {}", event.provider, event.error);
+        self.post(text).await
+    }
+}
+
+/// Discord limits an embed field to this many characters
+const DISCORD_FIELD_LIMIT: usize = 1024;
+
+#[derive(Serialize, Clone, PartialEq, Debug)]
+struct DiscordMessage {
+    embeds: Vec<DiscordEmbed>,
+}
+
+#[derive(Serialize, Clone, PartialEq, Debug)]
+struct DiscordEmbed {
+    title: String,
+    fields: Vec<DiscordField>,
+    timestamp: DateTime<Utc>,
+}
+
+#[derive(Serialize, Clone, PartialEq, Debug)]
+struct DiscordField {
+    name: &'static str,
+    value: String,
+    inline: bool,
+}
+
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+struct DiscordRateLimit {
+    retry_after: f64,
+}
+
+/// Lists the domains one per line, cutting off the domains that don't fit within discord's limit
+fn discord_domains(domains: &[String]) -> String {
+    let mut result = String::new();
+    for (i, domain) in domains.iter().enumerate() {
+        let remaining = domains.len() - i;
+        let more = format!("... and {} more", remaining);
+        let line = if result.is_empty() {
+            domain.clone()
+        } else {
+            format!("\n{}", domain)
+        };
+
+        // Leave room for the note of how many domains are left out
+        let reserve = if remaining > 1 { more.len() + 1 } else { 0 };
+        if result.len() + line.len() + reserve > DISCORD_FIELD_LIMIT {
+            if !result.is_empty() {
+                result.push('\n');
+            }
+            result.push_str(&more);
+            break;
+        }
+        result.push_str(&line);
+    }
+
+    if result.is_empty() {
+        String::from("-")
+    } else {
+        result
+    }
+}
+
+/// Posts an embed to a discord webhook
+#[derive(Debug)]
+pub struct DiscordNotifier<'a> {
+    client: &'a reqwest::Client,
+    url: String,
+}
+
+impl<'a> DiscordNotifier<'a> {
+    // The webhook url is a secret, so it is left out of errors
+    async fn post(&self, message: &DiscordMessage) -> Result<reqwest::Response, DnessError> {
+        self.client
+            .post(&self.url)
+            .json(message)
+            .send_traced()
+            .await
+            .map_err(|e| {
+                DnessError::message(format!(
+                    "unable to send discord notification: {}",
+                    e.without_url()
+                ))
+            })
+    }
+}
+
+#[async_trait]
+impl<'a> Notifier for DiscordNotifier<'a> {
+    async fn notify(&self, event: &UpdateEvent) -> Result<(), DnessError> {
+        let old_ip = event
+            .old_ip
+            .map(|x| x.to_string())
+            .unwrap_or_else(|| String::from("unknown"));
+        let message = DiscordMessage {
+            embeds: vec![DiscordEmbed {
+                title: String::from("dness: WAN IP changed"),
+                fields: vec![
+                    DiscordField {
+                        name: "Old IP",
+                        value: old_ip,
+                        inline: true,
+                    },
+                    DiscordField {
+                        name: "New IP",
+                        value: event.new_ip.to_string(),
+                        inline: true,
+                    },
+                    DiscordField {
+                        name: "Updated Domains",
+                        value: discord_domains(&event.domains),
+                        inline: false,
+                    },
+                ],
+                timestamp: event.timestamp,
+            }],
+        };
+
+        let mut response = self.post(&message).await?;
+
+        // Discord says how long to wait in the body of a rate limited response
+        if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            let wait = response
+                .json::<DiscordRateLimit>()
+                .await
+                .map(|x| x.retry_after)
+                .unwrap_or(1.0);
+            warn!("discord rate limited notification, retrying in {}s", wait);
+            tokio::time::sleep(Duration::from_secs_f64(wait.clamp(0.0, 60.0))).await;
+            response = self.post(&message).await?;
+        }
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(DnessError::message(format!(
+                "discord notification failed with status {}",
+                status
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// Publishes a plain text message to an ntfy topic
+#[derive(Debug)]
+pub struct NtfyNotifier<'a> {
+    client: &'a reqwest::Client,
+    url: String,
+    token: Option<String>,
+}
+
+impl<'a> NtfyNotifier<'a> {
+    async fn publish(&self, title: &str, priority: u8, message: String) -> Result<(), DnessError> {
+        let mut request = self
+            .client
+            .post(&self.url)
+            .header("Title", title)
+            .header("Priority", priority.to_string())
+            .body(message);
+
+        if let Some(token) = self.token.as_ref() {
+            request = request.bearer_auth(token);
+        }
+
+        request
+            .send_traced()
+            .await
+            .map_err(|e| DnessError::send_http(&self.url, "ntfy notification", e))?
+            .error_for_status()
+            .map_err(|e| DnessError::bad_response(&self.url, "ntfy notification", e))?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<'a> Notifier for NtfyNotifier<'a> {
+    async fn notify(&self, event: &UpdateEvent) -> Result<(), DnessError> {
+        let message = format!(
+            "WAN IP changed to {}. Updated {} records.",
+            event.new_ip, event.updated
+        );
+        self.publish("dness IP update", 3, message).await
+    }
+
+    async fn notify_failure(&self, event: &FailureEvent) -> Result<(), DnessError> {
+        let message = format!("{} failed: {}", event.provider, event.error);
+        self.publish("dness error", 4, message).await
+    }
+}
+
+/// The port where SMTP is encrypted from the start instead of upgraded with STARTTLS
+const SMTPS_PORT: u16 = 465;
+
+/// Emails a plain text message through an SMTP server
+#[derive(Debug)]
+pub struct SmtpNotifier {
+    config: SmtpConfig,
+}
+
+impl SmtpNotifier {
+    fn message(&self, event: &UpdateEvent) -> Result<Message, DnessError> {
+        let from = self.config.from.parse().map_err(|e| {
+            DnessError::message(format!(
+                "invalid smtp from address {}: {}",
+                self.config.from, e
+            ))
+        })?;
+        let to = self.config.to.parse().map_err(|e| {
+            DnessError::message(format!("invalid smtp to address {}: {}", self.config.to, e))
+        })?;
+
+        let old_ip = event
+            .old_ip
+            .map(|x| x.to_string())
+            .unwrap_or_else(|| String::from("unknown"));
+        let mut body = format!("Updated {} records:\n\n", event.updated);
+        for domain in &event.domains {
+            body.push_str(&format!("{}: {} -> {}\n", domain, old_ip, event.new_ip));
+        }
+
+        Message::builder()
+            .from(from)
+            .to(to)
+            .subject(format!("dness: WAN IP updated to {}", event.new_ip))
+            .header(ContentType::TEXT_PLAIN)
+            .body(body)
+            .map_err(|e| DnessError::message(format!("unable to build smtp notification: {}", e)))
+    }
+
+    fn transport(&self) -> Result<AsyncSmtpTransport<Tokio1Executor>, DnessError> {
+        let host = self.config.smtp_host.as_str();
+        let mut builder = match (self.config.smtp_tls, self.config.smtp_port) {
+            (true, Some(SMTPS_PORT)) => AsyncSmtpTransport::<Tokio1Executor>::relay(host),
+            (true, _) => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host),
+            (false, _) => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(
+                host,
+            )),
+        }
+        .map_err(|e| DnessError::message(format!("invalid smtp host {}: {}", host, e)))?;
+
+        if let Some(port) = self.config.smtp_port {
+            builder = builder.port(port);
+        }
+
+        if let (Some(username), Some(password)) = (
+            self.config.smtp_username.as_ref(),
+            self.config.smtp_password.as_ref(),
+        ) {
+            builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+
+        Ok(builder.build())
+    }
+}
+
+#[async_trait]
+impl Notifier for SmtpNotifier {
+    async fn notify(&self, event: &UpdateEvent) -> Result<(), DnessError> {
+        let message = self.message(event)?;
+        self.transport()?
+            .send(message)
+            .await
+            .map_err(|e| DnessError::message(format!("unable to send smtp notification: {}", e)))?;
+        Ok(())
+    }
+}
+
+/// Creates a notifier for each notification backend that is configured
+pub fn notifiers<'a>(
+    client: &'a reqwest::Client,
+    config: &NotificationsConfig,
+) -> Vec<Box<dyn Notifier + Send + Sync + 'a>> {
+    let mut result: Vec<Box<dyn Notifier + Send + Sync + 'a>> = Vec::new();
+    if let Some(url) = config.webhook_url.as_ref() {
+        result.push(Box::new(WebhookNotifier {
+            client,
+            url: url.clone(),
+            method: config.webhook_method.clone(),
+        }));
+    }
+
+    if let Some(url) = config.slack_webhook_url.as_ref() {
+        result.push(Box::new(SlackNotifier {
+            client,
+            url: url.clone(),
+        }));
+    }
+
+    if let Some(url) = config.discord_webhook_url.as_ref() {
+        result.push(Box::new(DiscordNotifier {
+            client,
+            url: url.clone(),
+        }));
+    }
+
+    if let Some(url) = config.ntfy_url.as_ref() {
+        result.push(Box::new(NtfyNotifier {
+            client,
+            url: url.clone(),
+            token: config.ntfy_token.clone(),
+        }));
+    }
+
+    if let Some(smtp) = config.smtp.as_ref() {
+        result.push(Box::new(SmtpNotifier {
+            config: smtp.clone(),
+        }));
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::io::Read;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    /// A request received by the webhook server
+    #[derive(Debug)]
+    struct HookRequest {
+        headers: Vec<(String, String)>,
+        body: String,
+    }
+
+    impl HookRequest {
+        fn header(&self, name: &str) -> Option<&str> {
+            self.headers
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.as_str())
+        }
+
+        fn json(&self) -> serde_json::Value {
+            serde_json::from_str(&self.body).unwrap()
+        }
+    }
+
+    /// Sends each request to the hook, which are answered by the given function of the number of
+    /// prior requests
+    macro_rules! webhook_server {
+        () => {
+            webhook_server!(|_| rouille::Response::empty_204())
+        };
+        ($respond:expr) => {{
+            use rouille::Response;
+            use rouille::Server;
+
+            let respond = $respond;
+            let attempts = std::sync::atomic::AtomicUsize::new(0);
+            let (body_tx, body_rx) = std::sync::mpsc::channel();
+            let server = Server::new("localhost:0", move |request| {
+                if request.url() != "/hook" || request.method() != "POST" {
+                    return Response::empty_404();
+                }
+
+                let headers = request
+                    .headers()
+                    .map(|(key, value)| (key.to_string(), value.to_string()))
+                    .collect();
+                let mut body = String::new();
+                request.data().unwrap().read_to_string(&mut body).unwrap();
+                body_tx.send(HookRequest { headers, body }).unwrap();
+                respond(attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst))
+            })
+            .unwrap();
+
+            let (tx, rx) = std::sync::mpsc::sync_channel(1);
+            let addr = server.server_addr().clone();
+            std::thread::spawn(move || {
+                while let Err(_) = rx.try_recv() {
+                    server.poll();
+                    std::thread::sleep(std::time::Duration::from_millis(50))
+                }
+            });
+            (tx, addr, body_rx)
+        }};
+    }
+
+    #[tokio::test]
+    async fn test_webhook_notify() {
+        let (tx, addr, body_rx) = webhook_server!();
+        let http_client = reqwest::Client::new();
+        let config = NotificationsConfig {
+            webhook_url: Some(format!("http://{}/hook", addr)),
+            webhook_method: String::from("post"),
+            slack_webhook_url: None,
+            discord_webhook_url: None,
+            ntfy_url: None,
+            ntfy_token: None,
+            smtp: None,
+        };
+
+        let event = UpdateEvent {
+            old_ip: Some(IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1))),
+            new_ip: IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2)),
+            domains: vec![String::from("example.com (cloudflare)")],
+            updated: 2,
+            timestamp: Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+        };
+
+        let notifiers = notifiers(&http_client, &config);
+        assert_eq!(notifiers.len(), 1);
+        notifiers[0].notify(&event).await.unwrap();
+        tx.send(()).unwrap();
+
+        let body = body_rx.recv().unwrap().json();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "old_ip": "1.1.1.1",
+                "new_ip": "2.2.2.2",
+                "domains": ["example.com (cloudflare)"],
+                "updated": 2,
+                "timestamp": "2024-01-01T00:00:00Z"
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_slack_notify() {
+        let (tx, addr, body_rx) = webhook_server!();
+        let http_client = reqwest::Client::new();
+        let config = NotificationsConfig {
+            webhook_url: None,
+            webhook_method: String::from("POST"),
+            slack_webhook_url: Some(format!("http://{}/hook", addr)),
+            discord_webhook_url: None,
+            ntfy_url: None,
+            ntfy_token: None,
+            smtp: None,
+        };
+
+        let event = UpdateEvent {
+            old_ip: None,
+            new_ip: IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2)),
+            domains: vec![String::from("example.com (cloudflare)")],
+            updated: 3,
+            timestamp: Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+        };
+
+        let failure = FailureEvent {
+            provider: String::from("example.com (godaddy)"),
+            error: String::from("timed out after 30s"),
+        };
+
+        let notifiers = notifiers(&http_client, &config);
+        assert_eq!(notifiers.len(), 1);
+        notifiers[0].notify(&event).await.unwrap();
+        let body = body_rx.recv().unwrap().json();
+        assert_eq!(
+            body,
+            serde_json::json!({ "text": "dness: WAN IP changed to 2.2.2.2. Updated 3 records." })
+        );
+
+        notifiers[0].notify_failure(&failure).await.unwrap();
+        tx.send(()).unwrap();
+        let body = body_rx.recv().unwrap().json();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "text": "dness error: example.com (godaddy) failed: timed out after 30s"
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_slack_notify_error_status() {
+        let (tx, addr, _body_rx) = webhook_server!();
+        let http_client = reqwest::Client::new();
+        let notifier = SlackNotifier {
+            client: &http_client,
+            url: format!("http://{}/missing", addr),
+        };
+
+        let failure = FailureEvent {
+            provider: String::from("example.com (godaddy)"),
+            error: String::from("timed out after 30s"),
+        };
+
+        let err = notifier.notify_failure(&failure).await.unwrap_err();
+        tx.send(()).unwrap();
+        assert_eq!(
+            err.to_string(),
+            "slack notification failed with status 404 Not Found"
+        );
+    }
+
+    #[test]
+    fn test_discord_domains() {
+        let domains = vec![String::from("a.com"), String::from("b.com")];
+        assert_eq!(discord_domains(&domains), "a.com\nb.com");
+        assert_eq!(discord_domains(&[]), "-");
+
+        let domains = (0..200)
+            .map(|i| format!("sub{}.example.com", i))
+            .collect::<Vec<_>>();
+        let value = discord_domains(&domains);
+        assert!(value.len() <= DISCORD_FIELD_LIMIT);
+        assert!(value.starts_with("sub0.example.com\nsub1.example.com"));
+        assert!(value.ends_with("more"));
+    }
+
+    #[tokio::test]
+    async fn test_discord_notify_rate_limited() {
+        // The first request is rate limited, and the retry succeeds
+        let (tx, addr, body_rx) = webhook_server!(|attempt| {
+            match attempt {
+            0 => rouille::Response::from_data(
+                "application/json",
+                r#"{"message": "You are being rate limited.", "retry_after": 0.1, "global": false}"#,
+            )
+            .with_status_code(429),
+            _ => rouille::Response::empty_204(),
+        }
+        });
+        let http_client = reqwest::Client::new();
+        let config = NotificationsConfig {
+            discord_webhook_url: Some(format!("http://{}/hook", addr)),
+            ..Default::default()
+        };
+
+        let event = UpdateEvent {
+            old_ip: Some(IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1))),
+            new_ip: IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2)),
+            domains: vec![String::from("example.com (cloudflare)")],
+            updated: 1,
+            timestamp: Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+        };
+
+        let notifiers = notifiers(&http_client, &config);
+        assert_eq!(notifiers.len(), 1);
+        notifiers[0].notify(&event).await.unwrap();
+        tx.send(()).unwrap();
+
+        let requests = body_rx.try_iter().collect::<Vec<_>>();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].body, requests[1].body);
+        assert_eq!(
+            requests[1].json(),
+            serde_json::json!({
+                "embeds": [{
+                    "title": "dness: WAN IP changed",
+                    "fields": [
+                        { "name": "Old IP", "value": "1.1.1.1", "inline": true },
+                        { "name": "New IP", "value": "2.2.2.2", "inline": true },
+                        {
+                            "name": "Updated Domains",
+                            "value": "example.com (cloudflare)",
+                            "inline": false
+                        }
+                    ],
+                    "timestamp": "2024-01-01T00:00:00Z"
+                }]
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ntfy_notify() {
+        let (tx, addr, body_rx) = webhook_server!();
+        let http_client = reqwest::Client::new();
+        let config = NotificationsConfig {
+            ntfy_url: Some(format!("http://{}/hook", addr)),
+            ntfy_token: Some(String::from("tk_1")),
+            ..Default::default()
+        };
+
+        let event = UpdateEvent {
+            old_ip: None,
+            new_ip: IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)),
+            domains: vec![String::from("example.com (cloudflare)")],
+            updated: 1,
+            timestamp: Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+        };
+
+        let failure = FailureEvent {
+            provider: String::from("example.com (godaddy)"),
+            error: String::from("timed out after 30s"),
+        };
+
+        let notifiers = notifiers(&http_client, &config);
+        assert_eq!(notifiers.len(), 1);
+        notifiers[0].notify(&event).await.unwrap();
+        notifiers[0].notify_failure(&failure).await.unwrap();
+        tx.send(()).unwrap();
+
+        let request = body_rx.recv().unwrap();
+        assert_eq!(request.header("Authorization"), Some("Bearer tk_1"));
+        assert_eq!(request.header("Title"), Some("dness IP update"));
+        assert_eq!(request.header("Priority"), Some("3"));
+        assert_eq!(
+            request.body,
+            "WAN IP changed to 2001:db8::1. Updated 1 records."
+        );
+
+        let request = body_rx.recv().unwrap();
+        assert_eq!(request.header("Authorization"), Some("Bearer tk_1"));
+        assert_eq!(request.header("Title"), Some("dness error"));
+        assert_eq!(request.header("Priority"), Some("4"));
+        assert_eq!(
+            request.body,
+            "example.com (godaddy) failed: timed out after 30s"
+        );
+    }
+
+    /// Accepts a single SMTP session, without TLS or authentication, and sends the data of the
+    /// email once received
+    fn smtp_server() -> (u16, std::sync::mpsc::Receiver<String>) {
+        use std::io::{BufRead, BufReader, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (data_tx, data_rx) = std::sync::mpsc::sync_channel(1);
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            let mut reader = BufReader::new(stream);
+            writer.write_all(b"220 localhost ESMTP\r\n").unwrap();
+
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 0 {
+                let command = line.to_ascii_uppercase();
+                if command.starts_with("DATA") {
+                    writer.write_all(b"354 go ahead\r\n").unwrap();
+                    let mut data = String::new();
+                    loop {
+                        let mut data_line = String::new();
+                        reader.read_line(&mut data_line).unwrap();
+                        if data_line == ".\r\n" {
+                            break;
+                        }
+                        data.push_str(&data_line);
+                    }
+                    data_tx.send(data).unwrap();
+                    writer.write_all(b"250 queued\r\n").unwrap();
+                } else if command.starts_with("QUIT") {
+                    writer.write_all(b"221 bye\r\n").unwrap();
+                    break;
+                } else {
+                    writer.write_all(b"250 localhost\r\n").unwrap();
+                }
+                line.clear();
+            }
+        });
+        (port, data_rx)
+    }
+
+    #[tokio::test]
+    async fn test_smtp_notify() {
+        let (port, data_rx) = smtp_server();
+        let http_client = reqwest::Client::new();
+        let config = NotificationsConfig {
+            webhook_url: None,
+            webhook_method: String::from("POST"),
+            slack_webhook_url: None,
+            discord_webhook_url: None,
+            ntfy_url: None,
+            ntfy_token: None,
+            smtp: Some(SmtpConfig {
+                smtp_host: String::from("127.0.0.1"),
+                smtp_port: Some(port),
+                smtp_username: None,
+                smtp_password: None,
+                smtp_tls: false,
+                from: String::from("dness <dness@example.com>"),
+                to: String::from("admin@example.com"),
+            }),
+        };
+
+        let event = UpdateEvent {
+            old_ip: Some(IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1))),
+            new_ip: IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2)),
+            domains: vec![
+                String::from("example.com (cloudflare)"),
+                String::from("example.org (porkbun)"),
+            ],
+            updated: 3,
+            timestamp: Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+        };
+
+        let notifiers = notifiers(&http_client, &config);
+        assert_eq!(notifiers.len(), 1);
+        notifiers[0].notify(&event).await.unwrap();
+
+        let data = data_rx.recv().unwrap();
+        assert!(data.contains("Subject: dness: WAN IP updated to 2.2.2.2\r\n"));
+        assert!(data.contains("To: admin@example.com\r\n"));
+        assert!(data.contains("example.com (cloudflare): 1.1.1.1 -> 2.2.2.2\r\n"));
+        assert!(data.contains("example.org (porkbun): 1.1.1.1 -> 2.2.2.2\r\n"));
+    }
+
+    #[tokio::test]
+    async fn test_smtp_notify_bad_address() {
+        let notifier = SmtpNotifier {
+            config: SmtpConfig {
+                smtp_host: String::from("127.0.0.1"),
+                smtp_port: None,
+                smtp_username: None,
+                smtp_password: None,
+                smtp_tls: true,
+                from: String::from("dness"),
+                to: String::from("admin@example.com"),
+            },
+        };
+
+        let event = UpdateEvent {
+            old_ip: None,
+            new_ip: IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2)),
+            domains: Vec::new(),
+            updated: 0,
+            timestamp: Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+        };
+
+        let err = notifier.notify(&event).await.unwrap_err();
+        assert!(err.to_string().contains("invalid smtp from address dness"));
+    }
+}
@@ -0,0 +1,208 @@
+use crate::config::OvhConfig;
+use crate::core::{
+    dry_run_prefix, parse_dyndns2_response, require_ipv4, DnsProvider, SendTraced, UpdateContext,
+    UpdateOutcome, Updates,
+};
+use crate::dns::DnsResolver;
+use crate::errors::DnessError;
+use crate::retry::retry;
+use async_trait::async_trait;
+use std::net::{IpAddr, Ipv4Addr};
+use tracing::{debug, info, warn};
+
+#[derive(Debug)]
+pub struct OvhProvider<'a> {
+    client: &'a reqwest::Client,
+    config: &'a OvhConfig,
+}
+
+impl<'a> OvhProvider<'a> {
+    /// https://help.ovhcloud.com/csm/en-dns-dynhost
+    pub async fn update_domain(
+        &self,
+        host: &str,
+        wan: Ipv4Addr,
+    ) -> Result<UpdateOutcome, DnessError> {
+        let base = self.config.base_url.trim_end_matches('/').to_string();
+        let get_url = format!("{}/nic/update", base);
+        let response = self
+            .client
+            .get(&get_url)
+            .query(&[
+                ("system", "dyndns"),
+                ("hostname", host),
+                ("myip", &wan.to_string()),
+            ])
+            .basic_auth(&self.config.username, Some(&self.config.password))
+            .send_traced()
+            .await
+            .map_err(|e| DnessError::send_http(&get_url, "ovh update", e))?
+            .error_for_status()
+            .map_err(|e| DnessError::bad_response(&get_url, "ovh update", e))?
+            .text()
+            .await
+            .map_err(|e| DnessError::deserialize(&get_url, "ovh update", e))?;
+
+        parse_dyndns2_response(&response)
+    }
+}
+
+#[tracing::instrument(skip(client, config), fields(domain = %config.hostname))]
+pub async fn update_domains(
+    client: &reqwest::Client,
+    config: &OvhConfig,
+    wan: Ipv4Addr,
+    dry_run: bool,
+    force: bool,
+) -> Result<Updates, DnessError> {
+    // uses the same strategy as he where we get the current records
+    // via dns and check if they need to be updated
+    let resolver = DnsResolver::create_cloudflare().await?;
+    let ovh = OvhProvider { client, config };
+
+    let mut results = Updates::default();
+
+    for record in &config.records {
+        let host_record = if record == "@" {
+            config.hostname.clone()
+        } else {
+            format!("{}.{}", record, &config.hostname)
+        };
+
+        if !force {
+            let dns_query = format!("{}.", &host_record);
+            let response = resolver.ipv4_lookup(&dns_query).await;
+
+            match response {
+                Ok(ip) if ip == wan => {
+                    results.current += 1;
+                    continue;
+                }
+                Ok(ip) => debug!("{} resolved to {}, updating to {}", host_record, ip, wan),
+                Err(e) => {
+                    // A freshly created dynhost record may not have propagated yet, so instead of
+                    // skipping the record, we let ovh be the judge of whether it exists
+                    warn!(
+                        "resolving ovh record ({}) encountered an error, will attempt update: {}",
+                        record, e
+                    );
+                }
+            }
+        }
+
+        let outcome = if dry_run {
+            UpdateOutcome::Updated
+        } else {
+            ovh.update_domain(&host_record, wan).await?
+        };
+
+        match outcome {
+            UpdateOutcome::Updated => {
+                info!(
+                    "{}{} from domain {} updated to {}",
+                    dry_run_prefix(dry_run),
+                    record,
+                    config.hostname,
+                    wan
+                );
+                results.updated += 1;
+            }
+            UpdateOutcome::Current => results.current += 1,
+            UpdateOutcome::Missing => {
+                warn!("{} does not exist in ovh", host_record);
+                results.missing += 1;
+            }
+        }
+    }
+
+    Ok(results)
+}
+
+#[async_trait]
+impl DnsProvider for OvhConfig {
+    async fn update(
+        &self,
+        ctx: &UpdateContext<'_>,
+        addr: IpAddr,
+    ) -> Result<Updates, Box<dyn std::error::Error>> {
+        let addr = require_ipv4(addr, ctx.name)?;
+        retry(ctx.policy, ctx.name, || {
+            update_domains(ctx.client, self, addr, ctx.dry_run, ctx.force)
+        })
+        .await
+        .map_err(|e| e.into())
+    }
+
+    fn name(&self) -> &str {
+        "ovh"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! ovh_server {
+        () => {{
+            use rouille::Response;
+            use rouille::Server;
+
+            let server = Server::new("localhost:0", |request| {
+                if request.url() != "/nic/update"
+                    || request.get_param("system").as_deref() != Some("dyndns")
+                {
+                    return Response::empty_404();
+                }
+
+                match request.get_param("hostname").as_deref() {
+                    Some("sub.example.com") => Response::text("good 2.2.2.2"),
+                    _ => Response::text("nohost"),
+                }
+            })
+            .unwrap();
+
+            let (tx, rx) = std::sync::mpsc::sync_channel(1);
+            let addr = server.server_addr().clone();
+            std::thread::spawn(move || {
+                while let Err(_) = rx.try_recv() {
+                    server.poll();
+                    std::thread::sleep(std::time::Duration::from_millis(50))
+                }
+            });
+            (tx, addr)
+        }};
+    }
+
+    #[tokio::test]
+    async fn test_ovh_update() {
+        let (tx, addr) = ovh_server!();
+        let http_client = reqwest::Client::new();
+        let new_ip = Ipv4Addr::new(2, 2, 2, 2);
+        let config = OvhConfig {
+            base_url: format!("http://{}", addr),
+            hostname: String::from("example.com"),
+            username: String::from("example.com-dynhost"),
+            password: String::from("secret-1"),
+            records: vec![String::from("sub")],
+            request_timeout_secs: None,
+            log_level: None,
+            name: None,
+        };
+
+        let summary = update_domains(&http_client, &config, new_ip, false, false)
+            .await
+            .unwrap();
+        tx.send(()).unwrap();
+
+        assert_eq!(
+            summary,
+            Updates {
+                current: 0,
+                updated: 1,
+                missing: 0,
+                failed: 0,
+                dry_run: false,
+            }
+        );
+    }
+}
@@ -1,14 +1,17 @@
-use crate::config::PorkbunConfig;
-use crate::core::Updates;
+use crate::config::{DnsConfig, IpType, PorkbunConfig};
+use crate::core::{dry_run_prefix, DnsProvider, SendTraced, UpdateContext, Updates};
 use crate::errors::DnessError;
-use log::{debug, info, warn};
+use crate::retry::retry;
+use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::BTreeMap as Map;
 use std::collections::HashSet;
-use std::net::Ipv4Addr;
+use std::net::IpAddr;
+use tracing::{debug, info, warn};
 
-const VALID_RECORD_TYPES: [&str; 1] = ["A"];
+/// The ttl of records that are created when one isn't configured
+const DEFAULT_TTL: &str = "600";
 
 #[derive(Deserialize, Serialize, PartialEq, Clone, Debug)]
 struct PorkbunResponse {
@@ -17,6 +20,17 @@ struct PorkbunResponse {
     records: Vec<PorkbunRecord>,
 }
 
+#[derive(Deserialize, PartialEq, Clone, Debug)]
+struct PorkbunPingResponse {
+    status: String,
+
+    #[serde(default, rename = "yourIp")]
+    your_ip: Option<String>,
+
+    #[serde(default)]
+    message: Option<String>,
+}
+
 #[derive(Deserialize, Serialize, PartialEq, Clone, Debug)]
 struct PorkbunRecord {
     id: String,
@@ -53,7 +67,9 @@ struct PorkbunClient<'a> {
     key: String,
     secret: String,
     records: HashSet<String>,
+    ttl: Option<String>,
     client: &'a reqwest::Client,
+    dry_run: bool,
 }
 
 impl<'a> PorkbunClient<'a> {
@@ -71,7 +87,56 @@ impl<'a> PorkbunClient<'a> {
         crate::core::log_missing_domains(&self.records, &actual, "Porkbun", &self.domain)
     }
 
-    async fn fetch_records(&self) -> Result<Vec<PorkbunRecord>, DnessError> {
+    // The expected records that aren't in Porkbun, in a stable order
+    fn missing_records(&self, remote_domains: &[PorkbunRecord]) -> Vec<String> {
+        let actual = remote_domains
+            .iter()
+            .map(|x| self.strip_domain_from_name(&x.name))
+            .collect::<HashSet<String>>();
+        let mut missing = self
+            .records
+            .iter()
+            .filter(|x| !actual.contains(*x))
+            .cloned()
+            .collect::<Vec<_>>();
+        missing.sort();
+        missing
+    }
+
+    /// Verifies the credentials before the records are fetched, returning the ip that porkbun
+    /// sees the request come from. Porkbun responds with an error status (and a non-200) when
+    /// the credentials are rejected, so the body is inspected before the status.
+    async fn ping(&self) -> Result<Option<IpAddr>, DnessError> {
+        let post_url = format!("{}/ping", self.base_url);
+        let response = self
+            .client
+            .post(&post_url)
+            .json(&PorkbunRecordsRequest {
+                apikey: self.key.clone(),
+                secretapikey: self.secret.clone(),
+            })
+            .send_traced()
+            .await
+            .map_err(|e| DnessError::send_http(&post_url, "porkbun ping", e))?;
+
+        let status = response.status();
+        let ping = response.json::<PorkbunPingResponse>().await;
+        match ping {
+            Ok(ping) if ping.status == "SUCCESS" => {
+                Ok(ping.your_ip.and_then(|x| x.parse::<IpAddr>().ok()))
+            }
+            Ok(ping) => Err(DnessError::message(format!(
+                "porkbun API ping failed: {}",
+                ping.message.as_deref().unwrap_or("no message given")
+            ))),
+            Err(e) => Err(DnessError::message(format!(
+                "porkbun API ping failed ({}): {}",
+                status, e
+            ))),
+        }
+    }
+
+    async fn fetch_records(&self, ip_type: IpType) -> Result<Vec<PorkbunRecord>, DnessError> {
         let post_url = format!("{}/dns/retrieve/{}", self.base_url, self.domain);
         let response = self
             .client
@@ -80,7 +145,7 @@ impl<'a> PorkbunClient<'a> {
                 apikey: self.key.clone(),
                 secretapikey: self.secret.clone(),
             })
-            .send()
+            .send_traced()
             .await
             .map_err(|e| DnessError::send_http(&post_url, "porkbun fetch records", e))?
             .error_for_status()
@@ -90,16 +155,16 @@ impl<'a> PorkbunClient<'a> {
             .map_err(|e| DnessError::deserialize(&post_url, "porkbun fetch records", e))?
             .records
             .into_iter()
-            .filter(|r| VALID_RECORD_TYPES.contains(&r.r#type.as_str()))
+            .filter(|r| r.r#type == ip_type.record_type())
             .collect();
         Ok(response)
     }
 
-    async fn update_record(
-        &self,
-        record: &PorkbunRecord,
-        addr: Ipv4Addr,
-    ) -> Result<(), DnessError> {
+    async fn update_record(&self, record: &PorkbunRecord, addr: IpAddr) -> Result<(), DnessError> {
+        if self.dry_run {
+            return Ok(());
+        }
+
         let post_url = format!("{}/dns/edit/{}/{}", self.base_url, self.domain, record.id);
 
         self.client
@@ -109,10 +174,10 @@ impl<'a> PorkbunClient<'a> {
                 secretapikey: self.secret.clone(),
                 name: self.strip_domain_from_name(&record.name),
                 content: addr.to_string(),
-                ttl: record.ttl.clone(),
+                ttl: self.ttl.clone().unwrap_or_else(|| record.ttl.clone()),
                 r#type: record.r#type.clone(),
             })
-            .send()
+            .send_traced()
             .await
             .map_err(|e| DnessError::send_http(&post_url, "porkbun update records", e))?
             .error_for_status()
@@ -121,22 +186,55 @@ impl<'a> PorkbunClient<'a> {
         Ok(())
     }
 
+    async fn create_record(&self, name: &str, addr: IpAddr) -> Result<(), DnessError> {
+        if self.dry_run {
+            return Ok(());
+        }
+
+        let post_url = format!("{}/dns/create/{}", self.base_url, self.domain);
+
+        self.client
+            .post(&post_url)
+            .json(&PorkbunRecordsEditRequest {
+                apikey: self.key.clone(),
+                secretapikey: self.secret.clone(),
+                name: String::from(name),
+                content: addr.to_string(),
+                ttl: self
+                    .ttl
+                    .clone()
+                    .unwrap_or_else(|| String::from(DEFAULT_TTL)),
+                r#type: String::from(IpType::from(addr).record_type()),
+            })
+            .send_traced()
+            .await
+            .map_err(|e| DnessError::send_http(&post_url, "porkbun create record", e))?
+            .error_for_status()
+            .map_err(|e| DnessError::bad_response(&post_url, "porkbun create record", e))?;
+
+        Ok(())
+    }
+
     async fn ensure_current_ip(
         &self,
         record: &PorkbunRecord,
-        addr: Ipv4Addr,
+        addr: IpAddr,
     ) -> Result<Updates, DnessError> {
         let mut current = 0;
         let mut updated = 0;
-        match record.content.parse::<Ipv4Addr>() {
+        match record.content.parse::<IpAddr>() {
             Ok(ip) => {
                 if ip != addr {
                     updated += 1;
                     self.update_record(record, addr).await?;
 
                     info!(
-                        "{} from domain {} updated from {} to {}",
-                        record.name, self.domain, record.content, addr
+                        "{}{} from domain {} updated from {} to {}",
+                        dry_run_prefix(self.dry_run),
+                        record.name,
+                        self.domain,
+                        record.content,
+                        addr
                     )
                 } else {
                     current += 1;
@@ -148,12 +246,16 @@ impl<'a> PorkbunClient<'a> {
             }
             Err(ref e) => {
                 updated += 1;
-                warn!("could not parse domain {} address {} as ipv4 -- will replace it. Original error: {}", record.name, record.content, e);
+                warn!("could not parse domain {} address {} as an ip -- will replace it. Original error: {}", record.name, record.content, e);
                 self.update_record(record, addr).await?;
 
                 info!(
-                    "{} from domain {} updated from {} to {}",
-                    record.name, self.domain, record.content, addr
+                    "{}{} from domain {} updated from {} to {}",
+                    dry_run_prefix(self.dry_run),
+                    record.name,
+                    self.domain,
+                    record.content,
+                    addr
                 )
             }
         }
@@ -166,46 +268,94 @@ impl<'a> PorkbunClient<'a> {
     }
 }
 
+/// The configured records as porkbun names them. To be consistent with other dns providers we
+/// allow the user to use '@' for root domain. Porkbun uses an empty string, so we map that here.
+fn record_names(records: &[String], domain: &str) -> HashSet<String> {
+    let mut names = HashSet::new();
+    for record in records {
+        let name = if record == "@" {
+            String::from("")
+        } else {
+            record.to_string()
+        };
+
+        if !names.insert(name) {
+            warn!(
+                "record {} of porkbun domain {} is listed more than once",
+                record, domain
+            );
+        }
+    }
+    names
+}
+
 /// Porkbun dynamic dns service works as the following:
 ///
-/// 1. Send a GET request to find all records in the domain
-/// 2. Filter records to just records in VALID_RECORD_TYPES, only "A" records when written
-/// 3. Find all the expected records (and log those that are missing) and check their current IP
-/// 4. Update the remote IP as needed, ensuring that original properties are preserved in the
-///    upload, so that we don't overwrite a property like TTL.
+/// 1. Ping the api to verify the credentials (and optionally use the ip that the api sees)
+/// 2. Send a GET request to find all records in the domain
+/// 3. Filter records to just the records of the address type: "A" for ipv4 and "AAAA" for ipv6
+/// 4. Find all the expected records (and log those that are missing) and check their current IP
+/// 5. Update the remote IP as needed, ensuring that original properties are preserved in the
+///    upload, so that we don't overwrite a property like TTL (unless a ttl is configured).
+/// 6. When configured, create the expected records that are missing
+#[tracing::instrument(skip(client, config), fields(domain = %config.domain))]
 pub async fn update_domains(
     client: &reqwest::Client,
     config: &PorkbunConfig,
-    addr: Ipv4Addr,
+    dns_config: &DnsConfig,
+    addr: IpAddr,
+    dry_run: bool,
 ) -> Result<Updates, DnessError> {
     let porkbun_client = PorkbunClient {
         base_url: config.base_url.trim_end_matches('/').to_string(),
         domain: config.domain.clone(),
         key: config.key.clone(),
         secret: config.secret.clone(),
-        records: config
-            .records
-            .iter()
-            .map(|r| {
-                // To be consistent with other dns providers we allow the user to use '@' for root
-                // domain. Porkbun uses an empty string, so we map that here.
-                if r == "@" {
-                    String::from("")
-                } else {
-                    r.to_string()
-                }
-            })
-            .collect(),
+        records: record_names(&config.records, &config.domain),
+        ttl: config.ttl.clone(),
         client,
+        dry_run,
+    };
+
+    let api_ip = porkbun_client.ping().await?;
+    let addr = match api_ip {
+        Some(ip) if config.use_api_ip && IpType::from(ip) == IpType::from(addr) => {
+            // The api ip is vetted like the resolved ip, as it could be a proxy's private address
+            if let Some(rejection) = dns_config.rejected_ip_reason(ip) {
+                return Err(DnessError::message(format!(
+                    "rejected the ip seen by the porkbun api {} as {}",
+                    ip, rejection
+                )));
+            }
+
+            debug!("using the ip seen by the porkbun api: {}", ip);
+            ip
+        }
+        _ => addr,
     };
 
-    let records = porkbun_client.fetch_records().await?;
+    let records = porkbun_client.fetch_records(IpType::from(addr)).await?;
     let missing = porkbun_client.log_missing_domains(&records) as i32;
     let mut summary = Updates {
         missing,
         ..Updates::default()
     };
 
+    if config.create_missing {
+        for name in porkbun_client.missing_records(&records) {
+            porkbun_client.create_record(&name, addr).await?;
+            summary.missing -= 1;
+            summary.updated += 1;
+            info!(
+                "{}{} from domain {} created with {}",
+                dry_run_prefix(dry_run),
+                name,
+                config.domain,
+                addr
+            );
+        }
+    }
+
     for record in records {
         if porkbun_client
             .records
@@ -218,9 +368,29 @@ pub async fn update_domains(
     Ok(summary)
 }
 
+#[async_trait]
+impl DnsProvider for PorkbunConfig {
+    async fn update(
+        &self,
+        ctx: &UpdateContext<'_>,
+        addr: IpAddr,
+    ) -> Result<Updates, Box<dyn std::error::Error>> {
+        retry(ctx.policy, ctx.name, || {
+            update_domains(ctx.client, self, ctx.config, addr, ctx.dry_run)
+        })
+        .await
+        .map_err(|e| e.into())
+    }
+
+    fn name(&self) -> &str {
+        "porkbun"
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr};
 
     #[test]
     fn deserialize_porkbun_response() {
@@ -254,6 +424,15 @@ mod tests {
                         prio: Some(String::from("0")),
                         other: expected_2.clone(),
                     },
+                    PorkbunRecord {
+                        id: String::from("354399919"),
+                        name: String::from("example.com"),
+                        r#type: String::from("AAAA"),
+                        content: String::from("2001:db8::1"),
+                        ttl: String::from("600"),
+                        prio: Some(String::from("0")),
+                        other: expected_2.clone(),
+                    },
                     PorkbunRecord {
                         id: String::from("354379285"),
                         name: String::from("example.com"),
@@ -268,21 +447,57 @@ mod tests {
         );
     }
 
+    fn porkbun_ttl_response(request: &rouille::Request, ttls: &[&str]) -> rouille::Response {
+        let body: serde_json::Value = rouille::input::json_input(request).unwrap();
+        if ttls.iter().any(|x| body["ttl"] == *x) {
+            rouille::Response::from_data("application/json", r#"{"status": "SUCCESS"}"#)
+        } else {
+            rouille::Response::text("unexpected ttl").with_status_code(400)
+        }
+    }
+
     macro_rules! porkbun_rouille_server {
         () => {{
             use rouille::Response;
             use rouille::Server;
 
             let server = Server::new("localhost:0", |request| match request.url().as_str() {
+                "/api/json/v3/ping" => {
+                    let body: serde_json::Value = rouille::input::json_input(request).unwrap();
+                    if body["apikey"] == "key-1" {
+                        Response::from_data(
+                            "application/json",
+                            r#"{"status": "SUCCESS", "yourIp": "2.2.2.3"}"#,
+                        )
+                    } else {
+                        Response::from_data(
+                            "application/json",
+                            r#"{"status": "ERROR", "message": "Invalid API key. (002)"}"#,
+                        )
+                        .with_status_code(400)
+                    }
+                }
                 "/api/json/v3/dns/retrieve/example.com" => Response::from_data(
                     "application/json",
                     include_bytes!("../assets/porkbun-get-records.json").to_vec(),
                 ),
+                // The ttl of a record is preserved unless a ttl of 900 is configured
                 "/api/json/v3/dns/edit/example.com/356408594" => {
-                    Response::from_data("application/json", r#"{"status": "SUCCESS"}"#)
+                    porkbun_ttl_response(request, &["600", "900"])
                 }
                 "/api/json/v3/dns/edit/example.com/354399918" => {
-                    Response::from_data("application/json", r#"{"status": "SUCCESS"}"#)
+                    porkbun_ttl_response(request, &["700", "900"])
+                }
+                "/api/json/v3/dns/edit/example.com/354399919" => {
+                    let body: serde_json::Value = rouille::input::json_input(request).unwrap();
+                    if body["type"] == "AAAA" && body["content"] == "2001:db8::2" {
+                        Response::from_data("application/json", r#"{"status": "SUCCESS"}"#)
+                    } else {
+                        Response::text("bad update").with_status_code(400)
+                    }
+                }
+                "/api/json/v3/dns/create/example.com" => {
+                    porkbun_ttl_response(request, &["600", "900"])
                 }
                 _ => Response::empty_404(),
             })
@@ -304,16 +519,25 @@ mod tests {
     async fn test_porkbun_update() {
         let (tx, addr) = porkbun_rouille_server!();
         let http_client = reqwest::Client::new();
-        let new_ip = Ipv4Addr::new(2, 2, 2, 1);
+        let new_ip = IpAddr::V4(Ipv4Addr::new(2, 2, 2, 1));
         let config = PorkbunConfig {
             base_url: format!("http://{}/api/json/v3", addr),
             domain: String::from("example.com"),
             key: String::from("key-1"),
             secret: String::from("secret-1"),
             records: vec![String::from("@"), String::from("sub")],
+            ip_types: vec![IpType::V4],
+            request_timeout_secs: None,
+            create_missing: false,
+            use_api_ip: false,
+            ttl: None,
+            log_level: None,
+            name: None,
         };
 
-        let summary = update_domains(&http_client, &config, new_ip).await.unwrap();
+        let summary = update_domains(&http_client, &config, &DnsConfig::default(), new_ip, false)
+            .await
+            .unwrap();
         tx.send(()).unwrap();
 
         assert_eq!(
@@ -322,6 +546,8 @@ mod tests {
                 current: 0,
                 updated: 2,
                 missing: 0,
+                failed: 0,
+                dry_run: false,
             }
         )
     }
@@ -330,16 +556,25 @@ mod tests {
     async fn test_porkbun_current() {
         let (tx, addr) = porkbun_rouille_server!();
         let http_client = reqwest::Client::new();
-        let new_ip = Ipv4Addr::new(2, 2, 2, 2);
+        let new_ip = IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2));
         let config = PorkbunConfig {
             base_url: format!("http://{}/api/json/v3", addr),
             domain: String::from("example.com"),
             key: String::from("key-1"),
             secret: String::from("secret-1"),
             records: vec![String::from("@"), String::from("sub")],
+            ip_types: vec![IpType::V4],
+            request_timeout_secs: None,
+            create_missing: false,
+            use_api_ip: false,
+            ttl: None,
+            log_level: None,
+            name: None,
         };
 
-        let summary = update_domains(&http_client, &config, new_ip).await.unwrap();
+        let summary = update_domains(&http_client, &config, &DnsConfig::default(), new_ip, false)
+            .await
+            .unwrap();
         tx.send(()).unwrap();
 
         assert_eq!(
@@ -348,6 +583,51 @@ mod tests {
                 current: 2,
                 updated: 0,
                 missing: 0,
+                failed: 0,
+                dry_run: false,
+            }
+        )
+    }
+
+    #[test]
+    fn test_porkbun_record_names() {
+        let records = vec![String::from("@"), String::from("www"), String::from("@")];
+        let names = record_names(&records, "example.com");
+        let expected = vec![String::from(""), String::from("www")];
+        assert_eq!(names, expected.into_iter().collect::<HashSet<_>>());
+    }
+
+    #[tokio::test]
+    async fn test_porkbun_root_only() {
+        let (tx, addr) = porkbun_rouille_server!();
+        let http_client = reqwest::Client::new();
+        let new_ip = IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2));
+        let config = PorkbunConfig {
+            base_url: format!("http://{}/api/json/v3", addr),
+            domain: String::from("example.com"),
+            key: String::from("key-1"),
+            secret: String::from("secret-1"),
+            records: vec![String::from("@"), String::from("@")],
+            ip_types: vec![IpType::V4],
+            request_timeout_secs: None,
+            create_missing: false,
+            use_api_ip: false,
+            ttl: None,
+            log_level: None,
+            name: None,
+        };
+
+        // The root record is found under its empty name and only counted once
+        let summary = update_domains(&http_client, &config, &DnsConfig::default(), new_ip, false)
+            .await
+            .unwrap();
+        tx.send(()).unwrap();
+
+        assert_eq!(
+            summary,
+            Updates {
+                current: 1,
+                ..Updates::default()
             }
         )
     }
@@ -356,25 +636,246 @@ mod tests {
     async fn test_porkbun_missing() {
         let (tx, addr) = porkbun_rouille_server!();
         let http_client = reqwest::Client::new();
-        let new_ip = Ipv4Addr::new(2, 2, 2, 2);
+        let new_ip = IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2));
+        let config = PorkbunConfig {
+            base_url: format!("http://{}/api/json/v3", addr),
+            domain: String::from("example.com"),
+            key: String::from("key-1"),
+            secret: String::from("secret-1"),
+            records: vec![String::from("@"), String::from("sub"), String::from("sub2")],
+            ip_types: vec![IpType::V4],
+            request_timeout_secs: None,
+            create_missing: false,
+            use_api_ip: false,
+            ttl: None,
+            log_level: None,
+            name: None,
+        };
+
+        let summary = update_domains(&http_client, &config, &DnsConfig::default(), new_ip, false)
+            .await
+            .unwrap();
+        tx.send(()).unwrap();
+
+        assert_eq!(
+            summary,
+            Updates {
+                current: 2,
+                updated: 0,
+                missing: 1,
+                failed: 0,
+                dry_run: false,
+            }
+        )
+    }
+
+    #[tokio::test]
+    async fn test_porkbun_create_missing() {
+        let (tx, addr) = porkbun_rouille_server!();
+        let http_client = reqwest::Client::new();
+        let new_ip = IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2));
         let config = PorkbunConfig {
             base_url: format!("http://{}/api/json/v3", addr),
             domain: String::from("example.com"),
             key: String::from("key-1"),
             secret: String::from("secret-1"),
             records: vec![String::from("@"), String::from("sub"), String::from("sub2")],
+            ip_types: vec![IpType::V4],
+            request_timeout_secs: None,
+            create_missing: true,
+            use_api_ip: false,
+            ttl: None,
+            log_level: None,
+            name: None,
         };
 
-        let summary = update_domains(&http_client, &config, new_ip).await.unwrap();
+        let summary = update_domains(&http_client, &config, &DnsConfig::default(), new_ip, false)
+            .await
+            .unwrap();
         tx.send(()).unwrap();
 
         assert_eq!(
             summary,
             Updates {
                 current: 2,
+                updated: 1,
+                missing: 0,
+                failed: 0,
+                dry_run: false,
+            }
+        )
+    }
+
+    fn ipv6_config(addr: std::net::SocketAddr) -> PorkbunConfig {
+        PorkbunConfig {
+            base_url: format!("http://{}/api/json/v3", addr),
+            domain: String::from("example.com"),
+            key: String::from("key-1"),
+            secret: String::from("secret-1"),
+            records: vec![String::from("@"), String::from("sub")],
+            ip_types: vec![IpType::V6],
+            request_timeout_secs: None,
+            create_missing: false,
+            use_api_ip: false,
+            ttl: None,
+            log_level: None,
+            name: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_porkbun_ipv6_current() {
+        let (tx, addr) = porkbun_rouille_server!();
+        let http_client = reqwest::Client::new();
+        let new_ip = IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1));
+        let config = ipv6_config(addr);
+
+        let summary = update_domains(&http_client, &config, &DnsConfig::default(), new_ip, false)
+            .await
+            .unwrap();
+        tx.send(()).unwrap();
+
+        assert_eq!(
+            summary,
+            Updates {
+                current: 1,
                 updated: 0,
                 missing: 1,
+                failed: 0,
+                dry_run: false,
+            }
+        )
+    }
+
+    #[tokio::test]
+    async fn test_porkbun_ipv6_update() {
+        let (tx, addr) = porkbun_rouille_server!();
+        let http_client = reqwest::Client::new();
+        let new_ip = IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 2));
+        let config = ipv6_config(addr);
+
+        let summary = update_domains(&http_client, &config, &DnsConfig::default(), new_ip, false)
+            .await
+            .unwrap();
+        tx.send(()).unwrap();
+
+        assert_eq!(
+            summary,
+            Updates {
+                current: 0,
+                updated: 1,
+                missing: 1,
+                failed: 0,
+                dry_run: false,
+            }
+        )
+    }
+
+    #[tokio::test]
+    async fn test_porkbun_configured_ttl() {
+        let (tx, addr) = porkbun_rouille_server!();
+        let http_client = reqwest::Client::new();
+        let new_ip = IpAddr::V4(Ipv4Addr::new(2, 2, 2, 1));
+        let config = PorkbunConfig {
+            base_url: format!("http://{}/api/json/v3", addr),
+            domain: String::from("example.com"),
+            key: String::from("key-1"),
+            secret: String::from("secret-1"),
+            records: vec![String::from("@"), String::from("sub"), String::from("sub2")],
+            ip_types: vec![IpType::V4],
+            request_timeout_secs: None,
+            create_missing: true,
+            use_api_ip: false,
+            ttl: Some(String::from("900")),
+            log_level: None,
+            name: None,
+        };
+
+        let summary = update_domains(&http_client, &config, &DnsConfig::default(), new_ip, false)
+            .await
+            .unwrap();
+        tx.send(()).unwrap();
+
+        assert_eq!(
+            summary,
+            Updates {
+                current: 0,
+                updated: 3,
+                missing: 0,
+                failed: 0,
+                dry_run: false,
+            }
+        )
+    }
+
+    #[tokio::test]
+    async fn test_porkbun_use_api_ip() {
+        let (tx, addr) = porkbun_rouille_server!();
+        let http_client = reqwest::Client::new();
+        let new_ip = IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2));
+        let config = PorkbunConfig {
+            ip_types: vec![IpType::V4],
+            use_api_ip: true,
+            ..ipv6_config(addr)
+        };
+
+        // The api sees 2.2.2.3, which replaces the resolved 2.2.2.2 that is already current
+        let summary = update_domains(&http_client, &config, &DnsConfig::default(), new_ip, false)
+            .await
+            .unwrap();
+        tx.send(()).unwrap();
+
+        assert_eq!(
+            summary,
+            Updates {
+                updated: 2,
+                ..Updates::default()
             }
         )
     }
+
+    #[tokio::test]
+    async fn test_porkbun_use_api_ip_rejected() {
+        let (tx, addr) = porkbun_rouille_server!();
+        let http_client = reqwest::Client::new();
+        let new_ip = IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2));
+        let config = PorkbunConfig {
+            ip_types: vec![IpType::V4],
+            use_api_ip: true,
+            ..ipv6_config(addr)
+        };
+        let dns_config = DnsConfig {
+            ip_blocklist: vec!["2.2.2.3/32".parse().unwrap()],
+            ..DnsConfig::default()
+        };
+
+        // The api sees 2.2.2.3, which is blocklisted
+        let result = update_domains(&http_client, &config, &dns_config, new_ip, false).await;
+        tx.send(()).unwrap();
+
+        let err = result.unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "rejected the ip seen by the porkbun api 2.2.2.3 as it is within the blocklisted 2.2.2.3/32"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_porkbun_ping_failed() {
+        let (tx, addr) = porkbun_rouille_server!();
+        let http_client = reqwest::Client::new();
+        let new_ip = IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2));
+        let config = PorkbunConfig {
+            key: String::from("wrong"),
+            ip_types: vec![IpType::V4],
+            ..ipv6_config(addr)
+        };
+
+        let result =
+            update_domains(&http_client, &config, &DnsConfig::default(), new_ip, false).await;
+        tx.send(()).unwrap();
+
+        let err = result.unwrap_err().to_string();
+        assert!(err.contains("porkbun API ping failed: Invalid API key. (002)"));
+    }
 }
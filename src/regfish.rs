@@ -0,0 +1,138 @@
+use crate::config::RegfishConfig;
+use crate::core::{
+    DnsCheckedRecords, DnsProvider, SendTraced, UpdateContext, UpdateOutcome, Updates,
+};
+use crate::errors::DnessError;
+use crate::retry::retry;
+use async_trait::async_trait;
+use std::net::IpAddr;
+
+#[derive(Debug)]
+pub struct RegfishProvider<'a> {
+    client: &'a reqwest::Client,
+    config: &'a RegfishConfig,
+}
+
+impl<'a> RegfishProvider<'a> {
+    /// https://www.regfish.de/domains/dyndns/dokumentation
+    pub async fn update_domain(
+        &self,
+        host: &str,
+        wan: IpAddr,
+    ) -> Result<UpdateOutcome, DnessError> {
+        let base = self.config.base_url.trim_end_matches('/').to_string();
+        let get_url = format!("{}/", base);
+
+        // ipv6 addresses are given in their own parameter
+        let ip_param = match wan {
+            IpAddr::V4(_) => "myip",
+            IpAddr::V6(_) => "myip6",
+        };
+
+        let response = self
+            .client
+            .get(&get_url)
+            .query(&[
+                ("fqdn", host),
+                ("forcehost", "1"),
+                (ip_param, &wan.to_string()),
+                ("token", self.config.token.as_str()),
+            ])
+            .send_traced()
+            .await
+            .map_err(|e| DnessError::send_http(&get_url, "regfish update", e))?
+            .error_for_status()
+            .map_err(|e| DnessError::bad_response(&get_url, "regfish update", e))?
+            .text()
+            .await
+            .map_err(|e| DnessError::deserialize(&get_url, "regfish update", e))?;
+
+        parse_regfish_response(&response)
+    }
+}
+
+/// Regfish responds with the outcome, a status code, and a description separated by pipes (eg:
+/// "success|100|update succeeded!")
+fn parse_regfish_response(response: &str) -> Result<UpdateOutcome, DnessError> {
+    let fields = response.trim().split('|').collect::<Vec<_>>();
+    let code = fields
+        .iter()
+        .find(|x| x.len() == 3 && x.bytes().all(|b| b.is_ascii_digit()))
+        .copied()
+        .unwrap_or_default();
+
+    match code {
+        "100" => Ok(UpdateOutcome::Updated),
+        "101" => Ok(UpdateOutcome::Current),
+        _ => Err(DnessError::message(format!(
+            "regfish update failed [{}]: {}",
+            code,
+            fields.last().copied().unwrap_or_default()
+        ))),
+    }
+}
+
+#[tracing::instrument(skip(client, config), fields(domain = %config.domain))]
+pub async fn update_domains(
+    client: &reqwest::Client,
+    config: &RegfishConfig,
+    wan: IpAddr,
+    dry_run: bool,
+    force: bool,
+) -> Result<Updates, DnessError> {
+    // uses the same strategy as namecheap where we get the current records
+    // via dns and check if they need to be updated
+    let regfish = &RegfishProvider { client, config };
+    DnsCheckedRecords {
+        provider: "regfish",
+        domain: &config.domain,
+        records: &config.records,
+        dry_run,
+        force,
+    }
+    .update(wan, |host| async move {
+        regfish.update_domain(&host, wan).await
+    })
+    .await
+}
+
+#[async_trait]
+impl DnsProvider for RegfishConfig {
+    async fn update(
+        &self,
+        ctx: &UpdateContext<'_>,
+        addr: IpAddr,
+    ) -> Result<Updates, Box<dyn std::error::Error>> {
+        retry(ctx.policy, ctx.name, || {
+            update_domains(ctx.client, self, addr, ctx.dry_run, ctx.force)
+        })
+        .await
+        .map_err(|e| e.into())
+    }
+
+    fn name(&self) -> &str {
+        "regfish"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_regfish_response() {
+        assert_eq!(
+            parse_regfish_response("success|100|update succeeded!\n").unwrap(),
+            UpdateOutcome::Updated
+        );
+        assert_eq!(
+            parse_regfish_response("success|101|no update needed").unwrap(),
+            UpdateOutcome::Current
+        );
+
+        let err = parse_regfish_response("failure|412|rate limit exceeded").unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("regfish update failed [412]: rate limit exceeded"));
+    }
+}
@@ -0,0 +1,416 @@
+use crate::config::{DnsConfig, IpResolverConfig, IpType};
+use crate::core::SendTraced;
+use crate::dns::{wan_lookup_ip, wan_lookup_ipv6};
+use crate::doh;
+use crate::errors::DnessError;
+use crate::interface::interface_ip;
+use async_trait::async_trait;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use tracing::warn;
+
+/// A source of the WAN IP
+#[async_trait]
+pub trait WanResolver: Send + Sync {
+    async fn resolve(&self, ip_type: IpType) -> Result<IpAddr, DnessError>;
+
+    /// The name of the resolver as written in the config
+    fn name(&self) -> &str;
+
+    /// Whether the resolver can be asked for an ipv6 address
+    fn supports_ipv6(&self) -> bool {
+        true
+    }
+
+    /// Whether the resolver should be asked for an address of the type
+    fn supports(&self, ip_type: IpType) -> bool {
+        ip_type == IpType::V4 || self.supports_ipv6()
+    }
+}
+
+/// Resolves the WAN IP by asking OpenDNS for the address of "myip.opendns.com"
+#[derive(Debug)]
+pub struct OpenDnsResolver;
+
+#[async_trait]
+impl WanResolver for OpenDnsResolver {
+    async fn resolve(&self, ip_type: IpType) -> Result<IpAddr, DnessError> {
+        match ip_type {
+            IpType::V4 => Ok(IpAddr::V4(wan_lookup_ip().await?)),
+            IpType::V6 => Ok(IpAddr::V6(wan_lookup_ipv6().await?)),
+        }
+    }
+
+    fn name(&self) -> &str {
+        "opendns"
+    }
+}
+
+#[derive(Debug)]
+pub struct IpifyResolver<'a> {
+    client: &'a reqwest::Client,
+}
+
+#[async_trait]
+impl<'a> WanResolver for IpifyResolver<'a> {
+    async fn resolve(&self, ip_type: IpType) -> Result<IpAddr, DnessError> {
+        let url = match ip_type {
+            IpType::V4 => "https://api.ipify.org/",
+            IpType::V6 => "https://api6.ipify.org/",
+        };
+        http_resolve_ip(self.client, url, ip_type).await
+    }
+
+    fn name(&self) -> &str {
+        "ipify"
+    }
+}
+
+/// Resolves the WAN IP from the configured `ip_resolver_url`
+#[derive(Debug)]
+pub struct HttpResolver<'a> {
+    client: &'a reqwest::Client,
+    url: Option<String>,
+}
+
+#[async_trait]
+impl<'a> WanResolver for HttpResolver<'a> {
+    async fn resolve(&self, ip_type: IpType) -> Result<IpAddr, DnessError> {
+        let url = self.url.as_deref().ok_or_else(|| {
+            DnessError::message(String::from(
+                "the http ip resolver requires ip_resolver_url to be set",
+            ))
+        })?;
+        http_resolve_ip(self.client, url, ip_type).await
+    }
+
+    fn name(&self) -> &str {
+        "http"
+    }
+
+    fn supports_ipv6(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Debug)]
+pub struct DohResolver<'a> {
+    client: &'a reqwest::Client,
+}
+
+#[async_trait]
+impl<'a> WanResolver for DohResolver<'a> {
+    async fn resolve(&self, ip_type: IpType) -> Result<IpAddr, DnessError> {
+        match ip_type {
+            IpType::V4 => Ok(IpAddr::V4(
+                doh::doh_lookup_ip(self.client, doh::OPENDNS_DOH_URL).await?,
+            )),
+            IpType::V6 => Err(unsupported_ipv6(self.name())),
+        }
+    }
+
+    fn name(&self) -> &str {
+        "doh"
+    }
+
+    fn supports_ipv6(&self) -> bool {
+        false
+    }
+}
+
+/// Resolves the WAN IP from the configured `ip_resolver_interface`
+#[derive(Debug)]
+pub struct InterfaceResolver {
+    interface: Option<String>,
+}
+
+#[async_trait]
+impl WanResolver for InterfaceResolver {
+    async fn resolve(&self, ip_type: IpType) -> Result<IpAddr, DnessError> {
+        let interface = self.interface.as_deref().ok_or_else(|| {
+            DnessError::message(String::from(
+                "the interface ip resolver requires ip_resolver_interface to be set",
+            ))
+        })?;
+        interface_ip(interface, ip_type)
+    }
+
+    fn name(&self) -> &str {
+        "interface"
+    }
+}
+
+/// A resolver that is only asked for one type of address, as the address types are configured
+/// with their own resolvers
+struct OnlyFor<'a> {
+    resolver: Box<dyn WanResolver + 'a>,
+    ip_type: IpType,
+}
+
+#[async_trait]
+impl<'a> WanResolver for OnlyFor<'a> {
+    async fn resolve(&self, ip_type: IpType) -> Result<IpAddr, DnessError> {
+        self.resolver.resolve(ip_type).await
+    }
+
+    fn name(&self) -> &str {
+        self.resolver.name()
+    }
+
+    fn supports_ipv6(&self) -> bool {
+        self.supports(IpType::V6)
+    }
+
+    fn supports(&self, ip_type: IpType) -> bool {
+        ip_type == self.ip_type && self.resolver.supports(ip_type)
+    }
+}
+
+fn unsupported_ipv6(resolver: &str) -> DnessError {
+    DnessError::message(format!(
+        "the {} ip resolver does not support ipv6",
+        resolver
+    ))
+}
+
+/// Constructs the configured resolvers in the order they should be tried. When the address types
+/// are configured with different resolvers, each resolver is only asked for its own type.
+pub fn wan_resolvers<'a>(
+    client: &'a reqwest::Client,
+    config: &DnsConfig,
+) -> Result<Vec<Box<dyn WanResolver + 'a>>, DnessError> {
+    let resolver_v4 = config.ip_resolver_for(IpType::V4);
+    let resolver_v6 = config.ip_resolver_for(IpType::V6);
+    if resolver_v4 == resolver_v6 {
+        return create_resolvers(client, config, resolver_v4);
+    }
+
+    let mut result: Vec<Box<dyn WanResolver + 'a>> = Vec::new();
+    for (ip_type, resolvers) in [(IpType::V4, resolver_v4), (IpType::V6, resolver_v6)] {
+        for resolver in create_resolvers(client, config, resolvers)? {
+            result.push(Box::new(OnlyFor { resolver, ip_type }));
+        }
+    }
+
+    Ok(result)
+}
+
+fn create_resolvers<'a>(
+    client: &'a reqwest::Client,
+    config: &DnsConfig,
+    resolvers: &IpResolverConfig,
+) -> Result<Vec<Box<dyn WanResolver + 'a>>, DnessError> {
+    let mut result: Vec<Box<dyn WanResolver + 'a>> = Vec::new();
+    for resolver in resolvers.resolvers() {
+        match resolver.to_ascii_lowercase().as_str() {
+            "opendns" => result.push(Box::new(OpenDnsResolver)),
+            "ipify" => result.push(Box::new(IpifyResolver { client })),
+            "http" => result.push(Box::new(HttpResolver {
+                client,
+                url: config.ip_resolver_url.clone(),
+            })),
+            "doh" => result.push(Box::new(DohResolver { client })),
+            "interface" => result.push(Box::new(InterfaceResolver {
+                interface: config.ip_resolver_interface.clone(),
+            })),
+            _ => {
+                return Err(DnessError::message(format!(
+                    "unrecognized ip resolver: {}",
+                    resolver
+                )))
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+/// Resolves the WAN IP with the resolvers that support the address type, where a failed resolver
+/// falls back to the next. The error of the last resolver is returned when all of them fail.
+async fn resolve_with(
+    resolvers: &[Box<dyn WanResolver + '_>],
+    ip_type: IpType,
+) -> Result<IpAddr, DnessError> {
+    let resolvers = resolvers
+        .iter()
+        .filter(|x| x.supports(ip_type))
+        .collect::<Vec<_>>();
+
+    let mut last_err = None;
+    for (i, resolver) in resolvers.iter().enumerate() {
+        match resolver.resolve(ip_type).await {
+            Ok(ip) => return Ok(ip),
+            Err(e) if i + 1 < resolvers.len() => {
+                warn!(
+                    "{} {} resolver failed, trying the next: {}",
+                    resolver.name(),
+                    ip_type,
+                    e
+                );
+            }
+            Err(e) => last_err = Some(e),
+        }
+    }
+
+    Err(last_err.unwrap_or_else(|| match ip_type {
+        IpType::V4 => DnessError::message(String::from("no ip resolver is configured")),
+        IpType::V6 => DnessError::message(String::from(
+            "none of the configured ip resolvers support ipv6",
+        )),
+    }))
+}
+
+pub async fn resolve_ip(resolvers: &[Box<dyn WanResolver + '_>]) -> Result<Ipv4Addr, DnessError> {
+    match resolve_with(resolvers, IpType::V4).await? {
+        IpAddr::V4(ip) => Ok(ip),
+        IpAddr::V6(ip) => Err(DnessError::message(format!(
+            "expected an ipv4 address but received: {}",
+            ip
+        ))),
+    }
+}
+
+pub async fn resolve_ipv6(resolvers: &[Box<dyn WanResolver + '_>]) -> Result<Ipv6Addr, DnessError> {
+    match resolve_with(resolvers, IpType::V6).await? {
+        IpAddr::V6(ip) => Ok(ip),
+        IpAddr::V4(ip) => Err(DnessError::message(format!(
+            "expected an ipv6 address but received: {}",
+            ip
+        ))),
+    }
+}
+
+/// Resolves the WAN IP from an endpoint that responds with the IP as plain text
+async fn http_resolve_ip(
+    client: &reqwest::Client,
+    url: &str,
+    ip_type: IpType,
+) -> Result<IpAddr, DnessError> {
+    let ip_text = client
+        .get(url)
+        .send_traced()
+        .await
+        .map_err(|e| DnessError::send_http(url, "http get ip", e))?
+        .error_for_status()
+        .map_err(|e| DnessError::bad_response(url, "http get ip", e))?
+        .text()
+        .await
+        .map_err(|e| DnessError::deserialize(url, "http get ip", e))?;
+
+    let ip_text = ip_text.trim();
+    let ip = ip_text
+        .parse::<IpAddr>()
+        .map_err(|_| DnessError::message(format!("unable to parse {} as an ip", ip_text)))?;
+
+    match (ip, ip_type) {
+        (IpAddr::V4(_), IpType::V4) | (IpAddr::V6(_), IpType::V6) => Ok(ip),
+        _ => Err(DnessError::message(format!(
+            "expected an {} address from {} but received: {}",
+            ip_type, url, ip
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! ip_server {
+        () => {{
+            use rouille::Response;
+            use rouille::Server;
+
+            let server = Server::new("localhost:0", |request| match request.url().as_str() {
+                "/ipv4" => Response::text("2.2.2.2\n"),
+                "/ipv6" => Response::text("2001:db8::1\n"),
+                _ => Response::empty_404(),
+            })
+            .unwrap();
+
+            let (tx, rx) = std::sync::mpsc::sync_channel(1);
+            let addr = server.server_addr().clone();
+            std::thread::spawn(move || {
+                while let Err(_) = rx.try_recv() {
+                    server.poll();
+                    std::thread::sleep(std::time::Duration::from_millis(50))
+                }
+            });
+            (tx, addr)
+        }};
+    }
+
+    #[tokio::test]
+    async fn test_http_resolve_ip() {
+        let (tx, addr) = ip_server!();
+        let http_client = reqwest::Client::new();
+        let url = format!("http://{}/ipv4", addr);
+        let ip = http_resolve_ip(&http_client, &url, IpType::V4)
+            .await
+            .unwrap();
+        tx.send(()).unwrap();
+        assert_eq!(ip, IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2)));
+    }
+
+    #[tokio::test]
+    async fn test_http_resolve_ip_mismatch() {
+        let (tx, addr) = ip_server!();
+        let http_client = reqwest::Client::new();
+        let url = format!("http://{}/ipv6", addr);
+        let err = http_resolve_ip(&http_client, &url, IpType::V4)
+            .await
+            .unwrap_err();
+        tx.send(()).unwrap();
+        assert!(format!("{}", err).contains("expected an ipv4 address"));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_ip_falls_back() {
+        let (tx, addr) = ip_server!();
+        let http_client = reqwest::Client::new();
+        let resolvers: Vec<Box<dyn WanResolver>> = vec![
+            Box::new(InterfaceResolver { interface: None }),
+            Box::new(HttpResolver {
+                client: &http_client,
+                url: Some(format!("http://{}/ipv4", addr)),
+            }),
+        ];
+
+        let ip = resolve_ip(&resolvers).await.unwrap();
+        tx.send(()).unwrap();
+        assert_eq!(ip, Ipv4Addr::new(2, 2, 2, 2));
+    }
+
+    #[tokio::test]
+    async fn test_resolvers_per_ip_type() {
+        let (tx, addr) = ip_server!();
+        let http_client = reqwest::Client::new();
+        let config = DnsConfig {
+            ip_resolver: IpResolverConfig::Single(String::from("http")),
+            ip_resolver_v6: Some(IpResolverConfig::Single(String::from("ipify"))),
+            ip_resolver_url: Some(format!("http://{}/ipv4", addr)),
+            ..DnsConfig::default()
+        };
+
+        let resolvers = wan_resolvers(&http_client, &config).unwrap();
+        let names = resolvers.iter().map(|x| x.name()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["http", "ipify"]);
+        assert!(!resolvers[1].supports(IpType::V4));
+
+        let ip = resolve_ip(&resolvers).await.unwrap();
+        tx.send(()).unwrap();
+        assert_eq!(ip, Ipv4Addr::new(2, 2, 2, 2));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_ipv6_skips_unsupported() {
+        let http_client = reqwest::Client::new();
+        let resolvers: Vec<Box<dyn WanResolver>> = vec![Box::new(DohResolver {
+            client: &http_client,
+        })];
+
+        let err = resolve_ipv6(&resolvers).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "none of the configured ip resolvers support ipv6"
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+use tracing::warn;
+
+/// Errors that may succeed if the request is attempted again
+pub trait Retryable {
+    fn is_retryable(&self) -> bool;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// The delay doubles after every attempt with up to an additional base delay of jitter so
+    /// that concurrent clients don't retry in lockstep
+    fn delay(&self, attempt: u32) -> Duration {
+        let backoff = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)));
+
+        let base_ms = self.base_delay.as_millis() as u64;
+        let jitter = if base_ms == 0 {
+            0
+        } else {
+            RandomState::new().build_hasher().finish() % base_ms
+        };
+
+        backoff.saturating_add(Duration::from_millis(jitter))
+    }
+}
+
+/// Executes the given function until it succeeds, returns a non-retryable error, or the retries
+/// are exhausted.
+pub async fn retry<T, E, F, Fut>(policy: &RetryPolicy, context: &str, mut f: F) -> Result<T, E>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    E: Retryable + fmt::Display,
+{
+    let mut attempt = 0;
+    loop {
+        match f().await {
+            Err(e) if e.is_retryable() && attempt < policy.max_retries => {
+                attempt += 1;
+                let delay = policy.delay(attempt);
+                warn!(
+                    "retrying {} (attempt {} of {}) in {}ms: {}",
+                    context,
+                    attempt,
+                    policy.max_retries,
+                    delay.as_millis(),
+                    e
+                );
+                tokio::time::sleep(delay).await;
+            }
+            res => return res,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[derive(Debug)]
+    struct TestError(bool);
+
+    impl Retryable for TestError {
+        fn is_retryable(&self) -> bool {
+            self.0
+        }
+    }
+
+    impl fmt::Display for TestError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "test error")
+        }
+    }
+
+    const POLICY: RetryPolicy = RetryPolicy {
+        max_retries: 3,
+        base_delay: Duration::from_millis(1),
+    };
+
+    #[test]
+    fn test_retry_delay() {
+        let policy = RetryPolicy {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+        };
+
+        let delay = policy.delay(3);
+        assert!(delay >= Duration::from_millis(2000));
+        assert!(delay < Duration::from_millis(2500));
+    }
+
+    #[tokio::test]
+    async fn test_retry_exhausted() {
+        let attempts = Cell::new(0);
+        let res: Result<(), _> = retry(&POLICY, "test", || async {
+            attempts.set(attempts.get() + 1);
+            Err(TestError(true))
+        })
+        .await;
+
+        assert!(res.is_err());
+        assert_eq!(attempts.get(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_retry_not_retryable() {
+        let attempts = Cell::new(0);
+        let res: Result<(), _> = retry(&POLICY, "test", || async {
+            attempts.set(attempts.get() + 1);
+            Err(TestError(false))
+        })
+        .await;
+
+        assert!(res.is_err());
+        assert_eq!(attempts.get(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_retry_success() {
+        let attempts = Cell::new(0);
+        let res = retry(&POLICY, "test", || async {
+            attempts.set(attempts.get() + 1);
+            if attempts.get() < 3 {
+                Err(TestError(true))
+            } else {
+                Ok(attempts.get())
+            }
+        })
+        .await;
+
+        assert_eq!(res.unwrap(), 3);
+    }
+}
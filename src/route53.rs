@@ -0,0 +1,588 @@
+use crate::config::{IpType, Route53Config};
+use crate::core::{dry_run_prefix, DnsProvider, SendTraced, UpdateContext, Updates};
+use crate::errors::DnessError;
+use crate::retry::retry;
+use async_trait::async_trait;
+use aws_credential_types::provider::ProvideCredentials;
+use aws_credential_types::Credentials;
+use aws_sigv4::http_request::{sign, SignableBody, SignableRequest, SigningSettings};
+use aws_sigv4::sign::v4;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::time::SystemTime;
+use tracing::{debug, info, warn};
+
+/// Route 53 is a global service whose requests are always signed for us-east-1
+const SIGNING_REGION: &str = "us-east-1";
+
+/// The ttl of records that are created
+const DEFAULT_TTL: u32 = 300;
+
+#[derive(Deserialize, PartialEq, Clone, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct ListResourceRecordSetsResponse {
+    resource_record_sets: ResourceRecordSets,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Clone, Debug, Default)]
+struct ResourceRecordSets {
+    #[serde(rename = "ResourceRecordSet", default)]
+    sets: Vec<ResourceRecordSet>,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Clone, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct ResourceRecordSet {
+    name: String,
+    r#type: String,
+    #[serde(rename = "TTL")]
+    ttl: Option<u32>,
+    resource_records: Option<ResourceRecords>,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Clone, Debug)]
+struct ResourceRecords {
+    #[serde(rename = "ResourceRecord", default)]
+    records: Vec<ResourceRecord>,
+}
+
+#[derive(Deserialize, Serialize, PartialEq, Clone, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct ResourceRecord {
+    value: String,
+}
+
+#[derive(Serialize, PartialEq, Clone, Debug)]
+#[serde(rename = "ChangeResourceRecordSetsRequest")]
+struct ChangeResourceRecordSetsRequest {
+    #[serde(rename = "@xmlns")]
+    xmlns: &'static str,
+    #[serde(rename = "ChangeBatch")]
+    change_batch: ChangeBatch,
+}
+
+#[derive(Serialize, PartialEq, Clone, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct ChangeBatch {
+    changes: Changes,
+}
+
+#[derive(Serialize, PartialEq, Clone, Debug)]
+struct Changes {
+    #[serde(rename = "Change")]
+    changes: Vec<Change>,
+}
+
+#[derive(Serialize, PartialEq, Clone, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct Change {
+    action: &'static str,
+    resource_record_set: ResourceRecordSet,
+}
+
+#[derive(Deserialize, PartialEq, Clone, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct ErrorResponse {
+    error: ApiError,
+}
+
+#[derive(Deserialize, PartialEq, Clone, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct ApiError {
+    code: String,
+    message: String,
+}
+
+/// Route 53 returns names fully qualified, lowercase, and with the wildcard escaped
+fn canonical_name(name: &str) -> String {
+    let name = name.trim_end_matches('.').to_ascii_lowercase();
+    let name = match name.strip_prefix('*') {
+        Some(rest) => format!("\\052{}", rest),
+        None => name,
+    };
+    format!("{}.", name)
+}
+
+fn api_error(context: &str, status: reqwest::StatusCode, body: &str) -> DnessError {
+    let msg = match quick_xml::de::from_str::<ErrorResponse>(body) {
+        Ok(response) => format!(
+            "{} failed with {}: {}: {}",
+            context, status, response.error.code, response.error.message
+        ),
+        Err(_) => format!("{} failed with {}: {}", context, status, body),
+    };
+
+    // Throttling and concurrent modifications are worth retrying
+    let is_transient = status.is_server_error()
+        || body.contains("Throttling")
+        || body.contains("PriorRequestNotComplete");
+    if is_transient {
+        DnessError::transient(msg)
+    } else {
+        DnessError::message(msg)
+    }
+}
+
+/// Resolves credentials with the standard AWS SDK credential chain: environment variables,
+/// config files, and then instance metadata
+async fn load_credentials(config: &Route53Config) -> Result<Credentials, DnessError> {
+    let mut loader = aws_config::defaults(aws_config::BehaviorVersion::latest());
+    if let Some(region) = config.region.as_ref() {
+        loader = loader.region(aws_config::Region::new(region.clone()));
+    }
+
+    let sdk_config = loader.load().await;
+    let provider = sdk_config.credentials_provider().ok_or_else(|| {
+        DnessError::message(String::from("no aws credentials provider is available"))
+    })?;
+
+    provider
+        .provide_credentials()
+        .await
+        .map_err(|e| DnessError::message(format!("unable to load aws credentials: {}", e)))
+}
+
+/// The headers that sign the request with sigv4 for route53 at the given time
+fn signing_headers(
+    credentials: &Credentials,
+    method: &reqwest::Method,
+    url: &reqwest::Url,
+    body: &[u8],
+    time: SystemTime,
+) -> Result<Vec<(String, String)>, Box<dyn std::error::Error>> {
+    let identity = credentials.clone().into();
+    let params = v4::SigningParams::builder()
+        .identity(&identity)
+        .region(SIGNING_REGION)
+        .name("route53")
+        .time(time)
+        .settings(SigningSettings::default())
+        .build()?
+        .into();
+
+    let request = SignableRequest::new(
+        method.as_str(),
+        url.as_str(),
+        std::iter::empty(),
+        SignableBody::Bytes(body),
+    )?;
+    let (instructions, _signature) = sign(request, &params)?.into_parts();
+    Ok(instructions
+        .headers()
+        .map(|(name, value)| (String::from(name), String::from(value)))
+        .collect())
+}
+
+#[derive(Debug)]
+struct Route53Client<'a> {
+    client: &'a reqwest::Client,
+    config: &'a Route53Config,
+    credentials: Credentials,
+}
+
+impl<'a> Route53Client<'a> {
+    fn zone_url(&self) -> String {
+        let zone = self
+            .config
+            .hosted_zone_id
+            .trim_start_matches("/hostedzone/");
+        format!(
+            "{}/2013-04-01/hostedzone/{}/rrset",
+            self.config.base_url.trim_end_matches('/'),
+            zone
+        )
+    }
+
+    /// Sends a request signed with sigv4, returning the response body
+    async fn send(
+        &self,
+        method: reqwest::Method,
+        url: reqwest::Url,
+        body: Vec<u8>,
+        context: &str,
+    ) -> Result<String, DnessError> {
+        let headers =
+            signing_headers(&self.credentials, &method, &url, &body, SystemTime::now())
+                .map_err(|e| DnessError::message(format!("unable to sign {}: {}", context, e)))?;
+
+        let mut request = self.client.request(method, url.clone()).body(body);
+        for (name, value) in headers {
+            request = request.header(name, value);
+        }
+
+        let response = request
+            .send_traced()
+            .await
+            .map_err(|e| DnessError::send_http(url.as_str(), context, e))?;
+        let status = response.status();
+        let text = response
+            .text()
+            .await
+            .map_err(|e| DnessError::deserialize(url.as_str(), context, e))?;
+
+        if !status.is_success() {
+            return Err(api_error(context, status, &text));
+        }
+
+        Ok(text)
+    }
+
+    /// https://docs.aws.amazon.com/Route53/latest/APIReference/API_ListResourceRecordSets.html
+    async fn get_record(
+        &self,
+        name: &str,
+        record_type: &str,
+    ) -> Result<Option<ResourceRecordSet>, DnessError> {
+        let mut url = reqwest::Url::parse(&self.zone_url()).map_err(|e| {
+            DnessError::message(format!("invalid route53 url {}: {}", self.zone_url(), e))
+        })?;
+        url.query_pairs_mut()
+            .append_pair("name", name)
+            .append_pair("type", record_type)
+            .append_pair("maxitems", "1");
+
+        let body = self
+            .send(
+                reqwest::Method::GET,
+                url,
+                Vec::new(),
+                "route53 list records",
+            )
+            .await?;
+
+        let response: ListResourceRecordSetsResponse =
+            quick_xml::de::from_str(&body).map_err(|e| {
+                DnessError::message(format!(
+                    "unable to parse route53 list records response ({}): {}",
+                    e, body
+                ))
+            })?;
+
+        // The listing starts at the requested name, so the first record may be a later one
+        Ok(response
+            .resource_record_sets
+            .sets
+            .into_iter()
+            .find(|x| x.name == name && x.r#type == record_type))
+    }
+
+    /// https://docs.aws.amazon.com/Route53/latest/APIReference/API_ChangeResourceRecordSets.html
+    async fn change_records(&self, changes: Vec<Change>) -> Result<(), DnessError> {
+        let request = ChangeResourceRecordSetsRequest {
+            xmlns: "https://route53.amazonaws.com/doc/2013-04-01/",
+            change_batch: ChangeBatch {
+                changes: Changes { changes },
+            },
+        };
+
+        let body = quick_xml::se::to_string(&request).map_err(|e| {
+            DnessError::message(format!("unable to serialize route53 changes: {}", e))
+        })?;
+
+        let url = reqwest::Url::parse(&format!("{}/", self.zone_url())).map_err(|e| {
+            DnessError::message(format!("invalid route53 url {}: {}", self.zone_url(), e))
+        })?;
+
+        self.send(
+            reqwest::Method::POST,
+            url,
+            body.into_bytes(),
+            "route53 change records",
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+fn upsert(name: String, record_type: &str, ttl: u32, addr: IpAddr) -> Change {
+    Change {
+        action: "UPSERT",
+        resource_record_set: ResourceRecordSet {
+            name,
+            r#type: String::from(record_type),
+            ttl: Some(ttl),
+            resource_records: Some(ResourceRecords {
+                records: vec![ResourceRecord {
+                    value: addr.to_string(),
+                }],
+            }),
+        },
+    }
+}
+
+/// Route 53 is updated as follows:
+///
+/// 1. For each record, list the record set of the address type by its name
+/// 2. Record sets that already point to the address are current
+/// 3. Record sets that point elsewhere are upserted with the address, keeping their TTL
+/// 4. Record sets that don't exist are always created, as an upsert works the same either way
+/// 5. All of the changes are sent as a single batch
+#[tracing::instrument(skip(client, config), fields(domain = %config.hosted_zone_id))]
+pub async fn update_domains(
+    client: &reqwest::Client,
+    config: &Route53Config,
+    addr: IpAddr,
+    dry_run: bool,
+) -> Result<Updates, DnessError> {
+    let route53 = Route53Client {
+        client,
+        config,
+        credentials: load_credentials(config).await?,
+    };
+
+    let record_type = IpType::from(addr).record_type();
+
+    // The changes are only logged once the batch succeeds, as route53 applies all or none of them
+    let mut summary = Updates::default();
+    let mut changes = Vec::new();
+    let mut messages = Vec::new();
+    for record in &config.records {
+        let name = canonical_name(record);
+        match route53.get_record(&name, record_type).await? {
+            None => {
+                messages.push(format!(
+                    "{}{} {} record created with {}",
+                    dry_run_prefix(dry_run),
+                    record,
+                    record_type,
+                    addr
+                ));
+                changes.push(upsert(name, record_type, DEFAULT_TTL, addr));
+            }
+            Some(ResourceRecordSet {
+                resource_records: None,
+                ..
+            }) => {
+                warn!(
+                    "{} {} record is an alias record and will not be updated",
+                    record, record_type
+                );
+            }
+            Some(set) => {
+                let values = set
+                    .resource_records
+                    .as_ref()
+                    .map(|x| x.records.iter().map(|r| r.value.as_str()).collect())
+                    .unwrap_or_else(Vec::new);
+
+                if values.len() == 1 && values[0].parse::<IpAddr>().ok() == Some(addr) {
+                    summary.current += 1;
+                    debug!("{} {} record is already current", record, record_type);
+                } else {
+                    messages.push(format!(
+                        "{}{} {} record updated from {} to {}",
+                        dry_run_prefix(dry_run),
+                        record,
+                        record_type,
+                        values.join(", "),
+                        addr
+                    ));
+                    let ttl = set.ttl.unwrap_or(DEFAULT_TTL);
+                    changes.push(upsert(name, record_type, ttl, addr));
+                }
+            }
+        }
+    }
+
+    if !changes.is_empty() && !dry_run {
+        route53.change_records(changes).await?;
+    }
+
+    for msg in &messages {
+        summary.updated += 1;
+        info!("{}", msg);
+    }
+
+    Ok(summary)
+}
+
+#[async_trait]
+impl DnsProvider for Route53Config {
+    async fn update(
+        &self,
+        ctx: &UpdateContext<'_>,
+        addr: IpAddr,
+    ) -> Result<Updates, Box<dyn std::error::Error>> {
+        retry(ctx.policy, ctx.name, || {
+            update_domains(ctx.client, self, addr, ctx.dry_run)
+        })
+        .await
+        .map_err(|e| e.into())
+    }
+
+    fn name(&self) -> &str {
+        "route53"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    macro_rules! route53_server {
+        () => {{
+            use rouille::Response;
+            use rouille::Server;
+
+            let server = Server::new("localhost:0", |request| {
+                // Every request must be signed
+                if request.header("Authorization").is_none() {
+                    return Response::text("unsigned").with_status_code(403);
+                }
+
+                match (request.method(), request.url().as_str()) {
+                    ("GET", "/2013-04-01/hostedzone/Z1/rrset") => {
+                        match request.get_param("name").as_deref() {
+                            Some("example.com.") => Response::from_data(
+                                "text/xml",
+                                include_bytes!("../assets/route53-list-records.xml").to_vec(),
+                            ),
+                            _ => Response::from_data(
+                                "text/xml",
+                                include_bytes!("../assets/route53-list-records-empty.xml").to_vec(),
+                            ),
+                        }
+                    }
+                    ("POST", "/2013-04-01/hostedzone/Z1/rrset/") => {
+                        let mut body = String::new();
+                        let mut data = request.data().unwrap();
+                        std::io::Read::read_to_string(&mut data, &mut body).unwrap();
+
+                        // Both changes are sent in a single batch
+                        if body.matches("<Change>").count() == 2 {
+                            Response::from_data(
+                                "text/xml",
+                                include_bytes!("../assets/route53-change-records.xml").to_vec(),
+                            )
+                        } else {
+                            Response::from_data(
+                                "text/xml",
+                                include_bytes!("../assets/route53-error.xml").to_vec(),
+                            )
+                            .with_status_code(400)
+                        }
+                    }
+                    _ => Response::empty_404(),
+                }
+            })
+            .unwrap();
+
+            let (tx, rx) = std::sync::mpsc::sync_channel(1);
+            let addr = server.server_addr().clone();
+            std::thread::spawn(move || {
+                while let Err(_) = rx.try_recv() {
+                    server.poll();
+                    std::thread::sleep(std::time::Duration::from_millis(50))
+                }
+            });
+            (tx, addr)
+        }};
+    }
+
+    fn config(addr: std::net::SocketAddr) -> Route53Config {
+        Route53Config {
+            base_url: format!("http://{}", addr),
+            hosted_zone_id: String::from("/hostedzone/Z1"),
+            records: vec![String::from("example.com"), String::from("sub.example.com")],
+            ip_types: vec![crate::config::IpType::V4],
+            region: None,
+            request_timeout_secs: None,
+            log_level: None,
+            name: None,
+        }
+    }
+
+    #[test]
+    fn test_canonical_name() {
+        assert_eq!(canonical_name("Example.com"), "example.com.");
+        assert_eq!(canonical_name("sub.example.com."), "sub.example.com.");
+        assert_eq!(canonical_name("*.example.com"), "\\052.example.com.");
+    }
+
+    #[test]
+    fn test_signing_headers() {
+        let credentials = Credentials::new("AKID", "secret", None, None, "test");
+        let url =
+            reqwest::Url::parse("https://route53.amazonaws.com/2013-04-01/hostedzone/Z1/rrset/")
+                .unwrap();
+        let time = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_704_067_200);
+        let sign = |credentials: &Credentials, body: &[u8]| {
+            signing_headers(credentials, &reqwest::Method::POST, &url, body, time)
+                .unwrap()
+                .into_iter()
+                .collect::<std::collections::HashMap<_, _>>()
+        };
+
+        // The request is signed for the route53 service in us-east-1 at the given time
+        let headers = sign(&credentials, b"<ChangeBatch/>");
+        assert_eq!(headers["x-amz-date"], "20240101T000000Z");
+        let authorization = &headers["authorization"];
+        assert!(authorization.starts_with(
+            "AWS4-HMAC-SHA256 Credential=AKID/20240101/us-east-1/route53/aws4_request, "
+        ));
+        assert!(authorization.contains("SignedHeaders=host;x-amz-date, Signature="));
+        assert!(!headers.contains_key("x-amz-security-token"));
+
+        // The signature covers the body and is the same for the same request
+        assert_eq!(sign(&credentials, b"<ChangeBatch/>"), headers);
+        assert_ne!(
+            sign(&credentials, b"<Other/>")["authorization"],
+            *authorization
+        );
+
+        // Temporary credentials send their session token along
+        let temporary =
+            Credentials::new("AKID", "secret", Some(String::from("token")), None, "test");
+        let headers = sign(&temporary, b"<ChangeBatch/>");
+        assert_eq!(headers["x-amz-security-token"], "token");
+        assert!(
+            headers["authorization"].contains("SignedHeaders=host;x-amz-date;x-amz-security-token")
+        );
+    }
+
+    #[test]
+    fn test_api_error() {
+        let body = include_str!("../assets/route53-error.xml");
+        let err = api_error(
+            "route53 change records",
+            reqwest::StatusCode::BAD_REQUEST,
+            body,
+        );
+        assert_eq!(
+            err.to_string(),
+            "route53 change records failed with 400 Bad Request: InvalidChangeBatch: Invalid request"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_route53_update() {
+        let (tx, addr) = route53_server!();
+        let http_client = reqwest::Client::new();
+        let config = config(addr);
+        let route53 = Route53Client {
+            client: &http_client,
+            config: &config,
+            credentials: Credentials::new("AKID", "secret", None, None, "test"),
+        };
+
+        let current = route53.get_record("example.com.", "A").await.unwrap();
+        let missing = route53.get_record("sub.example.com.", "A").await.unwrap();
+        assert_eq!(current.and_then(|x| x.ttl), Some(600));
+        assert_eq!(missing, None);
+
+        let new_ip = IpAddr::V4(Ipv4Addr::new(2, 2, 2, 1));
+        let changes = vec![
+            upsert(String::from("example.com."), "A", 600, new_ip),
+            upsert(String::from("sub.example.com."), "A", DEFAULT_TTL, new_ip),
+        ];
+        route53.change_records(changes).await.unwrap();
+
+        let err = route53
+            .change_records(vec![upsert(String::from("example.com."), "A", 600, new_ip)])
+            .await
+            .unwrap_err();
+        tx.send(()).unwrap();
+        assert!(err.to_string().contains("InvalidChangeBatch"));
+    }
+}
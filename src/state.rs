@@ -0,0 +1,332 @@
+use crate::circuit_breaker::Circuits;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io::{Error as IoError, ErrorKind};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+#[derive(Debug)]
+pub struct StateError {
+    kind: StateErrorKind,
+}
+
+#[derive(Debug)]
+pub enum StateErrorKind {
+    Read(IoError),
+    Parse(serde_json::Error),
+    Write(IoError),
+}
+
+impl error::Error for StateError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self.kind {
+            StateErrorKind::Read(ref e) => Some(e),
+            StateErrorKind::Parse(ref e) => Some(e),
+            StateErrorKind::Write(ref e) => Some(e),
+        }
+    }
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "state file issue: ")?;
+        match self.kind {
+            StateErrorKind::Read(ref _e) => write!(f, "unable to read file"),
+            StateErrorKind::Parse(ref _e) => write!(f, "a parsing error"),
+            StateErrorKind::Write(ref _e) => write!(f, "unable to write file"),
+        }
+    }
+}
+
+/// The WAN IP that was last successfully pushed to all configured domains
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct State {
+    #[serde(default)]
+    pub ipv4: Option<Ipv4Addr>,
+
+    #[serde(default)]
+    pub ipv6: Option<Ipv6Addr>,
+
+    pub updated_at: DateTime<Utc>,
+
+    /// The ids of cloudflare zones, which are kept alongside the addresses so that both are
+    /// written at once
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub zones: Zones,
+
+    /// The consecutive failures of domains that have failed since they last succeeded
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub circuits: Circuits,
+
+    /// When each record was last updated, so that updates can be spaced out
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub last_updated_at: LastUpdated,
+}
+
+/// When each record was last updated, keyed by the provider and the record's fully qualified name
+/// (eg: `godaddy/a.example.com`)
+pub type LastUpdated = BTreeMap<String, DateTime<Utc>>;
+
+/// The records that were updated less than the interval ago. An update from the future
+/// (eg: the clock was adjusted) is not trusted.
+pub fn recent_updates(
+    last_updated: &LastUpdated,
+    interval: chrono::Duration,
+    now: DateTime<Utc>,
+) -> LastUpdated {
+    last_updated
+        .iter()
+        .filter(|(_, at)| {
+            let age = now.signed_duration_since(**at);
+            age >= chrono::Duration::zero() && age < interval
+        })
+        .map(|(name, at)| (name.clone(), *at))
+        .collect()
+}
+
+/// Cloudflare zone ids keyed by the name of the zone
+pub type Zones = BTreeMap<String, CachedZone>;
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct CachedZone {
+    pub id: String,
+    pub cached_at: DateTime<Utc>,
+}
+
+/// The zone ids shared by the updates of a run, which may happen at the same time
+#[derive(Debug, Default)]
+pub struct ZoneCache {
+    zones: Mutex<Zones>,
+}
+
+impl ZoneCache {
+    pub fn new(zones: Zones) -> Self {
+        ZoneCache {
+            zones: Mutex::new(zones),
+        }
+    }
+
+    /// The cached id of the zone, unless it has outlived the ttl
+    pub fn get(&self, zone: &str, ttl: chrono::Duration, now: DateTime<Utc>) -> Option<String> {
+        let zones = self.zones.lock().ok()?;
+        let cached = zones.get(zone)?;
+        let age = now.signed_duration_since(cached.cached_at);
+        if age >= chrono::Duration::zero() && age < ttl {
+            Some(cached.id.clone())
+        } else {
+            None
+        }
+    }
+
+    /// Caches the id of the zone, returning the previously cached id when it differs
+    pub fn insert(&self, zone: &str, id: String, now: DateTime<Utc>) -> Option<String> {
+        let mut zones = self.zones.lock().ok()?;
+        let entry = CachedZone { id, cached_at: now };
+        zones
+            .insert(String::from(zone), entry.clone())
+            .map(|x| x.id)
+            .filter(|x| *x != entry.id)
+    }
+
+    pub fn remove(&self, zone: &str) {
+        if let Ok(mut zones) = self.zones.lock() {
+            zones.remove(zone);
+        }
+    }
+
+    pub fn zones(&self) -> Zones {
+        self.zones.lock().map(|x| x.clone()).unwrap_or_default()
+    }
+}
+
+impl State {
+    /// Whether the cached addresses match the resolved addresses and have not outlived the ttl.
+    /// A state from the future (eg: the clock was adjusted) is not trusted.
+    pub fn is_current(
+        &self,
+        wan: Ipv4Addr,
+        wan6: Option<Ipv6Addr>,
+        ttl: chrono::Duration,
+        now: DateTime<Utc>,
+    ) -> bool {
+        let age = now.signed_duration_since(self.updated_at);
+        self.ipv4 == Some(wan) && self.ipv6 == wan6 && age >= chrono::Duration::zero() && age < ttl
+    }
+}
+
+/// Reads the state file. A state file that does not exist yet is not an error.
+pub fn read_state<P: AsRef<Path>>(path: P) -> Result<Option<State>, StateError> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(StateError {
+                kind: StateErrorKind::Read(e),
+            })
+        }
+    };
+
+    serde_json::from_slice(&data)
+        .map(Some)
+        .map_err(|e| StateError {
+            kind: StateErrorKind::Parse(e),
+        })
+}
+
+/// Writes the state to a temporary file alongside the destination before renaming it into place
+/// so that a crash mid-write can't leave a truncated state file behind
+pub fn write_state<P: AsRef<Path>>(path: P, state: &State) -> Result<(), StateError> {
+    let path = path.as_ref();
+    let data = serde_json::to_vec_pretty(state).map_err(|e| StateError {
+        kind: StateErrorKind::Parse(e),
+    })?;
+
+    let tmp = temp_path(path);
+    fs::write(&tmp, data)
+        .and_then(|_| fs::rename(&tmp, path))
+        .map_err(|e| StateError {
+            kind: StateErrorKind::Write(e),
+        })
+}
+
+/// The path of the temporary file that is renamed into place over the given path
+pub fn temp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn test_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("dness-{}-{}.json", name, std::process::id()))
+    }
+
+    #[test]
+    fn test_state_round_trip() {
+        let path = test_path("round-trip");
+        let state = State {
+            ipv4: Some(Ipv4Addr::new(2, 2, 2, 2)),
+            ipv6: None,
+            updated_at: Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+            zones: Zones::from([(
+                String::from("example.com"),
+                CachedZone {
+                    id: String::from("0123"),
+                    cached_at: Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+                },
+            )]),
+            circuits: Circuits::new(),
+            last_updated_at: LastUpdated::from([(
+                String::from("example.com (cloudflare)"),
+                Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+            )]),
+        };
+
+        write_state(&path, &state).unwrap();
+        let actual = read_state(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(actual, Some(state));
+        assert!(!temp_path(&path).exists());
+    }
+
+    #[test]
+    fn test_state_missing() {
+        let path = test_path("missing");
+        assert_eq!(read_state(path).unwrap(), None);
+    }
+
+    #[test]
+    fn test_state_is_current() {
+        let ip = Ipv4Addr::new(2, 2, 2, 2);
+        let updated_at = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let state = State {
+            ipv4: Some(ip),
+            ipv6: None,
+            updated_at,
+            zones: Zones::new(),
+            circuits: Circuits::new(),
+            last_updated_at: LastUpdated::new(),
+        };
+
+        let ttl = chrono::Duration::seconds(3600);
+        let soon = updated_at + chrono::Duration::seconds(60);
+        let later = updated_at + chrono::Duration::seconds(3600);
+        let earlier = updated_at - chrono::Duration::seconds(60);
+        let ip6 = Some(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1));
+        assert!(state.is_current(ip, None, ttl, soon));
+        assert!(!state.is_current(Ipv4Addr::new(3, 3, 3, 3), None, ttl, soon));
+        assert!(!state.is_current(ip, ip6, ttl, soon));
+        assert!(!state.is_current(ip, None, ttl, later));
+        assert!(!state.is_current(ip, None, ttl, earlier));
+    }
+
+    #[test]
+    fn test_recent_updates() {
+        let updated_at = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let last_updated = LastUpdated::from([
+            (String::from("example.com (cloudflare)"), updated_at),
+            (
+                String::from("example.com (godaddy)"),
+                updated_at - chrono::Duration::seconds(600),
+            ),
+            (
+                String::from("example.com (porkbun)"),
+                updated_at + chrono::Duration::seconds(600),
+            ),
+        ]);
+
+        let interval = chrono::Duration::seconds(600);
+        let soon = updated_at + chrono::Duration::seconds(60);
+        let recent = recent_updates(&last_updated, interval, soon);
+        assert_eq!(
+            recent.keys().collect::<Vec<_>>(),
+            vec!["example.com (cloudflare)"]
+        );
+
+        let disabled = recent_updates(&last_updated, chrono::Duration::zero(), soon);
+        assert!(disabled.is_empty());
+    }
+
+    #[test]
+    fn test_zone_cache() {
+        let cached_at = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let ttl = chrono::Duration::seconds(86400);
+        let soon = cached_at + chrono::Duration::seconds(60);
+        let later = cached_at + ttl;
+
+        let cache = ZoneCache::default();
+        assert_eq!(cache.get("example.com", ttl, soon), None);
+        assert_eq!(
+            cache.insert("example.com", String::from("0123"), cached_at),
+            None
+        );
+        assert_eq!(
+            cache.get("example.com", ttl, soon),
+            Some(String::from("0123"))
+        );
+        assert_eq!(cache.get("example.com", ttl, later), None);
+
+        // Looking up the same id again refreshes the entry without reporting a change
+        assert_eq!(
+            cache.insert("example.com", String::from("0123"), soon),
+            None
+        );
+        assert_eq!(
+            cache.insert("example.com", String::from("4567"), soon),
+            Some(String::from("0123"))
+        );
+
+        cache.remove("example.com");
+        assert!(cache.zones().is_empty());
+    }
+}
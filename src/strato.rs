@@ -0,0 +1,203 @@
+use crate::config::StratoConfig;
+use crate::core::{
+    parse_dyndns2_response, DnsCheckedRecords, DnsProvider, SendTraced, UpdateContext,
+    UpdateOutcome, Updates,
+};
+use crate::errors::DnessError;
+use crate::retry::retry;
+use async_trait::async_trait;
+use std::net::IpAddr;
+
+#[derive(Debug)]
+pub struct StratoProvider<'a> {
+    client: &'a reqwest::Client,
+    config: &'a StratoConfig,
+}
+
+impl<'a> StratoProvider<'a> {
+    /// https://www.strato.de/faq/domains/so-einfach-richten-sie-dyndns-fuer-ihre-domains-ein/
+    pub async fn update_domain(
+        &self,
+        host: &str,
+        wan: IpAddr,
+    ) -> Result<UpdateOutcome, DnessError> {
+        let base = self.config.base_url.trim_end_matches('/').to_string();
+        let get_url = format!("{}/nic/update", base);
+
+        // strato authenticates with the record being updated as the username
+        let response = self
+            .client
+            .get(&get_url)
+            .query(&[("hostname", host), ("myip", &wan.to_string())])
+            .basic_auth(host, Some(&self.config.password))
+            .send_traced()
+            .await
+            .map_err(|e| DnessError::send_http(&get_url, "strato update", e))?
+            .error_for_status()
+            .map_err(|e| DnessError::bad_response(&get_url, "strato update", e))?
+            .text()
+            .await
+            .map_err(|e| DnessError::deserialize(&get_url, "strato update", e))?;
+
+        parse_dyndns2_response(&response)
+    }
+}
+
+#[tracing::instrument(skip(client, config), fields(domain = %config.domain))]
+pub async fn update_domains(
+    client: &reqwest::Client,
+    config: &StratoConfig,
+    wan: IpAddr,
+    dry_run: bool,
+    force: bool,
+) -> Result<Updates, DnessError> {
+    // uses the same strategy as namecheap where we get the current records
+    // via dns and check if they need to be updated
+    let strato = &StratoProvider { client, config };
+    DnsCheckedRecords {
+        provider: "strato",
+        domain: &config.domain,
+        records: &config.records,
+        dry_run,
+        force,
+    }
+    .update(
+        wan,
+        |host| async move { strato.update_domain(&host, wan).await },
+    )
+    .await
+}
+
+#[async_trait]
+impl DnsProvider for StratoConfig {
+    async fn update(
+        &self,
+        ctx: &UpdateContext<'_>,
+        addr: IpAddr,
+    ) -> Result<Updates, Box<dyn std::error::Error>> {
+        retry(ctx.policy, ctx.name, || {
+            update_domains(ctx.client, self, addr, ctx.dry_run, ctx.force)
+        })
+        .await
+        .map_err(|e| e.into())
+    }
+
+    fn name(&self) -> &str {
+        "strato"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::IpType;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+    use std::sync::{Arc, Mutex};
+
+    /// The decoded basic auth username and the hostname and myip params of a request
+    type StratoRequest = (String, Option<String>, Option<String>);
+
+    macro_rules! strato_server {
+        () => {{
+            use rouille::Response;
+            use rouille::Server;
+
+            let requests: Arc<Mutex<Vec<StratoRequest>>> = Arc::default();
+            let seen = requests.clone();
+            let server = Server::new("localhost:0", move |request| {
+                if request.url() != "/nic/update" {
+                    return Response::empty_404();
+                }
+
+                let auth = match rouille::input::basic_http_auth(request) {
+                    Some(auth) if auth.password == "my-pass" => auth,
+                    _ => return Response::text("badauth"),
+                };
+
+                let hostname = request.get_param("hostname");
+                let myip = request.get_param("myip");
+                seen.lock()
+                    .unwrap()
+                    .push((auth.login, hostname.clone(), myip.clone()));
+                match myip {
+                    Some(ip) if hostname.is_some() => Response::text(format!("good {}", ip)),
+                    _ => Response::text("notfqdn"),
+                }
+            })
+            .unwrap();
+
+            let (tx, rx) = std::sync::mpsc::sync_channel(1);
+            let addr = server.server_addr().clone();
+            std::thread::spawn(move || {
+                while let Err(_) = rx.try_recv() {
+                    server.poll();
+                    std::thread::sleep(std::time::Duration::from_millis(50))
+                }
+            });
+            (tx, addr, requests)
+        }};
+    }
+
+    #[tokio::test]
+    async fn test_strato_record_auth() {
+        let (tx, addr, requests) = strato_server!();
+        let http_client = reqwest::Client::new();
+        let config = StratoConfig {
+            base_url: format!("http://{}", addr),
+            domain: String::from("example.com"),
+            password: String::from("my-pass"),
+            records: vec![String::from("@"), String::from("sub")],
+            ip_types: vec![IpType::V4, IpType::V6],
+            request_timeout_secs: None,
+            log_level: None,
+            name: None,
+        };
+
+        let v4 = IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2));
+        let summary = update_domains(&http_client, &config, v4, false, true)
+            .await
+            .unwrap();
+
+        let v6 = IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1));
+        let config = StratoConfig {
+            records: vec![String::from("@")],
+            ..config
+        };
+        let summary6 = update_domains(&http_client, &config, v6, false, true)
+            .await
+            .unwrap();
+        tx.send(()).unwrap();
+
+        assert_eq!(
+            summary,
+            Updates {
+                updated: 2,
+                ..Updates::default()
+            }
+        );
+        assert_eq!(
+            summary6,
+            Updates {
+                updated: 1,
+                ..Updates::default()
+            }
+        );
+
+        // Each record authenticates with its own fully qualified name as the username
+        let request = |login: &str, hostname: &str, myip: &str| {
+            (
+                String::from(login),
+                Some(String::from(hostname)),
+                Some(String::from(myip)),
+            )
+        };
+        assert_eq!(
+            *requests.lock().unwrap(),
+            vec![
+                request("example.com", "example.com", "2.2.2.2"),
+                request("sub.example.com", "sub.example.com", "2.2.2.2"),
+                request("example.com", "example.com", "2001:db8::1"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,64 @@
+use crate::core::Updates;
+use serde::Serialize;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
+
+/// The totals of a run, written to stdout as a single line of JSON so that scripts don't need
+/// to parse the log
+#[derive(Serialize, Clone, PartialEq, Eq, Debug)]
+pub struct RunSummary {
+    pub updated: i32,
+    pub current: i32,
+    pub missing: i32,
+    pub failed: i32,
+    pub elapsed_ms: u64,
+    pub wan_ipv4: Option<Ipv4Addr>,
+    pub wan_ipv6: Option<Ipv6Addr>,
+}
+
+impl RunSummary {
+    pub fn new(
+        updates: &Updates,
+        elapsed: Duration,
+        addr: Option<Ipv4Addr>,
+        addr6: Option<Ipv6Addr>,
+    ) -> RunSummary {
+        RunSummary {
+            updated: updates.updated,
+            current: updates.current,
+            missing: updates.missing,
+            failed: updates.failed,
+            elapsed_ms: elapsed.as_millis() as u64,
+            wan_ipv4: addr,
+            wan_ipv6: addr6,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_json() {
+        let updates = Updates {
+            updated: 2,
+            current: 5,
+            ..Updates::default()
+        };
+        let summary = RunSummary::new(
+            &updates,
+            Duration::from_millis(1234),
+            Some(Ipv4Addr::new(1, 2, 3, 4)),
+            None,
+        );
+        assert_eq!(
+            summary.to_json(),
+            r#"{"updated":2,"current":5,"missing":0,"failed":0,"elapsed_ms":1234,"wan_ipv4":"1.2.3.4","wan_ipv6":null}"#
+        );
+    }
+}
@@ -0,0 +1,107 @@
+use crate::core::Updates;
+use sd_notify::NotifyState;
+use tracing::{debug, warn};
+
+/// Keeps systemd informed of a daemon running as a `Type=notify` service: it is ready once the
+/// first run completes, the status is the outcome of the last run, and the watchdog is pinged
+/// when the service asks for it
+#[derive(Debug)]
+pub struct SystemdNotifier {
+    ready: bool,
+    watchdog: Option<tokio::task::JoinHandle<()>>,
+}
+
+impl SystemdNotifier {
+    pub fn new() -> Self {
+        let mut usec = 0;
+        let watchdog = sd_notify::watchdog_enabled(false, &mut usec).then(|| {
+            // systemd recommends pinging at half of the timeout
+            let period = std::time::Duration::from_micros(usec / 2);
+            debug!(
+                "pinging the systemd watchdog every {}ms",
+                period.as_millis()
+            );
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(period);
+                loop {
+                    interval.tick().await;
+                    notify(&[NotifyState::Watchdog]);
+                }
+            })
+        });
+
+        SystemdNotifier {
+            ready: false,
+            watchdog,
+        }
+    }
+
+    pub fn run_finished(&mut self, updates: &Updates) {
+        let status = format!("last run: {}", updates);
+        if self.ready {
+            notify(&[NotifyState::Status(&status)]);
+        } else {
+            self.ready = true;
+            notify(&[NotifyState::Ready, NotifyState::Status(&status)]);
+        }
+    }
+
+    pub fn stopping(&self) {
+        notify(&[NotifyState::Stopping]);
+    }
+}
+
+impl Drop for SystemdNotifier {
+    fn drop(&mut self) {
+        if let Some(watchdog) = self.watchdog.take() {
+            watchdog.abort();
+        }
+    }
+}
+
+fn notify(state: &[NotifyState]) {
+    if let Err(e) = sd_notify::notify(false, state) {
+        warn!("could not notify systemd: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixDatagram;
+
+    #[test]
+    fn test_systemd_notifier() {
+        let path = std::env::temp_dir().join(format!("dness-notify-{}.sock", std::process::id()));
+        let socket = UnixDatagram::bind(&path).unwrap();
+        std::env::set_var("NOTIFY_SOCKET", &path);
+
+        let mut notifier = SystemdNotifier::new();
+        let updates = Updates {
+            updated: 1,
+            current: 2,
+            ..Updates::default()
+        };
+        notifier.run_finished(&updates);
+        notifier.run_finished(&Updates::default());
+        notifier.stopping();
+        std::env::remove_var("NOTIFY_SOCKET");
+
+        let mut messages = Vec::new();
+        for _ in 0..3 {
+            let mut buf = [0; 256];
+            let len = socket.recv(&mut buf).unwrap();
+            messages.push(String::from_utf8_lossy(&buf[..len]).into_owned());
+        }
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            messages,
+            vec![
+                "READY=1\nSTATUS=last run: updated: 1, already current: 2, missing: 0, failed: 0\n",
+                "STATUS=last run: updated: 0, already current: 0, missing: 0, failed: 0\n",
+                "STOPPING=1\n",
+            ]
+        );
+    }
+}
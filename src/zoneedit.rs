@@ -0,0 +1,167 @@
+use crate::config::ZoneEditConfig;
+use crate::core::{
+    DnsCheckedRecords, DnsProvider, SendTraced, UpdateContext, UpdateOutcome, Updates,
+};
+use crate::errors::DnessError;
+use crate::retry::retry;
+use async_trait::async_trait;
+use quick_xml::events::Event;
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+/// ZoneEdit responds with a single element, like `<SUCCESS CODE="200" TEXT="Update succeeded."
+/// ZONE="example.com" IP="2.2.2.2">` or `<ERROR CODE="709" TEXT="Invalid hostname">`, which isn't
+/// always closed, so the element and its attributes are read without deserializing a document
+fn parse_zoneedit_response(body: &str) -> Result<UpdateOutcome, DnessError> {
+    let unparseable =
+        || DnessError::message(format!("unable to parse zoneedit response: {}", body));
+    let mut reader = quick_xml::Reader::from_str(body);
+    let element = loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) | Ok(Event::Empty(e)) => break e,
+            Ok(Event::Eof) | Err(_) => return Err(unparseable()),
+            Ok(_) => continue,
+        }
+    };
+
+    let name = String::from_utf8_lossy(element.name().as_ref()).to_ascii_uppercase();
+    let attributes = element
+        .attributes()
+        .filter_map(|x| x.ok())
+        .filter_map(|x| {
+            let key = String::from_utf8_lossy(x.key.as_ref()).to_ascii_uppercase();
+            x.unescape_value()
+                .ok()
+                .map(|value| (key, value.into_owned()))
+        })
+        .collect::<HashMap<_, _>>();
+    let code = attributes
+        .get("CODE")
+        .map(|x| x.as_str())
+        .unwrap_or_default();
+    let text = attributes
+        .get("TEXT")
+        .map(|x| x.as_str())
+        .unwrap_or_default();
+
+    match (name.as_str(), code) {
+        ("SUCCESS", _) => Ok(UpdateOutcome::Updated),
+
+        // Duplicate updates for the same host and address
+        ("ERROR", "707") => Ok(UpdateOutcome::Current),
+
+        // The zone isn't set up in the account
+        ("ERROR", "701") => Ok(UpdateOutcome::Missing),
+        ("ERROR", _) => Err(DnessError::message(format!(
+            "zoneedit update failed [{}]: {}",
+            code, text
+        ))),
+        _ => Err(unparseable()),
+    }
+}
+
+#[derive(Debug)]
+pub struct ZoneEditProvider<'a> {
+    client: &'a reqwest::Client,
+    config: &'a ZoneEditConfig,
+}
+
+impl<'a> ZoneEditProvider<'a> {
+    /// https://support.zoneedit.com/en/knowledgebase/article/dynamic-dns
+    pub async fn update_domain(
+        &self,
+        host: &str,
+        wan: IpAddr,
+    ) -> Result<UpdateOutcome, DnessError> {
+        let base = self.config.base_url.trim_end_matches('/').to_string();
+        let get_url = format!("{}/auth/dynamic.html", base);
+
+        // ipv6 addresses are given in their own parameter
+        let ip_param = match wan {
+            IpAddr::V4(_) => "dnsto",
+            IpAddr::V6(_) => "ipv6",
+        };
+
+        let response = self
+            .client
+            .get(&get_url)
+            .query(&[("host", host), (ip_param, &wan.to_string())])
+            .basic_auth(&self.config.username, Some(&self.config.password))
+            .send_traced()
+            .await
+            .map_err(|e| DnessError::send_http(&get_url, "zoneedit update", e))?
+            .error_for_status()
+            .map_err(|e| DnessError::bad_response(&get_url, "zoneedit update", e))?
+            .text()
+            .await
+            .map_err(|e| DnessError::deserialize(&get_url, "zoneedit update", e))?;
+
+        parse_zoneedit_response(&response)
+    }
+}
+
+#[tracing::instrument(skip(client, config), fields(domain = %config.hostname))]
+pub async fn update_domains(
+    client: &reqwest::Client,
+    config: &ZoneEditConfig,
+    wan: IpAddr,
+    dry_run: bool,
+    force: bool,
+) -> Result<Updates, DnessError> {
+    // uses the same strategy as namecheap where we get the current records
+    // via dns and check if they need to be updated
+    let zoneedit = &ZoneEditProvider { client, config };
+    DnsCheckedRecords {
+        provider: "zoneedit",
+        domain: &config.hostname,
+        records: &config.records,
+        dry_run,
+        force,
+    }
+    .update(wan, |host| async move {
+        zoneedit.update_domain(&host, wan).await
+    })
+    .await
+}
+
+#[async_trait]
+impl DnsProvider for ZoneEditConfig {
+    async fn update(
+        &self,
+        ctx: &UpdateContext<'_>,
+        addr: IpAddr,
+    ) -> Result<Updates, Box<dyn std::error::Error>> {
+        retry(ctx.policy, ctx.name, || {
+            update_domains(ctx.client, self, addr, ctx.dry_run, ctx.force)
+        })
+        .await
+        .map_err(|e| e.into())
+    }
+
+    fn name(&self) -> &str {
+        "zoneedit"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_zoneedit_response() {
+        assert_eq!(
+            parse_zoneedit_response(r#"<SUCCESS CODE="200" TEXT="Update succeeded." />"#).unwrap(),
+            UpdateOutcome::Updated
+        );
+        assert_eq!(
+            parse_zoneedit_response(r#"<error code="707" text="Duplicate updates">"#).unwrap(),
+            UpdateOutcome::Current
+        );
+
+        let err = parse_zoneedit_response(r#"<ERROR CODE="709" TEXT="Invalid hostname">"#)
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "zoneedit update failed [709]: Invalid hostname");
+        assert!(parse_zoneedit_response("Bad Request").is_err());
+    }
+}
@@ -15,3 +15,133 @@ fn resolve_wan_on_no_arguments() {
         }
     }
 }
+
+/// Serves a fixed WAN IP as plain text for the http resolver
+fn ip_server() -> (std::sync::mpsc::SyncSender<()>, std::net::SocketAddr) {
+    let server = rouille::Server::new("localhost:0", |_request| {
+        rouille::Response::text("2.2.2.2\n")
+    })
+    .unwrap();
+    let addr = server.server_addr();
+    let (tx, rx) = std::sync::mpsc::sync_channel::<()>(1);
+    std::thread::spawn(move || {
+        while rx.try_recv().is_err() {
+            server.poll();
+            std::thread::sleep(std::time::Duration::from_millis(50))
+        }
+    });
+    (tx, addr)
+}
+
+fn print_ip(name: &str, contents: &str) -> String {
+    let config = std::env::temp_dir().join(format!("dness-{}-{}.toml", name, std::process::id()));
+    std::fs::write(&config, contents).unwrap();
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    let output = cmd.arg("--print-ip").arg("-c").arg(&config).ok();
+    std::fs::remove_file(&config).unwrap();
+
+    let output = output.unwrap();
+    String::from(std::str::from_utf8(&output.stdout).unwrap())
+}
+
+#[test]
+fn print_ip_with_http_resolver() {
+    let (tx, addr) = ip_server();
+    let contents = format!(
+        "ip_resolver = \"http\"\nip_resolver_url = \"http://{}/\"\n",
+        addr
+    );
+    let stdout = print_ip("print-ip", &contents);
+    tx.send(()).unwrap();
+    assert_eq!(stdout, "2.2.2.2\n");
+}
+
+#[test]
+fn print_ip_with_resolver_fallback() {
+    let (tx, addr) = ip_server();
+    let contents = format!(
+        "ip_resolver = [\"interface\", \"http\"]\nip_resolver_interface = \"dness-missing0\"\nip_resolver_url = \"http://{}/\"\n",
+        addr
+    );
+    let stdout = print_ip("resolver-fallback", &contents);
+    tx.send(()).unwrap();
+    assert_eq!(stdout, "2.2.2.2\n");
+}
+
+#[test]
+fn json_summary_on_stdout() {
+    let (tx, addr) = ip_server();
+    let config =
+        std::env::temp_dir().join(format!("dness-json-summary-{}.toml", std::process::id()));
+    let contents = format!(
+        "ip_resolver = \"http\"\nip_resolver_url = \"http://{}/\"\n",
+        addr
+    );
+    std::fs::write(&config, contents).unwrap();
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    let output = cmd.arg("--output").arg("json").arg("-c").arg(&config).ok();
+    std::fs::remove_file(&config).unwrap();
+    tx.send(()).unwrap();
+
+    let output = output.unwrap();
+    let stdout = std::str::from_utf8(&output.stdout).unwrap();
+    let summary: serde_json::Value = serde_json::from_str(stdout.trim()).unwrap();
+    assert_eq!(summary["updated"], 0);
+    assert_eq!(summary["wan_ipv4"], "2.2.2.2");
+    assert_eq!(summary["wan_ipv6"], serde_json::Value::Null);
+
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    assert!(stderr.contains("resolved address to"));
+}
+
+#[cfg(unix)]
+#[test]
+fn daemon_exits_cleanly_on_sigterm() {
+    use nix::sys::signal::{kill, Signal};
+    use nix::unistd::Pid;
+    use std::io::{BufRead, BufReader};
+    use std::process::Stdio;
+
+    let (tx, addr) = ip_server();
+    let config = std::env::temp_dir().join(format!("dness-sigterm-{}.toml", std::process::id()));
+    let contents = format!(
+        "ip_resolver = \"http\"\nip_resolver_url = \"http://{}/\"\n",
+        addr
+    );
+    std::fs::write(&config, contents).unwrap();
+
+    let mut child =
+        std::process::Command::new(assert_cmd::cargo::cargo_bin(env!("CARGO_PKG_NAME")))
+            .arg("--interval")
+            .arg("3600")
+            .arg("-c")
+            .arg(&config)
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+
+    // Wait for the first run to finish before asking the daemon to stop
+    let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+    let mut log = Vec::new();
+    for line in lines.by_ref() {
+        let line = line.unwrap();
+        let done = line.contains("processed all");
+        log.push(line);
+        if done {
+            break;
+        }
+    }
+
+    kill(Pid::from_raw(child.id() as i32), Signal::SIGTERM).unwrap();
+    log.extend(lines.map(|x| x.unwrap()));
+    let status = child.wait().unwrap();
+    std::fs::remove_file(&config).unwrap();
+    tx.send(()).unwrap();
+
+    assert!(status.success());
+    assert!(log
+        .iter()
+        .any(|x| x.contains("shutting down (runs: 1, updated: 0")));
+}